@@ -43,6 +43,8 @@ class ArbitrageProgramInstruction {
     swap_2_program_id: Uint8Array
     concurrency: number
     temperature: number
+    // Number of tagged `TryArbitrageOptions` entries; none means all defaults
+    option_count: number
     constructor(props: {
         swapProgram1: PublicKey
         swapProgram2: PublicKey
@@ -54,6 +56,7 @@ class ArbitrageProgramInstruction {
         this.swap_2_program_id = props.swapProgram2.toBuffer()
         this.concurrency = props.concurrency
         this.temperature = props.temperature
+        this.option_count = 0
     }
     toBuffer() {
         return Buffer.from(
@@ -73,6 +76,7 @@ const ArbitrageProgramInstructionSchema = new Map([
                 ['swap_2_program_id', [32]],
                 ['concurrency', 'u8'],
                 ['temperature', 'u8'],
+                ['option_count', 'u8'],
             ],
         },
     ],
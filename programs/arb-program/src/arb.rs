@@ -1,13 +1,30 @@
+use std::cell::Cell;
+use std::rc::Rc;
+
+use borsh::{BorshDeserialize, BorshSerialize};
 use solana_program::{
-    account_info::AccountInfo, entrypoint::ProgramResult, instruction::Instruction, msg,
-    program::invoke, pubkey::Pubkey,
+    account_info::AccountInfo,
+    entrypoint::ProgramResult,
+    instruction::Instruction,
+    program::{get_return_data, invoke, invoke_signed},
+    program_error::ProgramError,
+    pubkey::Pubkey,
 };
 
 use crate::{
+    arb_msg,
     error::ArbitrageProgramError,
-    partial_state::{ArbitrageMintInfo, ArbitrageTokenAccountInfo},
-    swap::determine_swap_receive,
+    partial_state::{ArbitrageMintInfo, ArbitrageTokenAccountInfo, PartialTokenAccountState},
+    record::RecordedOpportunity,
+    result::{
+        set_return_data, ArbitrageResult, LegResult, MaxSizeQuote, NoArbReport, ScanReport,
+        SkipReason, TradeQuote,
+    },
+    swap::{apply_fee, determine_weighted_swap_receive_checked, pow10, BPS_DENOMINATOR},
+    treasury::{collect_treasury_fee, sweep_dust},
+    triangular::{try_triangular_arbitrage, Swap3Accounts},
     util::{ArbtrageEvaluateOption, ToAccountMeta},
+    TryArbitrageOptions,
 };
 
 /// 尝试执行套利交易的参数结构体
@@ -25,12 +42,14 @@ pub struct TryArbitrageArgs<'a, 'b> {
     pub mints: Vec<ArbitrageMintInfo<'a, 'b>>,
     /// 交易支付方账户，用于支付交易费用和作为交易签名者
     pub payer: &'a AccountInfo<'b>,
+    /// 用户代币账户的所有者（交易授权方），作为 swap 指令的签名者
+    pub owner: &'a AccountInfo<'b>,
     /// SPL代币程序账户，用于执行代币相关的操作
     pub token_program: &'a AccountInfo<'b>,
     /// 系统程序账户，用于创建新账户等系统级操作
     pub system_program: &'a AccountInfo<'b>,
-    /// 关联代币程序账户，用于创建和管理关联代币账户
-    pub associated_token_program: &'a AccountInfo<'b>,
+    /// 关联代币程序账户，用于创建和管理关联代币账户；设置了 `omit_associated_token_program` 时为 `None`
+    pub associated_token_program: Option<&'a AccountInfo<'b>>,
     /// 第一个交易池的程序账户，用于调用第一个交易池的交换逻辑
     pub swap_1_program: &'a AccountInfo<'b>,
     /// 第二个交易池的程序账户，用于调用第二个交易池的交换逻辑
@@ -39,13 +58,121 @@ pub struct TryArbitrageArgs<'a, 'b> {
     pub swap_1_pool: &'a AccountInfo<'b>,
     /// 第二个交易池账户，包含第二个交易池的状态和配置信息
     pub swap_2_pool: &'a AccountInfo<'b>,
-    /// 温度参数，用于控制套利交易的敏感度或风险级别
+    /// 铸币账户无法解析而被排除在扫描之外的资产索引
+    pub excluded_assets: Vec<usize>,
+    /// 两个流动性池的手续费率
+    pub fees: PoolFees,
+    /// 交易的激进程度，价差百分比超过 `100 - temperature` 时才交易，数值越大越激进
     pub temperature: u8,
+    /// 指令携带的可选配置
+    pub options: TryArbitrageOptions,
+    /// 从套利计划 PDA 读取的计划；存在时只重新检查并执行该计划，不再扫描所有资产对
+    pub recorded_plan: Option<RecordedOpportunity>,
+    /// 收取 treasury 费用时接收费用的代币账户（支付资产的代币账户）
+    pub treasury: Option<&'a AccountInfo<'b>>,
+    /// 程序配置黑名单中的资产对 `(i, j)`（`i < j`），扫描时总是跳过
+    pub denied_pairs: Vec<(usize, usize)>,
+    /// 设置了 `persist_record` 时接收交易记录的日志程序
+    pub record_program: Option<&'a AccountInfo<'b>>,
+    /// 设置了 `sweep_dust` 时接收剩余中间资产的代币账户
+    pub dust_collector: Option<&'a AccountInfo<'b>>,
+    /// 设置了 `triangular` 时三角套利使用的 Swap #3 账户
+    pub swap_3: Option<Swap3Accounts<'a, 'b>>,
+    /// 已经发起的 CPI 数量，`TryArbitrageMulti` 在多次尝试之间共享同一个计数器
+    pub cpi_count: Rc<Cell<u16>>,
+}
+
+impl<'a, 'b> TryArbitrageArgs<'a, 'b> {
+    /// 记录即将发起的一次 CPI
+    pub(crate) fn count_cpi(&self) {
+        self.cpi_count.set(self.cpi_count.get().saturating_add(1));
+    }
+
+    /// 在扫描之前检查参数的一致性
+    ///
+    /// 四个列表必须长度相同且至少包含两个资产，swap 程序与流动性池的地址不能是默认的全零公钥。
+    ///
+    /// # 错误
+    /// * `ArbitrageProgramError::InvalidAccountsList` - 列表长度不一致、资产少于两个或流动性池地址未设置
+    /// * `ArbitrageProgramError::UnsetSwapProgramId` - swap 程序地址未设置
+    pub fn validate(&self) -> Result<(), ProgramError> {
+        let len = self.mints.len();
+        if len < 2
+            || self.token_accounts_user.len() != len
+            || self.token_accounts_swap_1.len() != len
+            || self.token_accounts_swap_2.len() != len
+        {
+            arb_msg!(
+                "Account lists must have equal lengths of at least 2: user {}, swap #1 {}, swap #2 {}, mints {}",
+                self.token_accounts_user.len(),
+                self.token_accounts_swap_1.len(),
+                self.token_accounts_swap_2.len(),
+                len
+            );
+            return Err(ArbitrageProgramError::InvalidAccountsList.into());
+        }
+        if self.swap_1_program.key.eq(&Pubkey::default())
+            || self.swap_2_program.key.eq(&Pubkey::default())
+        {
+            arb_msg!("Swap program accounts must be set");
+            return Err(ArbitrageProgramError::UnsetSwapProgramId.into());
+        }
+        if self.swap_1_pool.key.eq(&Pubkey::default())
+            || self.swap_2_pool.key.eq(&Pubkey::default())
+        {
+            arb_msg!("Liquidity pool accounts must be set");
+            return Err(ArbitrageProgramError::InvalidAccountsList.into());
+        }
+        Ok(())
+    }
+
+    /// 返回资产 `index` 在用户、两个流动性池中的代币账户及其铸币账户
+    pub(crate) fn asset_accounts(
+        &self,
+        index: usize,
+    ) -> Result<AssetAccounts<&'a AccountInfo<'b>>, ProgramError> {
+        Ok(AssetAccounts {
+            index,
+            user: self.token_accounts_user.get(index).ok_or_arb_err()?.0,
+            swap_1: self.token_accounts_swap_1.get(index).ok_or_arb_err()?.0,
+            swap_2: self.token_accounts_swap_2.get(index).ok_or_arb_err()?.0,
+            mint: self.mints.get(index).ok_or_arb_err()?.0,
+        })
+    }
+
+    /// 返回在 `venue` 上用资产 `pay` 换取资产 `receive` 时的权重 `(w_in, w_out)`，
+    /// 未配置权重时为 0，即按恒定乘积公式定价
+    pub(crate) fn pool_weights(&self, venue: Buy, pay: usize, receive: usize) -> (u8, u8) {
+        let weights = match venue {
+            Buy::Swap1 => &self.options.swap_1_weights,
+            Buy::Swap2 => &self.options.swap_2_weights,
+        };
+        (
+            weights.get(pay).copied().unwrap_or(0),
+            weights.get(receive).copied().unwrap_or(0),
+        )
+    }
+
+    /// 以资产 `index` 的最小单位返回利润下限
+    ///
+    /// 设置了 `min_profit_whole_tokens` 时 `min_profit` 以整币计，按该资产的小数位数换算，
+    /// 超出 `u64` 范围时视为无法满足。
+    pub(crate) fn min_profit(&self, index: usize) -> Result<u64, ProgramError> {
+        if !self.options.min_profit_whole_tokens {
+            return Ok(self.options.min_profit);
+        }
+        let decimals = self.mints.get(index).ok_or_arb_err()?.1;
+        Ok(u128::from(self.options.min_profit)
+            .checked_mul(pow10(decimals)?)
+            .and_then(|min_profit| u64::try_from(min_profit).ok())
+            .unwrap_or(u64::MAX))
+    }
 }
 
 /// 尝试在两个去中心化交易所池之间执行套利交易。
 ///
-/// 该函数会遍历所有资产对，计算在两个交易池之间的潜在套利机会，并在发现有利可图的交易时执行。
+/// 该函数会遍历所有资产对，计算在两个交易池之间的潜在套利机会。当超过阈值的资产对数量
+/// 达到 `min_qualifying_pairs` 时，执行其中价差最大的一个。
 ///
 /// # 参数
 ///
@@ -59,140 +186,1191 @@ pub struct TryArbitrageArgs<'a, 'b> {
 ///   - `swap_1_program`: 第一个交易程序的账户信息
 ///   - `swap_2_program`: 第二个交易程序的账户信息
 ///   - `payer`: 交易支付者账户
+///   - `owner`: 用户代币账户的所有者
 ///   - `token_program`: SPL Token 程序账户
 ///   - `system_program`: 系统程序账户
-///   - `associated_token_program`: 关联代币程序账户
+///   - `associated_token_program`: 关联代币程序账户（可省略）
+///   - `excluded_assets`: 被排除在扫描之外的资产索引
+///   - `fees`: 两个流动性池的手续费率
 ///   - `temperature`: 套利温度阈值，用于判断是否执行交易
+///   - `options`: 指令携带的可选配置，见 `TryArbitrageOptions`
+///   - `recorded_plan`: 从套利计划 PDA 读取的计划
+///   - `treasury`: 接收 treasury 费用的代币账户
+///   - `denied_pairs`: 黑名单中的资产对
 ///
 /// # 返回值
 ///
 /// * `ProgramResult` - 如果成功执行套利则返回 Ok，否则返回错误码。
 ///   - 成功执行后将返回相应的交易调用结果
-///   - 如果没有找到套利机会，则返回 `ArbitrageProgramError::NoArbitrage`
+///   - 如果没有找到套利机会，则返回 `ArbitrageProgramError::NoArbitrage`；
+///     设置了 `report_no_arbitrage` 时改为返回 Ok 并写入 `NoArbReport`
 pub fn try_arbitrage(args: TryArbitrageArgs<'_, '_>) -> ProgramResult {
-    msg!("Swap #1 Pool: {}", args.swap_1_pool.key);
-    msg!("Swap #2 Pool: {}", args.swap_2_pool.key);
-
-    let mints_len = args.mints.len();
-
-    // 遍历每一对不同的资产（i 和 j），尝试寻找套利路径
-    for i in 0..mints_len {
-        // 加载当前资产相关的用户账户和两个交易池中的账户及 Mint 信息
-        let user_i = args.token_accounts_user.get(i).ok_or_arb_err()?;
-        let swap_1_i = args.token_accounts_swap_1.get(i).ok_or_arb_err()?;
-        let swap_2_i = args.token_accounts_swap_2.get(i).ok_or_arb_err()?;
-        let mint_i = args.mints.get(i).ok_or_arb_err()?;
-
-        for j in (i + 1)..mints_len {
-            // 加载目标资产相关的用户账户和两个交易池中的账户及 Mint 信息
-            let user_j = args.token_accounts_user.get(j).ok_or_arb_err()?;
-            let swap_1_j = args.token_accounts_swap_1.get(j).ok_or_arb_err()?;
-            let swap_2_j = args.token_accounts_swap_2.get(j).ok_or_arb_err()?;
-            let mint_j = args.mints.get(j).ok_or_arb_err()?;
+    args.validate()?;
+    arb_msg!("Swap #1 Pool: {}", args.swap_1_pool.key);
+    arb_msg!("Swap #2 Pool: {}", args.swap_2_pool.key);
+
+    // 手续费过高的池子会吃掉任何价差，不与其交易
+    if let Some(max_fee_bps) = args.options.max_acceptable_fee_bps {
+        for (name, fee_bps) in [
+            ("Swap #1", args.fees.swap_1_fee_bps),
+            ("Swap #2", args.fees.swap_2_fee_bps),
+        ] {
+            if fee_bps > max_fee_bps {
+                arb_msg!(
+                    "Skipping {} pool: fee {} bps exceeds the maximum {} bps",
+                    name,
+                    fee_bps,
+                    max_fee_bps
+                );
+                return Err(ArbitrageProgramError::PoolFeeTooHigh.into());
+            }
+        }
+    }
+
+    let quotes = asset_quotes(&args)?;
+    if let Some(plan) = &args.recorded_plan {
+        return execute_recorded_plan(&args, &quotes, plan);
+    }
+    if let Some(swap_3) = &args.swap_3 {
+        return try_triangular_arbitrage(&args, &quotes, swap_3);
+    }
+
+    let mut scan = scan_opportunities(
+        &quotes,
+        &args.denied_pairs,
+        args.fees,
+        args.temperature,
+        args.options.max_edge_bps,
+        args.options.min_residual,
+        args.options.force_direction,
+        args.options.tie_break,
+        args.options.verbose,
+    )?;
+    // 第一档（全部余额、`temperature` 阈值）没有可执行的机会时，按缩小的数量和第二档阈值重新扫描
+    if let Some(tier) = args
+        .options
+        .secondary_tier
+        .filter(|_| scan.selected(args.options.min_qualifying_pairs).is_none())
+    {
+        arb_msg!(
+            "Primary tier found nothing, trying {} bps of size at temperature {}",
+            tier.size_bps,
+            tier.temperature
+        );
+        let reduced = tier.scale(&quotes);
+        scan = scan_opportunities(
+            &reduced,
+            &args.denied_pairs,
+            args.fees,
+            tier.temperature,
+            args.options.max_edge_bps,
+            args.options.min_residual,
+            args.options.force_direction,
+            args.options.tie_break,
+            args.options.verbose,
+        )?;
+    }
+    if let Some(opportunity) = scan.selected(args.options.min_qualifying_pairs) {
+        if args.options.quote_max_size {
+            return quote_max_size(&args, &quotes, opportunity);
+        }
+        return execute_opportunity(&args, opportunity);
+    }
+
+    if scan.qualifying_pairs > 0 {
+        arb_msg!(
+            "{} pair(s) qualified, {} required",
+            scan.qualifying_pairs,
+            args.options.min_qualifying_pairs
+        );
+    }
+    // 如果遍历完所有资产对仍未发现套利机会，则返回无套利错误
+    if args.options.verbose {
+        match scan.best_edge {
+            Some((edge, i, j)) => arb_msg!(
+                "No arbitrage: best edge was {} bps on pair ({},{})",
+                edge,
+                i,
+                j
+            ),
+            None => arb_msg!("No arbitrage: no pair could be priced"),
+        }
+    }
+    // 成功返回并报告最接近阈值的资产对，而不是让预检失败
+    if args.options.report_no_arbitrage {
+        return set_return_data(&NoArbReport {
+            best_edge_bps: scan.best_edge.map_or(0, |(edge, _, _)| edge),
+            best_pair: scan.best_edge.map(|(_, i, j)| (i as u8, j as u8)),
+            scan: scan.report,
+        });
+    }
+    // 交易失败时返回数据仍会出现在模拟结果中，便于调优策略参数
+    if let Some(reason) = scan.report.dominant_reason {
+        arb_msg!("No arbitrage: mostly {:?}", reason);
+    }
+    set_return_data(&scan.report)?;
+    Err(ArbitrageProgramError::NoArbitrage.into())
+}
+
+/// 提取每个资产的报价数据，扫描逻辑本身与 `AccountInfo` 无关
+///
+/// 设置了 `max_mints` 时只提取前 `max_mints` 个资产，限制 O(n²) 扫描的计算量；
+/// 设置了 `max_pay_pool_ratio_bps` 时，支付数量不超过两个流动性池中该资产较小余额的该比例。
+pub(crate) fn asset_quotes(
+    args: &TryArbitrageArgs<'_, '_>,
+) -> Result<Vec<AssetQuote>, ProgramError> {
+    let mut priced = args.mints.len();
+    if args.options.max_mints > 0 && priced > usize::from(args.options.max_mints) {
+        arb_msg!(
+            "Pricing only the first {} of {} mints",
+            args.options.max_mints,
+            priced
+        );
+        priced = usize::from(args.options.max_mints);
+    }
+    (0..priced)
+        .map(|i| {
+            let swap_1_balance = args.token_accounts_swap_1.get(i).ok_or_arb_err()?.3;
+            let swap_2_balance = args.token_accounts_swap_2.get(i).ok_or_arb_err()?.3;
+            let mut user_balance = args.token_accounts_user.get(i).ok_or_arb_err()?.3;
+            // 支付数量占池子余额过大时价格冲击过大，按比例上限截断
+            if let Some(ratio_bps) = args.options.max_pay_pool_ratio_bps {
+                // 结果不超过池子余额，不会溢出 u64
+                let cap = (u128::from(swap_1_balance.min(swap_2_balance)) * u128::from(ratio_bps)
+                    / BPS_DENOMINATOR) as u64;
+                if user_balance > cap {
+                    arb_msg!(
+                        "Asset {}: clamping pay {} to {} ({} bps of the pool)",
+                        i,
+                        user_balance,
+                        cap,
+                        ratio_bps
+                    );
+                    user_balance = cap;
+                }
+            }
+            // 资产必须同时出现在两个池子中才能在池子之间套利，只在一个池子中有余额时不必定价
+            let in_both_pools = swap_1_balance > 0 && swap_2_balance > 0;
+            if !in_both_pools {
+                arb_msg!(
+                    "Asset {}: not present in both pools ({} / {}), excluding",
+                    i,
+                    swap_1_balance,
+                    swap_2_balance
+                );
+            }
+            Ok(AssetQuote {
+                user_balance,
+                swap_1_balance,
+                swap_2_balance,
+                swap_1_weight: args.options.swap_1_weights.get(i).copied().unwrap_or(0),
+                swap_2_weight: args.options.swap_2_weights.get(i).copied().unwrap_or(0),
+                excluded: !in_both_pools || args.excluded_assets.contains(&i),
+            })
+        })
+        .collect()
+}
+
+/// 用当前余额重新检查记录的资产对，计划仍然超过阈值且方向不变时执行
+///
+/// # 返回值
+/// * `ProgramResult` - 计划不再成立时返回 `ArbitrageProgramError::StaleRecordedOpportunity`
+fn execute_recorded_plan(
+    args: &TryArbitrageArgs<'_, '_>,
+    quotes: &[AssetQuote],
+    plan: &RecordedOpportunity,
+) -> ProgramResult {
+    let (i, j) = (usize::from(plan.mint_i), usize::from(plan.mint_j));
+    let pair = [
+        *quotes.get(i).ok_or_arb_err()?,
+        *quotes.get(j).ok_or_arb_err()?,
+    ];
+    // 资产对在 `pair` 中的索引为 (0, 1)
+    let denied_in_pair: &[(usize, usize)] = if args.denied_pairs.contains(&(i.min(j), i.max(j))) {
+        &[(0, 1)]
+    } else {
+        &[]
+    };
+    let scan = scan_opportunities(
+        &pair,
+        denied_in_pair,
+        args.fees,
+        args.temperature,
+        args.options.max_edge_bps,
+        args.options.min_residual,
+        args.options.force_direction,
+        args.options.tie_break,
+        args.options.verbose,
+    )?;
+    match scan.best {
+        Some(opportunity) if opportunity.direction == plan.direction => {
+            arb_msg!("Executing plan recorded at slot {}", plan.slot);
+            execute_opportunity(
+                args,
+                &Opportunity {
+                    i,
+                    j,
+                    ..opportunity
+                },
+            )
+        }
+        _ => {
+            arb_msg!("Recorded pair ({},{}) no longer qualifies", i, j);
+            Err(ArbitrageProgramError::StaleRecordedOpportunity.into())
+        }
+    }
+}
+
+/// 扫描所需的单个资产报价数据
+///
+/// 与 `AccountInfo` 无关，链上的 `try_arbitrage` 与链下的模拟共用同一套扫描逻辑。
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct AssetQuote {
+    /// 用户持有的该资产余额
+    pub user_balance: u64,
+    /// Swap #1 流动性池持有的该资产余额
+    pub swap_1_balance: u64,
+    /// Swap #2 流动性池持有的该资产余额
+    pub swap_2_balance: u64,
+    /// 该资产在 Swap #1 加权池中的权重，0 表示恒定乘积池
+    pub swap_1_weight: u8,
+    /// 该资产在 Swap #2 加权池中的权重，0 表示恒定乘积池
+    pub swap_2_weight: u8,
+    /// 该资产是否被排除在扫描之外（调用方排除，或没有同时出现在两个池子中）
+    pub excluded: bool,
+}
+
+/// 两个流动性池的手续费率（基点）
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PoolFees {
+    /// Swap #1 流动性池的手续费率
+    pub swap_1_fee_bps: u16,
+    /// Swap #2 流动性池的手续费率
+    pub swap_2_fee_bps: u16,
+}
+
+impl PoolFees {
+    /// 返回指定交易所的手续费率
+    pub fn fee_bps(&self, venue: Buy) -> u16 {
+        match venue {
+            Buy::Swap1 => self.swap_1_fee_bps,
+            Buy::Swap2 => self.swap_2_fee_bps,
+        }
+    }
+}
+
+/// 扫描过程中发现的一个套利机会
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Opportunity {
+    /// 支付资产的索引
+    pub i: usize,
+    /// 中间资产的索引
+    pub j: usize,
+    /// 买入方向
+    pub direction: Buy,
+    /// 两个交易池报价之间的价差（基点）
+    pub edge_bps: u64,
+    /// 买入腿支付的资产 i 数量
+    pub pay_amount: u64,
+    /// 在 Swap #1 支付资产 i 预计获得的资产 j 数量
+    pub r_swap_1: u64,
+    /// 在 Swap #2 支付资产 i 预计获得的资产 j 数量
+    pub r_swap_2: u64,
+    /// 两个流动性池中资产 j 余额较小的一个，用于 `TieBreak::HigherLiquidity`
+    pub liquidity: u64,
+    /// 买入池持有的资产 i 余额，用于 `TieBreak::LowerPriceImpact`
+    pub buy_pool_balance: u64,
+}
+
+impl Opportunity {
+    /// 按 `rule` 判断价差相同时 `self` 是否优于 `other`
+    fn wins_tie(&self, other: &Opportunity, rule: TieBreak) -> bool {
+        match rule {
+            // 扫描按索引从小到大进行，先找到的机会索引更小
+            TieBreak::LowerIndex => false,
+            TieBreak::HigherLiquidity => self.liquidity > other.liquidity,
+            // 价格冲击约为 pay / pool，交叉相乘比较以避免除法
+            TieBreak::LowerPriceImpact => {
+                u128::from(self.pay_amount) * u128::from(other.buy_pool_balance)
+                    < u128::from(other.pay_amount) * u128::from(self.buy_pool_balance)
+            }
+        }
+    }
+}
+
+/// 第一档阈值没有发现机会时使用的第二档：更宽松的阈值配合更小的交易数量
+///
+/// 第一档即指令的 `temperature`，按用户的全部余额定价。
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SecondaryTier {
+    /// 第二档的温度阈值
+    pub temperature: u8,
+    /// 第二档的交易数量占用户余额的比例（基点，1..=10_000）
+    pub size_bps: u16,
+}
+
+impl SecondaryTier {
+    /// 交易数量比例是否在 (0, 100%] 之间
+    pub fn is_valid(&self) -> bool {
+        (1..=BPS_DENOMINATOR).contains(&u128::from(self.size_bps))
+    }
+
+    /// 按 `size_bps` 缩小每个资产的用户余额，得到第二档扫描使用的报价数据
+    fn scale(&self, quotes: &[AssetQuote]) -> Vec<AssetQuote> {
+        quotes
+            .iter()
+            .map(|quote| AssetQuote {
+                // 结果不超过原余额，不会溢出 u64
+                user_balance: (u128::from(quote.user_balance) * u128::from(self.size_bps)
+                    / BPS_DENOMINATOR) as u64,
+                ..*quote
+            })
+            .collect()
+    }
+}
+
+/// 优先费（compute unit price）建议值的参数，与 compute unit 上限无关
+///
+/// 建议值为 `base_micro_lamports + 预估利润 * profit_factor_bps / 10_000`，
+/// 利润越高建议的优先费越高，便于在竞争激烈时调优。
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PriorityFeeHint {
+    /// 基础的每 compute unit 价格（micro-lamports）
+    pub base_micro_lamports: u64,
+    /// 每单位预估利润（资产 i 的最小单位）对应增加的价格比例（基点）
+    pub profit_factor_bps: u16,
+}
+
+impl PriorityFeeHint {
+    /// 根据预估利润计算建议的每 compute unit 价格（micro-lamports），溢出时取 `u64::MAX`
+    pub fn recommend(&self, expected_profit: u64) -> u64 {
+        let scaled =
+            u128::from(expected_profit) * u128::from(self.profit_factor_bps) / BPS_DENOMINATOR;
+        u64::try_from(scaled)
+            .unwrap_or(u64::MAX)
+            .saturating_add(self.base_micro_lamports)
+    }
+}
+
+/// 多个资产对价差相同时选择哪一个执行
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TieBreak {
+    /// 选择索引较小（先扫描到）的资产对
+    #[default]
+    LowerIndex,
+    /// 选择两个流动性池中中间资产余额较小一侧更深的资产对
+    HigherLiquidity,
+    /// 选择支付数量相对买入池余额更小（价格冲击更小）的资产对
+    LowerPriceImpact,
+}
+
+/// 一次完整扫描的结果
+pub(crate) struct ScanOutcome {
+    /// 观察到的最大价差（基点）及对应的资产对，无论是否超过阈值
+    pub best_edge: Option<(u64, usize, usize)>,
+    /// 超过阈值的资产对数量
+    pub qualifying_pairs: usize,
+    /// 超过阈值的资产对中价差最大的机会
+    pub best: Option<Opportunity>,
+    /// 扫描过的资产对数量及被跳过的原因
+    pub report: ScanReport,
+}
+
+impl ScanOutcome {
+    /// 当超过阈值的资产对数量达到 `min_qualifying_pairs`（0 与 1 等价）时返回应执行的机会
+    pub fn selected(&self, min_qualifying_pairs: u8) -> Option<&Opportunity> {
+        if self.qualifying_pairs >= usize::from(min_qualifying_pairs.max(1)) {
+            self.best.as_ref()
+        } else {
+            None
+        }
+    }
+}
+
+/// 遍历每一对不同的资产（i 和 j），寻找两个交易池之间的套利机会
+///
+/// # 参数
+/// * `quotes` - 按资产顺序排列的报价数据
+/// * `denied_pairs` - 总是跳过的资产对 `(i, j)`，`i < j`
+/// * `fees` - 两个流动性池的手续费率，定价时先从支付数量中扣除
+/// * `temperature` - 套利温度阈值
+/// * `max_edge_bps` - 价差上限（基点），超过时跳过该资产对
+/// * `min_residual` - 兑换后池子至少要留下的资产 j 数量，不足时跳过该资产对
+/// * `force_direction` - 指定的买入池，设置时不按温度阈值判断方向
+/// * `tie_break` - 价差相同时的选择规则
+/// * `verbose` - 是否输出详细日志
+///
+/// # 返回值
+/// 返回扫描结果；定价失败（例如流动性不足）时返回错误
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn scan_opportunities(
+    quotes: &[AssetQuote],
+    denied_pairs: &[(usize, usize)],
+    fees: PoolFees,
+    temperature: u8,
+    max_edge_bps: Option<u16>,
+    min_residual: u64,
+    force_direction: Option<Buy>,
+    tie_break: TieBreak,
+    verbose: bool,
+) -> Result<ScanOutcome, ProgramError> {
+    let mut outcome = ScanOutcome {
+        best_edge: None,
+        qualifying_pairs: 0,
+        best: None,
+        report: ScanReport::default(),
+    };
+
+    for (i, quote_i) in quotes.iter().enumerate() {
+        for (j, quote_j) in quotes.iter().enumerate().skip(i + 1) {
+            outcome.report.scanned = outcome.report.scanned.saturating_add(1);
+            if quote_i.excluded || quote_j.excluded {
+                outcome.report.skip(SkipReason::Excluded);
+                continue;
+            }
+            if denied_pairs.contains(&(i, j)) {
+                outcome.report.skip(SkipReason::Denied);
+                continue;
+            }
+            // 支付数量为零时定价没有意义，不必浪费计算
+            if quote_i.user_balance == 0 {
+                arb_msg!(
+                    "Skipping pair ({},{}): user holds none of asset {}",
+                    i,
+                    j,
+                    i
+                );
+                outcome.report.skip(SkipReason::ZeroBalance);
+                continue;
+            }
 
             // 计算在两个交易池中进行兑换时预期能获得的目标资产数量
-            let r_swap_1 =
-                determine_swap_receive(swap_1_j.3, mint_j.1, swap_1_i.3, mint_i.1, user_i.3)?;
-            let r_swap_2 =
-                determine_swap_receive(swap_2_j.3, mint_j.1, swap_2_i.3, mint_i.1, user_i.3)?;
+            let r_swap_1 = determine_weighted_swap_receive_checked(
+                quote_j.swap_1_balance,
+                quote_i.swap_1_balance,
+                apply_fee(quote_i.user_balance, fees.swap_1_fee_bps)?,
+                (quote_i.swap_1_weight, quote_j.swap_1_weight),
+                0,
+            )?;
+            let r_swap_2 = determine_weighted_swap_receive_checked(
+                quote_j.swap_2_balance,
+                quote_i.swap_2_balance,
+                apply_fee(quote_i.user_balance, fees.swap_2_fee_bps)?,
+                (quote_i.swap_2_weight, quote_j.swap_2_weight),
+                0,
+            )?;
+
+            // 如果兑换金额为零或超过池子余额，则跳过此对资产；支付数量非零时接收数量为零
+            // 说明数量太小被向下取整为零，不能发起零数量的 CPI
+            if r_swap_1 == 0 || r_swap_2 == 0 {
+                arb_msg!(
+                    "DustRoundedToZero: pair ({},{}) pays {} but receives {} / {}",
+                    i,
+                    j,
+                    quote_i.user_balance,
+                    r_swap_1,
+                    r_swap_2
+                );
+                outcome.report.skip(SkipReason::ZeroReceive);
+                continue;
+            }
+            if r_swap_1 > quote_j.swap_1_balance || r_swap_2 > quote_j.swap_2_balance {
+                outcome.report.skip(SkipReason::InsufficientLiquidity);
+                continue;
+            }
+            // 把池子抽到接近于零会让之后的价格极端，卖出腿也容易失败
+            let residual =
+                (quote_j.swap_1_balance - r_swap_1).min(quote_j.swap_2_balance - r_swap_2);
+            if residual < min_residual {
+                arb_msg!(
+                    "Skipping pair ({},{}): pool would keep {} of asset {}, below min_residual {}",
+                    i,
+                    j,
+                    residual,
+                    j,
+                    min_residual
+                );
+                outcome.report.skip(SkipReason::BelowMinResidual);
+                continue;
+            }
 
-            // 如果兑换金额为零或超过池子余额，则跳过此对资产
-            if r_swap_1 == 0 || r_swap_1 > swap_1_j.3 || r_swap_2 == 0 || r_swap_2 > swap_2_j.3 {
+            let edge = edge_bps(r_swap_1, r_swap_2);
+            if outcome.best_edge.is_none_or(|(best, _, _)| edge > best) {
+                outcome.best_edge = Some((edge, i, j));
+            }
+            // 价差大得离谱时多半是池子状态陈旧或异常，交易进去只会亏损
+            if let Some(max) = max_edge_bps.filter(|max| edge > u64::from(*max)) {
+                arb_msg!(
+                    "Skipping pair ({},{}): edge {} bps exceeds max_edge_bps {}",
+                    i,
+                    j,
+                    edge,
+                    max
+                );
+                outcome.report.skip(SkipReason::TooGoodToBeTrue);
                 continue;
             }
 
-            // 检查是否存在套利机会
-            if let Some(trade) = check_for_arbitrage(r_swap_1, r_swap_2, args.temperature) {
-                // 若存在套利机会，则执行交易
-                msg!("PLACING TRADE!");
-                return match trade {
-                    // 在 Swap #1 上买入并在 Swap #2 上卖出
-                    Buy::Swap1 => {
-                        msg!("Buy on Swap #1 and sell on Swap #2");
-                        invoke_arbitrage(
-                            (
-                                *args.swap_1_program.key,
-                                &[
-                                    args.swap_1_pool.to_owned(),
-                                    mint_j.0.to_owned(),
-                                    swap_1_j.0.to_owned(),
-                                    user_j.0.to_owned(),
-                                    mint_i.0.to_owned(),
-                                    swap_1_i.0.to_owned(),
-                                    user_i.0.to_owned(),
-                                    args.payer.to_owned(),
-                                    args.token_program.to_owned(),
-                                    args.system_program.to_owned(),
-                                    args.associated_token_program.to_owned(),
-                                ],
-                                user_i.3,
-                            ),
-                            (
-                                *args.swap_2_program.key,
-                                &[
-                                    args.swap_2_pool.to_owned(),
-                                    mint_i.0.to_owned(),
-                                    swap_2_i.0.to_owned(),
-                                    user_i.0.to_owned(),
-                                    mint_j.0.to_owned(),
-                                    swap_2_j.0.to_owned(),
-                                    user_j.0.to_owned(),
-                                    args.payer.to_owned(),
-                                    args.token_program.to_owned(),
-                                    args.system_program.to_owned(),
-                                    args.associated_token_program.to_owned(),
-                                ],
-                                r_swap_1,
-                            ),
+            // 检查是否存在套利机会，记录价差最大的一个；指定了买入池时不检查温度阈值，
+            // 但仍要求该池报价更好，且按该方向往返一次预计换回的资产 i 多于支付数量
+            let direction = match force_direction {
+                Some(forced) => Some(forced).filter(|&forced| {
+                    let better = match forced {
+                        Buy::Swap1 => r_swap_1 > r_swap_2,
+                        Buy::Swap2 => r_swap_2 > r_swap_1,
+                    };
+                    better
+                        && quote_round_trip(
+                            quote_i,
+                            quote_j,
+                            fees,
+                            forced,
+                            quote_i.user_balance,
+                            false,
                         )
-                    }
-                    // 在 Swap #2 上买入并在 Swap #1 上卖出
-                    Buy::Swap2 => {
-                        msg!("Buy on Swap #2 and sell on Swap #1");
-                        invoke_arbitrage(
-                            (
-                                *args.swap_2_program.key,
-                                &[
-                                    args.swap_2_pool.to_owned(),
-                                    mint_j.0.to_owned(),
-                                    swap_2_j.0.to_owned(),
-                                    user_j.0.to_owned(),
-                                    mint_i.0.to_owned(),
-                                    swap_2_i.0.to_owned(),
-                                    user_i.0.to_owned(),
-                                    args.payer.to_owned(),
-                                    args.token_program.to_owned(),
-                                    args.system_program.to_owned(),
-                                    args.associated_token_program.to_owned(),
-                                ],
-                                r_swap_2,
-                            ),
-                            (
-                                *args.swap_1_program.key,
-                                &[
-                                    args.swap_1_pool.to_owned(),
-                                    mint_i.0.to_owned(),
-                                    swap_1_i.0.to_owned(),
-                                    user_i.0.to_owned(),
-                                    mint_j.0.to_owned(),
-                                    swap_1_j.0.to_owned(),
-                                    user_j.0.to_owned(),
-                                    args.payer.to_owned(),
-                                    args.token_program.to_owned(),
-                                    args.system_program.to_owned(),
-                                    args.associated_token_program.to_owned(),
-                                ],
-                                user_j.3,
-                            ),
-                        )
-                    }
+                        .is_some_and(|(_, _, amount_out)| amount_out > quote_i.user_balance)
+                }),
+                None => check_for_arbitrage(r_swap_1, r_swap_2, temperature, verbose),
+            };
+            if let Some(direction) = direction {
+                outcome.qualifying_pairs += 1;
+                let candidate = Opportunity {
+                    i,
+                    j,
+                    direction,
+                    edge_bps: edge,
+                    pay_amount: quote_i.user_balance,
+                    r_swap_1,
+                    r_swap_2,
+                    liquidity: quote_j.swap_1_balance.min(quote_j.swap_2_balance),
+                    buy_pool_balance: match direction {
+                        Buy::Swap1 => quote_i.swap_1_balance,
+                        Buy::Swap2 => quote_i.swap_2_balance,
+                    },
                 };
+                if outcome.best.as_ref().is_none_or(|b| {
+                    edge > b.edge_bps || (edge == b.edge_bps && candidate.wins_tie(b, tie_break))
+                }) {
+                    outcome.best = Some(candidate);
+                }
+            } else {
+                outcome.report.skip(SkipReason::BelowThreshold);
             }
         }
     }
+    Ok(outcome)
+}
+
+/// 执行扫描选出的套利机会：规划两条交易腿并依次调用两个交易所
+fn execute_opportunity(
+    args: &TryArbitrageArgs<'_, '_>,
+    opportunity: &Opportunity,
+) -> ProgramResult {
+    let (i, j) = (opportunity.i, opportunity.j);
+    let swap_1_i = args.token_accounts_swap_1.get(i).ok_or_arb_err()?;
+    let swap_1_j = args.token_accounts_swap_1.get(j).ok_or_arb_err()?;
+    let swap_2_i = args.token_accounts_swap_2.get(i).ok_or_arb_err()?;
+    let swap_2_j = args.token_accounts_swap_2.get(j).ok_or_arb_err()?;
+
+    arb_msg!("PLACING TRADE!");
+    match opportunity.direction {
+        Buy::Swap1 => arb_msg!("Buy on Swap #1 and sell on Swap #2"),
+        Buy::Swap2 => arb_msg!("Buy on Swap #2 and sell on Swap #1"),
+    }
+    let (mut buy, mut sell) = plan_legs(
+        &opportunity.direction,
+        &args.asset_accounts(i)?,
+        &args.asset_accounts(j)?,
+        LegAmounts {
+            pay_amount: opportunity.pay_amount,
+            r_swap_1: opportunity.r_swap_1,
+            r_swap_2: opportunity.r_swap_2,
+        },
+        args.options.conservative_sizing,
+    );
+    // 预估卖出腿换回的资产 i 数量
+    let (sell_pool_i, sell_pool_j) = match sell.venue {
+        Buy::Swap1 => (swap_1_i.3, swap_1_j.3),
+        Buy::Swap2 => (swap_2_i.3, swap_2_j.3),
+    };
+    let expected_amount_out = determine_weighted_swap_receive_checked(
+        sell_pool_i,
+        sell_pool_j,
+        apply_fee(sell.amount, args.fees.fee_bps(sell.venue))?,
+        args.pool_weights(sell.venue, j, i),
+        0,
+    )?;
+    if expected_amount_out == 0 {
+        arb_msg!(
+            "DustRoundedToZero: selling {} of asset {} returns nothing",
+            sell.amount,
+            j
+        );
+        return Err(ArbitrageProgramError::NoArbitrage.into());
+    }
+    // 卖出腿的接收目标取决于卖出池的余额，规划时无法确定
+    sell.amount_out = expected_amount_out;
+    buy.mode = args.options.buy_swap_mode;
+    sell.mode = args.options.sell_swap_mode;
+
+    // 预估利润低于 `min_profit` 时不值得交易
+    let expected_profit = expected_amount_out.saturating_sub(buy.amount);
+    let min_profit = args.min_profit(i)?;
+    if expected_profit < min_profit {
+        arb_msg!(
+            "Expected profit {} is below the minimum {}",
+            expected_profit,
+            min_profit
+        );
+        return Err(ArbitrageProgramError::NoArbitrage.into());
+    }
+    // 预估利润超过 `min_profit` 但没有留出足够的安全余量时只返回报价，不交易
+    if let Some(multiple_bps) = args.options.safety_multiple_bps {
+        let required_profit =
+            u64::try_from(u128::from(min_profit) * u128::from(multiple_bps) / BPS_DENOMINATOR)
+                .unwrap_or(u64::MAX);
+        if expected_profit < required_profit {
+            arb_msg!(
+                "Expected profit {} is below the safety margin {} ({} bps of {}), quoting only",
+                expected_profit,
+                required_profit,
+                multiple_bps,
+                min_profit
+            );
+            return set_return_data(&TradeQuote {
+                direction: opportunity.direction,
+                mint_i: i as u8,
+                mint_j: j as u8,
+                amount_in: buy.amount,
+                expected_profit,
+                required_profit,
+            });
+        }
+    }
 
-    // 如果遍历完所有资产对仍未发现套利机会，则返回无套利错误
-    Err(ArbitrageProgramError::NoArbitrage.into())
+    // 利润换算成 lamports 后不足以支付交易费用时不交易
+    if args.options.tx_cost_lamports > 0 {
+        let profit_lamports = profit_in_lamports(args, i, expected_profit)?;
+        if profit_lamports < args.options.tx_cost_lamports {
+            arb_msg!(
+                "Expected profit {} ({} lamports) does not cover the {} lamport transaction cost",
+                expected_profit,
+                profit_lamports,
+                args.options.tx_cost_lamports
+            );
+            return Err(ArbitrageProgramError::NoArbitrage.into());
+        }
+    }
+
+    if let Some(hint) = args.options.priority_fee_hint {
+        arb_msg!(
+            "Recommended compute unit price: {} micro-lamports (expected profit {})",
+            hint.recommend(expected_profit),
+            expected_profit
+        );
+    }
+
+    // 固定的 treasury 费用超过预估利润时不交易
+    if let Some(mode) = args.options.treasury_fee {
+        if mode
+            .fee(expected_profit, args.options.treasury_fee_rounding)
+            .is_none()
+        {
+            arb_msg!(
+                "Treasury fee {:?} exceeds expected profit {}",
+                mode,
+                expected_profit
+            );
+            return Err(ArbitrageProgramError::FeeExceedsProfit.into());
+        }
+    }
+
+    let legs = invoke_arbitrage(args, &[buy, sell])?;
+
+    if let (Some(max_drift_bps), Some(sell_result)) =
+        (args.options.max_estimate_drift_bps, legs.last())
+    {
+        warn_estimate_drift(sell_result.amount_out, expected_amount_out, max_drift_bps);
+    }
+    if let Some(mode) = args.options.treasury_fee {
+        let balance_before = args.token_accounts_user.get(i).ok_or_arb_err()?.3;
+        collect_treasury_fee(args, &buy.pay, balance_before, mode)?;
+    }
+    if let Some(dust_collector) = args.dust_collector {
+        let balance_before = args.token_accounts_user.get(j).ok_or_arb_err()?.3;
+        sweep_dust(args, &buy.receive, balance_before, dust_collector)?;
+    }
+
+    let positive_slippage = legs.last().map_or(0, |sell_result| {
+        sell_result.amount_out.saturating_sub(expected_amount_out)
+    });
+    if positive_slippage > 0 {
+        arb_msg!(
+            "Positive slippage: {} above the estimate",
+            positive_slippage
+        );
+    }
+    let effective_rate = match (legs.first(), legs.last()) {
+        (Some(buy_result), Some(sell_result)) => {
+            ArbitrageResult::effective_rate(buy_result.amount_in, sell_result.amount_out)
+        }
+        _ => 0,
+    };
+    let result = ArbitrageResult {
+        direction: opportunity.direction,
+        mint_i: i as u8,
+        mint_j: j as u8,
+        amount_in: buy.amount,
+        intermediate_amount: sell.amount,
+        expected_amount_out,
+        legs,
+        positive_slippage,
+        effective_rate,
+    };
+    if let Some(record_program) = args.record_program {
+        persist_trade_record(args, record_program, &result)?;
+    }
+    set_return_data(&result)
+}
+
+/// 把 Borsh 编码的交易结果通过 CPI 发送给日志程序，指令不携带任何账户
+fn persist_trade_record(
+    args: &TryArbitrageArgs<'_, '_>,
+    record_program: &AccountInfo,
+    result: &ArbitrageResult,
+) -> ProgramResult {
+    arb_msg!("Persisting trade record to {}", record_program.key);
+    args.count_cpi();
+    invoke(
+        &Instruction::new_with_bytes(*record_program.key, &borsh::to_vec(result)?, vec![]),
+        std::slice::from_ref(record_program),
+    )
+}
+
+/// 对选出的资产对搜索最大的可盈利支付数量，以 `MaxSizeQuote` 写入返回数据，不执行交易
+///
+/// 往返利润随支付数量先增后减：先三分搜索利润最高的数量，再在其与用户余额之间
+/// 二分搜索仍满足 `temperature` 阈值且利润不低于 `min_profit` 的最大数量。
+fn quote_max_size(
+    args: &TryArbitrageArgs<'_, '_>,
+    quotes: &[AssetQuote],
+    opportunity: &Opportunity,
+) -> ProgramResult {
+    let (i, j) = (opportunity.i, opportunity.j);
+    let (quote_i, quote_j) = (
+        quotes.get(i).ok_or_arb_err()?,
+        quotes.get(j).ok_or_arb_err()?,
+    );
+    let min_profit = args.min_profit(i)?.max(1);
+    // 无法定价的数量视为利润最低
+    let profit = |size: u64| -> i128 {
+        quote_round_trip(
+            quote_i,
+            quote_j,
+            args.fees,
+            opportunity.direction,
+            size,
+            args.options.conservative_sizing,
+        )
+        .map_or(i128::MIN, |(_, _, amount_out)| {
+            i128::from(amount_out) - i128::from(size)
+        })
+    };
+    let qualifies = |size: u64| -> bool {
+        quote_round_trip(
+            quote_i,
+            quote_j,
+            args.fees,
+            opportunity.direction,
+            size,
+            args.options.conservative_sizing,
+        )
+        .is_some_and(|(r_swap_1, r_swap_2, amount_out)| {
+            r_swap_1 > 0
+                && r_swap_2 > 0
+                && check_for_arbitrage(r_swap_1, r_swap_2, args.temperature, false).is_some()
+                && amount_out.saturating_sub(size) >= min_profit
+        })
+    };
+
+    // 三分搜索利润最高的支付数量
+    let (mut lo, mut hi) = (1u64, quote_i.user_balance.max(1));
+    while hi - lo > 2 {
+        let m1 = lo + (hi - lo) / 3;
+        let m2 = hi - (hi - lo) / 3;
+        if profit(m1) < profit(m2) {
+            lo = m1 + 1;
+        } else {
+            hi = m2;
+        }
+    }
+    let peak = (lo..=hi).max_by_key(|&size| profit(size)).unwrap_or(lo);
+
+    // 二分搜索利润最高点之后仍满足条件的最大数量
+    let max_size = if qualifies(peak) {
+        let (mut lo, mut hi) = (peak, quote_i.user_balance.max(peak));
+        while lo < hi {
+            let mid = lo + (hi - lo).div_ceil(2);
+            if qualifies(mid) {
+                lo = mid;
+            } else {
+                hi = mid - 1;
+            }
+        }
+        lo
+    } else {
+        0
+    };
+    let expected_profit = if max_size == 0 {
+        0
+    } else {
+        u64::try_from(profit(max_size)).unwrap_or(0)
+    };
+    arb_msg!(
+        "Max profitable size for pair ({},{}): {} (expected profit {})",
+        i,
+        j,
+        max_size,
+        expected_profit
+    );
+    set_return_data(&MaxSizeQuote {
+        direction: opportunity.direction,
+        mint_i: i as u8,
+        mint_j: j as u8,
+        max_size,
+        expected_profit,
+    })
+}
+
+/// 估算资产对 (i, j) 沿 `direction` 支付 `pay` 个资产 i 往返一次的结果
+///
+/// # 返回值
+/// `(r_swap_1, r_swap_2, amount_out)`：两个池子对资产 j 的接收预估值，以及卖出腿换回的资产 i
+/// 数量；接收数量超过池子余额或无法定价时返回 `None`
+fn quote_round_trip(
+    quote_i: &AssetQuote,
+    quote_j: &AssetQuote,
+    fees: PoolFees,
+    direction: Buy,
+    pay: u64,
+    conservative_sizing: bool,
+) -> Option<(u64, u64, u64)> {
+    // 按交易所返回 (资产 i 池余额, 资产 j 池余额, 资产 i 权重, 资产 j 权重)
+    let pools = |venue: Buy| match venue {
+        Buy::Swap1 => (
+            quote_i.swap_1_balance,
+            quote_j.swap_1_balance,
+            quote_i.swap_1_weight,
+            quote_j.swap_1_weight,
+        ),
+        Buy::Swap2 => (
+            quote_i.swap_2_balance,
+            quote_j.swap_2_balance,
+            quote_i.swap_2_weight,
+            quote_j.swap_2_weight,
+        ),
+    };
+    let receive = |venue: Buy| -> Option<u64> {
+        let (pool_i, pool_j, weight_i, weight_j) = pools(venue);
+        let r = determine_weighted_swap_receive_checked(
+            pool_j,
+            pool_i,
+            apply_fee(pay, fees.fee_bps(venue)).ok()?,
+            (weight_i, weight_j),
+            0,
+        )
+        .ok()?;
+        (r <= pool_j).then_some(r)
+    };
+    let (r_swap_1, r_swap_2) = (receive(Buy::Swap1)?, receive(Buy::Swap2)?);
+    let (sell_venue, mut bought) = match direction {
+        Buy::Swap1 => (Buy::Swap2, r_swap_1),
+        Buy::Swap2 => (Buy::Swap1, r_swap_2),
+    };
+    if conservative_sizing {
+        bought = r_swap_1.min(r_swap_2);
+    }
+    let (pool_i, pool_j, weight_i, weight_j) = pools(sell_venue);
+    let amount_out = determine_weighted_swap_receive_checked(
+        pool_i,
+        pool_j,
+        apply_fee(bought, fees.fee_bps(sell_venue)).ok()?,
+        (weight_j, weight_i),
+        0,
+    )
+    .ok()?;
+    Some((r_swap_1, r_swap_2, amount_out))
+}
+
+/// 按 Swap #1 流动性池的边际价格把资产 `i` 的利润换算为 lamports
+///
+/// `sol_asset_index` 指定的资产（包装 SOL）作为参考，其最小单位即 lamports。
+fn profit_in_lamports(
+    args: &TryArbitrageArgs<'_, '_>,
+    i: usize,
+    profit: u64,
+) -> Result<u64, ProgramError> {
+    let sol = usize::from(args.options.sol_asset_index);
+    if i == sol {
+        return Ok(profit);
+    }
+    let pool_sol = args.token_accounts_swap_1.get(sol).ok_or_arb_err()?.3;
+    let pool_i = args.token_accounts_swap_1.get(i).ok_or_arb_err()?.3;
+    if pool_i == 0 {
+        return Ok(0);
+    }
+    // 两个 u64 相乘不会超出 u128 的范围
+    Ok(
+        u64::try_from(u128::from(profit) * u128::from(pool_sol) / u128::from(pool_i))
+            .unwrap_or(u64::MAX),
+    )
+}
+
+/// 比较卖出腿实际换回的资产 i 数量与交易前的预估值
+///
+/// 偏差超过 `max_drift_bps` 时只输出警告日志而不报错，便于运维发现本程序的定价模型
+/// 与真实 swap 程序之间的偏差。
+///
+/// # 参数
+/// * `realized_amount_out` - 卖出腿实际换回的数量
+/// * `expected_amount_out` - 交易前预估的卖出腿换回数量
+/// * `max_drift_bps` - 允许的最大偏差（基点）
+fn warn_estimate_drift(realized_amount_out: u64, expected_amount_out: u64, max_drift_bps: u16) {
+    if expected_amount_out == 0 {
+        return;
+    }
+    let drift_bps = u128::from(realized_amount_out.abs_diff(expected_amount_out)) * BPS_DENOMINATOR
+        / u128::from(expected_amount_out);
+    if drift_bps > u128::from(max_drift_bps) {
+        arb_msg!(
+            "WARNING: realized output {} drifts {} bps from estimate {} (max {} bps)",
+            realized_amount_out,
+            drift_bps,
+            expected_amount_out,
+            max_drift_bps
+        );
+    }
+}
+
+/// 单个资产在一个交易对中涉及的账户
+///
+/// 泛型参数 `T` 使腿的规划逻辑与具体的账户类型解耦，链上使用 `&AccountInfo`，
+/// 规划逻辑本身只关心每个角色对应哪个账户。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct AssetAccounts<T> {
+    /// 资产在账户列表中的索引
+    pub index: usize,
+    /// 用户持有该资产的代币账户
+    pub user: T,
+    /// Swap #1 流动性池持有该资产的代币账户
+    pub swap_1: T,
+    /// Swap #2 流动性池持有该资产的代币账户
+    pub swap_2: T,
+    /// 该资产的铸币账户
+    pub mint: T,
+}
+
+impl<T: Copy> AssetAccounts<T> {
+    /// 返回指定交易所的流动性池持有该资产的代币账户
+    fn pool(&self, venue: Buy) -> T {
+        match venue {
+            Buy::Swap1 => self.swap_1,
+            Buy::Swap2 => self.swap_2,
+        }
+    }
+}
+
+/// 规划两条交易腿所需的数量
+#[derive(Debug, Clone, Copy)]
+struct LegAmounts {
+    /// 买入腿支付的资产 i 数量（用户的资产 i 余额）
+    pay_amount: u64,
+    /// 在 Swap #1 支付 `pay_amount` 预计获得的资产 j 数量
+    r_swap_1: u64,
+    /// 在 Swap #2 支付 `pay_amount` 预计获得的资产 j 数量
+    r_swap_2: u64,
+}
+
+/// 一条交易腿：在 `venue` 上支付 `amount` 数量的 `pay` 资产，换取 `receive` 资产
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct SwapLeg<T> {
+    /// 执行该腿的交易所
+    pub venue: Buy,
+    /// 支付的资产
+    pub pay: AssetAccounts<T>,
+    /// 接收的资产
+    pub receive: AssetAccounts<T>,
+    /// 支付的数量；`ExactOut` 模式下为允许支付的最大数量
+    pub amount: u64,
+    /// 预计获得的数量；`ExactOut` 模式下为固定的接收目标
+    pub amount_out: u64,
+    /// 该腿的兑换模式
+    pub mode: SwapMode,
+}
+
+impl<T> SwapLeg<T> {
+    /// 按兑换模式与执行该腿的 swap 程序的指令标识构建指令数据
+    pub(crate) fn ix_data(&self, options: &TryArbitrageOptions) -> Vec<u8> {
+        let discriminator = match self.venue {
+            Buy::Swap1 => options.swap_1_discriminator,
+            Buy::Swap2 => options.swap_2_discriminator,
+        };
+        build_ix_data(
+            discriminator,
+            options.amount_encoding,
+            self.mode,
+            self.amount,
+            self.amount_out,
+        )
+    }
+}
+
+/// swap 指令数据中数量的字节序
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AmountEncoding {
+    /// 小端序 `u64`，与 Borsh（以及 `ArbitrageResult`）的编码一致
+    #[default]
+    LittleEndian,
+    /// 大端序 `u64`，用于按网络字节序解析数量的 swap 程序
+    BigEndian,
+}
+
+impl AmountEncoding {
+    /// 按该字节序编码数量
+    pub fn encode(self, amount: u64) -> [u8; 8] {
+        match self {
+            Self::LittleEndian => amount.to_le_bytes(),
+            Self::BigEndian => amount.to_be_bytes(),
+        }
+    }
+
+    /// 按该字节序解码数量
+    pub fn decode(self, bytes: [u8; 8]) -> u64 {
+        match self {
+            Self::LittleEndian => u64::from_le_bytes(bytes),
+            Self::BigEndian => u64::from_be_bytes(bytes),
+        }
+    }
+}
+
+/// swap 程序识别指令的方式
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DiscriminatorKind {
+    /// Anchor 程序：按兑换模式取 `global:swap` 或 `global:swap_exact_out` 哈希的前 8 字节
+    #[default]
+    Anchor8,
+    /// 单字节指令标签，由调用方给出与所用兑换模式对应的标签
+    U8(u8),
+    /// 小端序 `u32` 指令标签，由调用方给出与所用兑换模式对应的标签
+    U32(u32),
+    /// 调用方直接给出的 8 字节指令标识，用于指令名与 Anchor 约定不同的程序，不再计算哈希
+    Custom([u8; 8]),
+}
+
+/// swap CPI 的兑换模式
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SwapMode {
+    /// 支付固定数量，接收数量随价格变化
+    #[default]
+    ExactIn,
+    /// 接收固定数量，支付数量随价格变化但不超过给定的上限
+    ExactOut,
+}
+
+type BuyLeg<T> = SwapLeg<T>;
+type SellLeg<T> = SwapLeg<T>;
+
+/// 根据套利方向规划买入腿与卖出腿
+///
+/// 买入腿总是在 `direction` 指定的交易所用资产 i 换取资产 j，支付用户的全部资产 i 余额；
+/// 卖出腿在另一个交易所把买入腿预计获得的资产 j 全部换回资产 i。
+///
+/// 当 `conservative_sizing` 为 true 时，卖出腿只卖出两个池子接收预估值中较小的一个，
+/// 即使买入腿的实际成交低于预估，卖出腿也不会因余额不足而失败。
+fn plan_legs<T: Copy>(
+    direction: &Buy,
+    i_accounts: &AssetAccounts<T>,
+    j_accounts: &AssetAccounts<T>,
+    amounts: LegAmounts,
+    conservative_sizing: bool,
+) -> (BuyLeg<T>, SellLeg<T>) {
+    let (buy_venue, sell_venue, mut bought) = match direction {
+        Buy::Swap1 => (Buy::Swap1, Buy::Swap2, amounts.r_swap_1),
+        Buy::Swap2 => (Buy::Swap2, Buy::Swap1, amounts.r_swap_2),
+    };
+    if conservative_sizing {
+        bought = amounts.r_swap_1.min(amounts.r_swap_2);
+    }
+    (
+        SwapLeg {
+            venue: buy_venue,
+            pay: *i_accounts,
+            receive: *j_accounts,
+            amount: amounts.pay_amount,
+            amount_out: bought,
+            mode: SwapMode::ExactIn,
+        },
+        SwapLeg {
+            venue: sell_venue,
+            pay: *j_accounts,
+            receive: *i_accounts,
+            amount: bought,
+            amount_out: 0,
+            mode: SwapMode::ExactIn,
+        },
+    )
+}
+
+/// 每个 swap CPI 携带的账户数量，省略关联代币程序时少一个
+pub const SWAP_CPI_ACCOUNTS: usize = 11;
+
+/// 组装一条交易腿的 CPI 账户列表
+///
+/// # 返回值
+/// 返回交易所程序 ID 以及按 swap 指令要求排列的账户：
+/// 流动性池、接收资产（mint、池账户、用户账户）、支付资产（mint、池账户、用户账户）、
+/// 用户代币账户的所有者、代币程序、系统程序、关联代币程序（未省略时）；
+/// 若该 swap 程序配置了账户重排表，则按重排表调整后的顺序返回
+pub(crate) fn leg_cpi<'a, 'b>(
+    args: &TryArbitrageArgs<'a, 'b>,
+    leg: &SwapLeg<&'a AccountInfo<'b>>,
+) -> (Pubkey, Vec<AccountInfo<'b>>) {
+    let (program, pool, order) = match leg.venue {
+        Buy::Swap1 => (
+            args.swap_1_program,
+            args.swap_1_pool,
+            &args.options.swap_1_account_order,
+        ),
+        Buy::Swap2 => (
+            args.swap_2_program,
+            args.swap_2_pool,
+            &args.options.swap_2_account_order,
+        ),
+    };
+    let mut accounts = vec![
+        pool.to_owned(),
+        leg.receive.mint.to_owned(),
+        leg.receive.pool(leg.venue).to_owned(),
+        leg.receive.user.to_owned(),
+        leg.pay.mint.to_owned(),
+        leg.pay.pool(leg.venue).to_owned(),
+        leg.pay.user.to_owned(),
+        args.owner.to_owned(),
+        args.token_program.to_owned(),
+        args.system_program.to_owned(),
+    ];
+    accounts.extend(args.associated_token_program.cloned());
+    // 按该 swap 程序的重排表调整账户顺序，重排表已在 `parse_arbitrage_accounts` 中校验过
+    if order.is_empty() {
+        return (*program.key, accounts);
+    }
+    (
+        *program.key,
+        order
+            .iter()
+            .map(|&k| accounts[usize::from(k)].clone())
+            .collect(),
+    )
 }
 
 /// 买入操作枚举类型
@@ -202,7 +1380,8 @@ pub fn try_arbitrage(args: TryArbitrageArgs<'_, '_>) -> ProgramResult {
 /// 变体说明：
 /// - Swap1: 第一种买入策略
 /// - Swap2: 第二种买入策略
-enum Buy {
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Buy {
     Swap1,
     Swap2,
 }
@@ -214,21 +1393,53 @@ enum Buy {
 /// # 参数
 /// * `r_swap_1` - 第一个交换池的汇率值
 /// * `r_swap_2` - 第二个交换池的汇率值
-/// * `temperature` - 温度参数，用于调整套利检测的敏感度，值越小越敏感
+/// * `temperature` - 交易的激进程度，值越大阈值 `100 - temperature` 越低、越容易交易；
+///   0 要求价差超过 100%
+/// * `verbose` - 是否把两个交换池报价完全相同（没有价差）的情况单独记录日志
 ///
 /// # 返回值
 /// * `Some(Buy::Swap1)` - 当第一个交换池存在套利机会时返回
 /// * `Some(Buy::Swap2)` - 当第二个交换池存在套利机会时返回
-/// * `None` - 当不存在套利机会时返回
-fn check_for_arbitrage(r_swap_1: u64, r_swap_2: u64, temperature: u8) -> Option<Buy> {
+/// * `None` - 当不存在套利机会或价格差异不是有限值时返回
+pub(crate) fn check_for_arbitrage(
+    r_swap_1: u64,
+    r_swap_2: u64,
+    temperature: u8,
+    verbose: bool,
+) -> Option<Buy> {
+    // 报价完全相同时没有价差，与价差低于阈值的情况分开记录
+    if r_swap_1 == r_swap_2 {
+        if verbose {
+            arb_msg!("No edge: both pools quote {}", r_swap_1);
+        }
+        return None;
+    }
+    compare_to_threshold(r_swap_1, r_swap_2, temperature)
+}
+
+/// `check_for_arbitrage` 的价差判断：价差百分比 `|r_swap_1 / r_swap_2 - 1| * 100`
+/// 超过阈值 `100 - temperature` 时返回购买建议，方向由带符号的价差决定：
+/// Swap #1 报价更高（价差为正）时在 Swap #1 买入，否则在 Swap #2 买入
+#[cfg(not(feature = "integer_only"))]
+fn compare_to_threshold(r_swap_1: u64, r_swap_2: u64, temperature: u8) -> Option<Buy> {
     // 计算套利检测阈值，温度越低阈值越高
     let threshold = 100.0 - temperature as f64;
-    // 计算两个交换池之间的价格差异百分比
-    let percent_diff = (r_swap_1 as f64 / r_swap_2 as f64 - 1.0).abs() * 100.0;
+    // 计算两个交换池之间带符号的价格差异百分比，阈值按绝对值判断
+    let signed_diff = (r_swap_1 as f64 / r_swap_2 as f64 - 1.0) * 100.0;
+    let percent_diff = signed_diff.abs();
+    // `NaN > threshold` 恒为 false，会悄无声息地隐藏机会；非有限值（NaN/Inf）一律视为无法定价
+    if !percent_diff.is_finite() {
+        arb_msg!(
+            "Non-finite price difference for rates {} / {}, skipping",
+            r_swap_1,
+            r_swap_2
+        );
+        return None;
+    }
     // 判断价格差异是否超过阈值
     if percent_diff > threshold {
-        // 根据价格差异的方向决定购买哪个交换池
-        if percent_diff > 0.0 {
+        // 根据价格差异的符号决定购买哪个交换池
+        if signed_diff > 0.0 {
             return Some(Buy::Swap1);
         } else {
             return Some(Buy::Swap2);
@@ -237,76 +1448,2073 @@ fn check_for_arbitrage(r_swap_1: u64, r_swap_2: u64, temperature: u8) -> Option<
     None
 }
 
-/// 执行套利交易函数，先后执行买入和卖出两个交易指令
-///
-/// # 参数
-/// * `buy` - 买入交易信息元组，包含程序ID、账户信息切片和买入金额
-/// * `sell` - 卖出交易信息元组，包含程序ID、账户信息切片和卖出金额
+/// `compare_to_threshold` 的整数实现，与浮点实现的判断一致
 ///
-/// # 返回值
-/// * `ProgramResult` - 程序执行结果，成功返回Ok(())，失败返回相应错误
-fn invoke_arbitrage(
-    buy: (Pubkey, &[AccountInfo], u64),
-    sell: (Pubkey, &[AccountInfo], u64),
-) -> ProgramResult {
-    // 构建买入和卖出指令的数据
-    let (buy_swap_ix_data, sell_swap_ix_data) = build_ix_datas(buy.2, sell.2);
-
-    // 创建买入交易指令
-    let ix_buy = Instruction::new_with_borsh(
-        buy.0,
-        &buy_swap_ix_data,
-        buy.1.iter().map(ToAccountMeta::to_account_meta).collect(),
-    );
-
-    // 创建卖出交易指令
-    let ix_sell = Instruction::new_with_borsh(
-        sell.0,
-        &sell_swap_ix_data,
-        sell.1.iter().map(ToAccountMeta::to_account_meta).collect(),
-    );
-
-    // 执行买入交易
-    msg!("Executing buy ...");
-    invoke(&ix_buy, buy.1)?;
+/// 价差百分比超过阈值等价于 `|r_swap_1 - r_swap_2| * 100 > (100 - temperature) * r_swap_2`，
+/// 两边都在 `i128` 中精确计算，不会溢出。
+#[cfg(feature = "integer_only")]
+fn compare_to_threshold(r_swap_1: u64, r_swap_2: u64, temperature: u8) -> Option<Buy> {
+    // 浮点实现中除以零得到非有限值，同样视为无法定价
+    if r_swap_2 == 0 {
+        arb_msg!(
+            "Non-finite price difference for rates {} / {}, skipping",
+            r_swap_1,
+            r_swap_2
+        );
+        return None;
+    }
+    let threshold = 100 - i128::from(temperature);
+    let diff = i128::from(r_swap_1.abs_diff(r_swap_2));
+    // 判断价格差异是否超过阈值
+    if diff * 100 > threshold * i128::from(r_swap_2) {
+        // 与浮点实现一致：阈值按价差的绝对值判断，方向由价差的符号决定
+        if r_swap_1 > r_swap_2 {
+            return Some(Buy::Swap1);
+        } else {
+            return Some(Buy::Swap2);
+        }
+    }
+    None
+}
 
-    // 执行卖出交易
-    msg!("Executing sell ...");
-    invoke(&ix_sell, sell.1)?;
+/// 计算两个交换池报价之间的价差，单位为基点（1 bps = 0.01%）
+///
+/// 与 `check_for_arbitrage` 使用相同的价差定义：`|r_swap_1 / r_swap_2 - 1|`。
+#[cfg(not(feature = "integer_only"))]
+fn edge_bps(r_swap_1: u64, r_swap_2: u64) -> u64 {
+    ((r_swap_1 as f64 / r_swap_2 as f64 - 1.0).abs() * 10_000.0) as u64
+}
 
-    Ok(())
+/// `edge_bps` 的整数实现（向下取整），`r_swap_2` 为零时与浮点实现一样饱和为 `u64::MAX`
+#[cfg(feature = "integer_only")]
+fn edge_bps(r_swap_1: u64, r_swap_2: u64) -> u64 {
+    if r_swap_2 == 0 {
+        return u64::MAX;
+    }
+    u64::try_from(u128::from(r_swap_1.abs_diff(r_swap_2)) * BPS_DENOMINATOR / u128::from(r_swap_2))
+        .unwrap_or(u64::MAX)
 }
 
-/// 构建交易指令数据
+/// 执行套利交易函数，按顺序执行每一条交易腿
 ///
-/// 该函数用于生成买入和卖出交易的指令数据，主要用于Solana程序交易
+/// 两腿套利依次为买入腿和卖出腿；每条腿执行前后读取用户的支付、接收代币账户余额，
+/// 得出该腿实际成交的数量。
 ///
 /// # 参数
-/// * `buy_amount` - 买入数量，类型为u64
-/// * `sell_amount` - 卖出数量，类型为u64
+/// * `args` - 已解析的账户，决定是否校验返回数据以及是否以 PDA 金库签名
+/// * `legs` - 按执行顺序排列的交易腿
 ///
 /// # 返回值
-/// 返回一个元组，包含两个16字节的数组：
-/// * 第一个元素是买入交易指令数据
-/// * 第二个元素是卖出交易指令数据
-fn build_ix_datas(buy_amount: u64, sell_amount: u64) -> ([u8; 16], [u8; 16]) {
-    let mut buy_swap_ix_data = [0u8; 16];
-    let mut sell_swap_ix_data = [0u8; 16];
+/// * `Ok(Vec<LegResult>)` - 每条交易腿实际成交的数量
+/// * `Err(ProgramError)` - 任一 CPI 失败或余额无法读取时返回；所有 CPI 的账户总数超过
+///   `max_cpi_accounts` 时在发起任何 CPI 之前返回 `ArbitrageProgramError::CpiAccountLimitExceeded`
+pub(crate) fn invoke_arbitrage<'a, 'b>(
+    args: &TryArbitrageArgs<'a, 'b>,
+    legs: &[SwapLeg<&'a AccountInfo<'b>>],
+) -> Result<Vec<LegResult>, ProgramError> {
+    let cpis: Vec<_> = legs.iter().map(|leg| leg_cpi(args, leg)).collect();
+    // 在运行时因账户过多而难以诊断地失败之前，先按上限检查
+    if let Some(max) = args.options.max_cpi_accounts {
+        let total: usize = cpis.iter().map(|(_, accounts)| accounts.len()).sum();
+        if total > usize::from(max) {
+            arb_msg!(
+                "CPIs reference {} accounts in total, above max_cpi_accounts {}",
+                total,
+                max
+            );
+            return Err(ArbitrageProgramError::CpiAccountLimitExceeded.into());
+        }
+    }
 
-    // 生成swap指令的哈希值，用于标识交易类型
-    let swap_ix_hash = solana_program::hash::hash(b"global:swap");
+    let balance =
+        |account| PartialTokenAccountState::try_deserialize(account, args.owner.key).map(|a| a.3);
+    let mut results = Vec::with_capacity(legs.len());
+    for (k, (leg, (program_id, accounts))) in legs.iter().zip(cpis).enumerate() {
+        arb_msg!("Executing leg {} on {:?} ...", k + 1, leg.venue);
+        let (pay_before, receive_before) = (balance(leg.pay.user)?, balance(leg.receive.user)?);
+        invoke_swap(args, program_id, &accounts, &leg.ix_data(&args.options))?;
+        let result = LegResult {
+            venue: leg.venue,
+            mint_in: leg.pay.index as u8,
+            mint_out: leg.receive.index as u8,
+            amount_in: pay_before.saturating_sub(balance(leg.pay.user)?),
+            amount_out: balance(leg.receive.user)?.saturating_sub(receive_before),
+        };
+        // 实际接收数量不得低于预估值的 `min_out_fraction_bps`
+        if let Some(fraction_bps) = args.options.min_out_fraction_bps {
+            // 结果不超过预估值，不会溢出 u64
+            let floor =
+                (u128::from(leg.amount_out) * u128::from(fraction_bps) / BPS_DENOMINATOR) as u64;
+            if result.amount_out < floor {
+                arb_msg!(
+                    "Leg {} received {}, below {} bps of the estimate {}",
+                    k + 1,
+                    result.amount_out,
+                    fraction_bps,
+                    leg.amount_out
+                );
+                return Err(ArbitrageProgramError::BelowMinReceive.into());
+            }
+        }
+        results.push(result);
+    }
+    Ok(results)
+}
 
-    // 将交易金额转换为大端字节序
-    let buy_amount_as_bytes: [u8; 8] = buy_amount.to_be_bytes();
-    let sell_amount_as_bytes: [u8; 8] = sell_amount.to_be_bytes();
+/// 调用一次 swap 程序
+///
+/// 设置了 `verify_swap_return` 时在 CPI 之后校验 swap 程序的返回数据。
+///
+/// # 参数
+/// * `args` - 已解析的账户
+/// * `swap_program_id` - swap 程序 ID
+/// * `accounts` - swap 指令的账户列表
+/// * `data` - swap 指令数据，见 `build_ix_data`
+pub(crate) fn invoke_swap(
+    args: &TryArbitrageArgs<'_, '_>,
+    swap_program_id: Pubkey,
+    accounts: &[AccountInfo],
+    data: &[u8],
+) -> ProgramResult {
+    let ix = Instruction::new_with_bytes(
+        swap_program_id,
+        data,
+        accounts
+            .iter()
+            .map(ToAccountMeta::to_account_meta)
+            .collect(),
+    );
+    invoke_as_owner(args, ix, accounts)?;
+    if args.options.verify_swap_return {
+        verify_swap_return(&swap_program_id)?;
+    }
+    Ok(())
+}
 
-    // 构造买入交易指令数据：前8字节为指令哈希，后8字节为交易金额
-    buy_swap_ix_data[..8].copy_from_slice(&swap_ix_hash.to_bytes()[..8]);
-    buy_swap_ix_data[8..].copy_from_slice(&buy_amount_as_bytes);
+/// 以代币账户所有者的身份发起 CPI
+///
+/// 所有者是钱包时它已经签名了交易，直接 `invoke`；设置了 `vault_seeds` 时所有者是本程序的
+/// PDA 金库，需要把它标记为签名者并用种子 `invoke_signed`。
+pub(crate) fn invoke_as_owner(
+    args: &TryArbitrageArgs<'_, '_>,
+    mut ix: Instruction,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    args.count_cpi();
+    let vault_seeds = &args.options.vault_seeds;
+    if vault_seeds.is_empty() {
+        return invoke(&ix, accounts);
+    }
+    ix.accounts
+        .iter_mut()
+        .filter(|meta| meta.pubkey.eq(args.owner.key))
+        .for_each(|meta| meta.is_signer = true);
+    let seeds: Vec<&[u8]> = vault_seeds.iter().map(Vec::as_slice).collect();
+    invoke_signed(&ix, accounts, &[&seeds])
+}
 
-    // 构造卖出交易指令数据：前8字节为指令哈希，后8字节为交易金额
-    sell_swap_ix_data[..8].copy_from_slice(&swap_ix_hash.to_bytes()[..8]);
-    sell_swap_ix_data[8..].copy_from_slice(&sell_amount_as_bytes);
+/// 校验刚刚完成的 swap CPI 设置了返回数据
+///
+/// 运行时会用设置返回数据的程序 ID 标记返回数据，因此要求返回数据非空，
+/// 且标记的程序 ID 与被调用的 swap 程序一致，而不是仅仅相信 CPI 返回了 `Ok`。
+fn verify_swap_return(swap_program_id: &Pubkey) -> ProgramResult {
+    match get_return_data() {
+        Some((program_id, data)) if program_id.eq(swap_program_id) && !data.is_empty() => Ok(()),
+        Some((program_id, _)) => {
+            arb_msg!("Swap program: {}", swap_program_id);
+            arb_msg!("Return data set by: {}", program_id);
+            Err(ArbitrageProgramError::SwapVerificationFailed.into())
+        }
+        None => {
+            arb_msg!("Swap program {} returned no data", swap_program_id);
+            Err(ArbitrageProgramError::SwapVerificationFailed.into())
+        }
+    }
+}
+
+/// 构建 swap 指令数据
+///
+/// # 参数
+/// * `discriminator` - swap 程序识别指令的方式
+/// * `encoding` - 数量的字节序，默认为小端序
+/// * `mode` - 兑换模式
+/// * `amount` - 支付的数量；`ExactOut` 模式下为允许支付的最大数量
+/// * `amount_out` - `ExactOut` 模式下固定的接收数量，`ExactIn` 模式下不编码
+///
+/// # 返回值
+/// 指令标识之后是按 `encoding` 编码的数量：
+/// * `ExactIn` - 支付数量
+/// * `ExactOut` - 接收数量和支付上限
+///
+/// `Anchor8` 的指令标识是 `global:swap`（`ExactOut` 为 `global:swap_exact_out`）指令哈希的前8字节，
+/// `U8` / `U32` 的指令标识分别是 1 字节、4 字节（小端序）的标签，`Custom` 原样使用给出的 8 字节。
+pub(crate) fn build_ix_data(
+    discriminator: DiscriminatorKind,
+    encoding: AmountEncoding,
+    mode: SwapMode,
+    amount: u64,
+    amount_out: u64,
+) -> Vec<u8> {
+    let mut swap_ix_data = match discriminator {
+        DiscriminatorKind::Anchor8 => {
+            // 生成swap指令的哈希值，用于标识交易类型
+            let preimage: &[u8] = match mode {
+                SwapMode::ExactIn => b"global:swap",
+                SwapMode::ExactOut => b"global:swap_exact_out",
+            };
+            let swap_ix_hash = solana_program::hash::hash(preimage);
+            swap_ix_hash.to_bytes()[..8].to_vec()
+        }
+        DiscriminatorKind::U8(tag) => vec![tag],
+        DiscriminatorKind::U32(tag) => tag.to_le_bytes().to_vec(),
+        DiscriminatorKind::Custom(discriminator) => discriminator.to_vec(),
+    };
+
+    // 数量默认为小端字节序，与 Borsh（以及 `ArbitrageResult`）的编码一致
+    if mode == SwapMode::ExactOut {
+        swap_ix_data.extend_from_slice(&encoding.encode(amount_out));
+    }
+    swap_ix_data.extend_from_slice(&encoding.encode(amount));
+
+    swap_ix_data
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processor::ACCOUNT_LAYOUT;
+    use crate::testing::{
+        account, install, program_account, return_data, take_cpis, take_logs, token_account,
+        token_amount, Market, MarketAccounts, MockPool, MockSwap, PROGRAM_ID,
+    };
+
+    #[test]
+    fn verbose_no_arbitrage_logs_the_best_edge() {
+        // 报价 1018 / 999，价差 190 bps，低于温度 90 对应的 10% 阈值
+        let market = Market::new(&[1_000, 0], &[1_000_000, 1_020_000], &[1_000_000; 2]);
+        install(market.mock_swap());
+        let accounts = market.build();
+        let options = TryArbitrageOptions {
+            verbose: true,
+            ..TryArbitrageOptions::default()
+        };
+
+        assert_eq!(
+            accounts.try_arbitrage(90, options),
+            Err(ArbitrageProgramError::NoArbitrage.into())
+        );
+        assert!(take_logs()
+            .iter()
+            .any(|line| line == "[ARB] No arbitrage: best edge was 190 bps on pair (0,1)"));
+    }
+
+    #[test]
+    fn best_edge_is_only_logged_when_verbose() {
+        let market = Market::new(&[1_000, 0], &[1_000_000, 1_020_000], &[1_000_000; 2]);
+        install(market.mock_swap());
+        let accounts = market.build();
+
+        assert!(accounts
+            .try_arbitrage(90, TryArbitrageOptions::default())
+            .is_err());
+        assert!(!take_logs().iter().any(|line| line.contains("best edge")));
+    }
+
+    /// 以账户名代替 `AccountInfo` 的资产账户，便于直接断言每条腿使用的账户
+    fn named_accounts(index: usize, asset: &'static str) -> AssetAccounts<&'static str> {
+        let names: &'static [&'static str; 4] = match asset {
+            "i" => &["user_i", "swap_1_i", "swap_2_i", "mint_i"],
+            _ => &["user_j", "swap_1_j", "swap_2_j", "mint_j"],
+        };
+        AssetAccounts {
+            index,
+            user: names[0],
+            swap_1: names[1],
+            swap_2: names[2],
+            mint: names[3],
+        }
+    }
+
+    const PLANNED: LegAmounts = LegAmounts {
+        pay_amount: 1_000,
+        r_swap_1: 1_998,
+        r_swap_2: 999,
+    };
+
+    #[test]
+    fn plan_legs_buying_on_swap_1() {
+        let (i, j) = (named_accounts(0, "i"), named_accounts(1, "j"));
+        let (buy, sell) = plan_legs(&Buy::Swap1, &i, &j, PLANNED, false);
+
+        assert_eq!(buy.venue, Buy::Swap1);
+        assert_eq!(
+            (buy.pay.user, buy.pay.pool(buy.venue)),
+            ("user_i", "swap_1_i")
+        );
+        assert_eq!(buy.receive.user, "user_j");
+        assert_eq!((buy.amount, buy.amount_out), (1_000, 1_998));
+        assert_eq!(sell.venue, Buy::Swap2);
+        assert_eq!(
+            (sell.pay.user, sell.pay.pool(sell.venue)),
+            ("user_j", "swap_2_j")
+        );
+        assert_eq!(sell.receive.user, "user_i");
+        assert_eq!(sell.amount, 1_998);
+    }
+
+    #[test]
+    fn plan_legs_buying_on_swap_2() {
+        let (i, j) = (named_accounts(0, "i"), named_accounts(1, "j"));
+        let (buy, sell) = plan_legs(&Buy::Swap2, &i, &j, PLANNED, false);
+
+        assert_eq!(buy.venue, Buy::Swap2);
+        assert_eq!(
+            (buy.pay.user, buy.pay.pool(buy.venue)),
+            ("user_i", "swap_2_i")
+        );
+        assert_eq!(buy.receive.user, "user_j");
+        assert_eq!((buy.amount, buy.amount_out), (1_000, 999));
+        assert_eq!(sell.venue, Buy::Swap1);
+        assert_eq!(
+            (sell.pay.user, sell.pay.pool(sell.venue)),
+            ("user_j", "swap_1_j")
+        );
+        assert_eq!(sell.receive.user, "user_i");
+        assert_eq!(sell.amount, 999);
+    }
+
+    /// 只有资产对 (0,1) 超过阈值：(0,2) 两边报价相同，用户不持有资产 1 与 2
+    fn one_qualifying_pair() -> Market {
+        Market::new(
+            &[1_000, 0, 0],
+            &[1_000_000, 2_000_000, 1_000_000],
+            &[1_000_000; 3],
+        )
+    }
+
+    #[test]
+    fn min_qualifying_pairs_blocks_a_lone_opportunity() {
+        let market = one_qualifying_pair();
+        install(market.mock_swap());
+        let accounts = market.build();
+        let options = TryArbitrageOptions {
+            min_qualifying_pairs: 2,
+            ..TryArbitrageOptions::default()
+        };
+
+        assert_eq!(
+            accounts.try_arbitrage(90, options),
+            Err(ArbitrageProgramError::NoArbitrage.into())
+        );
+        assert!(take_cpis().is_empty());
+        assert!(take_logs()
+            .iter()
+            .any(|line| line == "[ARB] 1 pair(s) qualified, 2 required"));
+    }
+
+    #[test]
+    fn min_qualifying_pairs_of_one_trades_the_lone_opportunity() {
+        let market = one_qualifying_pair();
+        install(market.mock_swap());
+        let accounts = market.build();
+        let options = TryArbitrageOptions {
+            min_qualifying_pairs: 1,
+            ..TryArbitrageOptions::default()
+        };
+
+        assert_eq!(accounts.try_arbitrage(90, options), Ok(()));
+        assert_eq!(take_cpis().len(), 2);
+    }
+
+    /// 在 Swap #1 买入资产 1、在 Swap #2 卖出的市场
+    fn skewed_market() -> Market {
+        Market::new(
+            &[1_000, 0],
+            &[1_000_000, 2_000_000],
+            &[1_000_000, 1_000_000],
+        )
+    }
+
+    #[test]
+    fn verify_swap_return_rejects_a_swap_without_return_data() {
+        let market = skewed_market();
+        install(market.mock_swap());
+        let accounts = market.build();
+        let options = TryArbitrageOptions {
+            verify_swap_return: true,
+            ..TryArbitrageOptions::default()
+        };
+
+        assert_eq!(
+            accounts.try_arbitrage(90, options),
+            Err(ArbitrageProgramError::SwapVerificationFailed.into())
+        );
+        // 第一条腿之后就停止
+        assert_eq!(take_cpis().len(), 1);
+    }
+
+    #[test]
+    fn verify_swap_return_accepts_swaps_that_set_return_data() {
+        let market = skewed_market();
+        let pool = |program_id| MockPool {
+            set_return_data: true,
+            ..MockPool::new(program_id)
+        };
+        install(MockSwap::new(vec![
+            pool(market.swap_1_program_id),
+            pool(market.swap_2_program_id),
+        ]));
+        let accounts = market.build();
+        let options = TryArbitrageOptions {
+            verify_swap_return: true,
+            ..TryArbitrageOptions::default()
+        };
+
+        assert_eq!(accounts.try_arbitrage(90, options), Ok(()));
+        assert_eq!(take_cpis().len(), 2);
+    }
+
+    #[test]
+    fn non_finite_price_difference_is_not_an_opportunity() {
+        install(MockSwap::default());
+        // r_swap_2 为零时比值为无穷大，`f64` 实现以前会悄无声息地给出错误的判断
+        assert_eq!(check_for_arbitrage(1_000, 0, 90, false), None);
+        assert_eq!(check_for_arbitrage(u64::MAX, 0, 100, false), None);
+        let logs = take_logs();
+        assert_eq!(logs.len(), 2);
+        assert!(logs
+            .iter()
+            .all(|line| line.starts_with("[ARB] Non-finite price difference for rates")));
+    }
+
+    #[test]
+    fn conservative_sizing_sells_the_smaller_estimate() {
+        let (i, j) = (named_accounts(0, "i"), named_accounts(1, "j"));
+        for direction in [Buy::Swap1, Buy::Swap2] {
+            let (buy, sell) = plan_legs(&direction, &i, &j, PLANNED, true);
+            assert_eq!(buy.amount, 1_000);
+            assert_eq!((buy.amount_out, sell.amount), (999, 999));
+        }
+        // 默认按买入池的预估值卖出
+        let (_, sell) = plan_legs(&Buy::Swap1, &i, &j, PLANNED, false);
+        assert_eq!(sell.amount, 1_998);
+    }
+
+    #[test]
+    fn conservative_sizing_changes_the_executed_sell_amount() {
+        let market = skewed_market();
+        // 买入腿在 Swap #1 换得 1998 个资产 1，按 Swap #2 的报价保守估计只有 999 个
+        for (conservative_sizing, sold) in [(false, 1_998u64), (true, 999)] {
+            install(market.mock_swap());
+            let accounts = market.build();
+            let options = TryArbitrageOptions {
+                conservative_sizing,
+                ..TryArbitrageOptions::default()
+            };
+
+            assert_eq!(accounts.try_arbitrage(90, options), Ok(()));
+            let cpis = take_cpis();
+            assert_eq!(cpis.len(), 2);
+            assert_eq!(cpis[1].data[8..], sold.to_le_bytes());
+            assert_eq!(token_amount(accounts.user(1)), 1_998 - sold);
+        }
+    }
+
+    #[test]
+    fn account_orders_permute_each_swap_cpi() {
+        // Swap #1 倒序，Swap #2 把前 3 个账户轮换到末尾
+        let reversed: Vec<u8> = (0..SWAP_CPI_ACCOUNTS as u8).rev().collect();
+        let rotated: Vec<u8> = (3..SWAP_CPI_ACCOUNTS as u8).chain(0..3).collect();
+        let market = skewed_market();
+        let mut mock = market.mock_swap();
+        mock.pools[0].account_order = reversed.clone();
+        mock.pools[1].account_order = rotated.clone();
+        install(mock);
+        let accounts = market.build();
+        let options = TryArbitrageOptions {
+            swap_1_account_order: reversed,
+            swap_2_account_order: rotated,
+            ..TryArbitrageOptions::default()
+        };
+
+        assert_eq!(accounts.try_arbitrage(90, options), Ok(()));
+        let cpis = take_cpis();
+        assert_eq!(cpis.len(), 2);
+        // 规范顺序中第 0 个账户为流动性池，第 7 个为所有者
+        assert_eq!(cpis[0].accounts[10].pubkey, *accounts.accounts[7].key);
+        assert_eq!(cpis[0].accounts[3].pubkey, *accounts.owner().key);
+        assert_eq!(cpis[1].accounts[8].pubkey, *accounts.accounts[8].key);
+        assert_eq!(cpis[1].accounts[4].pubkey, *accounts.owner().key);
+        assert_eq!(token_amount(accounts.user(0)), 1_994);
+    }
+
+    #[test]
+    fn account_order_must_be_a_permutation() {
+        let market = skewed_market();
+        install(market.mock_swap());
+        let options = TryArbitrageOptions {
+            swap_1_account_order: vec![0; SWAP_CPI_ACCOUNTS],
+            ..TryArbitrageOptions::default()
+        };
+
+        assert_eq!(
+            market.build().try_arbitrage(90, options),
+            Err(ProgramError::InvalidInstructionData)
+        );
+        assert!(take_cpis().is_empty());
+    }
+
+    #[test]
+    fn pool_fees_from_pool_data_reduce_the_quotes() {
+        // Swap #1 手续费 30 bps、Swap #2 100 bps，都记录在池子数据的偏移量 4 处
+        let mut market = skewed_market();
+        market.swap_1_pool_data[4..6].copy_from_slice(&30u16.to_le_bytes());
+        market.swap_2_pool_data[4..6].copy_from_slice(&100u16.to_le_bytes());
+        let mut mock = market.mock_swap();
+        mock.pools[0].fee_bps = 30;
+        mock.pools[1].fee_bps = 100;
+        install(mock);
+        let accounts = market.build();
+        let options = TryArbitrageOptions {
+            swap_1_fee_offset: Some(4),
+            swap_2_fee_offset: Some(4),
+            ..TryArbitrageOptions::default()
+        };
+
+        assert_eq!(accounts.try_arbitrage(90, options), Ok(()));
+        let cpis = take_cpis();
+        // 2e6 * 997 / (1e6 + 997)，没有手续费时为 1998
+        assert_eq!(cpis[1].data[8..], 1_992u64.to_le_bytes());
+        // 1e6 * 1972 / (1e6 + 1972)，其中 1972 为 1992 扣除 1% 手续费
+        assert_eq!(token_amount(accounts.user(0)), 1_968);
+    }
+
+    fn quote(user_balance: u64, swap_1_balance: u64, swap_2_balance: u64) -> AssetQuote {
+        AssetQuote {
+            user_balance,
+            swap_1_balance,
+            swap_2_balance,
+            swap_1_weight: 0,
+            swap_2_weight: 0,
+            excluded: false,
+        }
+    }
+
+    /// 按 `tie_break` 扫描，返回选中的资产对
+    fn tie_winner(quotes: &[AssetQuote], tie_break: TieBreak) -> (usize, usize) {
+        let scan = scan_opportunities(
+            quotes,
+            &[],
+            PoolFees::default(),
+            90,
+            None,
+            0,
+            None,
+            tie_break,
+            false,
+        )
+        .unwrap();
+        let best = scan.best.unwrap();
+        (best.i, best.j)
+    }
+
+    #[test]
+    fn tie_break_on_liquidity() {
+        // (0,1) 报价 2000 / 1000，(0,2) 报价 4000 / 2000，价差都是 10_000 bps，(0,2) 的池子更深
+        let quotes = [
+            quote(1_000, 999_000, 999_000),
+            quote(0, 2_000_000, 1_000_000),
+            quote(0, 4_000_000, 2_000_000),
+        ];
+        assert_eq!(tie_winner(&quotes, TieBreak::LowerIndex), (0, 1));
+        assert_eq!(tie_winner(&quotes, TieBreak::HigherLiquidity), (0, 2));
+        // 两个资产对的支付数量与买入池相同，价格冲击相同时保留先扫描到的资产对
+        assert_eq!(tie_winner(&quotes, TieBreak::LowerPriceImpact), (0, 1));
+    }
+
+    #[test]
+    fn tie_break_on_price_impact() {
+        // (0,2) 报价 2000 / 1000，(1,2) 报价 1000 / 500，价差相同；(0,1) 没有价差。
+        // 资产 1 的买入池更深，同样支付 1000 的价格冲击更小
+        let quotes = [
+            quote(1_000, 999_000, 999_000),
+            quote(1_000, 1_999_000, 1_999_000),
+            quote(0, 2_000_000, 1_000_000),
+        ];
+        assert_eq!(tie_winner(&quotes, TieBreak::LowerIndex), (0, 2));
+        assert_eq!(tie_winner(&quotes, TieBreak::HigherLiquidity), (0, 2));
+        assert_eq!(tie_winner(&quotes, TieBreak::LowerPriceImpact), (1, 2));
+    }
+
+    #[test]
+    fn scan_report_counts_each_skip_reason() {
+        // 用户只持有资产 0 与 2；资产 3 的池子只有 1 个最小单位，接收数量向下取整为零
+        let market = Market::new(
+            &[1_000, 0, 1_000, 0],
+            &[1_000_000, 1_000_000, 1_000_000, 1],
+            &[1_000_000, 1_000_000, 1_000_000, 1],
+        );
+        install(market.mock_swap());
+
+        assert_eq!(
+            market
+                .build()
+                .try_arbitrage(90, TryArbitrageOptions::default()),
+            Err(ArbitrageProgramError::NoArbitrage.into())
+        );
+        let report = ScanReport::try_from_slice(&return_data().unwrap().1).unwrap();
+        let mut skipped_by_reason = [0; crate::result::SKIP_REASONS];
+        // (0,1)、(0,2) 报价相同；(1,2)、(1,3) 用户不持有资产 1；(0,3)、(2,3) 接收数量为零
+        skipped_by_reason[SkipReason::BelowThreshold as usize] = 2;
+        skipped_by_reason[SkipReason::ZeroBalance as usize] = 2;
+        skipped_by_reason[SkipReason::ZeroReceive as usize] = 2;
+        assert_eq!(
+            report,
+            ScanReport {
+                scanned: 6,
+                skipped_by_reason,
+                dominant_reason: Some(SkipReason::BelowThreshold),
+            }
+        );
+    }
+
+    /// 用户代币账户由本程序 PDA 金库 `[b"vault", bump]` 持有、金库不签名的市场
+    fn vault_market() -> (MarketAccounts, Vec<Vec<u8>>) {
+        let (vault, bump) = Pubkey::find_program_address(&[b"vault"], &PROGRAM_ID);
+        let market = skewed_market();
+        install(market.mock_swap());
+        let mut accounts = market.build();
+        accounts.accounts[1] = account(
+            vault,
+            solana_system_interface::program::ID,
+            vec![],
+            false,
+            true,
+            false,
+        );
+        for (x, balance) in market.user_balances.iter().enumerate() {
+            let mint = *accounts.mint(x).key;
+            accounts.accounts[ACCOUNT_LAYOUT.len() + x] =
+                token_account(&mint, &vault, *balance, &spl_token::ID);
+        }
+        (accounts, vec![b"vault".to_vec(), vec![bump]])
+    }
+
+    #[test]
+    fn vault_seeds_sign_the_swap_cpis() {
+        let (accounts, vault_seeds) = vault_market();
+        let options = TryArbitrageOptions {
+            vault_seeds: vault_seeds.clone(),
+            ..TryArbitrageOptions::default()
+        };
+
+        assert_eq!(accounts.try_arbitrage(90, options), Ok(()));
+        let cpis = take_cpis();
+        assert_eq!(cpis.len(), 2);
+        for cpi in &cpis {
+            assert_eq!(cpi.signer_seeds, vec![vault_seeds.clone()]);
+            assert!(cpi.accounts[7].is_signer);
+            assert_eq!(cpi.accounts[7].pubkey, *accounts.owner().key);
+        }
+        assert_eq!(token_amount(accounts.user(0)), 1_994);
+    }
+
+    #[test]
+    fn vault_owner_needs_matching_seeds() {
+        let (accounts, _) = vault_market();
+        assert_eq!(
+            accounts.try_arbitrage(90, TryArbitrageOptions::default()),
+            Err(ProgramError::MissingRequiredSignature)
+        );
+        let options = TryArbitrageOptions {
+            vault_seeds: vec![b"other".to_vec(), vec![255]],
+            ..TryArbitrageOptions::default()
+        };
+        assert_eq!(
+            accounts.try_arbitrage(90, options),
+            Err(ProgramError::InvalidSeeds)
+        );
+        assert!(take_cpis().is_empty());
+    }
+
+    /// Swap #2 只按报价的 99% 成交：卖出腿预估换回 1994，实际换回 1974
+    fn underdelivering_trade(max_estimate_drift_bps: u16) -> Vec<String> {
+        let market = skewed_market();
+        let mut mock = market.mock_swap();
+        mock.pools[1].output_bps = 9_900;
+        install(mock);
+        let accounts = market.build();
+        let options = TryArbitrageOptions {
+            max_estimate_drift_bps: Some(max_estimate_drift_bps),
+            ..TryArbitrageOptions::default()
+        };
+
+        // 偏差只是警告，交易仍然成功
+        assert_eq!(accounts.try_arbitrage(90, options), Ok(()));
+        assert_eq!(token_amount(accounts.user(0)), 1_974);
+        take_logs()
+    }
+
+    #[test]
+    fn estimate_drift_beyond_the_limit_is_warned() {
+        assert!(underdelivering_trade(50).contains(
+            &"[ARB] WARNING: realized output 1974 drifts 100 bps from estimate 1994 (max 50 bps)"
+                .to_string()
+        ));
+    }
+
+    #[test]
+    fn estimate_drift_within_the_limit_is_not_warned() {
+        assert!(!underdelivering_trade(100)
+            .iter()
+            .any(|line| line.contains("WARNING")));
+    }
+
+    const LOOSE_TIER: SecondaryTier = SecondaryTier {
+        temperature: 99,
+        size_bps: 5_000,
+    };
+
+    #[test]
+    fn secondary_tier_trades_a_smaller_size() {
+        // 价差约 2%，低于第一档的 10% 阈值，超过第二档的 1% 阈值
+        let market = Market::new(&[1_000, 0], &[1_000_000, 1_020_000], &[1_000_000; 2]);
+        install(market.mock_swap());
+        let options = TryArbitrageOptions {
+            secondary_tier: Some(LOOSE_TIER),
+            ..TryArbitrageOptions::default()
+        };
+
+        assert_eq!(market.build().try_arbitrage(90, options), Ok(()));
+        assert_eq!(take_cpis()[0].data[8..], 500u64.to_le_bytes());
+        assert!(take_logs().contains(
+            &"[ARB] Primary tier found nothing, trying 5000 bps of size at temperature 99"
+                .to_string()
+        ));
+    }
+
+    #[test]
+    fn primary_tier_keeps_the_full_size() {
+        let market = skewed_market();
+        install(market.mock_swap());
+        let options = TryArbitrageOptions {
+            secondary_tier: Some(LOOSE_TIER),
+            ..TryArbitrageOptions::default()
+        };
+
+        assert_eq!(market.build().try_arbitrage(90, options), Ok(()));
+        assert_eq!(take_cpis()[0].data[8..], 1_000u64.to_le_bytes());
+        assert!(!take_logs().iter().any(|line| line.contains("Primary tier")));
+    }
+
+    #[test]
+    fn report_no_arbitrage_succeeds_with_a_report() {
+        let market = Market::new(&[1_000, 0], &[1_000_000, 1_020_000], &[1_000_000; 2]);
+        install(market.mock_swap());
+        let options = TryArbitrageOptions {
+            report_no_arbitrage: true,
+            ..TryArbitrageOptions::default()
+        };
+
+        assert_eq!(market.build().try_arbitrage(90, options), Ok(()));
+        assert!(take_cpis().is_empty());
+        let (program_id, data) = return_data().unwrap();
+        assert_eq!(program_id, PROGRAM_ID);
+        let report = NoArbReport::try_from_slice(&data).unwrap();
+        assert_eq!(
+            (report.best_edge_bps, report.best_pair),
+            (190, Some((0, 1)))
+        );
+        assert_eq!(report.scan.scanned, 1);
+        assert_eq!(
+            report.scan.dominant_reason,
+            Some(SkipReason::BelowThreshold)
+        );
+    }
+
+    /// 直接由 `Market::build` 的账户组装 `TryArbitrageArgs`，不经过 `process_arbitrage` 的校验
+    fn unchecked_args(accounts: &MarketAccounts) -> TryArbitrageArgs<'_, 'static> {
+        let token_accounts = |range: std::ops::Range<usize>| -> Vec<_> {
+            accounts.accounts[range]
+                .iter()
+                .map(|acc| {
+                    let data = acc.data.borrow();
+                    let key_at = |at: usize| Pubkey::try_from(&data[at..at + 32]).unwrap();
+                    (acc, key_at(0), key_at(32), token_amount(acc))
+                })
+                .collect()
+        };
+        let n = accounts.concurrency;
+        let fixed = ACCOUNT_LAYOUT.len();
+        TryArbitrageArgs {
+            token_accounts_user: token_accounts(fixed..fixed + n),
+            token_accounts_swap_1: token_accounts(fixed + n..fixed + 2 * n),
+            token_accounts_swap_2: token_accounts(fixed + 2 * n..fixed + 3 * n),
+            mints: accounts.accounts[fixed + 3 * n..fixed + 4 * n]
+                .iter()
+                .map(|mint| (mint, 6))
+                .collect(),
+            payer: &accounts.accounts[0],
+            owner: &accounts.accounts[1],
+            token_program: &accounts.accounts[2],
+            system_program: &accounts.accounts[3],
+            associated_token_program: Some(&accounts.accounts[4]),
+            swap_1_program: &accounts.accounts[5],
+            swap_2_program: &accounts.accounts[6],
+            swap_1_pool: &accounts.accounts[7],
+            swap_2_pool: &accounts.accounts[8],
+            excluded_assets: vec![],
+            fees: PoolFees::default(),
+            temperature: 90,
+            options: TryArbitrageOptions::default(),
+            recorded_plan: None,
+            treasury: None,
+            denied_pairs: vec![],
+            record_program: None,
+            dust_collector: None,
+            swap_3: None,
+            cpi_count: Rc::default(),
+        }
+    }
+
+    #[test]
+    fn validate_accepts_consistent_args() {
+        let accounts = skewed_market().build();
+        assert_eq!(unchecked_args(&accounts).validate(), Ok(()));
+    }
+
+    #[test]
+    fn validate_rejects_mismatched_list_lengths() {
+        let accounts = Market::new(&[1_000, 0, 0], &[1_000_000; 3], &[1_000_000; 3]).build();
+        let mut args = unchecked_args(&accounts);
+        args.token_accounts_swap_2.pop();
+        assert_eq!(
+            args.validate(),
+            Err(ArbitrageProgramError::InvalidAccountsList.into())
+        );
+
+        let mut args = unchecked_args(&accounts);
+        args.mints.pop();
+        assert_eq!(
+            args.validate(),
+            Err(ArbitrageProgramError::InvalidAccountsList.into())
+        );
+    }
+
+    #[test]
+    fn validate_needs_two_assets() {
+        let accounts = Market::new(&[1_000], &[1_000_000], &[1_000_000]).build();
+        assert_eq!(
+            unchecked_args(&accounts).validate(),
+            Err(ArbitrageProgramError::InvalidAccountsList.into())
+        );
+    }
+
+    #[test]
+    fn validate_rejects_an_unset_pool() {
+        let accounts = skewed_market().build();
+        let unset = account(
+            Pubkey::default(),
+            Pubkey::default(),
+            vec![],
+            false,
+            false,
+            false,
+        );
+        let mut args = unchecked_args(&accounts);
+        args.swap_2_pool = &unset;
+        assert_eq!(
+            args.validate(),
+            Err(ArbitrageProgramError::InvalidAccountsList.into())
+        );
+    }
+
+    #[test]
+    fn swap_modes_encode_the_amount_meaning() {
+        let exact_in = build_ix_data(
+            DiscriminatorKind::Anchor8,
+            AmountEncoding::LittleEndian,
+            SwapMode::ExactIn,
+            1_000,
+            1_998,
+        );
+        assert_eq!(
+            exact_in[..8],
+            solana_program::hash::hash(b"global:swap").to_bytes()[..8]
+        );
+        // 只编码支付数量
+        assert_eq!(exact_in[8..], 1_000u64.to_le_bytes());
+
+        let exact_out = build_ix_data(
+            DiscriminatorKind::Anchor8,
+            AmountEncoding::LittleEndian,
+            SwapMode::ExactOut,
+            1_000,
+            1_998,
+        );
+        assert_eq!(
+            exact_out[..8],
+            solana_program::hash::hash(b"global:swap_exact_out").to_bytes()[..8]
+        );
+        // 先是固定的接收数量，再是支付上限
+        assert_eq!(exact_out[8..16], 1_998u64.to_le_bytes());
+        assert_eq!(exact_out[16..], 1_000u64.to_le_bytes());
+    }
+
+    #[test]
+    fn exact_out_sell_leg_locks_the_amount_out() {
+        let market = skewed_market();
+        install(market.mock_swap());
+        let accounts = market.build();
+        let options = TryArbitrageOptions {
+            sell_swap_mode: SwapMode::ExactOut,
+            ..TryArbitrageOptions::default()
+        };
+
+        assert_eq!(accounts.try_arbitrage(90, options), Ok(()));
+        let cpis = take_cpis();
+        assert_eq!(cpis[0].data.len(), 16);
+        assert_eq!(cpis[1].data[8..16], 1_994u64.to_le_bytes());
+        assert_eq!(cpis[1].data[16..], 1_998u64.to_le_bytes());
+        assert_eq!(token_amount(accounts.user(0)), 1_994);
+        // 换回 1994 需要支付 1e6 * 1994 / (1e6 - 1994) 向上取整即 1998，正好用完上限
+        assert_eq!(token_amount(accounts.user(1)), 0);
+    }
+
+    #[test]
+    fn priority_fee_recommendation_scales_with_profit() {
+        let hint = PriorityFeeHint {
+            base_micro_lamports: 1_000,
+            profit_factor_bps: 5_000,
+        };
+        assert_eq!(hint.recommend(0), 1_000);
+        assert_eq!(hint.recommend(994), 1_497);
+        assert_eq!(hint.recommend(9_940), 5_970);
+        // 超过 u64 范围时饱和
+        let aggressive = PriorityFeeHint {
+            profit_factor_bps: 20_000,
+            ..hint
+        };
+        assert_eq!(aggressive.recommend(u64::MAX), u64::MAX);
+    }
+
+    #[test]
+    fn priority_fee_hint_is_logged_before_the_trade() {
+        let market = skewed_market();
+        install(market.mock_swap());
+        let options = TryArbitrageOptions {
+            priority_fee_hint: Some(PriorityFeeHint {
+                base_micro_lamports: 1_000,
+                profit_factor_bps: 5_000,
+            }),
+            ..TryArbitrageOptions::default()
+        };
+
+        assert_eq!(market.build().try_arbitrage(90, options), Ok(()));
+        assert!(take_logs().contains(
+            &"[ARB] Recommended compute unit price: 1497 micro-lamports (expected profit 994)"
+                .to_string()
+        ));
+    }
+
+    #[test]
+    fn three_leg_cycle_reports_chained_leg_results() {
+        let market = Market::new(&[1_000, 0, 0], &[1_000_000; 3], &[1_000_000; 3]);
+        install(market.mock_swap());
+        let accounts = market.build();
+        let args = unchecked_args(&accounts);
+        let assets: Vec<_> = (0..3).map(|x| args.asset_accounts(x).unwrap()).collect();
+        let leg = |venue, pay: usize, receive: usize, amount| SwapLeg {
+            venue,
+            pay: assets[pay],
+            receive: assets[receive],
+            amount,
+            amount_out: 0,
+            mode: SwapMode::ExactIn,
+        };
+
+        // 0 -> 1（Swap #1）、1 -> 2（Swap #2）、2 -> 0（Swap #1），每条腿支付上一条腿的预估接收数量
+        let legs = invoke_arbitrage(
+            &args,
+            &[
+                leg(Buy::Swap1, 0, 1, 1_000),
+                leg(Buy::Swap2, 1, 2, 999),
+                leg(Buy::Swap1, 2, 0, 998),
+            ],
+        )
+        .unwrap();
+
+        assert_eq!(take_cpis().len(), 3);
+        let realized: Vec<_> = legs
+            .iter()
+            .map(|l| (l.venue, l.mint_in, l.mint_out, l.amount_in, l.amount_out))
+            .collect();
+        assert_eq!(
+            realized,
+            vec![
+                (Buy::Swap1, 0, 1, 1_000, 999),
+                (Buy::Swap2, 1, 2, 999, 998),
+                // 第一条腿已向 Swap #1 的资产 0 池注入 1000，第三条腿因此多得一个单位
+                (Buy::Swap1, 2, 0, 998, 998),
+            ]
+        );
+        for pair in legs.windows(2) {
+            assert_eq!(pair[0].mint_out, pair[1].mint_in);
+            assert_eq!(pair[0].amount_out, pair[1].amount_in);
+        }
+    }
+
+    #[test]
+    fn zero_balance_pay_account_is_skipped_before_pricing() {
+        let mut market = skewed_market();
+        market.user_balances = vec![0, 0];
+        install(market.mock_swap());
+        let accounts = market.build();
+
+        assert_eq!(
+            accounts.try_arbitrage(90, TryArbitrageOptions::default()),
+            Err(ArbitrageProgramError::NoArbitrage.into())
+        );
+        assert!(take_cpis().is_empty());
+        let logs = take_logs();
+        assert!(logs.contains(&"[ARB] Skipping pair (0,1): user holds none of asset 0".to_string()));
+        assert!(logs.contains(&"[ARB] No arbitrage: mostly ZeroBalance".to_string()));
+    }
+
+    /// 只有资产对 (0,2) 存在套利机会的三资产市场
+    fn opportunity_on_third_mint() -> Market {
+        Market::new(
+            &[1_000, 0, 0],
+            &[1_000_000, 1_000_000, 2_000_000],
+            &[1_000_000, 1_000_000, 1_000_000],
+        )
+    }
+
+    #[test]
+    fn mints_beyond_max_mints_are_not_priced() {
+        let market = opportunity_on_third_mint();
+        install(market.mock_swap());
+        let accounts = market.build();
+        let mut args = unchecked_args(&accounts);
+        args.options.max_mints = 2;
+
+        assert_eq!(asset_quotes(&args).unwrap().len(), 2);
+        assert_eq!(
+            take_logs(),
+            vec!["[ARB] Pricing only the first 2 of 3 mints".to_string()]
+        );
+        for max_mints in [0, 3] {
+            args.options.max_mints = max_mints;
+            assert_eq!(asset_quotes(&args).unwrap().len(), 3);
+        }
+    }
+
+    #[test]
+    fn trimmed_mint_is_not_traded() {
+        let market = opportunity_on_third_mint();
+        install(market.mock_swap());
+        let accounts = market.build();
+        let options = |max_mints| TryArbitrageOptions {
+            max_mints,
+            ..TryArbitrageOptions::default()
+        };
+
+        assert_eq!(
+            accounts.try_arbitrage(90, options(2)),
+            Err(ArbitrageProgramError::NoArbitrage.into())
+        );
+        assert!(take_cpis().is_empty());
+        assert_eq!(accounts.try_arbitrage(90, options(3)), Ok(()));
+        assert_eq!(take_cpis().len(), 2);
+    }
+
+    fn with_tx_cost(tx_cost_lamports: u64, sol_asset_index: u8) -> TryArbitrageOptions {
+        TryArbitrageOptions {
+            tx_cost_lamports,
+            sol_asset_index,
+            ..TryArbitrageOptions::default()
+        }
+    }
+
+    #[test]
+    fn profitable_trade_not_covering_the_tx_cost_is_skipped() {
+        let market = skewed_market();
+        install(market.mock_swap());
+        let accounts = market.build();
+
+        // 利润 994 个资产 0，按 Swap #1 的 2:1 价格折合 1988 lamports（资产 1 为包装 SOL）
+        assert_eq!(
+            accounts.try_arbitrage(90, with_tx_cost(5_000, 1)),
+            Err(ArbitrageProgramError::NoArbitrage.into())
+        );
+        assert!(take_cpis().is_empty());
+        assert!(take_logs().contains(
+            &"[ARB] Expected profit 994 (1988 lamports) does not cover the 5000 lamport transaction cost"
+                .to_string()
+        ));
+
+        assert_eq!(accounts.try_arbitrage(90, with_tx_cost(1_988, 1)), Ok(()));
+        assert_eq!(take_cpis().len(), 2);
+    }
+
+    #[test]
+    fn profit_in_sol_is_compared_directly() {
+        let market = skewed_market();
+        install(market.mock_swap());
+        let accounts = market.build();
+
+        assert_eq!(
+            accounts.try_arbitrage(90, with_tx_cost(995, 0)),
+            Err(ArbitrageProgramError::NoArbitrage.into())
+        );
+        assert!(take_cpis().is_empty());
+        assert_eq!(accounts.try_arbitrage(90, with_tx_cost(994, 0)), Ok(()));
+    }
 
-    (buy_swap_ix_data, sell_swap_ix_data)
+    #[test]
+    fn identical_rates_log_no_edge_when_verbose() {
+        install(MockSwap::default());
+        assert_eq!(check_for_arbitrage(1_000, 1_000, 100, true), None);
+        assert_eq!(
+            take_logs(),
+            vec!["[ARB] No edge: both pools quote 1000".to_string()]
+        );
+
+        // 非 verbose 模式不记录；价差低于阈值时不是 "No edge"
+        assert_eq!(check_for_arbitrage(1_000, 1_000, 100, false), None);
+        assert_eq!(check_for_arbitrage(1_000, 999, 0, true), None);
+        assert!(take_logs().is_empty());
+    }
+
+    /// Swap #1 为低费率池（30 bps）、Swap #2 为高费率池（100 bps），费率都在池子数据的偏移量 4 处
+    fn low_and_high_fee_pools(
+        max_acceptable_fee_bps: u16,
+    ) -> (MarketAccounts, TryArbitrageOptions) {
+        let mut market = skewed_market();
+        market.swap_1_pool_data[4..6].copy_from_slice(&30u16.to_le_bytes());
+        market.swap_2_pool_data[4..6].copy_from_slice(&100u16.to_le_bytes());
+        let mut mock = market.mock_swap();
+        mock.pools[0].fee_bps = 30;
+        mock.pools[1].fee_bps = 100;
+        install(mock);
+        let options = TryArbitrageOptions {
+            swap_1_fee_offset: Some(4),
+            swap_2_fee_offset: Some(4),
+            max_acceptable_fee_bps: Some(max_acceptable_fee_bps),
+            ..TryArbitrageOptions::default()
+        };
+        (market.build(), options)
+    }
+
+    #[test]
+    fn high_fee_pool_is_skipped() {
+        let (accounts, options) = low_and_high_fee_pools(50);
+
+        assert_eq!(
+            accounts.try_arbitrage(90, options),
+            Err(ArbitrageProgramError::PoolFeeTooHigh.into())
+        );
+        assert!(take_cpis().is_empty());
+        let logs = take_logs();
+        assert!(logs.contains(
+            &"[ARB] Skipping Swap #2 pool: fee 100 bps exceeds the maximum 50 bps".to_string()
+        ));
+        assert!(!logs.iter().any(|log| log.contains("Skipping Swap #1 pool")));
+    }
+
+    #[test]
+    fn fee_cap_is_reported_apart_from_no_arbitrage() {
+        // 池子费率都在上限之内、但利润达不到 `min_profit` 时报告的是没有机会，而不是费率过高
+        let (accounts, options) = low_and_high_fee_pools(100);
+        let options = TryArbitrageOptions {
+            min_profit: u64::MAX,
+            ..options
+        };
+
+        assert_eq!(
+            accounts.try_arbitrage(90, options),
+            Err(ArbitrageProgramError::NoArbitrage.into())
+        );
+        assert!(!take_logs()
+            .iter()
+            .any(|log| log.contains("exceeds the maximum")));
+    }
+
+    #[test]
+    fn pools_within_the_fee_cap_trade() {
+        let (accounts, options) = low_and_high_fee_pools(100);
+
+        assert_eq!(accounts.try_arbitrage(90, options), Ok(()));
+        assert_eq!(take_cpis().len(), 2);
+    }
+
+    /// 不计手续费时在 Swap #1 用 2:1 的价格买入、在 Swap #2 用 1:1 的价格卖出的往返利润
+    fn skewed_round_trip_profit(size: u64) -> i128 {
+        let size = u128::from(size);
+        let bought = 2_000_000 * size / (1_000_000 + size);
+        let sold = 1_000_000 * bought / (1_000_000 + bought);
+        sold as i128 - size as i128
+    }
+
+    #[test]
+    fn quoted_max_size_is_the_largest_size_clearing_min_profit() {
+        let mut market = skewed_market();
+        market.user_balances = vec![1_000_000, 0];
+        install(market.mock_swap());
+        let accounts = market.build();
+        let min_profit = 20_000;
+        let options = TryArbitrageOptions {
+            quote_max_size: true,
+            min_profit,
+            ..TryArbitrageOptions::default()
+        };
+
+        assert_eq!(accounts.try_arbitrage(90, options), Ok(()));
+        assert!(take_cpis().is_empty());
+        let (_, data) = return_data().unwrap();
+        let quote = MaxSizeQuote::try_from_slice(&data).unwrap();
+
+        // 两个池子的报价始终相差一倍，阈值不起作用，最大数量只受 `min_profit` 约束
+        let expected = (1..=1_000_000u64)
+            .rev()
+            .find(|&size| skewed_round_trip_profit(size) >= i128::from(min_profit))
+            .unwrap();
+        assert_eq!(quote.max_size, expected);
+        assert!(skewed_round_trip_profit(quote.max_size + 1) < i128::from(min_profit));
+        assert_eq!(
+            i128::from(quote.expected_profit),
+            skewed_round_trip_profit(quote.max_size)
+        );
+        assert_eq!(
+            (quote.direction, quote.mint_i, quote.mint_j),
+            (Buy::Swap1, 0, 1)
+        );
+    }
+
+    #[test]
+    fn quote_without_a_profitable_size_reports_zero() {
+        let market = skewed_market();
+        install(market.mock_swap());
+        let accounts = market.build();
+        // 1000 的余额最多获利 994
+        let options = TryArbitrageOptions {
+            quote_max_size: true,
+            min_profit: 995,
+            ..TryArbitrageOptions::default()
+        };
+
+        assert_eq!(accounts.try_arbitrage(90, options), Ok(()));
+        let (_, data) = return_data().unwrap();
+        let quote = MaxSizeQuote::try_from_slice(&data).unwrap();
+        assert_eq!((quote.max_size, quote.expected_profit), (0, 0));
+    }
+
+    /// 利润约 1.6 个 6 位小数整币的市场，利润资产 0 的小数位数为 `decimals`
+    fn whole_token_market(decimals: u8) -> Market {
+        let mut market = Market::new(
+            &[1_600_000, 0],
+            &[10_000_000_000, 20_000_000_000],
+            &[10_000_000_000, 10_000_000_000],
+        );
+        market.decimals = vec![decimals, 6];
+        market
+    }
+
+    fn one_whole_token() -> TryArbitrageOptions {
+        TryArbitrageOptions {
+            min_profit: 1,
+            min_profit_whole_tokens: true,
+            ..TryArbitrageOptions::default()
+        }
+    }
+
+    #[test]
+    fn whole_token_min_profit_scales_with_decimals() {
+        for (decimals, expected) in [(6, 1_000_000), (9, 1_000_000_000)] {
+            let accounts = whole_token_market(decimals).build();
+            let mut args = unchecked_args(&accounts);
+            args.mints[0].1 = decimals;
+            args.options = one_whole_token();
+            assert_eq!(args.min_profit(0), Ok(expected));
+
+            // 未设置 `min_profit_whole_tokens` 时按最小单位计
+            args.options.min_profit_whole_tokens = false;
+            assert_eq!(args.min_profit(0), Ok(1));
+        }
+    }
+
+    #[test]
+    fn one_whole_token_clears_for_6_decimals_but_not_9() {
+        let market = whole_token_market(6);
+        install(market.mock_swap());
+        assert_eq!(market.build().try_arbitrage(90, one_whole_token()), Ok(()));
+        assert_eq!(take_cpis().len(), 2);
+
+        let market = whole_token_market(9);
+        install(market.mock_swap());
+        assert_eq!(
+            market.build().try_arbitrage(90, one_whole_token()),
+            Err(ArbitrageProgramError::NoArbitrage.into())
+        );
+        assert!(take_cpis().is_empty());
+    }
+
+    fn with_pay_pool_ratio(ratio_bps: u16) -> TryArbitrageOptions {
+        TryArbitrageOptions {
+            max_pay_pool_ratio_bps: Some(ratio_bps),
+            ..TryArbitrageOptions::default()
+        }
+    }
+
+    #[test]
+    fn pay_within_the_pool_ratio_is_unchanged() {
+        // 资产 0 在两个池子中的较小余额为 1e6，10 bps 即 1000，恰好等于用户余额
+        let market = skewed_market();
+        install(market.mock_swap());
+        let accounts = market.build();
+
+        assert_eq!(accounts.try_arbitrage(90, with_pay_pool_ratio(10)), Ok(()));
+        assert_eq!(take_cpis()[0].data[8..16], 1_000u64.to_le_bytes());
+        assert!(!take_logs().iter().any(|log| log.contains("clamping pay")));
+    }
+
+    #[test]
+    fn pay_beyond_the_pool_ratio_is_clamped() {
+        let market = skewed_market();
+        install(market.mock_swap());
+        let accounts = market.build();
+
+        assert_eq!(accounts.try_arbitrage(90, with_pay_pool_ratio(5)), Ok(()));
+        assert_eq!(take_cpis()[0].data[8..16], 500u64.to_le_bytes());
+        assert!(take_logs()
+            .contains(&"[ARB] Asset 0: clamping pay 1000 to 500 (5 bps of the pool)".to_string()));
+        // 未支付的 500 留在用户账户中，买入的 999 个资产 1 卖回 998 个资产 0
+        assert_eq!(token_amount(accounts.user(0)), 500 + 998);
+    }
+
+    #[test]
+    fn pay_pool_ratio_is_validated() {
+        let market = skewed_market();
+        for ratio_bps in [0, 10_001] {
+            install(market.mock_swap());
+            assert_eq!(
+                market
+                    .build()
+                    .try_arbitrage(90, with_pay_pool_ratio(ratio_bps)),
+                Err(ProgramError::InvalidInstructionData)
+            );
+        }
+    }
+
+    /// 默认构建与 `integer_only` 构建共用的判断用例：(r_swap_1, r_swap_2, temperature, 预期判断)
+    const DECISION_FIXTURES: &[(u64, u64, u8, Option<Buy>)] = &[
+        (1_998, 999, 90, Some(Buy::Swap1)),
+        (999, 1_998, 90, Some(Buy::Swap2)),
+        (1_000, 1_000, 99, None),
+        (1_050, 1_000, 90, None),
+        (1_101, 1_000, 90, Some(Buy::Swap1)),
+        (1_000, 1_101, 90, None),
+        (1_000, 1_112, 90, Some(Buy::Swap2)),
+        (2_001, 1_000, 0, Some(Buy::Swap1)),
+        (1_999, 1_000, 0, None),
+        (1, 1_000, 1, Some(Buy::Swap2)),
+        (u64::MAX, u64::MAX / 2, 50, Some(Buy::Swap1)),
+        (1_000, 0, 90, None),
+    ];
+
+    #[test]
+    fn decisions_match_the_shared_fixtures() {
+        install(MockSwap::default());
+        for &(r_swap_1, r_swap_2, temperature, expected) in DECISION_FIXTURES {
+            assert_eq!(
+                check_for_arbitrage(r_swap_1, r_swap_2, temperature, false),
+                expected,
+                "rates {} / {} at temperature {}",
+                r_swap_1,
+                r_swap_2,
+                temperature
+            );
+        }
+    }
+
+    /// 预估利润为 994 的市场，账户列表末尾追加日志程序
+    fn market_with_record_program(
+        record_program: &Pubkey,
+    ) -> (MarketAccounts, TryArbitrageOptions) {
+        let market = skewed_market();
+        install(market.mock_swap());
+        let mut accounts = market.build();
+        accounts.accounts.push(program_account(record_program));
+        let options = TryArbitrageOptions {
+            persist_record: true,
+            record_program: *record_program,
+            ..TryArbitrageOptions::default()
+        };
+        (accounts, options)
+    }
+
+    #[test]
+    fn successful_trade_persists_the_record_by_cpi() {
+        let record_program = Pubkey::new_unique();
+        let (accounts, options) = market_with_record_program(&record_program);
+
+        assert_eq!(accounts.try_arbitrage(90, options), Ok(()));
+        let cpis = take_cpis();
+        assert_eq!(cpis.len(), 3);
+        let record = &cpis[2];
+        assert_eq!(record.program_id, record_program);
+        assert!(record.accounts.is_empty());
+        // 记录与返回数据相同，都是 Borsh 编码的 `ArbitrageResult`
+        let (_, data) = return_data().unwrap();
+        assert_eq!(record.data, data);
+        let result = ArbitrageResult::try_from_slice(&record.data).unwrap();
+        assert_eq!(
+            (result.mint_i, result.mint_j, result.amount_in),
+            (0, 1, 1_000)
+        );
+    }
+
+    #[test]
+    fn record_program_must_match_the_instruction() {
+        let (accounts, mut options) = market_with_record_program(&Pubkey::new_unique());
+        options.record_program = Pubkey::new_unique();
+
+        assert_eq!(
+            accounts.try_arbitrage(90, options),
+            Err(ArbitrageProgramError::InvalidAccountsList.into())
+        );
+        assert!(take_cpis().is_empty());
+    }
+
+    fn traded_result(output_bps: u16) -> ArbitrageResult {
+        let market = skewed_market();
+        let mut mock = market.mock_swap();
+        mock.pools[1].output_bps = output_bps;
+        install(mock);
+        let accounts = market.build();
+        assert_eq!(
+            accounts.try_arbitrage(90, TryArbitrageOptions::default()),
+            Ok(())
+        );
+        let (_, data) = return_data().unwrap();
+        ArbitrageResult::try_from_slice(&data).unwrap()
+    }
+
+    #[test]
+    fn surplus_over_the_estimate_is_reported_as_positive_slippage() {
+        // 卖出腿多成交 1%：1994 * 1.01 向下取整为 2013
+        let result = traded_result(10_100);
+        assert_eq!(result.expected_amount_out, 1_994);
+        assert_eq!(result.legs[1].amount_out, 2_013);
+        assert_eq!(result.positive_slippage, 19);
+        assert!(take_logs().contains(&"[ARB] Positive slippage: 19 above the estimate".to_string()));
+    }
+
+    #[test]
+    fn exact_or_short_fills_report_no_positive_slippage() {
+        for output_bps in [10_000, 9_900] {
+            assert_eq!(traded_result(output_bps).positive_slippage, 0);
+            assert!(!take_logs()
+                .iter()
+                .any(|log| log.contains("Positive slippage")));
+        }
+    }
+
+    fn with_safety_multiple(safety_multiple_bps: u16) -> TryArbitrageOptions {
+        TryArbitrageOptions {
+            min_profit: 600,
+            safety_multiple_bps: Some(safety_multiple_bps),
+            ..TryArbitrageOptions::default()
+        }
+    }
+
+    #[test]
+    fn profit_clearing_min_profit_but_not_the_safety_multiple_is_only_quoted() {
+        // 预估利润 994 超过 `min_profit` 600，但不足 2 倍即 1200
+        let market = skewed_market();
+        install(market.mock_swap());
+        let accounts = market.build();
+
+        assert_eq!(
+            accounts.try_arbitrage(90, with_safety_multiple(20_000)),
+            Ok(())
+        );
+        assert!(take_cpis().is_empty());
+        assert_eq!(token_amount(accounts.user(0)), 1_000);
+        let (_, data) = return_data().unwrap();
+        assert_eq!(
+            TradeQuote::try_from_slice(&data).unwrap(),
+            TradeQuote {
+                direction: Buy::Swap1,
+                mint_i: 0,
+                mint_j: 1,
+                amount_in: 1_000,
+                expected_profit: 994,
+                required_profit: 1_200,
+            }
+        );
+        assert!(take_logs().contains(
+            &"[ARB] Expected profit 994 is below the safety margin 1200 (20000 bps of 600), quoting only"
+                .to_string()
+        ));
+    }
+
+    #[test]
+    fn profit_clearing_the_safety_multiple_trades() {
+        // 1.5 倍即 900，预估利润 994 足够
+        let market = skewed_market();
+        install(market.mock_swap());
+        let accounts = market.build();
+
+        assert_eq!(
+            accounts.try_arbitrage(90, with_safety_multiple(15_000)),
+            Ok(())
+        );
+        assert_eq!(take_cpis().len(), 2);
+    }
+
+    /// 卖出腿按报价的 `output_bps` 成交，每条腿的滑点下限为预估值的 `fraction_bps`
+    fn fill_with_floor(output_bps: u16, fraction_bps: u16) -> (MarketAccounts, ProgramResult) {
+        let market = skewed_market();
+        let mut mock = market.mock_swap();
+        mock.pools[1].output_bps = output_bps;
+        install(mock);
+        let accounts = market.build();
+        let options = TryArbitrageOptions {
+            min_out_fraction_bps: Some(fraction_bps),
+            ..TryArbitrageOptions::default()
+        };
+        let result = accounts.try_arbitrage(90, options);
+        (accounts, result)
+    }
+
+    #[test]
+    fn one_percent_tolerance_accepts_a_small_shortfall() {
+        // 卖出腿成交 1994 * 99.5% = 1984，不低于 1994 * 99% = 1974
+        let (accounts, result) = fill_with_floor(9_950, 9_900);
+        assert_eq!(result, Ok(()));
+        assert_eq!(token_amount(accounts.user(0)), 1_984);
+    }
+
+    #[test]
+    fn no_tolerance_rejects_any_shortfall() {
+        let (_, result) = fill_with_floor(9_950, 10_000);
+        assert_eq!(result, Err(ArbitrageProgramError::BelowMinReceive.into()));
+        assert!(take_logs().contains(
+            &"[ARB] Leg 2 received 1984, below 10000 bps of the estimate 1994".to_string()
+        ));
+
+        // 按预估值全额成交时没有容差也能通过
+        let (_, result) = fill_with_floor(10_000, 10_000);
+        assert_eq!(result, Ok(()));
+    }
+
+    #[test]
+    fn tiny_pay_rounding_to_zero_is_skipped_as_dust() {
+        // 支付 1 个资产 0 最多换得 1e6 / 1e9 个资产 1，向下取整为零
+        let market = Market::new(
+            &[1, 0],
+            &[1_000_000_000, 1_000_000],
+            &[1_000_000_000, 2_000_000],
+        );
+        install(market.mock_swap());
+        let accounts = market.build();
+
+        assert_eq!(
+            accounts.try_arbitrage(90, TryArbitrageOptions::default()),
+            Err(ArbitrageProgramError::NoArbitrage.into())
+        );
+        assert!(take_cpis().is_empty());
+        assert!(take_logs().contains(
+            &"[ARB] DustRoundedToZero: pair (0,1) pays 1 but receives 0 / 0".to_string()
+        ));
+    }
+
+    #[test]
+    fn each_discriminator_kind_produces_its_leading_bytes() {
+        let anchor = &solana_program::hash::hash(b"global:swap").to_bytes()[..8];
+        let custom = [9, 8, 7, 6, 5, 4, 3, 2];
+        for (kind, leading) in [
+            (DiscriminatorKind::Anchor8, anchor.to_vec()),
+            (DiscriminatorKind::U8(7), vec![7]),
+            (DiscriminatorKind::U32(0x0102_0304), vec![4, 3, 2, 1]),
+            (DiscriminatorKind::Custom(custom), custom.to_vec()),
+        ] {
+            let data = build_ix_data(
+                kind,
+                AmountEncoding::LittleEndian,
+                SwapMode::ExactIn,
+                1_000,
+                0,
+            );
+            assert_eq!(data[..leading.len()], leading[..], "{:?}", kind);
+            assert_eq!(data[leading.len()..], 1_000u64.to_le_bytes());
+        }
+    }
+
+    #[test]
+    fn single_byte_tag_reaches_the_swap_program() {
+        let market = skewed_market();
+        let mut mock = market.mock_swap();
+        mock.pools[0].discriminator_len = 1;
+        install(mock);
+        let accounts = market.build();
+        let options = TryArbitrageOptions {
+            swap_1_discriminator: DiscriminatorKind::U8(3),
+            ..TryArbitrageOptions::default()
+        };
+
+        assert_eq!(accounts.try_arbitrage(90, options), Ok(()));
+        let cpis = take_cpis();
+        assert_eq!(
+            cpis[0].data[..],
+            [&[3][..], &1_000u64.to_le_bytes()].concat()[..]
+        );
+        assert_eq!(cpis[1].data.len(), 16);
+    }
+
+    fn with_max_edge(max_edge_bps: u16) -> TryArbitrageOptions {
+        TryArbitrageOptions {
+            max_edge_bps: Some(max_edge_bps),
+            ..TryArbitrageOptions::default()
+        }
+    }
+
+    #[test]
+    fn realistic_edge_below_max_edge_trades() {
+        // Swap #1 的资产 1 贵 20%，价差约 2000 bps
+        let market = Market::new(
+            &[1_000, 0],
+            &[1_000_000, 1_200_000],
+            &[1_000_000, 1_000_000],
+        );
+        install(market.mock_swap());
+        let accounts = market.build();
+
+        assert_eq!(accounts.try_arbitrage(90, with_max_edge(5_000)), Ok(()));
+        assert_eq!(take_cpis().len(), 2);
+    }
+
+    #[test]
+    fn absurd_edge_above_max_edge_is_skipped() {
+        // 报价相差一倍，价差 10000 bps
+        let market = skewed_market();
+        install(market.mock_swap());
+        let accounts = market.build();
+
+        assert_eq!(
+            accounts.try_arbitrage(90, with_max_edge(5_000)),
+            Err(ArbitrageProgramError::NoArbitrage.into())
+        );
+        assert!(take_cpis().is_empty());
+        let logs = take_logs();
+        assert!(logs.contains(
+            &"[ARB] Skipping pair (0,1): edge 10000 bps exceeds max_edge_bps 5000".to_string()
+        ));
+        assert!(logs.contains(&"[ARB] No arbitrage: mostly TooGoodToBeTrue".to_string()));
+    }
+
+    #[test]
+    fn asset_present_in_only_one_pool_is_excluded_from_the_scan() {
+        // 资产 2 只在 Swap #1 中有余额；用户只持有资产 2
+        let market = Market::new(
+            &[0, 0, 1_000],
+            &[1_000_000, 1_000_000, 1_000_000],
+            &[1_000_000, 1_000_000, 0],
+        );
+        install(market.mock_swap());
+        let accounts = market.build();
+
+        let quotes = asset_quotes(&unchecked_args(&accounts)).unwrap();
+        assert_eq!(
+            quotes.iter().map(|q| q.excluded).collect::<Vec<_>>(),
+            vec![false, false, true]
+        );
+        assert!(take_logs().contains(
+            &"[ARB] Asset 2: not present in both pools (1000000 / 0), excluding".to_string()
+        ));
+
+        assert_eq!(
+            accounts.try_arbitrage(90, TryArbitrageOptions::default()),
+            Err(ArbitrageProgramError::NoArbitrage.into())
+        );
+        assert!(take_cpis().is_empty());
+        let (_, data) = return_data().unwrap();
+        let report = ScanReport::try_from_slice(&data).unwrap();
+        assert_eq!(report.scanned, 3);
+        assert_eq!(report.skipped_by_reason[SkipReason::Excluded as usize], 2);
+        assert_eq!(report.dominant_reason, Some(SkipReason::Excluded));
+    }
+
+    #[test]
+    fn pair_leaving_too_little_residual_is_skipped() {
+        // 资产 1 的接收数量为 1998 / 999，Swap #2 兑换后只留下 1_000_000 - 999 = 999_001
+        for (min_residual, traded) in [(999_001, true), (999_002, false)] {
+            let market = skewed_market();
+            install(market.mock_swap());
+            let accounts = market.build();
+            let options = TryArbitrageOptions {
+                min_residual,
+                ..TryArbitrageOptions::default()
+            };
+
+            let result = accounts.try_arbitrage(90, options);
+            if traded {
+                assert_eq!(result, Ok(()));
+                assert_eq!(token_amount(accounts.user(0)), 1_994);
+                continue;
+            }
+            assert_eq!(result, Err(ArbitrageProgramError::NoArbitrage.into()));
+            assert!(take_cpis().is_empty());
+            assert!(take_logs().contains(
+                &"[ARB] Skipping pair (0,1): pool would keep 999001 of asset 1, below min_residual 999002"
+                    .to_string()
+            ));
+            let (_, data) = return_data().unwrap();
+            let report = ScanReport::try_from_slice(&data).unwrap();
+            assert_eq!(report.dominant_reason, Some(SkipReason::BelowMinResidual));
+        }
+    }
+
+    #[test]
+    fn no_arbitrage_reports_the_dominant_skip_reason() {
+        // 两个池子报价完全相同；资产 2 的池子很浅，兑换后只剩不到 5000
+        let scan = |min_residual| {
+            let market = Market::new(
+                &[1_000, 1_000, 0],
+                &[1_000_000, 1_000_000, 5_000],
+                &[1_000_000, 1_000_000, 5_000],
+            );
+            install(market.mock_swap());
+            let options = TryArbitrageOptions {
+                min_residual,
+                ..TryArbitrageOptions::default()
+            };
+
+            assert_eq!(
+                market.build().try_arbitrage(90, options),
+                Err(ArbitrageProgramError::NoArbitrage.into())
+            );
+            assert!(take_cpis().is_empty());
+            ScanReport::try_from_slice(&return_data().unwrap().1).unwrap()
+        };
+
+        // 流动性过滤为主：(0,2)、(1,2) 会抽干资产 2 的池子，只有 (0,1) 因阈值被跳过
+        let report = scan(10_000);
+        assert_eq!(
+            report.skipped_by_reason[SkipReason::BelowMinResidual as usize],
+            2
+        );
+        assert_eq!(
+            report.skipped_by_reason[SkipReason::BelowThreshold as usize],
+            1
+        );
+        assert_eq!(report.dominant_reason, Some(SkipReason::BelowMinResidual));
+
+        // 阈值过滤为主：三个资产对都能兑换，但价差为零
+        let report = scan(0);
+        assert_eq!(
+            report.skipped_by_reason[SkipReason::BelowThreshold as usize],
+            3
+        );
+        assert_eq!(report.dominant_reason, Some(SkipReason::BelowThreshold));
+    }
+
+    #[test]
+    fn cpi_account_cap_is_checked_before_any_cpi() {
+        // 每条交易腿的 CPI 引用 SWAP_CPI_ACCOUNTS 个账户，两条腿正好 22 个
+        for (max, result) in [
+            (22, Ok(())),
+            (
+                21,
+                Err(ArbitrageProgramError::CpiAccountLimitExceeded.into()),
+            ),
+        ] {
+            let market = skewed_market();
+            install(market.mock_swap());
+            let options = TryArbitrageOptions {
+                max_cpi_accounts: Some(max),
+                ..TryArbitrageOptions::default()
+            };
+
+            assert_eq!(market.build().try_arbitrage(90, options), result);
+            assert_eq!(take_cpis().len(), if result.is_ok() { 2 } else { 0 });
+        }
+        assert!(take_logs().contains(
+            &"[ARB] CPIs reference 22 accounts in total, above max_cpi_accounts 21".to_string()
+        ));
+
+        // 三条腿共 33 个账户，上限 32 时一条腿都不执行
+        let market = Market::new(&[1_000, 0, 0], &[1_000_000; 3], &[1_000_000; 3]);
+        install(market.mock_swap());
+        let accounts = market.build();
+        let mut args = unchecked_args(&accounts);
+        args.options.max_cpi_accounts = Some(3 * SWAP_CPI_ACCOUNTS as u16 - 1);
+        let assets: Vec<_> = (0..3).map(|x| args.asset_accounts(x).unwrap()).collect();
+        let leg = |venue, pay: usize, receive: usize| SwapLeg {
+            venue,
+            pay: assets[pay],
+            receive: assets[receive],
+            amount: 1_000,
+            amount_out: 0,
+            mode: SwapMode::ExactIn,
+        };
+
+        assert_eq!(
+            invoke_arbitrage(
+                &args,
+                &[
+                    leg(Buy::Swap1, 0, 1),
+                    leg(Buy::Swap2, 1, 2),
+                    leg(Buy::Swap1, 2, 0),
+                ],
+            ),
+            Err(ArbitrageProgramError::CpiAccountLimitExceeded.into())
+        );
+        assert!(take_cpis().is_empty());
+        assert!(take_logs().contains(
+            &"[ARB] CPIs reference 33 accounts in total, above max_cpi_accounts 32".to_string()
+        ));
+    }
+
+    #[test]
+    fn forced_direction_trades_below_the_threshold() {
+        // 价差约 2%，温度 90 的阈值下不会交易；指定报价更好的买入池时按该方向交易
+        for (forced, s1, s2) in [
+            (Buy::Swap1, [1_000_000, 1_020_000], [1_000_000; 2]),
+            (Buy::Swap2, [1_000_000; 2], [1_000_000, 1_020_000]),
+        ] {
+            let market = Market::new(&[1_000, 0], &s1, &s2);
+            install(market.mock_swap());
+            let accounts = market.build();
+            assert_eq!(
+                accounts.try_arbitrage(90, TryArbitrageOptions::default()),
+                Err(ArbitrageProgramError::NoArbitrage.into())
+            );
+
+            let market = Market::new(&[1_000, 0], &s1, &s2);
+            install(market.mock_swap());
+            let accounts = market.build();
+            let options = TryArbitrageOptions {
+                force_direction: Some(forced),
+                ..TryArbitrageOptions::default()
+            };
+            assert_eq!(accounts.try_arbitrage(90, options), Ok(()));
+            let cpis = take_cpis();
+            let buy_program = match forced {
+                Buy::Swap1 => accounts.swap_1_program_id,
+                Buy::Swap2 => accounts.swap_2_program_id,
+            };
+            assert_eq!(cpis[0].program_id, buy_program);
+            assert_eq!(token_amount(accounts.user(0)), 1_016);
+        }
+    }
+
+    #[test]
+    fn forced_direction_against_the_better_pool_is_skipped() {
+        // Swap #1 报价更好时强制在 Swap #2 买入不会交易
+        let market = Market::new(&[1_000, 0], &[1_000_000, 1_020_000], &[1_000_000; 2]);
+        install(market.mock_swap());
+        let options = TryArbitrageOptions {
+            force_direction: Some(Buy::Swap2),
+            ..TryArbitrageOptions::default()
+        };
+
+        assert_eq!(
+            market.build().try_arbitrage(90, options),
+            Err(ArbitrageProgramError::NoArbitrage.into())
+        );
+        assert!(take_cpis().is_empty());
+    }
+
+    #[test]
+    fn temperature_boundary_is_monotonic() {
+        // 价差正好等于 `100 - temperature` 时不交易，多一个单位就交易；比值都是二进制精确值，
+        // 浮点与整数实现在边界上一致
+        for (temperature, boundary) in [(0, 2_000), (25, 1_750), (50, 1_500), (75, 1_250)] {
+            assert_eq!(
+                check_for_arbitrage(boundary, 1_000, temperature, false),
+                None
+            );
+            assert_eq!(
+                check_for_arbitrage(boundary + 1, 1_000, temperature, false),
+                Some(Buy::Swap1)
+            );
+        }
+        assert_eq!(check_for_arbitrage(500, 1_000, 50, false), None);
+        assert_eq!(check_for_arbitrage(499, 1_000, 50, false), Some(Buy::Swap2));
+
+        // 同一组报价下温度越高越容易交易：一旦开始交易，更高的温度不会停止交易
+        let first = (0..100)
+            .position(|t| check_for_arbitrage(1_200, 1_000, t, false).is_some())
+            .unwrap();
+        assert_eq!(first, 81);
+        assert!((81..100).all(|t| check_for_arbitrage(1_200, 1_000, t, false).is_some()));
+    }
+
+    #[test]
+    fn higher_swap_2_quote_buys_on_swap_2() {
+        assert_eq!(
+            check_for_arbitrage(1_000, 2_000, 90, false),
+            Some(Buy::Swap2)
+        );
+        assert_eq!(
+            check_for_arbitrage(2_000, 1_000, 90, false),
+            Some(Buy::Swap1)
+        );
+        // 阈值按价差的绝对值判断：两个方向上约 5% 的价差在温度 90 下都不交易
+        assert_eq!(check_for_arbitrage(1_000, 1_050, 90, false), None);
+        assert_eq!(check_for_arbitrage(1_050, 1_000, 90, false), None);
+
+        // Swap #2 的资产 1 更便宜：在 Swap #2 买入、在 Swap #1 卖出
+        let market = Market::new(&[1_000, 0], &[1_000_000; 2], &[1_000_000, 2_000_000]);
+        install(market.mock_swap());
+        let accounts = market.build();
+        assert_eq!(
+            accounts.try_arbitrage(90, TryArbitrageOptions::default()),
+            Ok(())
+        );
+        let cpis = take_cpis();
+        assert_eq!(cpis[0].program_id, accounts.swap_2_program_id);
+        assert_eq!(cpis[1].program_id, accounts.swap_1_program_id);
+        assert_eq!(token_amount(accounts.user(0)), 1_994);
+    }
+
+    #[test]
+    fn profit_exactly_at_min_profit_trades() {
+        // 预估利润为 994：等于 `min_profit` 时交易，多一个单位就不交易
+        for (min_profit, traded) in [(993, true), (994, true), (995, false)] {
+            let market = skewed_market();
+            install(market.mock_swap());
+            let accounts = market.build();
+            let options = TryArbitrageOptions {
+                min_profit,
+                ..TryArbitrageOptions::default()
+            };
+
+            let result = accounts.try_arbitrage(90, options);
+            if traded {
+                assert_eq!(result, Ok(()));
+                assert_eq!(take_cpis().len(), 2);
+                assert_eq!(token_amount(accounts.user(0)), 1_994);
+            } else {
+                assert_eq!(result, Err(ArbitrageProgramError::NoArbitrage.into()));
+                assert!(take_cpis().is_empty());
+                assert!(take_logs()
+                    .contains(&"[ARB] Expected profit 994 is below the minimum 995".to_string()));
+            }
+        }
+    }
+
+    #[test]
+    fn custom_discriminators_are_sent_per_swap_program() {
+        let (swap_1, swap_2) = (
+            [1, 2, 3, 4, 5, 6, 7, 8],
+            [0xf8, 0xc6, 0x9e, 0x91, 0xe1, 0x75, 0x87, 0xc8],
+        );
+        // 16 字节：原样的 8 字节指令标识，随后是小端序的数量
+        assert_eq!(
+            build_ix_data(
+                DiscriminatorKind::Custom(swap_1),
+                AmountEncoding::LittleEndian,
+                SwapMode::ExactIn,
+                1_000_000,
+                0
+            ),
+            [1, 2, 3, 4, 5, 6, 7, 8, 0x40, 0x42, 0x0f, 0, 0, 0, 0, 0]
+        );
+
+        let market = skewed_market();
+        install(market.mock_swap());
+        let accounts = market.build();
+        let options = TryArbitrageOptions {
+            swap_1_discriminator: DiscriminatorKind::Custom(swap_1),
+            swap_2_discriminator: DiscriminatorKind::Custom(swap_2),
+            ..TryArbitrageOptions::default()
+        };
+
+        assert_eq!(accounts.try_arbitrage(90, options), Ok(()));
+        let cpis = take_cpis();
+        assert_eq!(
+            cpis[0].data,
+            [&swap_1[..], &1_000u64.to_le_bytes()].concat()
+        );
+        assert_eq!(
+            cpis[1].data,
+            [&swap_2[..], &1_998u64.to_le_bytes()].concat()
+        );
+    }
+
+    #[test]
+    fn amounts_are_encoded_little_endian() {
+        // 1_000_000 = 0x0f4240，按 Borsh 的小端序 `u64` 编码，而不是大端序
+        let data = build_ix_data(
+            DiscriminatorKind::U8(0),
+            AmountEncoding::LittleEndian,
+            SwapMode::ExactIn,
+            1_000_000,
+            0,
+        );
+        assert_eq!(data[1..], [0x40, 0x42, 0x0f, 0, 0, 0, 0, 0]);
+        assert_ne!(data[1..], 1_000_000u64.to_be_bytes());
+        assert_eq!(u64::try_from_slice(&data[1..]).unwrap(), 1_000_000);
+
+        let data = build_ix_data(
+            DiscriminatorKind::U8(0),
+            AmountEncoding::LittleEndian,
+            SwapMode::ExactOut,
+            1_000_000,
+            2_000_000,
+        );
+        assert_eq!(
+            <(u64, u64)>::try_from_slice(&data[1..]).unwrap(),
+            (2_000_000, 1_000_000)
+        );
+    }
+
+    #[test]
+    fn amounts_can_be_encoded_big_endian() {
+        let data = build_ix_data(
+            DiscriminatorKind::U8(0),
+            AmountEncoding::BigEndian,
+            SwapMode::ExactIn,
+            1_000_000,
+            0,
+        );
+        assert_eq!(data[1..], [0, 0, 0, 0, 0, 0x0f, 0x42, 0x40]);
+
+        let data = build_ix_data(
+            DiscriminatorKind::U8(0),
+            AmountEncoding::BigEndian,
+            SwapMode::ExactOut,
+            1_000_000,
+            2_000_000,
+        );
+        assert_eq!(data[1..9], 2_000_000u64.to_be_bytes());
+        assert_eq!(data[9..], 1_000_000u64.to_be_bytes());
+        for encoding in [AmountEncoding::LittleEndian, AmountEncoding::BigEndian] {
+            assert_eq!(encoding.decode(encoding.encode(1_000_000)), 1_000_000);
+        }
+    }
+
+    #[test]
+    fn big_endian_amounts_reach_the_swap_programs() {
+        let market = skewed_market();
+        let mut mock = market.mock_swap();
+        for pool in &mut mock.pools {
+            pool.amount_encoding = AmountEncoding::BigEndian;
+        }
+        install(mock);
+        let accounts = market.build();
+        let options = TryArbitrageOptions {
+            amount_encoding: AmountEncoding::BigEndian,
+            ..TryArbitrageOptions::default()
+        };
+
+        assert_eq!(accounts.try_arbitrage(90, options), Ok(()));
+        let cpis = take_cpis();
+        assert_eq!(cpis[0].data[8..], 1_000u64.to_be_bytes());
+        assert_eq!(cpis[1].data[8..], 1_998u64.to_be_bytes());
+        assert_eq!(token_amount(accounts.user(0)), 1_994);
+    }
 }
@@ -0,0 +1,357 @@
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    program::invoke_signed,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    rent::Rent,
+    sysvar::Sysvar,
+};
+use solana_system_interface::instruction as system_instruction;
+
+use crate::{
+    arb_msg,
+    error::ArbitrageProgramError,
+    partial_state::ArbitrageMintInfo,
+    util::{read_versioned, versioned_len, write_versioned},
+};
+
+/// 程序配置 PDA 的种子前缀，完整种子为 `[CONFIG_SEED, authority]`
+///
+/// 每个 authority 拥有自己的配置 PDA，任何人都无法抢先初始化别人的配置。
+pub const CONFIG_SEED: &[u8] = b"config";
+
+/// 黑名单最多容纳的资产对数量，配置 PDA 按该容量一次性分配空间
+pub const MAX_DENIED_PAIRS: usize = 32;
+
+/// 配置 PDA 的数据长度：版本字节 + authority + Vec 长度前缀 + 满容量的资产对
+pub const CONFIG_ACCOUNT_LEN: usize = versioned_len(32 + 4 + MAX_DENIED_PAIRS * 64);
+
+/// 由 `authority` 管理、`TryArbitrage` 通过 `denylist_authority` 选用的程序配置
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ArbitrageConfig {
+    /// 有权修改配置的账户
+    pub authority: Pubkey,
+    /// 永远不参与扫描的资产对 `(mint_a, mint_b)`，与顺序无关
+    pub denied_pairs: Vec<(Pubkey, Pubkey)>,
+}
+
+impl ArbitrageConfig {
+    /// 资产对 `(a, b)` 是否在黑名单中（与顺序无关）
+    pub fn is_denied(&self, a: &Pubkey, b: &Pubkey) -> bool {
+        self.denied_pairs.iter().any(|pair| same_pair(pair, a, b))
+    }
+
+    /// 把黑名单换算成本次指令中的资产索引对 `(i, j)`，`i < j`
+    pub fn denied_indices(&self, mints: &[ArbitrageMintInfo]) -> Vec<(usize, usize)> {
+        let mut pairs = vec![];
+        for (i, a) in mints.iter().enumerate() {
+            for (j, b) in mints.iter().enumerate().skip(i + 1) {
+                if self.is_denied(a.0.key, b.0.key) {
+                    pairs.push((i, j));
+                }
+            }
+        }
+        pairs
+    }
+}
+
+/// `pair` 与 `(a, b)` 是否为同一个资产对（与顺序无关）
+fn same_pair((x, y): &(Pubkey, Pubkey), a: &Pubkey, b: &Pubkey) -> bool {
+    (x.eq(a) && y.eq(b)) || (x.eq(b) && y.eq(a))
+}
+
+/// 推导 `authority` 的程序配置 PDA
+pub fn find_config_address(program_id: &Pubkey, authority: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[CONFIG_SEED, authority.as_ref()], program_id)
+}
+
+/// 读取并校验 `authority` 的程序配置 PDA
+///
+/// # 错误
+/// * `ProgramError::InvalidSeeds` - 地址不是 `authority` 的程序配置 PDA
+/// * `ProgramError::IllegalOwner` - PDA 不属于本程序
+/// * `ArbitrageProgramError::UnsupportedAccountVersion` - 配置的数据版本未知
+/// * `ProgramError::InvalidAccountData` - 配置无法解析
+pub fn read_config(
+    program_id: &Pubkey,
+    config_account: &AccountInfo,
+    authority: &Pubkey,
+) -> Result<ArbitrageConfig, ProgramError> {
+    if !config_account
+        .key
+        .eq(&find_config_address(program_id, authority).0)
+    {
+        arb_msg!(
+            "Config {} is not the config PDA of authority {}",
+            config_account.key,
+            authority
+        );
+        return Err(ProgramError::InvalidSeeds);
+    }
+    if !config_account.owner.eq(program_id) {
+        arb_msg!("Config {} is not owned by this program", config_account.key);
+        return Err(ProgramError::IllegalOwner);
+    }
+    read_versioned(config_account)
+}
+
+/// 处理修改资产对黑名单的指令
+///
+/// 账户依次为：支付方（签名）、authority（签名）、authority 的程序配置 PDA、系统程序。
+/// 配置 PDA 由 `[CONFIG_SEED, authority]` 推导，不存在时由支付方支付租金创建；
+/// 已存在时签名者必须是配置中记录的 authority。
+///
+/// # 参数
+/// * `program_id` - 本程序 ID
+/// * `accounts` - 见上
+/// * `add` - 加入黑名单的资产对
+/// * `remove` - 移出黑名单的资产对（与顺序无关）
+///
+/// # 错误
+/// * `ProgramError::MissingRequiredSignature` - authority 没有签名
+/// * `ArbitrageProgramError::ConfigAuthorityMismatch` - 签名者不是配置中记录的 authority
+/// * `ProgramError::InvalidInstructionData` - 黑名单超过 `MAX_DENIED_PAIRS`
+pub fn process_update_denylist(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    add: &[(Pubkey, Pubkey)],
+    remove: &[(Pubkey, Pubkey)],
+) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let payer = next_account_info(accounts_iter)?;
+    let authority = next_account_info(accounts_iter)?;
+    let config_account = next_account_info(accounts_iter)?;
+    let system_program = next_account_info(accounts_iter)?;
+
+    if !authority.is_signer {
+        arb_msg!("Config authority {} must sign", authority.key);
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let mut config = if config_account.data_is_empty() {
+        let (address, bump) = find_config_address(program_id, authority.key);
+        if !config_account.key.eq(&address) {
+            arb_msg!("Config: expected {}, got {}", address, config_account.key);
+            return Err(ProgramError::InvalidSeeds);
+        }
+        invoke_signed(
+            &system_instruction::create_account(
+                payer.key,
+                config_account.key,
+                Rent::get()?.minimum_balance(CONFIG_ACCOUNT_LEN),
+                CONFIG_ACCOUNT_LEN as u64,
+                program_id,
+            ),
+            &[
+                payer.clone(),
+                config_account.clone(),
+                system_program.clone(),
+            ],
+            &[&[CONFIG_SEED, authority.key.as_ref(), &[bump]]],
+        )?;
+        ArbitrageConfig {
+            authority: *authority.key,
+            denied_pairs: vec![],
+        }
+    } else {
+        let config = read_config(program_id, config_account, authority.key)?;
+        if !config.authority.eq(authority.key) {
+            arb_msg!(
+                "Config authority is {}, got {}",
+                config.authority,
+                authority.key
+            );
+            return Err(ArbitrageProgramError::ConfigAuthorityMismatch.into());
+        }
+        config
+    };
+
+    config
+        .denied_pairs
+        .retain(|(a, b)| !remove.iter().any(|pair| same_pair(pair, a, b)));
+    for (a, b) in add {
+        if !config.is_denied(a, b) {
+            config.denied_pairs.push((*a, *b));
+        }
+    }
+    if config.denied_pairs.len() > MAX_DENIED_PAIRS {
+        arb_msg!(
+            "Denylist holds at most {} pairs, got {}",
+            MAX_DENIED_PAIRS,
+            config.denied_pairs.len()
+        );
+        return Err(ProgramError::InvalidInstructionData);
+    }
+
+    arb_msg!("Denylist now holds {} pair(s)", config.denied_pairs.len());
+    write_versioned(config_account, &config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{account, install, take_cpis, Market, MarketAccounts, PROGRAM_ID};
+    use crate::TryArbitrageOptions;
+
+    /// 预估利润为 994 的市场，账户列表末尾追加 `authority` 的配置 PDA，黑名单为 `denied_pairs`
+    fn market_with_denylist(
+        authority: &Pubkey,
+        denied_pairs: impl FnOnce(&MarketAccounts) -> Vec<(Pubkey, Pubkey)>,
+    ) -> MarketAccounts {
+        let market = Market::new(
+            &[1_000, 0],
+            &[1_000_000, 2_000_000],
+            &[1_000_000, 1_000_000],
+        );
+        install(market.mock_swap());
+        let mut accounts = market.build();
+        let config = ArbitrageConfig {
+            authority: *authority,
+            denied_pairs: denied_pairs(&accounts),
+        };
+        accounts.accounts.push(config_account(authority, &config));
+        accounts
+    }
+
+    fn config_account(authority: &Pubkey, config: &ArbitrageConfig) -> AccountInfo<'static> {
+        let (address, _) = find_config_address(&PROGRAM_ID, authority);
+        let config_account = account(
+            address,
+            PROGRAM_ID,
+            vec![0; CONFIG_ACCOUNT_LEN],
+            false,
+            true,
+            false,
+        );
+        write_versioned(&config_account, config).unwrap();
+        config_account
+    }
+
+    fn with_denylist(authority: &Pubkey) -> TryArbitrageOptions {
+        TryArbitrageOptions {
+            use_denylist: true,
+            denylist_authority: *authority,
+            ..TryArbitrageOptions::default()
+        }
+    }
+
+    #[test]
+    fn denied_pair_is_skipped() {
+        let authority = Pubkey::new_unique();
+        // 黑名单与顺序无关：按 (mint_1, mint_0) 记录的资产对同样被跳过
+        let accounts = market_with_denylist(&authority, |accounts| {
+            vec![(*accounts.mint(1).key, *accounts.mint(0).key)]
+        });
+
+        assert_eq!(
+            accounts.try_arbitrage(90, with_denylist(&authority)),
+            Err(ArbitrageProgramError::NoArbitrage.into())
+        );
+        assert!(take_cpis().is_empty());
+    }
+
+    #[test]
+    fn allowed_pair_still_trades() {
+        let authority = Pubkey::new_unique();
+        let accounts = market_with_denylist(&authority, |accounts| {
+            vec![(*accounts.mint(0).key, Pubkey::new_unique())]
+        });
+
+        assert_eq!(
+            accounts.try_arbitrage(90, with_denylist(&authority)),
+            Ok(())
+        );
+        assert_eq!(take_cpis().len(), 2);
+    }
+
+    #[test]
+    fn config_of_another_authority_is_rejected() {
+        let authority = Pubkey::new_unique();
+        let accounts = market_with_denylist(&authority, |_| vec![]);
+
+        assert_eq!(
+            accounts.try_arbitrage(90, with_denylist(&Pubkey::new_unique())),
+            Err(ProgramError::InvalidSeeds)
+        );
+    }
+
+    fn update_accounts(authority: &Pubkey, config: &ArbitrageConfig) -> Vec<AccountInfo<'static>> {
+        vec![
+            account(
+                Pubkey::new_unique(),
+                Pubkey::default(),
+                vec![],
+                true,
+                true,
+                false,
+            ),
+            account(*authority, Pubkey::default(), vec![], true, false, false),
+            config_account(authority, config),
+            account(
+                Pubkey::default(),
+                Pubkey::default(),
+                vec![],
+                false,
+                false,
+                true,
+            ),
+        ]
+    }
+
+    #[test]
+    fn update_adds_and_removes_pairs() {
+        let authority = Pubkey::new_unique();
+        let (a, b, c) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+        let config = ArbitrageConfig {
+            authority,
+            denied_pairs: vec![(a, b)],
+        };
+        let accounts = update_accounts(&authority, &config);
+
+        // 移除时与顺序无关，重复加入的资产对只保留一份
+        process_update_denylist(&PROGRAM_ID, &accounts, &[(a, c), (c, a)], &[(b, a)]).unwrap();
+
+        let config = read_config(&PROGRAM_ID, &accounts[2], &authority).unwrap();
+        assert_eq!(config.denied_pairs, vec![(a, c)]);
+        assert!(config.is_denied(&c, &a));
+        assert!(!config.is_denied(&a, &b));
+    }
+
+    #[test]
+    fn update_requires_the_recorded_authority() {
+        let authority = Pubkey::new_unique();
+        let config = ArbitrageConfig {
+            authority: Pubkey::new_unique(),
+            denied_pairs: vec![],
+        };
+        let accounts = update_accounts(&authority, &config);
+
+        assert_eq!(
+            process_update_denylist(&PROGRAM_ID, &accounts, &[], &[]),
+            Err(ArbitrageProgramError::ConfigAuthorityMismatch.into())
+        );
+    }
+
+    #[test]
+    fn future_version_config_is_rejected() {
+        let authority = Pubkey::new_unique();
+        let config = ArbitrageConfig {
+            authority,
+            denied_pairs: vec![],
+        };
+        let account = config_account(&authority, &config);
+        assert_eq!(read_config(&PROGRAM_ID, &account, &authority), Ok(config));
+
+        account.data.borrow_mut()[0] = crate::util::ACCOUNT_VERSION + 1;
+        assert_eq!(
+            read_config(&PROGRAM_ID, &account, &authority),
+            Err(ArbitrageProgramError::UnsupportedAccountVersion.into())
+        );
+    }
+}
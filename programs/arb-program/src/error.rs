@@ -1,4 +1,4 @@
-use solana_program::msg;
+use crate::arb_msg;
 /// 定义套利程序中可能发生的错误类型枚举
 ///
 /// 该枚举包含了在执行套利交易过程中可能遇到的各种错误情况，
@@ -23,6 +23,73 @@ pub enum ArbitrageProgramError {
     /// error so that preflight fails
     #[error("No arbitrage opportunity detected")]
     NoArbitrage,
+    /// Swap #1 and swap #2 were given the same program ID without explicitly
+    /// allowing intra-program arbitrage
+    #[error("Swap #1 and swap #2 use the same program ID. Set `allow_same_program` to arbitrage between pools of a single program")]
+    IdenticalSwapPrograms,
+    /// A user token account is not the canonical associated token account for
+    /// its owner and mint
+    #[error(
+        "A user token account is not the associated token account derived from its owner and mint"
+    )]
+    NonCanonicalTokenAccount,
+    /// An intermediate value in the pricing math does not fit in its integer type
+    #[error("Arithmetic overflow in pricing math")]
+    MathOverflow,
+    /// The same token account was passed for two different assets of a single
+    /// liquidity pool
+    #[error("A liquidity pool's token account list contains the same account more than once")]
+    DuplicateTokenAccount,
+    /// A swap program did not set return data identifying itself after the
+    /// swap CPI, so the swap's success could not be verified
+    #[error(
+        "Swap verification failed: the swap program did not return data tagged with its program ID"
+    )]
+    SwapVerificationFailed,
+    /// The computed receive amount is below the requested minimum
+    #[error("Computed receive amount is below the minimum receive floor")]
+    BelowMinReceive,
+    /// A swap program ID was left as the default (all-zeros) pubkey
+    #[error("Swap program ID is unset (the default all-zeros pubkey)")]
+    UnsetSwapProgramId,
+    /// The recorded opportunity no longer clears the threshold at current balances
+    #[error("The recorded opportunity is stale: it no longer clears the threshold")]
+    StaleRecordedOpportunity,
+    /// The configured flat treasury fee would exceed the trade's profit
+    #[error("The treasury fee exceeds the trade's profit")]
+    FeeExceedsProfit,
+    /// A liquidity pool's token account list references the same mint more than once
+    #[error("A liquidity pool's token account list contains the same mint more than once")]
+    DuplicatePoolMint,
+    /// A liquidity pool's last-update slot is older than the configured staleness limit
+    #[error("A liquidity pool has not been updated within the allowed number of slots")]
+    StalePool,
+    /// A per-asset account has the same key as one of the fixed accounts
+    #[error(
+        "A user, pool or mint account aliases one of the fixed accounts (payer, programs or pools)"
+    )]
+    AliasedFixedAccount,
+    /// The signer is not the authority recorded in the program config
+    #[error("The signer is not the program config's authority")]
+    ConfigAuthorityMismatch,
+    /// A program-owned account was written with a data format version this build does not know
+    #[error("Unsupported account data version")]
+    UnsupportedAccountVersion,
+    /// A pool token account balance changed between two reads within the same instruction
+    #[error("A pool reserve changed while the instruction was being parsed")]
+    ReserveMutated,
+    /// An account the swap CPIs mutate was passed without the writable flag
+    #[error("An account the swaps write to is not writable")]
+    AccountNotWritable,
+    /// The pools' token accounts are owned by different token programs
+    #[error("The pools' token accounts mix SPL Token and Token-2022")]
+    MixedTokenPrograms,
+    /// The swap CPIs would reference more accounts than `max_cpi_accounts`
+    #[error("The swap CPIs reference more accounts than allowed")]
+    CpiAccountLimitExceeded,
+    /// A pool charges more than `max_acceptable_fee_bps`
+    #[error("A pool's fee exceeds the maximum acceptable fee")]
+    PoolFeeTooHigh,
 }
 impl From<ArbitrageProgramError> for solana_program::program_error::ProgramError {
     /// 将ArbitrageProgramError转换为Solana程序错误
@@ -40,6 +107,6 @@ impl From<ArbitrageProgramError> for solana_program::program_error::ProgramError
 
 impl ArbitrageProgramError {
     pub fn log(&self) {
-        msg!("{}", &self.to_string());
+        arb_msg!("{}", &self.to_string());
     }
 }
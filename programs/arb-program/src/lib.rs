@@ -1,8 +1,18 @@
 pub mod arb;
+pub mod config;
 pub mod error;
 pub mod partial_state;
 pub mod processor;
+pub mod rebalance;
+pub mod record;
+pub mod result;
+#[cfg(feature = "client")]
+pub mod simulate;
 pub mod swap;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+pub mod treasury;
+pub mod triangular;
 pub mod util;
 
 use borsh::{BorshDeserialize, BorshSerialize};
@@ -14,8 +24,8 @@ use solana_program::{
 #[derive(BorshSerialize, BorshDeserialize, Debug)]
 /// ArbitrageProgramInstruction 枚举定义了套利程序的指令类型
 ///
-/// 该枚举用于表示套利交易程序中的不同操作指令，目前包含一个TryArbitrage变体，
-/// 用于尝试执行套利交易操作。
+/// 该枚举用于表示套利交易程序中的不同操作指令，包含用于尝试执行套利交易操作的
+/// TryArbitrage 变体，以及复用同一套定价逻辑调整库存配比的 Rebalance 变体。
 ///
 /// # 变体说明
 ///
@@ -26,17 +36,401 @@ use solana_program::{
 /// * `swap_1_program_id` - 第一个swap程序的公钥标识，用于识别第一个交易对的swap程序
 /// * `swap_2_program_id` - 第二个swap程序的公钥标识，用于识别第二个交易对的swap程序
 /// * `concurrency` - 并发级别，控制同时执行的交易数量
-/// * `temperature` - 温度参数，可能用于控制交易的激进程度或风险水平
+/// * `temperature` - 交易的激进程度：价差百分比超过 `100 - temperature` 时才交易。
+///   数值越大越激进，0 最保守（价差必须超过 100%，实际上几乎不会交易）；
+///   不小于 100 时阈值为零，只有设置了 `allow_zero_threshold` 才接受
+/// * `options` - 可选配置，见 `TryArbitrageOptions`
+///
+/// ## Rebalance
+/// 把用户持仓向目标配比调整一步，账户布局与 TryArbitrage 相同
+///
+/// ### 字段说明
+/// * `swap_1_program_id` / `swap_2_program_id` - 两个swap程序的公钥标识
+/// * `concurrency` - 参与再平衡的资产数量
+/// * `target_bps` - 按资产顺序排列的目标配比（基点），总和必须为 10_000
+///
+/// ## RecordOpportunity
+/// 字段与 TryArbitrage 相同：扫描套利机会，但不执行交易，而是把选中的机会写入
+/// 套利计划 PDA（紧跟在四个账户列表之后），之后可由设置了 `execute_recorded_plan`
+/// 的 TryArbitrage 读取并执行。会追加其他可选账户的选项（treasury、黑名单、交易记录、
+/// 零头归集、三角套利、`execute_recorded_plan`）不适用于该指令，设置时返回
+/// `InvalidInstructionData`
+///
+/// ## UpdateDenylist
+/// 由 authority 修改自己的程序配置 PDA（种子 `[b"config", authority]`）中的资产对黑名单，
+/// 设置了 `use_denylist` 且 `denylist_authority` 为该 authority 的 TryArbitrage 总是跳过黑名单中
+/// 的资产对。账户依次为支付方、authority、程序配置 PDA、系统程序；配置 PDA 不存在时创建
+///
+/// ### 字段说明
+/// * `add` - 加入黑名单的资产对 `(mint_a, mint_b)`
+/// * `remove` - 移出黑名单的资产对，与顺序无关
+///
+/// ## DebugParse
+/// 按 `TryArbitrage` 的解析逻辑解析单个账户并以 `ParsedAccount` 写入返回数据，用于排查账户布局
+///
+/// ### 字段说明
+/// * `which` - 0 按代币账户解析，1 按铸币账户解析
+/// * `index` - 待解析账户在账户列表中的下标
+///
+/// ## TryArbitrageMulti
+/// 在一条指令中依次执行多次互不重叠的套利尝试，每次尝试使用自己的账户段（按顺序首尾相接）。
+/// 没有发现套利机会（或池子费率超过 `max_acceptable_fee_bps`）的尝试不会回滚其他尝试，每次尝试的结果码以 `MultiArbitrageResult`
+/// 写入返回数据（只有结果码，每次尝试自己的返回数据会被覆盖）；其他错误可能发生在部分交易腿
+/// 执行之后，仍然回滚整条指令
+///
+/// ### 字段说明
+/// * `attempts` - 按执行顺序排列的套利尝试，见 `ArbitrageAttempt`
+/// * `max_total_cpis` - 所有尝试合计最多发起的 CPI 数量，达到上限后不再执行后续尝试并记录日志；
+///   0 表示不限制
+/// * `pool_check_policy` - 某次尝试的流动性池地址不是对应 swap 程序的池子 PDA 时，
+///   回滚整条指令（默认）还是只跳过该尝试，见 `PoolCheckPolicy`
 pub enum ArbitrageProgramInstruction {
     TryArbitrage {
         swap_1_program_id: Pubkey,
         swap_2_program_id: Pubkey,
         concurrency: u8,
         temperature: u8,
+        options: TryArbitrageOptions,
+    },
+    Rebalance {
+        swap_1_program_id: Pubkey,
+        swap_2_program_id: Pubkey,
+        concurrency: u8,
+        target_bps: Vec<u16>,
+    },
+    RecordOpportunity {
+        swap_1_program_id: Pubkey,
+        swap_2_program_id: Pubkey,
+        concurrency: u8,
+        temperature: u8,
+        options: TryArbitrageOptions,
+    },
+    UpdateDenylist {
+        add: Vec<(Pubkey, Pubkey)>,
+        remove: Vec<(Pubkey, Pubkey)>,
+    },
+    DebugParse {
+        which: u8,
+        index: u8,
     },
+    TryArbitrageMulti {
+        attempts: Vec<ArbitrageAttempt>,
+        max_total_cpis: u8,
+        pool_check_policy: PoolCheckPolicy,
+    },
+}
+
+#[derive(Debug, Clone, Default)]
+/// TryArbitrageOptions 定义了 TryArbitrage 指令的可选配置
+///
+/// 这些配置紧跟在 `temperature` 之后，以带标签的条目序列化：先是一个 `u8` 条目数，
+/// 随后每个条目为一个 `u8` 字段标签加上该字段的 Borsh 编码。只有不等于默认值的字段才会写出，
+/// 缺省的字段取默认值，默认值保持程序原有的行为。标签按字段声明顺序从 0 开始分配、一经分配不再改变，
+/// 新增字段只追加新的标签；条目必须按标签严格递增排列，未知标签视为格式错误。
+///
+/// # 字段说明
+/// * `allow_same_program` - 是否允许两个swap程序相同（用于同一程序内不同池子之间的套利）
+/// * `verbose` - 是否输出详细日志，用于阈值调优等调试场景
+/// * `min_qualifying_pairs` - 至少需要多少个资产对超过阈值才执行交易（0 与 1 等价）
+/// * `require_canonical_ata` - 是否要求用户的代币账户为 (owner, mint) 推导出的规范 ATA
+/// * `verify_swap_return` - 是否要求每个 swap CPI 都设置了由该 swap 程序标记的非空返回数据
+/// * `decimals_overrides` - 按资产顺序排列的小数位数覆盖值，`Some` 时优先于铸币账户中的小数位数
+///   （例如包装资产的链上小数位数与池子逻辑预期的不一致）；恒定乘积与加权定价中小数位数相互抵消，
+///   有效小数位数只影响 `min_decimals` 的排除与按整币计的 `min_profit`，转账仍使用铸币账户中的值
+/// * `conservative_sizing` - 是否按两个池子中较小的接收预估值确定卖出数量，
+///   即使成交不利也能保住价差；默认按买入池的预估值卖出
+/// * `swap_1_account_order` / `swap_2_account_order` - 发往对应 swap 程序的 CPI 账户重排表，
+///   第 `k` 个账户取默认顺序中下标为 `order[k]` 的账户；为空时使用默认顺序
+/// * `skip_unparseable_mints` - 铸币账户无法解析时，是把该资产排除在扫描之外继续执行，
+///   还是直接中止整个指令（默认）
+/// * `swap_1_fee_offset` / `swap_2_fee_offset` - 手续费率（`u16` 小端序，基点）在对应流动性池
+///   账户数据中的偏移量；为 `None` 时按无手续费定价
+/// * `tie_break` - 多个资产对价差相同时的选择规则，默认选择索引较小的资产对
+/// * `execute_recorded_plan` - 是否只执行 `RecordOpportunity` 记录的计划（用当前余额重新检查），
+///   为 true 时套利计划 PDA 紧跟在四个账户列表之后
+/// * `treasury_fee` - 套利成功后向 treasury 代币账户收取的费用，设置时 treasury 代币账户
+///   （支付资产的代币账户）跟在套利计划 PDA（如有）之后
+/// * `vault_seeds` - 代币账户所有者为本程序 PDA 金库时推导该金库的种子（包含 bump），
+///   设置后由本程序用 `invoke_signed` 代替所有者签名；为空时所有者必须签名交易
+/// * `max_estimate_drift_bps` - 交易后实际换回数量与预估值之间允许的最大偏差（基点），
+///   超过时只输出警告日志，用于发现定价模型与真实 swap 程序之间的偏差；为 `None` 时不比较
+/// * `secondary_tier` - 第一档（`temperature` 阈值、全部余额）没有发现机会时，按更宽松的阈值
+///   和缩小的交易数量重新扫描一次；为 `None` 时只使用第一档
+/// * `report_no_arbitrage` - 没有套利机会时是否成功返回并写入 `NoArbReport` 返回数据，
+///   默认返回 `NoArbitrage` 错误让预检失败
+/// * `min_decimals` - 可信铸币的最少小数位数（应用覆盖值之后），涉及小数位数更少的铸币的资产对
+///   不参与扫描；默认 0 即不限制
+/// * `buy_swap_mode` / `sell_swap_mode` - 买入腿、卖出腿的兑换模式，默认 `ExactIn`（支付固定数量）；
+///   `ExactOut` 时以预估的接收数量作为固定目标、以规划的支付数量作为上限，
+///   用于卖出腿时可以锁定换回的资产 i 数量
+/// * `swap_1_slot_offset` / `swap_2_slot_offset` - 最后更新 slot（`u64` 小端序）在对应流动性池
+///   账户数据中的偏移量；为 `None` 时不检查该池子是否过时
+/// * `max_pool_staleness_slots` - 池子最后更新 slot 允许落后当前 slot 的最大数量，
+///   超过时返回 `StalePool`；为 `None` 时不检查
+/// * `swap_1_weights` / `swap_2_weights` - 对应流动性池为加权池（Balancer 式）时按资产顺序
+///   排列的权重，例如 80/20 池为 `[80, 20]`；为空时按恒定乘积公式定价
+/// * `priority_fee_hint` - 设置时在交易前输出建议的优先费（每 compute unit 的 micro-lamports），
+///   由基础价格加上与预估利润成比例的部分组成
+/// * `use_denylist` - 是否跳过 `denylist_authority` 的程序配置 PDA 黑名单中的资产对，
+///   为 true 时配置 PDA 跟在 treasury 代币账户（如有）之后
+/// * `max_mints` - 最多参与定价的资产数量（与账户数量 `concurrency` 无关），超出的资产
+///   不参与扫描；默认 0 即不限制
+/// * `tx_cost_lamports` - 预估的交易费用（基础费用加优先费，lamports），预估利润按 Swap #1
+///   流动性池的边际价格换算成 lamports 后不足该值时不交易；默认 0 即不检查
+/// * `sol_asset_index` - 换算交易费用时作为参考的包装 SOL 资产索引
+/// * `swap_2_shared_accounts` - 按资产顺序排列的账户复用表，`Some(k)` 表示 Swap #2 该资产的
+///   池子代币账户与 Swap #1 资产 k 的池子代币账户是同一个账户，此时 Swap #2 账户列表省略该项；
+///   该账户按 Swap #1 流动性池校验所有者，持有的铸币必须是 Swap #2 该资产的铸币；
+///   为空时两个池子的代币账户各自按位置传入
+/// * `allow_zero_threshold` - 是否允许 `temperature`（或第二档温度）不小于 100；此时阈值
+///   `100 - temperature` 不大于零，任何非零价差都会触发交易，默认视为配置错误并拒绝
+/// * `paranoid` - 是否在解析完铸币后重新读取池子代币账户余额，与第一次读取不一致时返回
+///   `ReserveMutated`
+/// * `max_acceptable_fee_bps` - 可接受的最高池子手续费率（基点），任一池子超过时在报价之前返回
+///   `PoolFeeTooHigh`（只有两个池子，跳过其中一个就没有可套利的池子对）；为 `None` 时不限制
+/// * `metadata_decimals` - 按资产顺序排列，`Some(offset)` 表示该资产的有效小数位数（`u8`）
+///   记录在单独的元数据账户中的 `offset` 处，优先于铸币账户中的值（`decimals_overrides` 仍然
+///   优先），与 `decimals_overrides` 一样只影响 `min_decimals` 与按整币计的 `min_profit`；
+///   元数据账户按资产顺序跟在其他可选账户之后
+/// * `min_profit` - 往返一次预估至少获得的利润（资产 i 的最小单位），不足时不交易；默认 0 即不限制
+/// * `quote_max_size` - 是否只报价不交易：对选出的资产对搜索仍满足阈值与 `min_profit` 的
+///   最大支付数量，并以 `MaxSizeQuote` 写入返回数据
+/// * `min_profit_whole_tokens` - 是否以整币为单位解释 `min_profit`（按资产 i 的小数位数换算），
+///   例如 1 表示至少 1 USDC，与资产的小数位数无关
+/// * `max_pay_pool_ratio_bps` - 支付数量占流动性池该资产余额（两个池子中较小的一个）的最大比例
+///   （基点，1..=10_000），超过时按该比例截断支付数量；为 `None` 时不限制
+/// * `persist_record` - 是否在交易成功后把 Borsh 编码的 `ArbitrageResult` 通过 CPI 发送给
+///   `record_program`，使记录在本程序日志被清理后仍然保留；日志程序账户跟在程序配置 PDA
+///   （如有）之后
+/// * `record_program` - 接收交易记录的日志程序地址
+/// * `safety_multiple_bps` - 执行交易要求的预估利润相对 `min_profit` 的倍数（基点，例如
+///   15_000 表示 1.5 倍）；未达到时不交易，以 `TradeQuote` 写入返回数据并成功返回
+/// * `min_out_fraction_bps` - 每条交易腿的滑点下限占该腿预估接收数量的比例（基点，1..=10_000，
+///   例如 9_900 表示最多接受低于预估 1%），实际接收数量低于下限时返回 `BelowMinReceive`；
+///   为 `None` 时不检查
+/// * `require_uniform_token_program` - 是否要求两个流动性池的所有代币账户属于同一个代币程序
+///   （SPL Token 或 Token-2022），混用时返回 `MixedTokenPrograms`
+/// * `swap_1_discriminator` / `swap_2_discriminator` - 对应 swap 程序识别指令的方式，默认为
+///   Anchor 的 8 字节哈希；也可以直接给出 8 字节指令标识，非 Anchor 程序可以使用 1 字节或 `u32` 指令标签
+/// * `max_edge_bps` - 价差上限（基点），价差超过它的资产对多半来自陈旧或异常的池子，直接跳过；
+///   `None` 表示不设上限
+/// * `treasury_fee_rounding` - 按比例收取的 treasury 费用的取整方向，默认向下取整（对用户有利）
+/// * `min_residual` - 兑换后两个池子都至少要留下的资产 j 数量，比接收数量不超过池子余额更严格；
+///   不满足时跳过该资产对，0 表示不检查
+/// * `max_cpi_accounts` - 所有 swap CPI 引用的账户总数上限，超过时在发起 CPI 之前失败；
+///   `None` 表示不检查
+/// * `force_direction` - 指定买入池（来自链下信号），设置时跳过按温度阈值的方向判断，
+///   只要该池的报价优于另一个池、且按该方向往返一次预计有利润就交易；`None` 表示由程序判断
+/// * `sweep_dust` - 卖出腿完成后，是否把两条腿没有完全抵消而新增的中间资产转入 dust 收集代币账户；
+///   设置时该账户跟在日志程序（如有）之后
+/// * `triangular` - 设置时改为评估经过 Swap #1、Swap #2 与第三个流动性池的三资产循环，
+///   见 `TriangularConfig`；Swap #3 程序、流动性池与按资产顺序排列的代币账户跟在 dust 收集账户（如有）之后
+/// * `denylist_authority` - 设置了 `use_denylist` 时读取其黑名单的配置 authority，程序配置 PDA
+///   由 `[b"config", denylist_authority]` 推导；设置了 `use_denylist` 时不能为默认公钥
+/// * `omit_associated_token_program` - 设置时账户列表省略固定账户 `associated_token_program`，
+///   其后的账户依次前移；swap CPI 也不再携带该账户，`swap_*_account_order` 相应为 10 项的排列
+/// * `amount_encoding` - 所有 swap 指令数据中数量的字节序，默认为与 Borsh 一致的小端序
+pub struct TryArbitrageOptions {
+    pub allow_same_program: bool,
+    pub verbose: bool,
+    pub min_qualifying_pairs: u8,
+    pub require_canonical_ata: bool,
+    pub verify_swap_return: bool,
+    pub decimals_overrides: Vec<Option<u8>>,
+    pub conservative_sizing: bool,
+    pub swap_1_account_order: Vec<u8>,
+    pub swap_2_account_order: Vec<u8>,
+    pub skip_unparseable_mints: bool,
+    pub swap_1_fee_offset: Option<u32>,
+    pub swap_2_fee_offset: Option<u32>,
+    pub tie_break: arb::TieBreak,
+    pub execute_recorded_plan: bool,
+    pub treasury_fee: Option<treasury::FeeMode>,
+    pub vault_seeds: Vec<Vec<u8>>,
+    pub max_estimate_drift_bps: Option<u16>,
+    pub secondary_tier: Option<arb::SecondaryTier>,
+    pub report_no_arbitrage: bool,
+    pub min_decimals: u8,
+    pub buy_swap_mode: arb::SwapMode,
+    pub sell_swap_mode: arb::SwapMode,
+    pub swap_1_slot_offset: Option<u32>,
+    pub swap_2_slot_offset: Option<u32>,
+    pub max_pool_staleness_slots: Option<u64>,
+    pub swap_1_weights: Vec<u8>,
+    pub swap_2_weights: Vec<u8>,
+    pub priority_fee_hint: Option<arb::PriorityFeeHint>,
+    pub use_denylist: bool,
+    pub max_mints: u8,
+    pub tx_cost_lamports: u64,
+    pub sol_asset_index: u8,
+    pub swap_2_shared_accounts: Vec<Option<u8>>,
+    pub allow_zero_threshold: bool,
+    pub paranoid: bool,
+    pub max_acceptable_fee_bps: Option<u16>,
+    pub metadata_decimals: Vec<Option<u32>>,
+    pub min_profit: u64,
+    pub quote_max_size: bool,
+    pub min_profit_whole_tokens: bool,
+    pub max_pay_pool_ratio_bps: Option<u16>,
+    pub persist_record: bool,
+    pub record_program: Pubkey,
+    pub safety_multiple_bps: Option<u16>,
+    pub min_out_fraction_bps: Option<u16>,
+    pub require_uniform_token_program: bool,
+    pub swap_1_discriminator: arb::DiscriminatorKind,
+    pub swap_2_discriminator: arb::DiscriminatorKind,
+    pub max_edge_bps: Option<u16>,
+    pub treasury_fee_rounding: treasury::FeeRounding,
+    pub min_residual: u64,
+    pub max_cpi_accounts: Option<u16>,
+    pub force_direction: Option<arb::Buy>,
+    pub sweep_dust: bool,
+    pub triangular: Option<triangular::TriangularConfig>,
+    pub denylist_authority: Pubkey,
+    pub omit_associated_token_program: bool,
+    pub amount_encoding: arb::AmountEncoding,
 }
 
-/**
+/// 为 `TryArbitrageOptions` 生成带标签的 Borsh 编码，见其文档
+macro_rules! tagged_options {
+    ($($tag:literal => $field:ident,)*) => {
+        impl BorshSerialize for TryArbitrageOptions {
+            fn serialize<W: std::io::Write>(&self, writer: &mut W) -> std::io::Result<()> {
+                let default = Self::default();
+                let mut entries = Vec::new();
+                $(
+                    let value = borsh::to_vec(&self.$field)?;
+                    if value != borsh::to_vec(&default.$field)? {
+                        entries.push(($tag as u8, value));
+                    }
+                )*
+                (entries.len() as u8).serialize(writer)?;
+                for (tag, value) in entries {
+                    tag.serialize(writer)?;
+                    writer.write_all(&value)?;
+                }
+                Ok(())
+            }
+        }
+
+        impl BorshDeserialize for TryArbitrageOptions {
+            fn deserialize_reader<R: std::io::Read>(reader: &mut R) -> std::io::Result<Self> {
+                let mut options = Self::default();
+                let mut previous: Option<u8> = None;
+                for _ in 0..u8::deserialize_reader(reader)? {
+                    let tag = u8::deserialize_reader(reader)?;
+                    // 严格递增的标签保证同一字段不会出现两次
+                    if previous.is_some_and(|previous| tag <= previous) {
+                        return Err(std::io::Error::new(
+                            std::io::ErrorKind::InvalidData,
+                            format!("option tag {tag} out of order"),
+                        ));
+                    }
+                    previous = Some(tag);
+                    match tag {
+                        $($tag => options.$field = BorshDeserialize::deserialize_reader(reader)?,)*
+                        _ => {
+                            return Err(std::io::Error::new(
+                                std::io::ErrorKind::InvalidData,
+                                format!("unknown option tag {tag}"),
+                            ))
+                        }
+                    }
+                }
+                Ok(options)
+            }
+        }
+    };
+}
+
+tagged_options! {
+    0 => allow_same_program,
+    1 => verbose,
+    2 => min_qualifying_pairs,
+    3 => require_canonical_ata,
+    4 => verify_swap_return,
+    5 => decimals_overrides,
+    6 => conservative_sizing,
+    7 => swap_1_account_order,
+    8 => swap_2_account_order,
+    9 => skip_unparseable_mints,
+    10 => swap_1_fee_offset,
+    11 => swap_2_fee_offset,
+    12 => tie_break,
+    13 => execute_recorded_plan,
+    14 => treasury_fee,
+    15 => vault_seeds,
+    16 => max_estimate_drift_bps,
+    17 => secondary_tier,
+    18 => report_no_arbitrage,
+    19 => min_decimals,
+    20 => buy_swap_mode,
+    21 => sell_swap_mode,
+    22 => swap_1_slot_offset,
+    23 => swap_2_slot_offset,
+    24 => max_pool_staleness_slots,
+    25 => swap_1_weights,
+    26 => swap_2_weights,
+    27 => priority_fee_hint,
+    28 => use_denylist,
+    29 => max_mints,
+    30 => tx_cost_lamports,
+    31 => sol_asset_index,
+    32 => swap_2_shared_accounts,
+    33 => allow_zero_threshold,
+    34 => paranoid,
+    35 => max_acceptable_fee_bps,
+    36 => metadata_decimals,
+    37 => min_profit,
+    38 => quote_max_size,
+    39 => min_profit_whole_tokens,
+    40 => max_pay_pool_ratio_bps,
+    41 => persist_record,
+    42 => record_program,
+    43 => safety_multiple_bps,
+    44 => min_out_fraction_bps,
+    45 => require_uniform_token_program,
+    46 => swap_1_discriminator,
+    47 => swap_2_discriminator,
+    48 => max_edge_bps,
+    49 => treasury_fee_rounding,
+    50 => min_residual,
+    51 => max_cpi_accounts,
+    52 => force_direction,
+    53 => sweep_dust,
+    54 => triangular,
+    55 => denylist_authority,
+    56 => omit_associated_token_program,
+    57 => amount_encoding,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone)]
+/// `TryArbitrageMulti` 中的一次独立套利尝试
+///
+/// # 字段说明
+/// * `swap_1_program_id` / `swap_2_program_id` / `concurrency` / `temperature` / `options` -
+///   与 `TryArbitrage` 的同名字段相同
+/// * `account_count` - 本次尝试的账户段长度，账户段布局与 `TryArbitrage` 的账户列表相同
+pub struct ArbitrageAttempt {
+    pub swap_1_program_id: Pubkey,
+    pub swap_2_program_id: Pubkey,
+    pub concurrency: u8,
+    pub temperature: u8,
+    pub account_count: u16,
+    pub options: TryArbitrageOptions,
+}
+
+/// `TryArbitrageMulti` 中流动性池地址校验（`check_pool_address`）失败时的处理方式
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PoolCheckPolicy {
+    /// 回滚整条指令
+    #[default]
+    Abort,
+    /// 跳过使用该池子的尝试，记录 `InvalidSwapNotEnoughLiquidity` 结果码并继续执行后续尝试
+    Skip,
+}
+
+/*
  * 程序入口点宏调用
  *
  * 该宏将`process`函数注册为程序的入口点。当程序启动时，
@@ -56,7 +450,13 @@ entrypoint!(process);
 ///
 /// # 返回值
 /// * `ProgramResult` - 程序执行结果，成功或错误信息
-fn process(_program_id: &Pubkey, accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+fn process(program_id: &Pubkey, accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+    // 空指令数据与格式错误的指令数据分开报告，便于客户端排查
+    if data.is_empty() {
+        arb_msg!("Instruction data is empty");
+        return Err(ProgramError::InvalidInstructionData);
+    }
+
     // 解析指令数据并根据指令类型执行相应处理
     match ArbitrageProgramInstruction::try_from_slice(data) {
         Ok(ix) => match ix {
@@ -65,14 +465,141 @@ fn process(_program_id: &Pubkey, accounts: &[AccountInfo], data: &[u8]) -> Progr
                 swap_2_program_id,
                 concurrency,
                 temperature,
+                options,
             } => processor::process_arbitrage(
+                program_id,
                 accounts,
                 &swap_1_program_id,
                 &swap_2_program_id,
                 concurrency,
                 temperature,
+                options,
+            ),
+            ArbitrageProgramInstruction::Rebalance {
+                swap_1_program_id,
+                swap_2_program_id,
+                concurrency,
+                target_bps,
+            } => processor::process_rebalance(
+                program_id,
+                accounts,
+                &swap_1_program_id,
+                &swap_2_program_id,
+                concurrency,
+                &target_bps,
+            ),
+            ArbitrageProgramInstruction::RecordOpportunity {
+                swap_1_program_id,
+                swap_2_program_id,
+                concurrency,
+                temperature,
+                options,
+            } => processor::process_record_opportunity(
+                program_id,
+                accounts,
+                &swap_1_program_id,
+                &swap_2_program_id,
+                concurrency,
+                temperature,
+                options,
+            ),
+            ArbitrageProgramInstruction::UpdateDenylist { add, remove } => {
+                config::process_update_denylist(program_id, accounts, &add, &remove)
+            }
+            ArbitrageProgramInstruction::DebugParse { which, index } => {
+                processor::process_debug_parse(accounts, which, index)
+            }
+            ArbitrageProgramInstruction::TryArbitrageMulti {
+                attempts,
+                max_total_cpis,
+                pool_check_policy,
+            } => processor::process_arbitrage_multi(
+                program_id,
+                accounts,
+                attempts,
+                max_total_cpis,
+                pool_check_policy,
             ),
         },
-        Err(_) => Err(ProgramError::InvalidInstructionData),
+        Err(e) => {
+            // 例如 TryArbitrage 缺少 temperature 字节时，Borsh 会报告输入长度不足
+            arb_msg!(
+                "Malformed or truncated instruction data (tag {}, {} bytes): {}",
+                data[0],
+                data.len(),
+                e
+            );
+            Err(ProgramError::InvalidInstructionData)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{install, take_logs, MockSwap, PROGRAM_ID};
+
+    #[test]
+    fn empty_instruction_data_is_reported_as_empty() {
+        install(MockSwap::default());
+        assert_eq!(
+            process(&PROGRAM_ID, &[], &[]),
+            Err(ProgramError::InvalidInstructionData)
+        );
+        assert_eq!(take_logs(), vec!["[ARB] Instruction data is empty"]);
+    }
+
+    #[test]
+    fn truncated_try_arbitrage_is_reported_as_malformed() {
+        // TryArbitrage 标签、两个 swap 程序与 concurrency，缺少 temperature 字节
+        let mut data = vec![0u8];
+        data.extend_from_slice(&[1u8; 32]);
+        data.extend_from_slice(&[2u8; 32]);
+        data.push(2);
+
+        install(MockSwap::default());
+        assert_eq!(
+            process(&PROGRAM_ID, &[], &data),
+            Err(ProgramError::InvalidInstructionData)
+        );
+        let logs = take_logs();
+        assert_eq!(logs.len(), 1);
+        assert!(logs[0]
+            .starts_with("[ARB] Malformed or truncated instruction data (tag 0, 66 bytes): "));
+    }
+
+    #[test]
+    fn default_options_encode_as_zero_entries() {
+        assert_eq!(
+            borsh::to_vec(&TryArbitrageOptions::default()).unwrap(),
+            vec![0]
+        );
+        let options = TryArbitrageOptions::try_from_slice(&[0]).unwrap();
+        assert!(!options.verbose);
+        assert_eq!(options.min_profit, 0);
+    }
+
+    #[test]
+    fn only_non_default_options_are_written_with_their_tags() {
+        let options = TryArbitrageOptions {
+            verbose: true,
+            min_profit: 7,
+            ..Default::default()
+        };
+        let mut expected = vec![2, 1, 1, 37];
+        expected.extend_from_slice(&7u64.to_le_bytes());
+        assert_eq!(borsh::to_vec(&options).unwrap(), expected);
+
+        let decoded = TryArbitrageOptions::try_from_slice(&expected).unwrap();
+        assert!(decoded.verbose);
+        assert_eq!(decoded.min_profit, 7);
+        assert!(!decoded.allow_same_program);
+    }
+
+    #[test]
+    fn unknown_or_repeated_option_tags_are_rejected() {
+        assert!(TryArbitrageOptions::try_from_slice(&[1, 200, 1]).is_err());
+        assert!(TryArbitrageOptions::try_from_slice(&[2, 1, 1, 1, 0]).is_err());
+        assert!(TryArbitrageOptions::try_from_slice(&[2, 1, 1, 0, 1]).is_err());
     }
 }
@@ -1,7 +1,9 @@
+use crate::arb_msg;
 use crate::error::ArbitrageProgramError;
 use bytemuck::{Pod, Zeroable};
-use solana_program::{account_info::AccountInfo, msg, program_error::ProgramError, pubkey::Pubkey};
+use solana_program::{account_info::AccountInfo, program_error::ProgramError, pubkey::Pubkey};
 use spl_pod::optional_keys::OptionalNonZeroPubkey;
+use spl_token_2022::extension::StateWithExtensions;
 
 #[repr(C)]
 #[derive(Debug, Default, Copy, Clone, Pod, Zeroable)]
@@ -35,6 +37,8 @@ impl PartialTokenAccountState {
     /// 尝试从账户信息中反序列化代币账户数据
     ///
     /// 该函数验证账户数据的长度和所有者，并尝试将其解析为代币账户信息。
+    /// Token-2022 账户（例如带 immutable-owner 扩展的账户）先按扩展布局完整解析，
+    /// 长度不足 72 字节之外的畸形扩展账户也会被拒绝。
     ///
     /// # 参数
     /// * `account_info` - 要反序列化的账户信息引用
@@ -48,7 +52,7 @@ impl PartialTokenAccountState {
     ) -> Result<ArbitrageTokenAccountInfo<'a, 'b>, ProgramError> {
         // 验证账户数据长度是否足够（至少72字节）
         if account_info.data_len() < 72 {
-            msg!(
+            arb_msg!(
                 "Data too small. Should be 72 bytes. Found len: {}",
                 account_info.data_len()
             );
@@ -56,26 +60,38 @@ impl PartialTokenAccountState {
             return Err(ArbitrageProgramError::InvalidAccountsList.into());
         }
 
-        // 尝试将账户数据的前72字节转换为代币账户结构
-        match bytemuck::try_from_bytes::<Self>(&account_info.data.borrow()[..72]) {
-            Ok(partial_token) => {
-                // 验证账户所有者是否匹配
-                if !partial_token.owner.eq(owner) {
-                    msg!("Owner mismatch");
-                    msg!("Expected: {}", owner);
-                    msg!("Got:      {}", partial_token.owner);
-                    msg!("Token Account: {}", account_info.key);
+        let (mint, token_owner, amount) = if account_info.owner.eq(&spl_token_2022::ID) {
+            // Token-2022 账户按扩展布局完整解析，字段取自解析出的基础账户状态
+            match StateWithExtensions::<spl_token_2022::state::Account>::unpack(
+                &account_info.data.borrow(),
+            ) {
+                Ok(state) => (state.base.mint, state.base.owner, state.base.amount),
+                Err(_) => {
+                    arb_msg!("Malformed Token-2022 account: {}", account_info.key);
                     return Err(ArbitrageProgramError::InvalidAccountsList.into());
                 }
-                Ok((
-                    account_info,
+            }
+        } else {
+            // 尝试将账户数据的前72字节转换为代币账户结构
+            match bytemuck::try_from_bytes::<Self>(&account_info.data.borrow()[..72]) {
+                Ok(partial_token) => (
                     partial_token.mint,
                     partial_token.owner,
                     partial_token.amount,
-                ))
+                ),
+                Err(_) => return Err(ArbitrageProgramError::InvalidAccountsList.into()),
             }
-            Err(_) => Err(ArbitrageProgramError::InvalidAccountsList.into()),
+        };
+
+        // 验证账户所有者是否匹配
+        if !token_owner.eq(owner) {
+            arb_msg!("Owner mismatch");
+            arb_msg!("Expected: {}", owner);
+            arb_msg!("Got:      {}", token_owner);
+            arb_msg!("Token Account: {}", account_info.key);
+            return Err(ArbitrageProgramError::InvalidAccountsList.into());
         }
+        Ok((account_info, mint, token_owner, amount))
     }
 }
 
@@ -95,7 +111,6 @@ pub struct PartialMintState {
 
 /// 仲裁铸币信息类型别名
 ///
-
 /// 定义一个用于套利挖矿信息的类型别名
 ///
 /// 该类型别名用于表示套利挖矿相关的账户信息和权限级别
@@ -124,38 +139,156 @@ impl PartialMintState {
     pub fn try_deserialize<'a, 'b>(
         account_info: &'a AccountInfo<'b>,
     ) -> Result<ArbitrageMintInfo<'a, 'b>, ProgramError> {
-        // 检查账户数据长度是否足够（至少41字节）
-        if account_info.data_len() < 41 {
-            msg!(
+        // 获取前41字节的数据用于反序列化，账户数据长度不足时返回错误而不是 panic
+        let account_data = account_info.data.borrow();
+        let Some(data) = account_data.get(..41) else {
+            arb_msg!(
                 "Data too small. Should be 41 bytes. Found len: {}",
                 account_info.data_len()
             );
 
             return Err(ArbitrageProgramError::InvalidAccountsList.into());
-        }
-
-        // 获取前41字节的数据用于反序列化
-        let data = &account_info.data.borrow()[..41];
+        };
 
         // 尝试反序列化前40字节为主要结构体数据
-        match bytemuck::try_from_bytes::<Self>(&data[..40]) {
-            Ok(_) => {
+        match data.get(..40).map(bytemuck::try_from_bytes::<Self>) {
+            Some(Ok(_)) => {
                 // 从第41字节获取小数位数
                 let decimals = match data.get(40) {
                     Some(d) => *d,
                     None => {
-                        msg!("Could not get decimals");
-                        msg!("Mint: {}", account_info.key);
+                        arb_msg!("Could not get decimals");
+                        arb_msg!("Mint: {}", account_info.key);
                         return Err(ArbitrageProgramError::InvalidAccountsList.into());
                     }
                 };
                 Ok((account_info, decimals))
             }
-            Err(_) => {
-                msg!("Failed to deserialize mint account");
-                msg!("Mint: {}", account_info.key);
-                return Err(ArbitrageProgramError::InvalidAccountsList.into());
+            _ => {
+                arb_msg!("Failed to deserialize mint account");
+                arb_msg!("Mint: {}", account_info.key);
+                Err(ArbitrageProgramError::InvalidAccountsList.into())
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{account, install, mint_account, take_logs, MockSwap};
+    use solana_program::program_pack::Pack;
+    use spl_token_2022::extension::{
+        immutable_owner::ImmutableOwner, BaseStateWithExtensionsMut, ExtensionType,
+        StateWithExtensionsMut,
+    };
+
+    #[test]
+    fn mint_decimals_are_read_from_byte_40() {
+        let mint = mint_account(9);
+        assert_eq!(PartialMintState::try_deserialize(&mint).map(|m| m.1), Ok(9));
+
+        // 41 字节正好足够解析
+        let mut data = vec![0; 41];
+        data[40] = 6;
+        let short = account(
+            Pubkey::new_unique(),
+            spl_token::ID,
+            data,
+            false,
+            false,
+            false,
+        );
+        assert_eq!(
+            PartialMintState::try_deserialize(&short).map(|m| m.1),
+            Ok(6)
+        );
+    }
+
+    #[test]
+    fn forty_byte_mint_is_rejected_without_panicking() {
+        install(MockSwap::default());
+        let mint = account(
+            Pubkey::new_unique(),
+            spl_token::ID,
+            vec![0; 40],
+            false,
+            false,
+            false,
+        );
+
+        assert_eq!(
+            PartialMintState::try_deserialize(&mint).map(|m| m.1),
+            Err(ArbitrageProgramError::InvalidAccountsList.into())
+        );
+        assert_eq!(
+            take_logs(),
+            vec!["[ARB] Data too small. Should be 41 bytes. Found len: 40"]
+        );
+    }
+
+    /// 带 immutable-owner 扩展、由 Token-2022 持有的已初始化代币账户
+    fn immutable_owner_account(mint: &Pubkey, owner: &Pubkey, amount: u64) -> AccountInfo<'static> {
+        let len = ExtensionType::try_calculate_account_len::<spl_token_2022::state::Account>(&[
+            ExtensionType::ImmutableOwner,
+        ])
+        .unwrap();
+        let mut data = vec![0; len];
+        let mut state =
+            StateWithExtensionsMut::<spl_token_2022::state::Account>::unpack_uninitialized(
+                &mut data,
+            )
+            .unwrap();
+        state.init_extension::<ImmutableOwner>(true).unwrap();
+        state.base = spl_token_2022::state::Account {
+            mint: *mint,
+            owner: *owner,
+            amount,
+            state: spl_token_2022::state::AccountState::Initialized,
+            ..Default::default()
+        };
+        state.pack_base();
+        state.init_account_type().unwrap();
+        account(
+            Pubkey::new_unique(),
+            spl_token_2022::ID,
+            data,
+            false,
+            true,
+            false,
+        )
+    }
+
+    #[test]
+    fn immutable_owner_account_is_parsed_from_the_base_state() {
+        let (mint, owner) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let token = immutable_owner_account(&mint, &owner, 5_000);
+        assert!(token.data_len() > spl_token_2022::state::Account::LEN);
+
+        let (_, parsed_mint, parsed_owner, amount) =
+            PartialTokenAccountState::try_deserialize(&token, &owner).unwrap();
+        assert_eq!((parsed_mint, parsed_owner, amount), (mint, owner, 5_000));
+        assert_eq!(
+            PartialTokenAccountState::try_deserialize(&token, &Pubkey::new_unique()).map(|t| t.3),
+            Err(ArbitrageProgramError::InvalidAccountsList.into())
+        );
+    }
+
+    #[test]
+    fn malformed_extended_token_2022_account_is_rejected() {
+        install(MockSwap::default());
+        let owner = Pubkey::new_unique();
+        let token = immutable_owner_account(&Pubkey::new_unique(), &owner, 5_000);
+        // 账户类型字节既不是未初始化也不是代币账户
+        token.data.borrow_mut()[spl_token_2022::state::Account::LEN] = 7;
+
+        assert_eq!(
+            PartialTokenAccountState::try_deserialize(&token, &owner).map(|t| t.3),
+            Err(ArbitrageProgramError::InvalidAccountsList.into())
+        );
+        assert_eq!(
+            take_logs(),
+            vec![format!("[ARB] Malformed Token-2022 account: {}", token.key)]
+        );
+    }
+}
@@ -1,61 +1,778 @@
+use std::cell::Cell;
+use std::ops::Range;
+use std::rc::Rc;
+
 use solana_program::{
     account_info::{next_account_info, AccountInfo},
     entrypoint::ProgramResult,
+    program_error::ProgramError,
     pubkey::Pubkey,
 };
-use spl_associated_token_account::tools::account;
 
-use crate::arb::{try_arbitrage, TryArbitrageArgs};
+use crate::arb::{try_arbitrage, PoolFees, TryArbitrageArgs, SWAP_CPI_ACCOUNTS};
+use crate::arb_msg;
+use crate::config::read_config;
+use crate::error::ArbitrageProgramError;
 use crate::partial_state::{PartialMintState, PartialTokenAccountState};
-use crate::util::check_pool_address;
+use crate::rebalance::{check_rebalance_targets, rebalance};
+use crate::record::{read_record, record_opportunity};
+use crate::result::{set_return_data, MultiArbitrageResult, ParsedAccount};
+use crate::triangular::parse_swap_3_accounts;
+use crate::util::{
+    check_account_permutation, check_canonical_ata, check_distinct_pool_mints,
+    check_distinct_token_accounts, check_pool_address, check_pool_freshness,
+    check_reserves_unchanged, check_vault_owner, check_writable, read_metadata_decimals,
+    read_pool_fee_bps, ArbtrageEvaluateOption,
+};
+use crate::{ArbitrageAttempt, PoolCheckPolicy, TryArbitrageOptions};
+
+/// `TryArbitrage` 指令固定部分的账户顺序
+///
+/// 固定账户之后依次是四个长度为 `concurrency` 的账户列表：
+/// 用户代币账户、Swap #1 池子代币账户、Swap #2 池子代币账户、铸币账户。
+/// 设置了 `swap_2_shared_accounts` 时，Swap #2 账户列表省略复用 Swap #1 账户的资产；
+/// 设置了 `omit_associated_token_program` 时省略 `associated_token_program`，其后的账户依次前移。
+/// 可选账户按以下顺序紧跟在四个账户列表之后（从下标 `expected_account_count` 开始）：
+/// 套利计划 PDA（`RecordOpportunity`，或设置了 `execute_recorded_plan` 时）、
+/// treasury 代币账户（设置了 `treasury_fee` 时）、程序配置 PDA（设置了 `use_denylist` 时）、
+/// 日志程序（设置了 `persist_record` 时）、dust 收集代币账户（设置了 `sweep_dust` 时）、
+/// Swap #3 程序、流动性池与 `concurrency` 个代币账户（设置了 `triangular` 时），最后是 `metadata_decimals` 中为 `Some` 的资产的元数据账户（按资产顺序）。
+pub const ACCOUNT_LAYOUT: &[&str] = &[
+    "payer",
+    "owner",
+    "token_program",
+    "system_program",
+    "associated_token_program",
+    "swap_1_program",
+    "swap_2_program",
+    "swap_1_pool",
+    "swap_2_pool",
+];
+
+/// 固定账户之后按资产重复的账户列表数量
+pub const PER_ASSET_ACCOUNT_LISTS: usize = 4;
+
+/// 账户列表相对 `ACCOUNT_LAYOUT` 的完整布局省略的账户
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct OmittedAccounts {
+    /// 是否省略了固定账户 `associated_token_program`
+    pub associated_token_program: bool,
+    /// 复用 Swap #1 账户、因而从 Swap #2 账户列表中省略的数量，见 `check_shared_accounts`
+    pub shared_accounts: usize,
+}
+
+impl OmittedAccounts {
+    /// 只省略了 `shared_accounts` 个 Swap #2 账户
+    pub fn shared(shared_accounts: usize) -> Self {
+        Self {
+            shared_accounts,
+            ..Self::default()
+        }
+    }
+
+    /// 按指令的配置确定省略的账户，并校验 Swap #2 的账户复用表
+    pub fn from_options(
+        options: &TryArbitrageOptions,
+        concurrency: u8,
+    ) -> Result<Self, ProgramError> {
+        Ok(Self {
+            associated_token_program: options.omit_associated_token_program,
+            shared_accounts: check_shared_accounts(&options.swap_2_shared_accounts, concurrency)?,
+        })
+    }
+
+    /// 实际传入的固定账户名称，按账户列表中的顺序排列
+    pub fn fixed_layout(&self) -> impl Iterator<Item = &'static str> + '_ {
+        ACCOUNT_LAYOUT
+            .iter()
+            .copied()
+            .filter(|name| !self.associated_token_program || *name != "associated_token_program")
+    }
+
+    /// 实际传入的固定账户数量
+    pub fn fixed_accounts(&self) -> usize {
+        ACCOUNT_LAYOUT.len() - usize::from(self.associated_token_program)
+    }
+
+    /// 每个 swap CPI 实际携带的账户数量，省略的关联代币程序也不会转发给 swap 程序
+    pub fn swap_cpi_accounts(&self) -> usize {
+        SWAP_CPI_ACCOUNTS - usize::from(self.associated_token_program)
+    }
+}
+
+/// 计算给定 `concurrency` 下指令至少需要的账户数量
+///
+/// `omitted` 为相对完整布局省略的账户。
+pub fn expected_account_count(concurrency: u8, omitted: OmittedAccounts) -> usize {
+    omitted.fixed_accounts() + PER_ASSET_ACCOUNT_LISTS * usize::from(concurrency)
+        - omitted.shared_accounts
+}
+
+/// 计算四个按资产重复的账户列表在账户切片中的下标范围
+///
+/// 依次为用户代币账户、Swap #1 池子代币账户、Swap #2 池子代币账户、铸币账户；
+/// Swap #2 列表比其他列表短 `omitted.shared_accounts` 项。
+pub fn account_segments(
+    concurrency: u8,
+    omitted: OmittedAccounts,
+) -> [Range<usize>; PER_ASSET_ACCOUNT_LISTS] {
+    let len = usize::from(concurrency);
+    let mut start = omitted.fixed_accounts();
+    std::array::from_fn(|k| {
+        let segment_len = if k == 2 {
+            len - omitted.shared_accounts
+        } else {
+            len
+        };
+        let segment = start..start + segment_len;
+        start = segment.end;
+        segment
+    })
+}
+
+/// 检查 Swap #2 的账户复用表并返回省略的账户数量
+///
+/// 复用表为空，或按资产顺序为每个资产给出一项，且引用的 Swap #1 资产索引都在范围内。
+///
+/// # 错误
+/// * `ProgramError::InvalidInstructionData` - 当长度与 `concurrency` 不一致或索引越界时返回
+pub fn check_shared_accounts(
+    shared: &[Option<u8>],
+    concurrency: u8,
+) -> Result<usize, ProgramError> {
+    if !shared.is_empty()
+        && (shared.len() != usize::from(concurrency)
+            || shared.iter().flatten().any(|&k| k >= concurrency))
+    {
+        arb_msg!(
+            "Shared account map {:?} must have {} entries referencing assets below {}",
+            shared,
+            concurrency,
+            concurrency
+        );
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    Ok(shared.iter().flatten().count())
+}
+
+/// 检查按资产重复的账户都没有与固定账户使用相同的地址
+///
+/// 用户代币账户、池子代币账户或铸币账户与支付方、程序或流动性池相同，说明账户列表是伪造的。
+///
+/// # 错误
+/// * `ArbitrageProgramError::AliasedFixedAccount` - 当存在这样的账户时返回，附带第一个这样的账户的下标
+pub fn check_fixed_account_aliasing(
+    accounts: &[AccountInfo],
+    concurrency: u8,
+    omitted: OmittedAccounts,
+) -> Result<(), AccountOrderingError> {
+    let fixed_accounts = omitted.fixed_accounts();
+    let (fixed, rest) = accounts.split_at(fixed_accounts.min(accounts.len()));
+    let per_asset = rest
+        .get(..expected_account_count(concurrency, omitted) - fixed_accounts)
+        .unwrap_or(rest);
+    for (index, account) in per_asset.iter().enumerate() {
+        if let Some(name) = fixed
+            .iter()
+            .zip(omitted.fixed_layout())
+            .find_map(|(f, name)| f.key.eq(account.key).then_some(name))
+        {
+            let index = fixed_accounts + index;
+            arb_msg!(
+                "Account {} at index {} aliases fixed account `{}`",
+                account.key,
+                index,
+                name
+            );
+            return Err(AccountOrderingError::new(
+                index,
+                ArbitrageProgramError::AliasedFixedAccount,
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// `validate_account_ordering` 发现的第一个不符合布局的账户
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AccountOrderingError {
+    /// 第一个不符合布局的账户下标；账户数量不足时为缺少的第一个下标，
+    /// 资产数量本身无效时为按资产重复的账户列表的起始下标
+    pub index: usize,
+    /// 对应的程序错误，`TryArbitrage` 返回的就是它
+    pub error: ProgramError,
+}
+
+impl AccountOrderingError {
+    fn new(index: usize, error: ArbitrageProgramError) -> Self {
+        Self {
+            index,
+            error: error.into(),
+        }
+    }
+}
+
+impl From<AccountOrderingError> for ProgramError {
+    fn from(e: AccountOrderingError) -> Self {
+        e.error
+    }
+}
+
+/// 确认账户列表符合 `ACCOUNT_LAYOUT` 描述的布局，供 `TryArbitrage` 与客户端工具共用
+///
+/// 依次检查账户数量、四个账户列表的范围、按资产重复的账户没有冒用固定账户，
+/// 以及三个代币账户列表中的账户都属于代币程序（SPL Token 或 Token-2022）。
+/// 只检查布局本身，不解析账户数据；铸币列表交给 `skip_unparseable_mints` 的策略处理。
+/// 第一个不符合的下标随错误一起返回，并写入日志。
+///
+/// # 参数
+/// * `accounts` - 指令的账户列表
+/// * `concurrency` - 资产数量，客户端工具可以直接传入账户列表推算出的数量
+///
+/// # 错误
+/// * `ArbitrageProgramError::InvalidAccountsList` - 资产数量超过指令支持的 `u8` 范围、账户数量不足、
+///   列表重叠或账户不属于代币程序
+/// * `ArbitrageProgramError::AliasedFixedAccount` - 按资产重复的账户冒用了固定账户
+pub fn validate_account_ordering(
+    accounts: &[AccountInfo],
+    concurrency: u16,
+) -> Result<(), AccountOrderingError> {
+    validate_partial_account_ordering(accounts, concurrency, OmittedAccounts::default())
+}
+
+/// 与 `validate_account_ordering` 相同，但账户列表省略了 `omitted` 中的账户：
+/// 复用 Swap #1 的 Swap #2 账户（见 `check_shared_accounts`）或关联代币程序
+pub fn validate_partial_account_ordering(
+    accounts: &[AccountInfo],
+    concurrency: u16,
+    omitted: OmittedAccounts,
+) -> Result<(), AccountOrderingError> {
+    let invalid =
+        |index| AccountOrderingError::new(index, ArbitrageProgramError::InvalidAccountsList);
+    let fixed_accounts = omitted.fixed_accounts();
+    let Ok(concurrency) = u8::try_from(concurrency) else {
+        arb_msg!("Concurrency {} exceeds {}", concurrency, u8::MAX);
+        return Err(invalid(fixed_accounts));
+    };
+    let expected_accounts = expected_account_count(concurrency, omitted);
+    if accounts.len() < expected_accounts {
+        arb_msg!(
+            "Expected at least {} accounts for concurrency {}, got {}",
+            expected_accounts,
+            concurrency,
+            accounts.len()
+        );
+        return Err(invalid(accounts.len()));
+    }
+    check_account_segments(&account_segments(concurrency, omitted), fixed_accounts)
+        .map_err(|_| invalid(fixed_accounts))?;
+    check_fixed_account_aliasing(accounts, concurrency, omitted)?;
+    for (index, account) in accounts
+        .iter()
+        .enumerate()
+        .take(expected_accounts - usize::from(concurrency))
+        .skip(fixed_accounts)
+    {
+        if !account.owner.eq(&spl_token::ID) && !account.owner.eq(&spl_token_2022::ID) {
+            arb_msg!(
+                "Account {} at index {} is owned by {}, not a token program",
+                account.key,
+                index,
+                account.owner
+            );
+            return Err(invalid(index));
+        }
+    }
+    Ok(())
+}
+
+/// 检查按资产重复的账户列表都位于前 `fixed_accounts` 个固定账户之后，且彼此按顺序排列、互不重叠
+///
+/// # 错误
+/// * `ArbitrageProgramError::InvalidAccountsList` - 当某个列表与固定账户或前一个列表重叠时返回
+pub fn check_account_segments(segments: &[Range<usize>], fixed_accounts: usize) -> ProgramResult {
+    let mut next_free = fixed_accounts;
+    for (k, segment) in segments.iter().enumerate() {
+        if segment.start < next_free || segment.end < segment.start {
+            arb_msg!(
+                "Account segment {} ({:?}) overlaps accounts before index {}",
+                k,
+                segment,
+                next_free
+            );
+            return Err(ArbitrageProgramError::InvalidAccountsList.into());
+        }
+        next_free = segment.end;
+    }
+    Ok(())
+}
 
 /// 处理套利交易逻辑的主函数。
 ///
 /// 该函数解析传入的账户信息，验证交易池地址，并为用户、两个交易池准备代币账户和铸币信息，
 /// 最终调用 `try_arbitrage` 执行实际的套利操作。
 ///
+/// 设置了 `execute_recorded_plan` 时，先读取并校验套利计划 PDA，只执行其中记录的计划；
+/// 设置了 `use_denylist` 时，从程序配置 PDA 读取资产对黑名单。
+///
 /// # 参数说明
-/// - `accounts`: 包含所有相关账户信息的切片，用于交易和状态读取。
-/// - `swap_1_program_id`: 第一个去中心化交易所（DEX）的程序 ID。
-/// - `swap_2_program_id`: 第二个去中心化交易所（DEX）的程序 ID。
-/// - `concurrency`: 并行处理的代币对数量。
-/// - `temperature`: 控制套利行为的参数（具体含义由业务逻辑定义）。
+/// - `program_id`: 本程序 ID，用于校验套利计划 PDA。
+/// - 其余参数与 `parse_arbitrage_accounts` 相同。
 ///
 /// # 返回值
 /// 返回 `ProgramResult`，表示操作是否成功执行。
 pub fn process_arbitrage(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    swap_1_program_id: &Pubkey,
+    swap_2_program_id: &Pubkey,
+    concurrency: u8,
+    temperature: u8,
+    options: TryArbitrageOptions,
+) -> ProgramResult {
+    process_counted_arbitrage(
+        program_id,
+        accounts,
+        swap_1_program_id,
+        swap_2_program_id,
+        concurrency,
+        temperature,
+        options,
+        &Rc::default(),
+    )
+}
+
+/// 与 `process_arbitrage` 相同，并把发起的 CPI 数量累加到 `cpi_count`
+#[allow(clippy::too_many_arguments)]
+fn process_counted_arbitrage(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    swap_1_program_id: &Pubkey,
+    swap_2_program_id: &Pubkey,
+    concurrency: u8,
+    temperature: u8,
+    options: TryArbitrageOptions,
+    cpi_count: &Rc<Cell<u16>>,
+) -> ProgramResult {
+    let execute_recorded_plan = options.execute_recorded_plan;
+    let collect_fee = options.treasury_fee.is_some();
+    let use_denylist = options.use_denylist.then_some(options.denylist_authority);
+    let persist_record = options.persist_record.then_some(options.record_program);
+    let sweep_dust = options.sweep_dust;
+    let triangular = options.triangular;
+    let omitted = OmittedAccounts::from_options(&options, concurrency)?;
+    let trailing_accounts = usize::from(execute_recorded_plan)
+        + usize::from(collect_fee)
+        + usize::from(use_denylist.is_some())
+        + usize::from(persist_record.is_some())
+        + usize::from(sweep_dust)
+        + triangular.map_or(0, |_| 2 + usize::from(concurrency));
+    let mut args = parse_arbitrage_accounts(
+        program_id,
+        accounts,
+        swap_1_program_id,
+        swap_2_program_id,
+        concurrency,
+        temperature,
+        options,
+        trailing_accounts,
+    )?;
+    args.cpi_count = Rc::clone(cpi_count);
+    // swap CPI 会修改两个流动性池账户
+    check_writable(args.swap_1_pool, "Swap #1 pool")?;
+    check_writable(args.swap_2_pool, "Swap #2 pool")?;
+    // swap CPI 会从用户代币账户扣款或入账，报告第一个不可写账户的资产索引
+    if let Some((index, (account, _, _, _))) = args
+        .token_accounts_user
+        .iter()
+        .enumerate()
+        .find(|(_, (account, _, _, _))| !account.is_writable)
+    {
+        arb_msg!(
+            "User token account {} (asset {}) must be writable",
+            account.key,
+            index
+        );
+        return Err(ArbitrageProgramError::AccountNotWritable.into());
+    }
+    let trailing = &mut accounts
+        .iter()
+        .skip(expected_account_count(concurrency, omitted));
+    if execute_recorded_plan {
+        args.recorded_plan = Some(read_record(
+            program_id,
+            next_trailing_account(trailing, "opportunity record")?,
+            args.owner.key,
+            args.swap_1_pool.key,
+            args.swap_2_pool.key,
+        )?);
+    }
+    if collect_fee {
+        args.treasury = Some(next_trailing_account(trailing, "treasury")?);
+    }
+    if let Some(authority) = use_denylist {
+        let config = read_config(
+            program_id,
+            next_trailing_account(trailing, "config")?,
+            &authority,
+        )?;
+        args.denied_pairs = config.denied_indices(&args.mints);
+    }
+    if let Some(record_program_id) = persist_record {
+        let record_program = next_trailing_account(trailing, "record program")?;
+        if !record_program.key.eq(&record_program_id) || !record_program.executable {
+            arb_msg!(
+                "Record program: expected executable {}, got {}",
+                record_program_id,
+                record_program.key
+            );
+            return Err(ArbitrageProgramError::InvalidAccountsList.into());
+        }
+        args.record_program = Some(record_program);
+    }
+    if sweep_dust {
+        let dust_collector = next_trailing_account(trailing, "dust collector")?;
+        check_writable(dust_collector, "Dust collector")?;
+        args.dust_collector = Some(dust_collector);
+    }
+    if let Some(config) = triangular {
+        let mints: Vec<Pubkey> = args.mints.iter().map(|(mint, _)| *mint.key).collect();
+        let swap_3 = parse_swap_3_accounts(trailing, config, &mints)?;
+        check_pool_address(&config.swap_3_program_id, swap_3.pool.key)?;
+        check_writable(swap_3.pool, "Swap #3 pool")?;
+        check_distinct_token_accounts(swap_3.pool.key, &swap_3.token_accounts)?;
+        args.swap_3 = Some(swap_3);
+    }
+    try_arbitrage(args)
+}
+
+/// 处理记录套利机会的指令。
+///
+/// 账户布局与 `TryArbitrage` 相同，套利计划 PDA 紧跟在四个账户列表之后。
+/// 该指令只扫描并记录，不接受会在四个账户列表之后追加其他可选账户的选项
+/// （`execute_recorded_plan`、`treasury_fee`、`use_denylist`、`persist_record`、
+/// `sweep_dust`、`triangular`），元数据账户因此总是紧跟在套利计划 PDA 之后。
+///
+/// # 参数说明
+/// - `program_id`: 本程序 ID，用于推导套利计划 PDA。
+/// - 其余参数与 `parse_arbitrage_accounts` 相同。
+///
+/// # 返回值
+/// 返回 `ProgramResult`，表示操作是否成功执行。
+pub fn process_record_opportunity(
+    program_id: &Pubkey,
     accounts: &[AccountInfo],
     swap_1_program_id: &Pubkey,
     swap_2_program_id: &Pubkey,
     concurrency: u8,
     temperature: u8,
+    options: TryArbitrageOptions,
+) -> ProgramResult {
+    if options.execute_recorded_plan
+        || options.treasury_fee.is_some()
+        || options.use_denylist
+        || options.persist_record
+        || options.sweep_dust
+        || options.triangular.is_some()
+    {
+        arb_msg!("RecordOpportunity does not accept options that add trailing accounts");
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    let omitted = OmittedAccounts::from_options(&options, concurrency)?;
+    let args = parse_arbitrage_accounts(
+        program_id,
+        accounts,
+        swap_1_program_id,
+        swap_2_program_id,
+        concurrency,
+        temperature,
+        options,
+        1,
+    )?;
+    let trailing = &mut accounts
+        .iter()
+        .skip(expected_account_count(concurrency, omitted));
+    record_opportunity(
+        program_id,
+        args,
+        next_trailing_account(trailing, "opportunity record")?,
+    )
+}
+
+/// 按 `ACCOUNT_LAYOUT` 描述的顺序取出下一个紧跟在四个账户列表之后的可选账户
+fn next_trailing_account<'a, 'b, I: Iterator<Item = &'a AccountInfo<'b>>>(
+    trailing: &mut I,
+    name: &str,
+) -> Result<&'a AccountInfo<'b>, ProgramError>
+where
+    'b: 'a,
+{
+    trailing.next().ok_or_else(|| {
+        arb_msg!("Missing the {} account", name);
+        ProgramError::NotEnoughAccountKeys
+    })
+}
+
+/// 处理库存再平衡指令。
+///
+/// 账户布局与 `TryArbitrage` 相同（见 `ACCOUNT_LAYOUT`），解析与校验逻辑也完全共用，
+/// 随后调用 `rebalance` 把持仓向 `target_bps` 描述的目标配比调整。
+///
+/// # 参数说明
+/// - `program_id`: 本程序 ID。
+/// - `accounts`: 与 `TryArbitrage` 布局相同的账户列表。
+/// - `swap_1_program_id`: 第一个去中心化交易所（DEX）的程序 ID。
+/// - `swap_2_program_id`: 第二个去中心化交易所（DEX）的程序 ID。
+/// - `concurrency`: 参与再平衡的资产数量。
+/// - `target_bps`: 按资产顺序排列的目标配比（基点），总和必须为 10_000。
+///
+/// # 返回值
+/// 返回 `ProgramResult`，表示操作是否成功执行。
+pub fn process_rebalance(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    swap_1_program_id: &Pubkey,
+    swap_2_program_id: &Pubkey,
+    concurrency: u8,
+    target_bps: &[u16],
 ) -> ProgramResult {
+    check_rebalance_targets(target_bps, concurrency)?;
+    rebalance(
+        parse_arbitrage_accounts(
+            program_id,
+            accounts,
+            swap_1_program_id,
+            swap_2_program_id,
+            concurrency,
+            0,
+            TryArbitrageOptions::default(),
+            0,
+        )?,
+        target_bps,
+    )
+}
+
+/// 解析并校验 `ACCOUNT_LAYOUT` 描述的账户列表。
+///
+/// 该函数验证交易池地址，并为用户、两个交易池准备代币账户和铸币信息。
+///
+/// `payer` 只负责支付交易费用，用户代币账户的所有者由单独的 `owner` 账户表示，
+/// 两者可以是同一个账户。
+///
+/// # 参数说明
+/// - `program_id`: 本程序 ID，用于校验 PDA 金库所有者。
+/// - `accounts`: 包含所有相关账户信息的切片，用于交易和状态读取。
+/// - `swap_1_program_id`: 第一个去中心化交易所（DEX）的程序 ID。
+/// - `swap_2_program_id`: 第二个去中心化交易所（DEX）的程序 ID。
+/// - `concurrency`: 并行处理的代币对数量。
+/// - `temperature`: 交易的激进程度，价差百分比超过 `100 - temperature` 时才交易，数值越大越激进。
+/// - `options`: 指令携带的可选配置，见 `TryArbitrageOptions`。
+/// - `trailing_accounts`: 元数据账户之前的可选账户数量（套利计划 PDA、treasury 等）。
+///
+/// # 返回值
+/// 返回解析后的 `TryArbitrageArgs`。
+#[allow(clippy::too_many_arguments)]
+fn parse_arbitrage_accounts<'a, 'b>(
+    program_id: &Pubkey,
+    accounts: &'a [AccountInfo<'b>],
+    swap_1_program_id: &Pubkey,
+    swap_2_program_id: &Pubkey,
+    concurrency: u8,
+    temperature: u8,
+    options: TryArbitrageOptions,
+    trailing_accounts: usize,
+) -> Result<TryArbitrageArgs<'a, 'b>, ProgramError> {
+    // 客户端忘记设置程序 ID 时会得到全零公钥，尽早给出明确的错误，
+    // 而不是让 `check_pool_address` 针对零地址推导池子 PDA 失败
+    for (name, program_id) in [
+        ("Swap #1", swap_1_program_id),
+        ("Swap #2", swap_2_program_id),
+    ] {
+        if *program_id == Pubkey::default() {
+            arb_msg!("{} program ID is the default pubkey", name);
+            return Err(ArbitrageProgramError::UnsetSwapProgramId.into());
+        }
+    }
+
+    // 两个 swap 程序相同时套利没有意义，除非显式允许同一程序内的池子间套利
+    if swap_1_program_id == swap_2_program_id && !options.allow_same_program {
+        arb_msg!("Swap program: {}", swap_1_program_id);
+        return Err(ArbitrageProgramError::IdenticalSwapPrograms.into());
+    }
+
+    // 比例收费不能超过 100%
+    if options.treasury_fee.is_some_and(|mode| !mode.is_valid()) {
+        arb_msg!("Invalid treasury fee {:?}", options.treasury_fee);
+        return Err(ProgramError::InvalidInstructionData);
+    }
+
+    // 阈值为 `100 - temperature`，温度达到 100 时任何非零价差都会触发交易，几乎一定是配置错误
+    for tier_temperature in
+        std::iter::once(temperature).chain(options.secondary_tier.map(|tier| tier.temperature))
+    {
+        if tier_temperature >= 100 {
+            if !options.allow_zero_threshold {
+                arb_msg!(
+                    "Temperature {} leaves no arbitrage threshold; set allow_zero_threshold to proceed",
+                    tier_temperature
+                );
+                return Err(ProgramError::InvalidInstructionData);
+            }
+            arb_msg!(
+                "WARNING: temperature {} leaves no arbitrage threshold, any price difference trades",
+                tier_temperature
+            );
+        }
+    }
+
+    // 第二档的交易数量比例必须在 (0, 100%] 之间
+    if options.secondary_tier.is_some_and(|tier| !tier.is_valid()) {
+        arb_msg!("Invalid secondary tier {:?}", options.secondary_tier);
+        return Err(ProgramError::InvalidInstructionData);
+    }
+
+    // 持久化交易记录时必须指定日志程序
+    if options.persist_record && options.record_program == Pubkey::default() {
+        arb_msg!("persist_record is set but the record program is the default pubkey");
+        return Err(ProgramError::InvalidInstructionData);
+    }
+
+    // 使用黑名单时必须指定配置的 authority
+    if options.use_denylist && options.denylist_authority == Pubkey::default() {
+        arb_msg!("use_denylist is set but the denylist authority is the default pubkey");
+        return Err(ProgramError::InvalidInstructionData);
+    }
+
+    // 支付数量占池子余额的比例上限必须在 (0, 100%] 之间
+    if options
+        .max_pay_pool_ratio_bps
+        .is_some_and(|ratio_bps| !(1..=10_000).contains(&ratio_bps))
+    {
+        arb_msg!(
+            "Invalid pay/pool ratio {:?}",
+            options.max_pay_pool_ratio_bps
+        );
+        return Err(ProgramError::InvalidInstructionData);
+    }
+
+    // 滑点下限占预估值的比例必须在 (0, 100%] 之间
+    if options
+        .min_out_fraction_bps
+        .is_some_and(|fraction_bps| !(1..=10_000).contains(&fraction_bps))
+    {
+        arb_msg!(
+            "Invalid min-out fraction {:?}",
+            options.min_out_fraction_bps
+        );
+        return Err(ProgramError::InvalidInstructionData);
+    }
+
+    // 换算交易费用时参考的 SOL 资产必须在资产列表中
+    if options.tx_cost_lamports > 0 && options.sol_asset_index >= concurrency {
+        arb_msg!(
+            "SOL asset index {} is out of range for concurrency {}",
+            options.sol_asset_index,
+            concurrency
+        );
+        return Err(ProgramError::InvalidInstructionData);
+    }
+
+    // 加权池的权重必须为每个资产给出一个非零值
+    for weights in [&options.swap_1_weights, &options.swap_2_weights] {
+        if !weights.is_empty()
+            && (weights.len() != usize::from(concurrency) || weights.contains(&0))
+        {
+            arb_msg!(
+                "Pool weights {:?} must have {} non-zero entries",
+                weights,
+                concurrency
+            );
+            return Err(ProgramError::InvalidInstructionData);
+        }
+    }
+
+    // 账户列表必须符合 `ACCOUNT_LAYOUT` 描述的布局
+    let omitted = OmittedAccounts::from_options(&options, concurrency)?;
+    validate_partial_account_ordering(accounts, u16::from(concurrency), omitted)?;
+
+    // 账户重排表必须是 swap CPI 账户的合法排列
+    check_account_permutation(
+        swap_1_program_id,
+        &options.swap_1_account_order,
+        omitted.swap_cpi_accounts(),
+    )?;
+    check_account_permutation(
+        swap_2_program_id,
+        &options.swap_2_account_order,
+        omitted.swap_cpi_accounts(),
+    )?;
+
+    // 以下解析顺序与 `ACCOUNT_LAYOUT` 保持一致
     let accounts_iter = &mut accounts.iter();
     let payer = next_account_info(accounts_iter)?;
+    let owner = next_account_info(accounts_iter)?;
     let token_program = next_account_info(accounts_iter)?;
     let system_program = next_account_info(accounts_iter)?;
-    let associated_token_program = next_account_info(accounts_iter)?;
+    let associated_token_program = if omitted.associated_token_program {
+        None
+    } else {
+        Some(next_account_info(accounts_iter)?)
+    };
     let swap_1_program = next_account_info(accounts_iter)?;
     let swap_2_program = next_account_info(accounts_iter)?;
     let swap_1_pool = next_account_info(accounts_iter)?;
     let swap_2_pool = next_account_info(accounts_iter)?;
 
+    // 代币账户的所有者（交易授权方）可以与手续费支付方不同，但必须签名；
+    // 所有者是本程序的 PDA 金库时，由本程序用金库种子代为签名
+    if !options.vault_seeds.is_empty() {
+        check_vault_owner(program_id, owner.key, &options.vault_seeds)?;
+    } else if !owner.is_signer {
+        arb_msg!("Owner {} must sign the transaction", owner.key);
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
     // 验证两个交易池的地址是否与指定的程序 ID 匹配
     check_pool_address(swap_1_program_id, swap_1_pool.key)?;
     check_pool_address(swap_2_program_id, swap_2_pool.key)?;
 
+    // 按配置的偏移量从池子账户数据中读取手续费率
+    let fees = PoolFees {
+        swap_1_fee_bps: read_pool_fee_bps(swap_1_pool, options.swap_1_fee_offset)?,
+        swap_2_fee_bps: read_pool_fee_bps(swap_2_pool, options.swap_2_fee_offset)?,
+    };
+
+    // 长时间没有更新的池子价格可能已经过时，不与其交易
+    check_pool_freshness(
+        swap_1_pool,
+        options.swap_1_slot_offset,
+        options.max_pool_staleness_slots,
+    )?;
+    check_pool_freshness(
+        swap_2_pool,
+        options.swap_2_slot_offset,
+        options.max_pool_staleness_slots,
+    )?;
+
     // 解析用户相关的代币账户状态
     let token_accounts_user = {
         let mut accts = vec![];
         for _x in 0..concurrency {
             accts.push(PartialTokenAccountState::try_deserialize(
                 next_account_info(accounts_iter)?,
-                payer.key,
+                owner.key,
             )?);
         }
         accts
     };
 
+    // 要求用户的代币账户（同时也是接收账户）必须是规范的 ATA
+    if options.require_canonical_ata {
+        for (account, mint, owner, _) in &token_accounts_user {
+            check_canonical_ata(account, owner, mint)?;
+        }
+    }
+
     // 解析第一个交易池相关的代币账户状态
     let token_accounts_swap_1 = {
         let mut accts = vec![];
@@ -68,36 +785,164 @@ pub fn process_arbitrage(
         accts
     };
 
-    // 解析第二个交易池相关的代币账户状态
+    // 解析第二个交易池相关的代币账户状态，复用的账户直接取自 Swap #1 的列表：
+    // 它已经按 Swap #1 流动性池校验过所有者，持有的铸币在解析铸币账户之后校验
     let token_accounts_swap_2 = {
         let mut accts = vec![];
-        for _x in 0..concurrency {
-            accts.push(PartialTokenAccountState::try_deserialize(
-                next_account_info(accounts_iter)?,
-                swap_2_pool.key,
-            )?);
+        for x in 0..usize::from(concurrency) {
+            let Some(k) = options.swap_2_shared_accounts.get(x).copied().flatten() else {
+                accts.push(PartialTokenAccountState::try_deserialize(
+                    next_account_info(accounts_iter)?,
+                    swap_2_pool.key,
+                )?);
+                continue;
+            };
+            accts.push(*token_accounts_swap_1.get(usize::from(k)).ok_or_arb_err()?);
         }
         accts
     };
 
-    // 解析所有涉及的铸币信息
+    // 同一个池子的代币账户不能重复，否则池子余额会被重复计算
+    check_distinct_token_accounts(swap_1_pool.key, &token_accounts_swap_1)?;
+    check_distinct_token_accounts(swap_2_pool.key, &token_accounts_swap_2)?;
+    // 同一个池子的代币账户也不能对应同一个铸币
+    check_distinct_pool_mints(swap_1_pool.key, &token_accounts_swap_1)?;
+    check_distinct_pool_mints(swap_2_pool.key, &token_accounts_swap_2)?;
+    // 两条腿混用 SPL Token 与 Token-2022 可能导致难以排查的失败
+    if options.require_uniform_token_program {
+        let mut pool_accounts = token_accounts_swap_1.iter().chain(&token_accounts_swap_2);
+        if let Some((first, _, _, _)) = pool_accounts.next() {
+            if let Some((account, _, _, _)) =
+                pool_accounts.find(|(account, _, _, _)| !account.owner.eq(first.owner))
+            {
+                arb_msg!(
+                    "Pool token account {} is owned by {}, but {} is owned by {}",
+                    account.key,
+                    account.owner,
+                    first.key,
+                    first.owner
+                );
+                return Err(ArbitrageProgramError::MixedTokenPrograms.into());
+            }
+        }
+    }
+
+    // 解析所有涉及的铸币信息，指令中的小数位数覆盖值优先于铸币账户中的值
+    let mut excluded_assets = vec![];
     let mints = {
         let mut accts = vec![];
-        for _x in 0..concurrency {
-            accts.push(PartialMintState::try_deserialize(next_account_info(
-                accounts_iter,
-            )?)?);
+        for x in 0..usize::from(concurrency) {
+            let mint_account = next_account_info(accounts_iter)?;
+            let (mint, decimals) = match PartialMintState::try_deserialize(mint_account) {
+                Ok(parsed) => parsed,
+                // 按配置的策略把无法解析的资产排除在扫描之外，保留占位以维持资产索引
+                Err(_) if options.skip_unparseable_mints => {
+                    arb_msg!(
+                        "Mint {} (asset {}) could not be parsed, excluding it",
+                        mint_account.key,
+                        x
+                    );
+                    excluded_assets.push(x);
+                    accts.push((mint_account, 0));
+                    continue;
+                }
+                Err(e) => return Err(e),
+            };
+            // 元数据账户中的小数位数优先于铸币账户中的值
+            let decimals = match options.metadata_decimals.get(x).copied().flatten() {
+                Some(offset) => {
+                    let position = expected_account_count(concurrency, omitted)
+                        + trailing_accounts
+                        + options.metadata_decimals[..x].iter().flatten().count();
+                    let metadata = accounts
+                        .get(position)
+                        .ok_or(ProgramError::NotEnoughAccountKeys)?;
+                    let metadata_decimals = read_metadata_decimals(metadata, offset)?;
+                    arb_msg!(
+                        "Mint {}: decimals {} -> {} from metadata {}",
+                        mint.key,
+                        decimals,
+                        metadata_decimals,
+                        metadata.key
+                    );
+                    metadata_decimals
+                }
+                None => decimals,
+            };
+            let decimals = match options.decimals_overrides.get(x).copied().flatten() {
+                Some(override_decimals) => {
+                    arb_msg!(
+                        "Mint {}: overriding decimals {} -> {}",
+                        mint.key,
+                        decimals,
+                        override_decimals
+                    );
+                    override_decimals
+                }
+                None => decimals,
+            };
+            // 小数位数过少的铸币换算精度太差，涉及它的资产对都不参与扫描
+            if decimals < options.min_decimals {
+                arb_msg!(
+                    "Mint {} (asset {}) has {} decimals, below the minimum {}, excluding it",
+                    mint.key,
+                    x,
+                    decimals,
+                    options.min_decimals
+                );
+                excluded_assets.push(x);
+            }
+            accts.push((mint, decimals));
         }
         accts
     };
 
-    // 调用核心套利逻辑函数
-    try_arbitrage(TryArbitrageArgs {
+    // 复用的账户必须持有 Swap #2 对应资产的铸币
+    for (x, k) in options.swap_2_shared_accounts.iter().enumerate() {
+        let (Some(k), Some((shared, mint, _, _)), Some((mint_account, _))) =
+            (k, token_accounts_swap_2.get(x), mints.get(x))
+        else {
+            continue;
+        };
+        if !mint.eq(mint_account.key) {
+            arb_msg!(
+                "Shared token account {} (Swap #1 asset {}) holds mint {}, which is not asset {}",
+                shared.key,
+                k,
+                mint,
+                x
+            );
+            return Err(ArbitrageProgramError::InvalidAccountsList.into());
+        }
+    }
+
+    // 偏执模式：解析完铸币后再读一次池子余额，防范异常的 CPI 重入在指令执行中途修改余额
+    if options.paranoid {
+        check_reserves_unchanged(swap_1_pool.key, &token_accounts_swap_1)?;
+        // 复用的账户属于 Swap #1 流动性池，已经随 Swap #1 的账户一起检查
+        let own_accounts: Vec<_> = token_accounts_swap_2
+            .iter()
+            .enumerate()
+            .filter(|(x, _)| {
+                options
+                    .swap_2_shared_accounts
+                    .get(*x)
+                    .copied()
+                    .flatten()
+                    .is_none()
+            })
+            .map(|(_, account)| *account)
+            .collect();
+        check_reserves_unchanged(swap_2_pool.key, &own_accounts)?;
+    }
+
+    Ok(TryArbitrageArgs {
         token_accounts_user,
         token_accounts_swap_1,
         token_accounts_swap_2,
         mints,
         payer,
+        owner,
         token_program,
         system_program,
         associated_token_program,
@@ -105,6 +950,1705 @@ pub fn process_arbitrage(
         swap_2_program,
         swap_1_pool,
         swap_2_pool,
+        excluded_assets,
+        fees,
         temperature,
+        options,
+        recorded_plan: None,
+        treasury: None,
+        denied_pairs: vec![],
+        record_program: None,
+        dust_collector: None,
+        swap_3: None,
+        cpi_count: Rc::default(),
     })
 }
+
+/// 处理调试账户解析的指令。
+///
+/// 对 `accounts[index]` 运行与 `TryArbitrage` 相同的解析逻辑，并以 `ParsedAccount`
+/// 写入返回数据，便于排查生产环境中的账户布局问题。代币账户的所有者校验使用
+/// 账户数据中记录的所有者，因此只检查数据格式。
+///
+/// # 参数说明
+/// - `accounts`: 任意账户列表。
+/// - `which`: 0 按代币账户解析，1 按铸币账户解析。
+/// - `index`: 待解析账户在 `accounts` 中的下标。
+///
+/// # 返回值
+/// 返回 `ProgramResult`；`which` 未知时返回 `ProgramError::InvalidInstructionData`。
+pub fn process_debug_parse(accounts: &[AccountInfo], which: u8, index: u8) -> ProgramResult {
+    let account = accounts.get(usize::from(index)).ok_or_else(|| {
+        arb_msg!("No account at index {}", index);
+        ProgramError::NotEnoughAccountKeys
+    })?;
+    let parsed = match which {
+        0 => {
+            // 所有者位于代币账户数据的第 32..64 字节
+            let owner = account
+                .try_borrow_data()?
+                .get(32..64)
+                .and_then(|bytes| Pubkey::try_from(bytes).ok())
+                .unwrap_or_default();
+            let (_, mint, owner, amount) =
+                PartialTokenAccountState::try_deserialize(account, &owner)?;
+            ParsedAccount::TokenAccount {
+                mint,
+                owner,
+                amount,
+            }
+        }
+        1 => {
+            let (_, decimals) = PartialMintState::try_deserialize(account)?;
+            // 数据长度已由 `try_deserialize` 校验
+            let supply =
+                bytemuck::try_from_bytes::<PartialMintState>(&account.try_borrow_data()?[..40])
+                    .map_err(|_| ProgramError::InvalidAccountData)?
+                    .supply;
+            ParsedAccount::Mint { decimals, supply }
+        }
+        _ => {
+            arb_msg!("Unknown DebugParse kind {}", which);
+            return Err(ProgramError::InvalidInstructionData);
+        }
+    };
+    arb_msg!("Parsed account {}: {:?}", account.key, parsed);
+    set_return_data(&parsed)
+}
+
+/// 处理在一条指令中执行多次独立套利尝试的指令。
+///
+/// 每次尝试按 `account_count` 依次切出自己的账户段，并按 `TryArbitrage` 处理。
+/// 返回 `NoArbitrage` 或 `PoolFeeTooHigh` 的尝试在发起任何 CPI 之前就已结束，可以安全地跳过而不影响其他尝试；
+/// 其他错误可能发生在部分交易腿执行之后，此时必须回滚整条指令。
+///
+/// # 参数说明
+/// - `program_id`: 本程序 ID。
+/// - `accounts`: 所有尝试的账户段，按尝试顺序首尾相接。
+/// - `attempts`: 按执行顺序排列的套利尝试。
+/// - `max_total_cpis`: 所有尝试合计最多发起的 CPI 数量，0 表示不限制。下一次尝试最多可能发起的
+///   CPI（见 `max_attempt_cpis`）会使总数超过上限时，不再执行它及其后的尝试。
+/// - `pool_check_policy`: 为 `Skip` 时先校验每次尝试的两个流动性池地址，不匹配的尝试在执行之前跳过。
+///
+/// # 返回值
+/// 返回 `ProgramResult`；成功时以 `MultiArbitrageResult` 写入每次已执行尝试的结果码，
+/// 因 CPI 上限而未执行的尝试没有结果码。
+/// 只返回结果码，每次尝试自己写入的返回数据会被覆盖。
+/// 账户段总长度与账户数量不一致时返回 `ArbitrageProgramError::InvalidAccountsList`。
+pub fn process_arbitrage_multi(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    attempts: Vec<ArbitrageAttempt>,
+    max_total_cpis: u8,
+    pool_check_policy: PoolCheckPolicy,
+) -> ProgramResult {
+    let mut remaining = accounts;
+    let mut codes = Vec::with_capacity(attempts.len());
+    let cpi_count = Rc::new(Cell::new(0));
+    let total = attempts.len();
+    for (k, attempt) in attempts.into_iter().enumerate() {
+        let needed = max_attempt_cpis(&attempt.options);
+        if max_total_cpis > 0 && cpi_count.get() + needed > u16::from(max_total_cpis) {
+            arb_msg!(
+                "CPI cap {} reached after {} CPI(s), skipping attempts {}..{}",
+                max_total_cpis,
+                cpi_count.get(),
+                k,
+                total
+            );
+            return set_return_data(&MultiArbitrageResult { codes });
+        }
+        let Some((segment, rest)) = remaining.split_at_checked(usize::from(attempt.account_count))
+        else {
+            arb_msg!(
+                "Attempt {} needs {} accounts, only {} left",
+                k,
+                attempt.account_count,
+                remaining.len()
+            );
+            return Err(ArbitrageProgramError::InvalidAccountsList.into());
+        };
+        remaining = rest;
+        if pool_check_policy == PoolCheckPolicy::Skip {
+            if let Some(pool) = mismatched_pool(segment, &attempt) {
+                arb_msg!(
+                    "Attempt {} skipped: {} is not the liquidity pool of its swap program",
+                    k,
+                    pool
+                );
+                codes.push(ArbitrageProgramError::InvalidSwapNotEnoughLiquidity as u32);
+                continue;
+            }
+        }
+        let code = match process_counted_arbitrage(
+            program_id,
+            segment,
+            &attempt.swap_1_program_id,
+            &attempt.swap_2_program_id,
+            attempt.concurrency,
+            attempt.temperature,
+            attempt.options,
+            &cpi_count,
+        ) {
+            Ok(()) => 0,
+            // 不变量：`NoArbitrage` 与 `PoolFeeTooHigh` 只会在发起任何 CPI（swap、转账、创建账户）
+            // 之前返回，该尝试没有修改任何账户，跳过它不会留下只执行了一半的交易。
+            // 在 CPI 之后返回这两个错误会破坏这一点，新增的失败路径必须使用其他错误
+            Err(ProgramError::Custom(code))
+                if code == ArbitrageProgramError::NoArbitrage as u32 =>
+            {
+                arb_msg!("Attempt {} found no arbitrage", k);
+                code
+            }
+            Err(ProgramError::Custom(code))
+                if code == ArbitrageProgramError::PoolFeeTooHigh as u32 =>
+            {
+                arb_msg!("Attempt {} skipped: a pool's fee exceeds the maximum", k);
+                code
+            }
+            Err(e) => return Err(e),
+        };
+        codes.push(code);
+    }
+    if !remaining.is_empty() {
+        arb_msg!("{} account(s) left after the last attempt", remaining.len());
+        return Err(ArbitrageProgramError::InvalidAccountsList.into());
+    }
+    set_return_data(&MultiArbitrageResult { codes })
+}
+
+/// 返回账户段中第一个不是对应 swap 程序池子 PDA 的流动性池地址；
+/// 账户段过短时返回 `None`，交由 `process_arbitrage` 报告
+fn mismatched_pool<'a>(
+    segment: &'a [AccountInfo],
+    attempt: &ArbitrageAttempt,
+) -> Option<&'a Pubkey> {
+    // 两个流动性池是最后两个固定账户
+    let swap_2_pool =
+        ACCOUNT_LAYOUT.len() - 1 - usize::from(attempt.options.omit_associated_token_program);
+    [
+        (segment.get(swap_2_pool - 1)?, &attempt.swap_1_program_id),
+        (segment.get(swap_2_pool)?, &attempt.swap_2_program_id),
+    ]
+    .into_iter()
+    .find(|(pool, program_id)| check_pool_address(program_id, pool.key).is_err())
+    .map(|(pool, _)| pool.key)
+}
+
+/// 一次套利尝试最多可能发起的 CPI 数量：两条（三角套利为三条）交易腿，
+/// 以及 treasury 费用、dust 归集与交易记录各一次
+fn max_attempt_cpis(options: &TryArbitrageOptions) -> u16 {
+    let legs = if options.triangular.is_some() { 3 } else { 2 };
+    legs + u16::from(options.treasury_fee.is_some())
+        + u16::from(options.sweep_dust)
+        + u16::from(options.persist_record)
+}
+
+#[cfg(test)]
+mod tests {
+    use borsh::BorshDeserialize;
+
+    use super::*;
+    use crate::error::ArbitrageProgramError;
+    use crate::result::{NoArbReport, SkipReason};
+    use crate::testing::{
+        account, install, program_account, return_data, set_slot, set_token_amount, take_cpis,
+        take_logs, token_account, token_amount, Market, MarketAccounts, MockSwap, PROGRAM_ID,
+    };
+
+    /// 两个池子报价相同、用户持有资产 0 的市场
+    fn flat_market() -> Market {
+        Market::new(&[1_000, 0], &[1_000_000; 2], &[1_000_000; 2])
+    }
+
+    #[test]
+    fn identical_swap_programs_are_rejected() {
+        let mut market = flat_market();
+        market.swap_2_program_id = market.swap_1_program_id;
+        install(market.mock_swap());
+        let accounts = market.build();
+
+        assert_eq!(
+            accounts.try_arbitrage(90, TryArbitrageOptions::default()),
+            Err(ArbitrageProgramError::IdenticalSwapPrograms.into())
+        );
+        assert!(take_cpis().is_empty());
+    }
+
+    #[test]
+    fn allow_same_program_scans_pools_of_one_program() {
+        let mut market = flat_market();
+        market.swap_2_program_id = market.swap_1_program_id;
+        install(market.mock_swap());
+        let accounts = market.build();
+        let options = TryArbitrageOptions {
+            allow_same_program: true,
+            ..TryArbitrageOptions::default()
+        };
+
+        // 通过了程序检查，两个池子报价相同因而没有套利机会
+        assert_eq!(
+            accounts.try_arbitrage(90, options),
+            Err(ArbitrageProgramError::NoArbitrage.into())
+        );
+    }
+
+    #[test]
+    fn require_canonical_ata_rejects_non_canonical_user_accounts() {
+        let market = flat_market();
+        install(market.mock_swap());
+        let accounts = market.build();
+        let options = TryArbitrageOptions {
+            require_canonical_ata: true,
+            ..TryArbitrageOptions::default()
+        };
+
+        assert_eq!(
+            accounts.try_arbitrage(90, options),
+            Err(ArbitrageProgramError::NonCanonicalTokenAccount.into())
+        );
+    }
+
+    #[test]
+    fn duplicated_pool_token_account_is_rejected() {
+        let market = flat_market();
+        install(market.mock_swap());
+        let mut accounts = market.build();
+        // Swap #1 的资产 1 复用了资产 0 的池子代币账户
+        let duplicate = accounts.swap_1(0).clone();
+        let position = ACCOUNT_LAYOUT.len() + accounts.concurrency + 1;
+        accounts.accounts[position] = duplicate;
+
+        assert_eq!(
+            accounts.try_arbitrage(90, TryArbitrageOptions::default()),
+            Err(ArbitrageProgramError::DuplicateTokenAccount.into())
+        );
+    }
+
+    /// 在 Swap #1 买入资产 1、在 Swap #2 卖出的市场
+    fn skewed_market() -> Market {
+        Market::new(&[1_000, 0], &[1_000_000, 2_000_000], &[1_000_000; 2])
+    }
+
+    #[test]
+    fn distinct_owner_authorizes_the_swaps() {
+        let market = skewed_market();
+        install(market.mock_swap());
+        let accounts = market.build();
+        let (payer, owner) = (*accounts.accounts[0].key, *accounts.owner().key);
+        assert_ne!(payer, owner);
+
+        assert_eq!(
+            accounts.try_arbitrage(90, TryArbitrageOptions::default()),
+            Ok(())
+        );
+        for cpi in take_cpis() {
+            // `leg_cpi` 的第 8 个账户是代币账户的所有者
+            assert_eq!(cpi.accounts[7].pubkey, owner);
+            assert!(cpi.accounts.iter().all(|meta| meta.pubkey != payer));
+        }
+    }
+
+    #[test]
+    fn owner_must_sign() {
+        let market = skewed_market();
+        install(market.mock_swap());
+        let mut accounts = market.build();
+        accounts.accounts[1].is_signer = false;
+
+        assert_eq!(
+            accounts.try_arbitrage(90, TryArbitrageOptions::default()),
+            Err(ProgramError::MissingRequiredSignature)
+        );
+    }
+
+    #[test]
+    fn user_token_accounts_must_belong_to_the_owner_not_the_payer() {
+        let market = skewed_market();
+        install(market.mock_swap());
+        let mut accounts = market.build();
+        accounts.accounts.swap(0, 1);
+
+        assert_eq!(
+            accounts.try_arbitrage(90, TryArbitrageOptions::default()),
+            Err(ArbitrageProgramError::InvalidAccountsList.into())
+        );
+        assert!(take_cpis().is_empty());
+    }
+
+    #[test]
+    fn decimals_override_takes_precedence_over_the_mint() {
+        // 恒定乘积定价中小数位数相互抵消，覆盖值通过按整币计的 `min_profit` 影响是否交易：
+        // 预估利润 994 个最小单位，铸币的 6 位小数下不足 1 个整币，覆盖为 2 位小数后超过
+        let market = skewed_market();
+        let whole_token = TryArbitrageOptions {
+            min_profit: 1,
+            min_profit_whole_tokens: true,
+            ..TryArbitrageOptions::default()
+        };
+
+        install(market.mock_swap());
+        assert_eq!(
+            market.build().try_arbitrage(90, whole_token.clone()),
+            Err(ArbitrageProgramError::NoArbitrage.into())
+        );
+        assert!(take_cpis().is_empty());
+
+        install(market.mock_swap());
+        let options = TryArbitrageOptions {
+            decimals_overrides: vec![Some(2), None],
+            ..whole_token
+        };
+        let accounts = market.build();
+        assert_eq!(accounts.try_arbitrage(90, options), Ok(()));
+        assert_eq!(take_cpis().len(), 2);
+        let expected = format!(
+            "[ARB] Mint {}: overriding decimals 6 -> 2",
+            accounts.mint(0).key
+        );
+        assert!(take_logs().contains(&expected));
+    }
+
+    #[test]
+    fn one_instruction_executes_at_most_one_pair() {
+        // 资产对 (0,1) 与 (0,2) 都超过阈值，仍然只执行一次往返（两个 CPI）
+        let market = Market::new(
+            &[1_000, 0, 0],
+            &[1_000_000, 2_000_000, 2_000_000],
+            &[1_000_000; 3],
+        );
+        install(market.mock_swap());
+        let accounts = market.build();
+
+        assert_eq!(
+            accounts.try_arbitrage(90, TryArbitrageOptions::default()),
+            Ok(())
+        );
+        assert_eq!(take_cpis().len(), 2);
+    }
+
+    #[test]
+    fn account_count_is_nine_plus_four_per_asset() {
+        assert_eq!(ACCOUNT_LAYOUT.len(), 9);
+        for concurrency in [0u8, 1, 2, 5, u8::MAX] {
+            assert_eq!(
+                expected_account_count(concurrency, OmittedAccounts::default()),
+                9 + 4 * usize::from(concurrency)
+            );
+        }
+        // 复用的 Swap #2 账户从列表中省略
+        assert_eq!(
+            expected_account_count(3, OmittedAccounts::shared(2)),
+            9 + 4 * 3 - 2
+        );
+        assert_eq!(
+            account_segments(3, OmittedAccounts::shared(2)),
+            [9..12, 12..15, 15..16, 16..19]
+        );
+    }
+
+    #[test]
+    fn missing_account_is_rejected() {
+        let market = flat_market();
+        install(market.mock_swap());
+        let mut accounts = market.build();
+        assert_eq!(
+            accounts.accounts.len(),
+            expected_account_count(2, OmittedAccounts::default())
+        );
+        accounts.accounts.pop();
+
+        assert_eq!(
+            accounts.try_arbitrage(90, TryArbitrageOptions::default()),
+            Err(ArbitrageProgramError::InvalidAccountsList.into())
+        );
+    }
+
+    /// 资产对 (0,1) 超过阈值、资产 2 的铸币账户无法解析的市场
+    fn market_with_unparseable_mint() -> MarketAccounts {
+        let market = Market::new(
+            &[1_000, 0, 0],
+            &[1_000_000, 2_000_000, 1_000_000],
+            &[1_000_000; 3],
+        );
+        install(market.mock_swap());
+        let mut accounts = market.build();
+        let index = expected_account_count(3, OmittedAccounts::default()) - 1;
+        accounts.accounts[index] = account(
+            *accounts.mint(2).key,
+            spl_token::id(),
+            vec![0; 3],
+            false,
+            false,
+            false,
+        );
+        accounts
+    }
+
+    #[test]
+    fn unparseable_mint_aborts_by_default() {
+        let accounts = market_with_unparseable_mint();
+
+        assert_eq!(
+            accounts.try_arbitrage(90, TryArbitrageOptions::default()),
+            Err(ArbitrageProgramError::InvalidAccountsList.into())
+        );
+        assert!(take_cpis().is_empty());
+    }
+
+    #[test]
+    fn skip_unparseable_mints_excludes_the_asset() {
+        let accounts = market_with_unparseable_mint();
+        let options = TryArbitrageOptions {
+            skip_unparseable_mints: true,
+            ..TryArbitrageOptions::default()
+        };
+
+        assert_eq!(accounts.try_arbitrage(90, options), Ok(()));
+        assert_eq!(take_cpis().len(), 2);
+        let expected = format!(
+            "[ARB] Mint {} (asset 2) could not be parsed, excluding it",
+            accounts.mint(2).key
+        );
+        assert!(take_logs().contains(&expected));
+    }
+
+    #[test]
+    fn trades_never_create_token_accounts() {
+        let market = skewed_market();
+        install(market.mock_swap());
+        let accounts = market.build();
+
+        assert_eq!(
+            accounts.try_arbitrage(90, TryArbitrageOptions::default()),
+            Ok(())
+        );
+        let programs: Vec<_> = take_cpis().iter().map(|cpi| cpi.program_id).collect();
+        assert_eq!(
+            programs,
+            vec![accounts.swap_1_program_id, accounts.swap_2_program_id]
+        );
+    }
+
+    #[test]
+    fn missing_user_token_account_is_rejected_not_created() {
+        let market = skewed_market();
+        install(market.mock_swap());
+        let mut accounts = market.build();
+        let index = ACCOUNT_LAYOUT.len() + 1;
+        let key = *accounts.user(1).key;
+        accounts.accounts[index] = account(
+            key,
+            solana_system_interface::program::ID,
+            vec![],
+            false,
+            true,
+            false,
+        );
+
+        assert_eq!(
+            accounts.try_arbitrage(90, TryArbitrageOptions::default()),
+            Err(ArbitrageProgramError::InvalidAccountsList.into())
+        );
+        assert!(take_cpis().is_empty());
+    }
+
+    #[test]
+    fn default_swap_program_id_is_rejected() {
+        for (swap, name) in [(1, "Swap #1"), (2, "Swap #2")] {
+            let mut market = flat_market();
+            if swap == 1 {
+                market.swap_1_program_id = Pubkey::default();
+            } else {
+                market.swap_2_program_id = Pubkey::default();
+            }
+            install(market.mock_swap());
+
+            assert_eq!(
+                market
+                    .build()
+                    .try_arbitrage(90, TryArbitrageOptions::default()),
+                Err(ArbitrageProgramError::UnsetSwapProgramId.into())
+            );
+            assert!(take_cpis().is_empty());
+            let expected = format!("[ARB] {name} program ID is the default pubkey");
+            assert!(take_logs().contains(&expected));
+        }
+    }
+
+    #[test]
+    fn computed_segments_do_not_overlap() {
+        for (concurrency, shared) in [(0, 0), (1, 0), (3, 2), (u8::MAX, 7)] {
+            assert_eq!(
+                check_account_segments(
+                    &account_segments(concurrency, OmittedAccounts::shared(shared)),
+                    ACCOUNT_LAYOUT.len()
+                ),
+                Ok(())
+            );
+        }
+    }
+
+    #[test]
+    fn miscomputed_layout_overlap_is_caught() {
+        // 忘记计入 owner 账户、从下标 8 开始的布局与固定的 swap_2_pool 重叠
+        let off_by_one = [8..10, 10..12, 12..14, 14..16];
+        assert_eq!(
+            check_account_segments(&off_by_one, ACCOUNT_LAYOUT.len()),
+            Err(ArbitrageProgramError::InvalidAccountsList.into())
+        );
+        // 相邻列表重叠
+        let overlapping = [9..11, 10..12, 12..14, 14..16];
+        assert_eq!(
+            check_account_segments(&overlapping, ACCOUNT_LAYOUT.len()),
+            Err(ArbitrageProgramError::InvalidAccountsList.into())
+        );
+    }
+
+    #[test]
+    fn repeated_mint_within_a_pool_is_rejected() {
+        let market = flat_market();
+        install(market.mock_swap());
+        let mut accounts = market.build();
+        // Swap #2 的第二个代币账户也持有资产 0
+        let (mint_0, pool) = (*accounts.mint(0).key, *accounts.accounts[8].key);
+        let index = account_segments(2, OmittedAccounts::default())[2].start + 1;
+        accounts.accounts[index] = token_account(&mint_0, &pool, 1_000_000, &spl_token::ID);
+
+        assert_eq!(
+            accounts.try_arbitrage(90, TryArbitrageOptions::default()),
+            Err(ArbitrageProgramError::DuplicatePoolMint.into())
+        );
+        let expected = format!("[ARB] Mint {mint_0} is used for assets 0 and 1");
+        assert!(take_logs().contains(&expected));
+    }
+
+    /// 只有资产对 (0,1) 超过阈值，资产 1 的铸币没有小数位
+    fn zero_decimal_opportunity() -> Market {
+        let mut market = Market::new(
+            &[1_000, 0, 0],
+            &[1_000_000, 2_000_000, 1_000_000],
+            &[1_000_000; 3],
+        );
+        market.decimals[1] = 0;
+        market
+    }
+
+    #[test]
+    fn min_decimals_skips_a_zero_decimal_mint() {
+        let market = zero_decimal_opportunity();
+        install(market.mock_swap());
+        let accounts = market.build();
+        let options = TryArbitrageOptions {
+            min_decimals: 2,
+            ..TryArbitrageOptions::default()
+        };
+
+        assert_eq!(
+            accounts.try_arbitrage(90, options),
+            Err(ArbitrageProgramError::NoArbitrage.into())
+        );
+        assert!(take_cpis().is_empty());
+        let expected = format!(
+            "[ARB] Mint {} (asset 1) has 0 decimals, below the minimum 2, excluding it",
+            accounts.mint(1).key
+        );
+        assert!(take_logs().contains(&expected));
+    }
+
+    #[test]
+    fn zero_decimal_mints_are_trusted_by_default() {
+        let market = zero_decimal_opportunity();
+        install(market.mock_swap());
+
+        assert_eq!(
+            market
+                .build()
+                .try_arbitrage(90, TryArbitrageOptions::default()),
+            Ok(())
+        );
+        assert_eq!(take_cpis().len(), 2);
+    }
+
+    /// 两个池子的最后更新 slot 记录在池子数据的偏移量 8 处，当前 slot 为 1000
+    fn market_updated_at(swap_1_slot: u64, swap_2_slot: u64) -> MarketAccounts {
+        let mut market = skewed_market();
+        market.swap_1_pool_data[8..16].copy_from_slice(&swap_1_slot.to_le_bytes());
+        market.swap_2_pool_data[8..16].copy_from_slice(&swap_2_slot.to_le_bytes());
+        install(market.mock_swap());
+        set_slot(1_000);
+        market.build()
+    }
+
+    fn staleness_limit(max_pool_staleness_slots: u64) -> TryArbitrageOptions {
+        TryArbitrageOptions {
+            swap_1_slot_offset: Some(8),
+            swap_2_slot_offset: Some(8),
+            max_pool_staleness_slots: Some(max_pool_staleness_slots),
+            ..TryArbitrageOptions::default()
+        }
+    }
+
+    #[test]
+    fn fresh_pools_are_traded() {
+        let accounts = market_updated_at(995, 990);
+        assert_eq!(accounts.try_arbitrage(90, staleness_limit(10)), Ok(()));
+        assert_eq!(take_cpis().len(), 2);
+    }
+
+    #[test]
+    fn stale_pool_is_rejected() {
+        let accounts = market_updated_at(995, 989);
+        assert_eq!(
+            accounts.try_arbitrage(90, staleness_limit(10)),
+            Err(ArbitrageProgramError::StalePool.into())
+        );
+        assert!(take_cpis().is_empty());
+        let expected = format!(
+            "[ARB] Pool {}: last updated at slot 989, 11 slots ago (max 10)",
+            accounts.accounts[8].key
+        );
+        assert!(take_logs().contains(&expected));
+    }
+
+    #[test]
+    fn mint_aliasing_the_payer_is_rejected() {
+        let market = flat_market();
+        install(market.mock_swap());
+        let mut accounts = market.build();
+        let payer = *accounts.accounts[0].key;
+        let index = account_segments(2, OmittedAccounts::default())[3].start + 1;
+        accounts.accounts[index] = account(payer, spl_token::ID, vec![0; 82], false, false, false);
+
+        assert_eq!(
+            accounts.try_arbitrage(90, TryArbitrageOptions::default()),
+            Err(ArbitrageProgramError::AliasedFixedAccount.into())
+        );
+        let expected =
+            format!("[ARB] Account {payer} at index {index} aliases fixed account `payer`");
+        assert!(take_logs().contains(&expected));
+    }
+
+    /// 两个不同程序的流动性池，Swap #2 资产 0 的代币账户省略、复用属于 Swap #1 流动性池的账户
+    fn market_sharing_asset_0(
+        market: Market,
+        shared: Vec<Option<u8>>,
+    ) -> (MarketAccounts, TryArbitrageOptions) {
+        install(market.mock_swap());
+        let mut accounts = market.build();
+        accounts
+            .accounts
+            .remove(ACCOUNT_LAYOUT.len() + 2 * accounts.concurrency);
+        let options = TryArbitrageOptions {
+            swap_2_shared_accounts: shared,
+            ..TryArbitrageOptions::default()
+        };
+        (accounts, options)
+    }
+
+    fn parse(
+        accounts: &MarketAccounts,
+        options: TryArbitrageOptions,
+    ) -> Result<TryArbitrageArgs<'_, 'static>, ProgramError> {
+        parse_arbitrage_accounts(
+            &PROGRAM_ID,
+            &accounts.accounts,
+            &accounts.swap_1_program_id,
+            &accounts.swap_2_program_id,
+            accounts.concurrency as u8,
+            90,
+            options,
+            0,
+        )
+    }
+
+    #[test]
+    fn token_account_shared_between_two_pool_roles() {
+        let (accounts, options) = market_sharing_asset_0(flat_market(), vec![Some(0), None]);
+        let expected = expected_account_count(2, OmittedAccounts::shared(1));
+        assert_eq!(accounts.accounts.len(), expected);
+
+        let args = parse(&accounts, options.clone()).unwrap();
+        assert_eq!(args.token_accounts_swap_2.len(), 2);
+        assert_eq!(
+            args.token_accounts_swap_2[0].0.key,
+            args.token_accounts_swap_1[0].0.key
+        );
+        assert_eq!(
+            args.token_accounts_swap_2[0].1,
+            args.token_accounts_swap_1[0].1
+        );
+        // Swap #2 资产 1 仍按位置传入，紧跟在 Swap #1 列表之后
+        assert_eq!(
+            args.token_accounts_swap_2[1].0.key,
+            accounts.accounts[ACCOUNT_LAYOUT.len() + 4].key
+        );
+
+        // 两个池子报价相同，通过了账户解析但没有套利机会
+        assert_eq!(
+            accounts.try_arbitrage(90, options),
+            Err(ArbitrageProgramError::NoArbitrage.into())
+        );
+    }
+
+    #[test]
+    fn shared_account_holding_another_asset_is_rejected() {
+        // Swap #2 资产 0 复用 Swap #1 资产 1 的账户，资产 1 的位置上是 Swap #2 自己的资产 0 账户：
+        // 池子内铸币互不相同，但复用的账户持有的不是资产 0 的铸币
+        let market = flat_market();
+        install(market.mock_swap());
+        let mut accounts = market.build();
+        let (shared, mint_1) = (*accounts.swap_1(1).key, *accounts.mint(1).key);
+        accounts
+            .accounts
+            .remove(ACCOUNT_LAYOUT.len() + 2 * accounts.concurrency + 1);
+        let options = TryArbitrageOptions {
+            swap_2_shared_accounts: vec![Some(1), None],
+            ..TryArbitrageOptions::default()
+        };
+        assert_eq!(
+            parse(&accounts, options).err(),
+            Some(ArbitrageProgramError::InvalidAccountsList.into())
+        );
+        let expected = format!(
+            "[ARB] Shared token account {shared} (Swap #1 asset 1) holds mint {mint_1}, which is not asset 0"
+        );
+        assert!(take_logs().contains(&expected));
+    }
+
+    #[test]
+    fn shared_account_map_is_validated() {
+        for shared in [vec![Some(0)], vec![Some(2), None]] {
+            let (accounts, options) = market_sharing_asset_0(flat_market(), shared);
+            assert_eq!(
+                parse(&accounts, options).err(),
+                Some(ProgramError::InvalidInstructionData)
+            );
+        }
+    }
+
+    #[test]
+    fn mismatched_pool_address_aborts_the_scan() {
+        // 单次尝试只有两个池子，排除任何一个都无法套利，因此地址不匹配总是中止；
+        // 跳过策略只用于 `TryArbitrageMulti`，见 `PoolCheckPolicy`
+        for (index, program) in [(7, 5), (8, 6)] {
+            let market = skewed_market();
+            install(market.mock_swap());
+            let mut accounts = market.build();
+            let owner = *accounts.accounts[program].key;
+            accounts.accounts[index] =
+                account(Pubkey::new_unique(), owner, vec![0; 64], false, true, false);
+
+            assert_eq!(
+                accounts.try_arbitrage(90, TryArbitrageOptions::default()),
+                Err(ArbitrageProgramError::InvalidSwapNotEnoughLiquidity.into())
+            );
+            assert!(take_cpis().is_empty());
+        }
+    }
+
+    #[test]
+    fn temperature_100_requires_allow_zero_threshold() {
+        let market = skewed_market();
+        install(market.mock_swap());
+        let accounts = market.build();
+
+        assert_eq!(
+            accounts.try_arbitrage(100, TryArbitrageOptions::default()),
+            Err(ProgramError::InvalidInstructionData)
+        );
+        assert!(take_logs().contains(
+            &"[ARB] Temperature 100 leaves no arbitrage threshold; set allow_zero_threshold to proceed"
+                .to_string()
+        ));
+        let secondary_tier = TryArbitrageOptions {
+            secondary_tier: Some(crate::arb::SecondaryTier {
+                temperature: 100,
+                size_bps: 5_000,
+            }),
+            ..TryArbitrageOptions::default()
+        };
+        assert_eq!(
+            accounts.try_arbitrage(90, secondary_tier),
+            Err(ProgramError::InvalidInstructionData)
+        );
+        assert!(take_cpis().is_empty());
+    }
+
+    #[test]
+    fn allowed_zero_threshold_warns_and_trades() {
+        let market = skewed_market();
+        install(market.mock_swap());
+        let accounts = market.build();
+        let options = TryArbitrageOptions {
+            allow_zero_threshold: true,
+            ..TryArbitrageOptions::default()
+        };
+
+        assert_eq!(accounts.try_arbitrage(100, options), Ok(()));
+        assert_eq!(take_cpis().len(), 2);
+        assert!(take_logs().contains(
+            &"[ARB] WARNING: temperature 100 leaves no arbitrage threshold, any price difference trades"
+                .to_string()
+        ));
+    }
+
+    #[test]
+    fn paranoid_mode_trades_when_reserves_are_unchanged() {
+        let market = skewed_market();
+        install(market.mock_swap());
+        let accounts = market.build();
+        let options = TryArbitrageOptions {
+            paranoid: true,
+            ..TryArbitrageOptions::default()
+        };
+
+        assert_eq!(accounts.try_arbitrage(90, options), Ok(()));
+        assert_eq!(take_cpis().len(), 2);
+    }
+
+    #[test]
+    fn trade_owner_signs_and_the_payer_funds_no_accounts() {
+        // 程序不创建 ATA，也就没有需要与交易所有者区分的租金支付方：
+        // swap CPI 只带所有者签名，从不引用支付方
+        let market = skewed_market();
+        install(market.mock_swap());
+        let accounts = market.build();
+        let payer = *accounts.accounts[0].key;
+        assert_ne!(&payer, accounts.owner().key);
+
+        assert_eq!(
+            accounts.try_arbitrage(90, TryArbitrageOptions::default()),
+            Ok(())
+        );
+        for cpi in take_cpis() {
+            assert!(cpi.accounts.iter().all(|meta| meta.pubkey != payer));
+            let signers: Vec<_> = cpi
+                .accounts
+                .iter()
+                .filter(|meta| meta.is_signer)
+                .map(|meta| meta.pubkey)
+                .collect();
+            assert_eq!(signers, vec![*accounts.owner().key]);
+        }
+    }
+
+    #[test]
+    fn validate_account_ordering_accepts_the_market_layout() {
+        install(MockSwap::default());
+        let accounts = flat_market().build();
+        assert_eq!(validate_account_ordering(&accounts.accounts, 2), Ok(()));
+        // 末尾追加的可选账户不影响布局检查
+        let mut with_trailing = accounts.accounts.clone();
+        with_trailing.push(account(
+            Pubkey::new_unique(),
+            Pubkey::default(),
+            vec![],
+            false,
+            false,
+            false,
+        ));
+        assert_eq!(validate_account_ordering(&with_trailing, 2), Ok(()));
+    }
+
+    #[test]
+    fn validate_account_ordering_reports_the_first_offending_index() {
+        install(MockSwap::default());
+        let accounts = flat_market().build();
+        let invalid = |index| {
+            Err(AccountOrderingError {
+                index,
+                error: ArbitrageProgramError::InvalidAccountsList.into(),
+            })
+        };
+
+        assert_eq!(
+            validate_account_ordering(&accounts.accounts, 256),
+            invalid(9)
+        );
+        // 缺少最后一个铸币账户（下标 16）
+        assert_eq!(
+            validate_account_ordering(&accounts.accounts[..16], 2),
+            invalid(16)
+        );
+
+        // Swap #1 资产 1（下标 12）与 Swap #2 资产 0（下标 13）都不属于代币程序，只报告前者
+        let mut not_token = accounts.accounts.clone();
+        for index in [12, 13] {
+            not_token[index] = account(
+                Pubkey::new_unique(),
+                Pubkey::default(),
+                vec![0; 165],
+                false,
+                true,
+                false,
+            );
+        }
+        take_logs();
+        assert_eq!(validate_account_ordering(&not_token, 2), invalid(12));
+        assert_eq!(
+            take_logs(),
+            vec![format!(
+                "[ARB] Account {} at index 12 is owned by {}, not a token program",
+                not_token[12].key,
+                Pubkey::default()
+            )]
+        );
+
+        // 用户资产 0 冒用 Swap #1 流动性池
+        let mut aliased = accounts.accounts.clone();
+        aliased[9] = aliased[7].clone();
+        let error = validate_account_ordering(&aliased, 2).unwrap_err();
+        assert_eq!(error.index, 9);
+        assert_eq!(
+            ProgramError::from(error),
+            ArbitrageProgramError::AliasedFixedAccount.into()
+        );
+    }
+
+    #[test]
+    fn shared_account_ordering_expects_a_shorter_swap_2_list() {
+        let (accounts, _) = market_sharing_asset_0(flat_market(), vec![Some(0), None]);
+        assert_eq!(
+            validate_partial_account_ordering(&accounts.accounts, 2, OmittedAccounts::shared(1)),
+            Ok(())
+        );
+        // 不按复用布局检查时，少了一个账户
+        assert_eq!(
+            validate_account_ordering(&accounts.accounts, 2).map_err(|e| e.index),
+            Err(16)
+        );
+    }
+
+    #[test]
+    fn metadata_decimals_take_precedence_over_the_mint() {
+        // 与 `decimals_override_takes_precedence_over_the_mint` 相同的市场：利润 994 个最小单位，
+        // 按铸币的 6 位小数不足 1 个整币，按元数据中的 2 位小数超过 1 个整币
+        let market = skewed_market();
+        let whole_token = TryArbitrageOptions {
+            min_profit: 1,
+            min_profit_whole_tokens: true,
+            ..TryArbitrageOptions::default()
+        };
+        let metadata = |accounts: &mut MarketAccounts| {
+            let mut data = vec![0; 8];
+            data[3] = 2;
+            let metadata = account(
+                Pubkey::new_unique(),
+                Pubkey::new_unique(),
+                data,
+                false,
+                false,
+                false,
+            );
+            accounts.accounts.push(metadata.clone());
+            metadata
+        };
+
+        // 只有铸币的小数位数：不交易
+        install(market.mock_swap());
+        let mut accounts = market.build();
+        metadata(&mut accounts);
+        assert_eq!(
+            accounts.try_arbitrage(90, whole_token.clone()),
+            Err(ArbitrageProgramError::NoArbitrage.into())
+        );
+        assert!(take_cpis().is_empty());
+
+        // 资产 0 的小数位数取自元数据账户偏移量 3 处：交易
+        install(market.mock_swap());
+        let mut accounts = market.build();
+        let metadata = metadata(&mut accounts);
+        let options = TryArbitrageOptions {
+            metadata_decimals: vec![Some(3), None],
+            ..whole_token
+        };
+        assert_eq!(accounts.try_arbitrage(90, options.clone()), Ok(()));
+        assert_eq!(take_cpis().len(), 2);
+        let expected = format!(
+            "[ARB] Mint {}: decimals 6 -> 2 from metadata {}",
+            accounts.mint(0).key,
+            metadata.key
+        );
+        assert!(take_logs().contains(&expected));
+
+        // 缺少元数据账户
+        install(market.mock_swap());
+        assert_eq!(
+            market.build().try_arbitrage(90, options),
+            Err(ProgramError::NotEnoughAccountKeys)
+        );
+    }
+
+    #[test]
+    fn associated_token_program_is_a_required_fixed_account() {
+        assert_eq!(ACCOUNT_LAYOUT[4], "associated_token_program");
+
+        // 不创建 ATA 时也转发给每个 swap CPI，作为最后一个账户
+        let market = skewed_market();
+        install(market.mock_swap());
+        let accounts = market.build();
+        assert_eq!(
+            accounts.try_arbitrage(90, TryArbitrageOptions::default()),
+            Ok(())
+        );
+        for cpi in take_cpis() {
+            assert_eq!(
+                cpi.accounts.last().map(|meta| meta.pubkey),
+                Some(spl_associated_token_account::ID)
+            );
+        }
+
+        // 未设置 `omit_associated_token_program` 时省略该账户，其余账户整体前移，账户数量不足
+        install(market.mock_swap());
+        let mut accounts = market.build();
+        accounts.accounts.remove(4);
+        assert_eq!(
+            accounts.try_arbitrage(90, TryArbitrageOptions::default()),
+            Err(ArbitrageProgramError::InvalidAccountsList.into())
+        );
+        assert!(take_cpis().is_empty());
+    }
+
+    fn omitting_associated_token_program() -> TryArbitrageOptions {
+        TryArbitrageOptions {
+            omit_associated_token_program: true,
+            ..TryArbitrageOptions::default()
+        }
+    }
+
+    #[test]
+    fn omitted_associated_token_program_shifts_the_layout() {
+        let omitted = OmittedAccounts {
+            associated_token_program: true,
+            shared_accounts: 1,
+        };
+        assert_eq!(omitted.fixed_accounts(), 8);
+        assert_eq!(omitted.swap_cpi_accounts(), SWAP_CPI_ACCOUNTS - 1);
+        assert!(omitted
+            .fixed_layout()
+            .all(|name| name != "associated_token_program"));
+        assert_eq!(expected_account_count(2, omitted), 8 + 4 * 2 - 1);
+        assert_eq!(
+            account_segments(2, omitted),
+            [8..10, 10..12, 12..13, 13..15]
+        );
+    }
+
+    #[test]
+    fn trade_without_associated_token_program() {
+        let market = skewed_market();
+        install(market.mock_swap());
+        let mut accounts = market.build();
+        let associated_token_program = *accounts.accounts.remove(4).key;
+        assert_eq!(associated_token_program, spl_associated_token_account::ID);
+
+        assert_eq!(
+            accounts.try_arbitrage(90, omitting_associated_token_program()),
+            Ok(())
+        );
+        let cpis = take_cpis();
+        assert_eq!(cpis.len(), 2);
+        for cpi in cpis {
+            assert_eq!(cpi.accounts.len(), SWAP_CPI_ACCOUNTS - 1);
+            assert!(cpi
+                .accounts
+                .iter()
+                .all(|meta| meta.pubkey != associated_token_program));
+        }
+        // 账户前移一位后，用户的资产 0 账户位于第 8 个
+        assert_eq!(token_amount(&accounts.accounts[8]), 1_994);
+    }
+
+    #[test]
+    fn omitted_associated_token_program_requires_ten_entry_account_orders() {
+        let market = skewed_market();
+        install(market.mock_swap());
+        let mut accounts = market.build();
+        accounts.accounts.remove(4);
+        let options = TryArbitrageOptions {
+            swap_1_account_order: (0..SWAP_CPI_ACCOUNTS as u8).collect(),
+            ..omitting_associated_token_program()
+        };
+
+        assert_eq!(
+            accounts.try_arbitrage(90, options),
+            Err(ProgramError::InvalidInstructionData)
+        );
+        assert!(take_cpis().is_empty());
+    }
+
+    #[test]
+    fn later_instruction_sees_prices_moved_between_instructions() {
+        // 同一条指令内池子余额不会变化，重新扫描只会得到相同的判断；
+        // 价格在两条 TryArbitrage 指令之间变化后，后一条指令才能看到新的机会
+        let market = flat_market();
+        install(market.mock_swap());
+        let accounts = market.build();
+        assert_eq!(
+            accounts.try_arbitrage(90, TryArbitrageOptions::default()),
+            Err(ArbitrageProgramError::NoArbitrage.into())
+        );
+        assert!(take_cpis().is_empty());
+
+        // 交易中靠前的指令把 Swap #1 的资产 1 余额推高一倍
+        set_token_amount(accounts.swap_1(1), 2_000_000);
+        assert_eq!(
+            accounts.try_arbitrage(90, TryArbitrageOptions::default()),
+            Ok(())
+        );
+        assert_eq!(take_cpis().len(), 2);
+    }
+
+    fn debug_parse(accounts: &[AccountInfo], which: u8, index: u8) -> ParsedAccount {
+        process_debug_parse(accounts, which, index).unwrap();
+        let (_, data) = return_data().unwrap();
+        ParsedAccount::try_from_slice(&data).unwrap()
+    }
+
+    #[test]
+    fn debug_parse_reads_back_a_known_token_account() {
+        install(MockSwap::default());
+        let (mint, owner) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let accounts = [
+            token_account(&Pubkey::new_unique(), &owner, 1, &spl_token::ID),
+            token_account(&mint, &owner, 42_000, &spl_token::ID),
+        ];
+
+        assert_eq!(
+            debug_parse(&accounts, 0, 1),
+            ParsedAccount::TokenAccount {
+                mint,
+                owner,
+                amount: 42_000,
+            }
+        );
+    }
+
+    #[test]
+    fn debug_parse_reads_back_a_known_mint() {
+        install(MockSwap::default());
+        // 32 字节的铸币权限、8 字节的供应量，第 41 个字节为小数位数
+        let mut data = vec![0; 82];
+        data[32..40].copy_from_slice(&123_456u64.to_le_bytes());
+        data[40] = 9;
+        let mint = account(
+            Pubkey::new_unique(),
+            spl_token::ID,
+            data,
+            false,
+            false,
+            false,
+        );
+
+        assert_eq!(
+            debug_parse(&[mint], 1, 0),
+            ParsedAccount::Mint {
+                decimals: 9,
+                supply: 123_456,
+            }
+        );
+    }
+
+    #[test]
+    fn debug_parse_rejects_unknown_kinds_and_indices() {
+        install(MockSwap::default());
+        let accounts = [token_account(
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            1,
+            &spl_token::ID,
+        )];
+        assert_eq!(
+            process_debug_parse(&accounts, 2, 0),
+            Err(ProgramError::InvalidInstructionData)
+        );
+        assert_eq!(
+            process_debug_parse(&accounts, 0, 1),
+            Err(ProgramError::NotEnoughAccountKeys)
+        );
+        // 40 字节的缓冲区读不到小数位数
+        let short = account(
+            Pubkey::new_unique(),
+            spl_token::ID,
+            vec![0; 40],
+            false,
+            false,
+            false,
+        );
+        assert_eq!(
+            process_debug_parse(&[short], 1, 0),
+            Err(ArbitrageProgramError::InvalidAccountsList.into())
+        );
+    }
+
+    #[test]
+    fn read_only_pool_accounts_are_rejected() {
+        for (index, name) in [(7, "Swap #1 pool"), (8, "Swap #2 pool")] {
+            let market = skewed_market();
+            install(market.mock_swap());
+            let mut accounts = market.build();
+            accounts.accounts[index].is_writable = false;
+
+            assert_eq!(
+                accounts.try_arbitrage(90, TryArbitrageOptions::default()),
+                Err(ArbitrageProgramError::AccountNotWritable.into())
+            );
+            assert!(take_cpis().is_empty());
+            let expected = format!(
+                "[ARB] {} {} must be writable",
+                name, accounts.accounts[index].key
+            );
+            assert!(take_logs().contains(&expected));
+        }
+    }
+
+    #[test]
+    fn read_only_user_token_account_is_reported_by_index() {
+        // 资产 1 与资产 2 的用户代币账户都只读，只报告第一个
+        let market = Market::new(
+            &[1_000, 0, 0],
+            &[1_000_000, 2_000_000, 1_000_000],
+            &[1_000_000; 3],
+        );
+        install(market.mock_swap());
+        let mut accounts = market.build();
+        for index in [ACCOUNT_LAYOUT.len() + 1, ACCOUNT_LAYOUT.len() + 2] {
+            accounts.accounts[index].is_writable = false;
+        }
+
+        assert_eq!(
+            accounts.try_arbitrage(90, TryArbitrageOptions::default()),
+            Err(ArbitrageProgramError::AccountNotWritable.into())
+        );
+        assert!(take_cpis().is_empty());
+        let expected = format!(
+            "[ARB] User token account {} (asset 1) must be writable",
+            accounts.user(1).key
+        );
+        assert!(take_logs().contains(&expected));
+    }
+
+    /// Swap #2 资产 0 的池子代币账户换成 `token_program` 持有的同名账户
+    fn market_with_swap_2_token_program(token_program: &Pubkey) -> MarketAccounts {
+        let market = skewed_market();
+        install(market.mock_swap());
+        let mut accounts = market.build();
+        let index = ACCOUNT_LAYOUT.len() + 2 * accounts.concurrency;
+        accounts.accounts[index] = token_account(
+            accounts.mint(0).key,
+            accounts.accounts[8].key,
+            1_000_000,
+            token_program,
+        );
+        accounts
+    }
+
+    fn uniform_token_program() -> TryArbitrageOptions {
+        TryArbitrageOptions {
+            require_uniform_token_program: true,
+            ..TryArbitrageOptions::default()
+        }
+    }
+
+    #[test]
+    fn uniform_token_programs_pass_the_check() {
+        let accounts = market_with_swap_2_token_program(&spl_token::ID);
+        assert_eq!(accounts.try_arbitrage(90, uniform_token_program()), Ok(()));
+        assert_eq!(take_cpis().len(), 2);
+
+        let mut market = skewed_market();
+        market.token_program = spl_token_2022::ID;
+        install(market.mock_swap());
+        assert_eq!(
+            market.build().try_arbitrage(90, uniform_token_program()),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn mixed_token_programs_are_rejected_when_required() {
+        let accounts = market_with_swap_2_token_program(&spl_token_2022::ID);
+        assert_eq!(
+            accounts.try_arbitrage(90, uniform_token_program()),
+            Err(ArbitrageProgramError::MixedTokenPrograms.into())
+        );
+        assert!(take_cpis().is_empty());
+        let expected = format!(
+            "[ARB] Pool token account {} is owned by {}, but {} is owned by {}",
+            accounts.swap_2(0).key,
+            spl_token_2022::ID,
+            accounts.swap_1(0).key,
+            spl_token::ID
+        );
+        assert!(take_logs().contains(&expected));
+
+        // 未设置该选项时不检查
+        assert_eq!(
+            accounts.try_arbitrage(90, TryArbitrageOptions::default()),
+            Ok(())
+        );
+    }
+
+    /// 按 `MarketAccounts` 组装一次套利尝试
+    fn attempt(accounts: &MarketAccounts) -> ArbitrageAttempt {
+        ArbitrageAttempt {
+            swap_1_program_id: accounts.swap_1_program_id,
+            swap_2_program_id: accounts.swap_2_program_id,
+            concurrency: accounts.concurrency as u8,
+            temperature: 90,
+            account_count: accounts.accounts.len() as u16,
+            options: TryArbitrageOptions::default(),
+        }
+    }
+
+    #[test]
+    fn multi_attempt_without_arbitrage_does_not_revert_the_other() {
+        // 第一次尝试的两个池子报价相同，第二次尝试有利润为 994 的机会
+        let (flat, skewed) = (flat_market(), skewed_market());
+        let mut mock = flat.mock_swap();
+        mock.pools.extend(skewed.mock_swap().pools);
+        install(mock);
+        let (flat, skewed) = (flat.build(), skewed.build());
+        let accounts: Vec<_> = flat
+            .accounts
+            .iter()
+            .chain(&skewed.accounts)
+            .cloned()
+            .collect();
+
+        assert_eq!(
+            process_arbitrage_multi(
+                &PROGRAM_ID,
+                &accounts,
+                vec![attempt(&flat), attempt(&skewed)],
+                0,
+                PoolCheckPolicy::Abort
+            ),
+            Ok(())
+        );
+        let cpis = take_cpis();
+        assert_eq!(cpis.len(), 2);
+        assert_eq!(cpis[0].program_id, skewed.swap_1_program_id);
+        assert_eq!(token_amount(skewed.user(0)), 1_994);
+        assert_eq!(token_amount(flat.user(0)), 1_000);
+        assert!(take_logs().contains(&"[ARB] Attempt 0 found no arbitrage".to_string()));
+        let result = MultiArbitrageResult::try_from_slice(&return_data().unwrap().1).unwrap();
+        assert_eq!(
+            result.codes,
+            vec![ArbitrageProgramError::NoArbitrage as u32, 0]
+        );
+    }
+
+    #[test]
+    fn multi_attempt_pool_check_follows_the_omitted_layout() {
+        // 省略关联代币程序的尝试中流动性池前移一位，按跳过策略也不能被误判为错误的池子地址
+        let market = skewed_market();
+        install(market.mock_swap());
+        let mut accounts = market.build();
+        accounts.accounts.remove(4);
+        let attempt = ArbitrageAttempt {
+            account_count: accounts.accounts.len() as u16,
+            options: omitting_associated_token_program(),
+            ..attempt(&accounts)
+        };
+
+        assert_eq!(
+            process_arbitrage_multi(
+                &PROGRAM_ID,
+                &accounts.accounts,
+                vec![attempt],
+                0,
+                PoolCheckPolicy::Skip
+            ),
+            Ok(())
+        );
+        assert_eq!(take_cpis().len(), 2);
+        let result = MultiArbitrageResult::try_from_slice(&return_data().unwrap().1).unwrap();
+        assert_eq!(result.codes, vec![0]);
+    }
+
+    #[test]
+    fn multi_attempt_segments_must_cover_the_account_list() {
+        let market = skewed_market();
+        install(market.mock_swap());
+        let mut accounts = market.build();
+        let mut long = attempt(&accounts);
+        long.account_count += 1;
+
+        // 账户段超出账户列表时在执行之前失败
+        assert_eq!(
+            process_arbitrage_multi(
+                &PROGRAM_ID,
+                &accounts.accounts,
+                vec![long],
+                0,
+                PoolCheckPolicy::Abort
+            ),
+            Err(ArbitrageProgramError::InvalidAccountsList.into())
+        );
+        assert!(take_cpis().is_empty());
+        let needed = accounts.accounts.len() + 1;
+        assert!(take_logs().contains(&format!(
+            "[ARB] Attempt 0 needs {} accounts, only {} left",
+            needed,
+            needed - 1
+        )));
+
+        // 最后一次尝试之后还有多余的账户时整条指令回滚
+        let exact = attempt(&accounts);
+        accounts
+            .accounts
+            .push(program_account(&Pubkey::new_unique()));
+        assert_eq!(
+            process_arbitrage_multi(
+                &PROGRAM_ID,
+                &accounts.accounts,
+                vec![exact],
+                0,
+                PoolCheckPolicy::Abort
+            ),
+            Err(ArbitrageProgramError::InvalidAccountsList.into())
+        );
+        assert!(take_logs().contains(&"[ARB] 1 account(s) left after the last attempt".to_string()));
+    }
+
+    #[test]
+    fn decimals_override_keeps_a_mint_above_min_decimals() {
+        // 铸币账户中资产 1 为 0 位小数，覆盖为 6 位后不再被 `min_decimals` 排除
+        let market = zero_decimal_opportunity();
+        let options = TryArbitrageOptions {
+            min_decimals: 2,
+            decimals_overrides: vec![None, Some(6)],
+            ..TryArbitrageOptions::default()
+        };
+
+        install(market.mock_swap());
+        let accounts = market.build();
+        assert_eq!(accounts.try_arbitrage(90, options), Ok(()));
+        assert_eq!(take_cpis().len(), 2);
+        let expected = format!(
+            "[ARB] Mint {}: overriding decimals 0 -> 6",
+            accounts.mint(1).key
+        );
+        assert!(take_logs().contains(&expected));
+    }
+
+    #[test]
+    fn max_total_cpis_stops_after_the_first_qualifying_attempt() {
+        // 两次尝试都有利润为 994 的机会，每次执行两条交易腿
+        let (first, second) = (skewed_market(), skewed_market());
+        let mut mock = first.mock_swap();
+        mock.pools.extend(second.mock_swap().pools);
+        install(mock);
+        let (first, second) = (first.build(), second.build());
+        let accounts: Vec<_> = first
+            .accounts
+            .iter()
+            .chain(&second.accounts)
+            .cloned()
+            .collect();
+
+        assert_eq!(
+            process_arbitrage_multi(
+                &PROGRAM_ID,
+                &accounts,
+                vec![attempt(&first), attempt(&second)],
+                3,
+                PoolCheckPolicy::Abort
+            ),
+            Ok(())
+        );
+        let cpis = take_cpis();
+        assert_eq!(cpis.len(), 2);
+        assert_eq!(cpis[0].program_id, first.swap_1_program_id);
+        assert_eq!(token_amount(first.user(0)), 1_994);
+        assert_eq!(token_amount(second.user(0)), 1_000);
+        assert!(take_logs().contains(
+            &"[ARB] CPI cap 3 reached after 2 CPI(s), skipping attempts 1..2".to_string()
+        ));
+        let result = MultiArbitrageResult::try_from_slice(&return_data().unwrap().1).unwrap();
+        assert_eq!(result.codes, vec![0]);
+
+        // 不限制时两次尝试都执行
+        let (first, second) = (skewed_market(), skewed_market());
+        let mut mock = first.mock_swap();
+        mock.pools.extend(second.mock_swap().pools);
+        install(mock);
+        let (first, second) = (first.build(), second.build());
+        let accounts: Vec<_> = first
+            .accounts
+            .iter()
+            .chain(&second.accounts)
+            .cloned()
+            .collect();
+        assert_eq!(
+            process_arbitrage_multi(
+                &PROGRAM_ID,
+                &accounts,
+                vec![attempt(&first), attempt(&second)],
+                0,
+                PoolCheckPolicy::Abort
+            ),
+            Ok(())
+        );
+        assert_eq!(take_cpis().len(), 4);
+    }
+
+    #[test]
+    fn pools_of_different_programs_trade_through_a_shared_account() {
+        // Swap #2 的资产 0 经由属于 Swap #1 流动性池的账户结算，两个池子的资产 0 余额相同
+        let (accounts, options) = market_sharing_asset_0(skewed_market(), vec![Some(0), None]);
+        assert_ne!(accounts.swap_1_program_id, accounts.swap_2_program_id);
+        let shared = *accounts.swap_1(0).key;
+        let options = TryArbitrageOptions {
+            paranoid: true,
+            ..options
+        };
+
+        assert_eq!(accounts.try_arbitrage(90, options), Ok(()));
+        let cpis = take_cpis();
+        assert_eq!(cpis.len(), 2);
+        // 卖出腿由 Swap #2 执行，接收资产 0 的池子账户是复用的账户
+        assert_eq!(cpis[1].program_id, accounts.swap_2_program_id);
+        assert_eq!(cpis[1].accounts[2].pubkey, shared);
+    }
+
+    /// 三次都有利润的尝试，第二次尝试的 Swap #2 流动性池不是该程序的池子 PDA
+    fn attempts_with_one_bad_pool() -> (Vec<MarketAccounts>, Pubkey) {
+        let markets = [skewed_market(), skewed_market(), skewed_market()];
+        let mut mock = MockSwap::new(vec![]);
+        for market in &markets {
+            mock.pools.extend(market.mock_swap().pools);
+        }
+        install(mock);
+        let mut built: Vec<_> = markets.iter().map(Market::build).collect();
+        let bad = Pubkey::new_unique();
+        let owner = *built[1].accounts[6].key;
+        built[1].accounts[8] = account(bad, owner, vec![0; 64], false, true, false);
+        (built, bad)
+    }
+
+    #[test]
+    fn bad_pool_in_one_attempt_aborts_the_batch_by_default() {
+        let (markets, _) = attempts_with_one_bad_pool();
+        let accounts: Vec<_> = markets.iter().flat_map(|m| m.accounts.clone()).collect();
+
+        assert_eq!(
+            process_arbitrage_multi(
+                &PROGRAM_ID,
+                &accounts,
+                markets.iter().map(attempt).collect(),
+                0,
+                PoolCheckPolicy::Abort
+            ),
+            Err(ArbitrageProgramError::InvalidSwapNotEnoughLiquidity.into())
+        );
+    }
+
+    #[test]
+    fn skip_policy_excludes_the_attempt_with_a_bad_pool() {
+        let (markets, bad) = attempts_with_one_bad_pool();
+        let accounts: Vec<_> = markets.iter().flat_map(|m| m.accounts.clone()).collect();
+
+        assert_eq!(
+            process_arbitrage_multi(
+                &PROGRAM_ID,
+                &accounts,
+                markets.iter().map(attempt).collect(),
+                0,
+                PoolCheckPolicy::Skip
+            ),
+            Ok(())
+        );
+        let cpis = take_cpis();
+        assert_eq!(cpis.len(), 4);
+        assert_eq!(cpis[0].program_id, markets[0].swap_1_program_id);
+        assert_eq!(cpis[2].program_id, markets[2].swap_1_program_id);
+        assert_eq!(token_amount(markets[1].user(0)), 1_000);
+        assert!(take_logs().contains(&format!(
+            "[ARB] Attempt 1 skipped: {bad} is not the liquidity pool of its swap program"
+        )));
+        let result = MultiArbitrageResult::try_from_slice(&return_data().unwrap().1).unwrap();
+        assert_eq!(
+            result.codes,
+            vec![
+                0,
+                ArbitrageProgramError::InvalidSwapNotEnoughLiquidity as u32,
+                0
+            ]
+        );
+    }
+
+    #[test]
+    fn multi_attempt_over_the_fee_cap_is_skipped() {
+        // 第一次尝试的 Swap #2 费率 100 bps 超过上限 50 bps，第二次尝试没有设置上限
+        let (expensive, cheap) = (skewed_market(), skewed_market());
+        let mut expensive = expensive;
+        expensive.swap_2_pool_data[4..6].copy_from_slice(&100u16.to_le_bytes());
+        let mut mock = expensive.mock_swap();
+        mock.pools.extend(cheap.mock_swap().pools);
+        install(mock);
+        let (expensive, cheap) = (expensive.build(), cheap.build());
+        let accounts: Vec<_> = expensive
+            .accounts
+            .iter()
+            .chain(&cheap.accounts)
+            .cloned()
+            .collect();
+        let mut capped = attempt(&expensive);
+        capped.options.swap_2_fee_offset = Some(4);
+        capped.options.max_acceptable_fee_bps = Some(50);
+
+        assert_eq!(
+            process_arbitrage_multi(
+                &PROGRAM_ID,
+                &accounts,
+                vec![capped, attempt(&cheap)],
+                0,
+                PoolCheckPolicy::Abort
+            ),
+            Ok(())
+        );
+        assert_eq!(take_cpis().len(), 2);
+        assert!(take_logs()
+            .contains(&"[ARB] Attempt 0 skipped: a pool's fee exceeds the maximum".to_string()));
+        let result = MultiArbitrageResult::try_from_slice(&return_data().unwrap().1).unwrap();
+        assert_eq!(
+            result.codes,
+            vec![ArbitrageProgramError::PoolFeeTooHigh as u32, 0]
+        );
+    }
+
+    #[test]
+    fn metadata_decimals_below_min_decimals_exclude_the_asset_from_the_scan() {
+        // 铸币账户中资产 0 为 6 位小数，元数据账户偏移量 3 处记录的有效小数位数为 0
+        let market = skewed_market();
+        install(market.mock_swap());
+        let mut accounts = market.build();
+        accounts.accounts.push(account(
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            vec![0; 8],
+            false,
+            false,
+            false,
+        ));
+        let options = TryArbitrageOptions {
+            min_decimals: 2,
+            metadata_decimals: vec![Some(3), None],
+            report_no_arbitrage: true,
+            ..TryArbitrageOptions::default()
+        };
+
+        assert_eq!(accounts.try_arbitrage(90, options), Ok(()));
+        assert!(take_cpis().is_empty());
+        let report = NoArbReport::try_from_slice(&return_data().unwrap().1).unwrap();
+        assert_eq!(report.best_pair, None);
+        assert_eq!(
+            report.scan.skipped_by_reason[SkipReason::Excluded as usize],
+            1
+        );
+        assert_eq!(report.scan.dominant_reason, Some(SkipReason::Excluded));
+    }
+}
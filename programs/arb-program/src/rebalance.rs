@@ -0,0 +1,196 @@
+use solana_program::{entrypoint::ProgramResult, program_error::ProgramError};
+
+use crate::{
+    arb::{invoke_swap, leg_cpi, Buy, SwapLeg, SwapMode, TryArbitrageArgs},
+    arb_msg,
+    error::ArbitrageProgramError,
+    swap::{apply_fee, determine_weighted_swap_receive_checked, BPS_DENOMINATOR},
+    util::ArbtrageEvaluateOption,
+};
+
+/// 检查再平衡的目标配比
+///
+/// 目标配比必须按资产顺序为每个资产给出一个值，且总和为 10_000 bps。
+///
+/// # 错误
+/// * `ProgramError::InvalidInstructionData` - 当长度与 `concurrency` 不一致或总和不为 10_000 时返回
+pub fn check_rebalance_targets(target_bps: &[u16], concurrency: u8) -> ProgramResult {
+    let total: u128 = target_bps.iter().map(|&bps| u128::from(bps)).sum();
+    if target_bps.len() != usize::from(concurrency) || total != BPS_DENOMINATOR {
+        arb_msg!(
+            "Rebalance targets {:?} must have {} entries summing to {} bps",
+            target_bps,
+            concurrency,
+            BPS_DENOMINATOR
+        );
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    Ok(())
+}
+
+/// 把用户持仓向目标配比调整一步
+///
+/// 以资产 0 为计价单位，按 Swap #1 流动性池的边际价格估算每个资产的持仓价值，
+/// 找出超出目标最多的资产 i 与低于目标最多的资产 j，在报价更好的交易所用资产 i
+/// 换取资产 j，换出的价值为两者偏差中较小的一个。
+///
+/// # 参数
+/// * `args` - 与 `TryArbitrage` 相同的已解析账户
+/// * `target_bps` - 按资产顺序排列的目标配比（基点）
+///
+/// # 返回值
+/// * `ProgramResult` - 持仓已经处于目标配比时不执行任何交易并返回 Ok
+pub fn rebalance(args: TryArbitrageArgs<'_, '_>, target_bps: &[u16]) -> ProgramResult {
+    let values = holding_values(&args)?;
+    let total = values.iter().try_fold(0u128, |acc, &value| {
+        acc.checked_add(value)
+            .ok_or(ArbitrageProgramError::MathOverflow)
+    })?;
+
+    // 分别找出超出目标最多与低于目标最多的资产：(偏差, 资产索引)
+    let mut surplus: Option<(u128, usize)> = None;
+    let mut deficit: Option<(u128, usize)> = None;
+    for (index, (&value, &bps)) in values.iter().zip(target_bps).enumerate() {
+        let target = total
+            .checked_mul(u128::from(bps))
+            .ok_or(ArbitrageProgramError::MathOverflow)?
+            / BPS_DENOMINATOR;
+        if value > target && surplus.is_none_or(|(gap, _)| value - target > gap) {
+            surplus = Some((value - target, index));
+        }
+        if value < target && deficit.is_none_or(|(gap, _)| target - value > gap) {
+            deficit = Some((target - value, index));
+        }
+    }
+    let (Some((surplus_value, i)), Some((deficit_value, j))) = (surplus, deficit) else {
+        arb_msg!("Inventory is already at the target allocation");
+        return Ok(());
+    };
+
+    // 把要换出的价值（以资产 0 计价）换算回资产 i 的数量，不超过用户的资产 i 余额
+    let numeraire_pool = args.token_accounts_swap_1.first().ok_or_arb_err()?.3;
+    let pool_i = args.token_accounts_swap_1.get(i).ok_or_arb_err()?.3;
+    let user_i = args.token_accounts_user.get(i).ok_or_arb_err()?.3;
+    let pay_value = surplus_value.min(deficit_value);
+    let pay_amount = u64::try_from(
+        pay_value
+            .checked_mul(u128::from(pool_i))
+            .ok_or(ArbitrageProgramError::MathOverflow)?
+            / u128::from(numeraire_pool),
+    )
+    .unwrap_or(u64::MAX)
+    .min(user_i);
+    if pay_amount == 0 {
+        arb_msg!("Inventory is already at the target allocation");
+        return Ok(());
+    }
+
+    // 选择接收数量更多的交易所
+    let quote = |venue: Buy| -> Result<u64, ProgramError> {
+        let (pools, fee_bps) = match venue {
+            Buy::Swap1 => (&args.token_accounts_swap_1, args.fees.swap_1_fee_bps),
+            Buy::Swap2 => (&args.token_accounts_swap_2, args.fees.swap_2_fee_bps),
+        };
+        determine_weighted_swap_receive_checked(
+            pools.get(j).ok_or_arb_err()?.3,
+            pools.get(i).ok_or_arb_err()?.3,
+            apply_fee(pay_amount, fee_bps)?,
+            args.pool_weights(venue, i, j),
+            0,
+        )
+    };
+    let (r_swap_1, r_swap_2) = (quote(Buy::Swap1)?, quote(Buy::Swap2)?);
+    let (venue, amount_out) = if r_swap_2 > r_swap_1 {
+        (Buy::Swap2, r_swap_2)
+    } else {
+        (Buy::Swap1, r_swap_1)
+    };
+
+    arb_msg!(
+        "Rebalancing: paying {} of asset {} for asset {} on {:?}",
+        pay_amount,
+        i,
+        j,
+        venue
+    );
+    let leg = SwapLeg {
+        venue,
+        pay: args.asset_accounts(i)?,
+        receive: args.asset_accounts(j)?,
+        amount: pay_amount,
+        amount_out,
+        mode: SwapMode::ExactIn,
+    };
+    let (program_id, accounts) = leg_cpi(&args, &leg);
+    invoke_swap(&args, program_id, &accounts, &leg.ix_data(&args.options))
+}
+
+/// 以资产 0 为计价单位，按 Swap #1 流动性池的边际价格估算用户每个资产的持仓价值
+fn holding_values(args: &TryArbitrageArgs<'_, '_>) -> Result<Vec<u128>, ProgramError> {
+    let numeraire_pool = args.token_accounts_swap_1.first().ok_or_arb_err()?.3;
+    args.token_accounts_user
+        .iter()
+        .zip(&args.token_accounts_swap_1)
+        .map(|(user, pool)| {
+            if pool.3 == 0 {
+                arb_msg!("Swap #1 pool holds none of mint {}", pool.1);
+                return Err(ArbitrageProgramError::InvalidSwapNotEnoughLiquidity.into());
+            }
+            // 两个 u64 相乘不会超出 u128 的范围
+            Ok(u128::from(user.3) * u128::from(numeraire_pool) / u128::from(pool.3))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processor::process_rebalance;
+    use crate::testing::{install, take_cpis, token_amount, Market, MarketAccounts, PROGRAM_ID};
+
+    fn try_rebalance(accounts: &MarketAccounts, target_bps: &[u16]) -> ProgramResult {
+        process_rebalance(
+            &PROGRAM_ID,
+            &accounts.accounts,
+            &accounts.swap_1_program_id,
+            &accounts.swap_2_program_id,
+            accounts.concurrency as u8,
+            target_bps,
+        )
+    }
+
+    #[test]
+    fn rebalance_moves_inventory_toward_the_target() {
+        let market = Market::new(&[1_000, 0], &[1_000_000; 2], &[1_000_000; 2]);
+        install(market.mock_swap());
+        let accounts = market.build();
+
+        assert_eq!(try_rebalance(&accounts, &[5_000, 5_000]), Ok(()));
+        assert_eq!(take_cpis().len(), 1);
+        // 换出价值为 500 的资产 0，按恒定乘积公式换得 1e6 * 500 / (1e6 + 500) 个资产 1
+        assert_eq!(token_amount(accounts.user(0)), 500);
+        assert_eq!(token_amount(accounts.user(1)), 499);
+    }
+
+    #[test]
+    fn balanced_inventory_makes_no_swap() {
+        let market = Market::new(&[1_000, 1_000], &[1_000_000; 2], &[1_000_000; 2]);
+        install(market.mock_swap());
+
+        assert_eq!(try_rebalance(&market.build(), &[5_000, 5_000]), Ok(()));
+        assert!(take_cpis().is_empty());
+    }
+
+    #[test]
+    fn rebalance_targets_must_sum_to_one_hundred_percent() {
+        assert_eq!(check_rebalance_targets(&[5_000, 5_000], 2), Ok(()));
+        assert_eq!(
+            check_rebalance_targets(&[5_000, 4_999], 2),
+            Err(ProgramError::InvalidInstructionData)
+        );
+        assert_eq!(
+            check_rebalance_targets(&[10_000], 2),
+            Err(ProgramError::InvalidInstructionData)
+        );
+    }
+}
@@ -0,0 +1,309 @@
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::{
+    account_info::AccountInfo, clock::Clock, entrypoint::ProgramResult, program::invoke_signed,
+    program_error::ProgramError, pubkey::Pubkey, rent::Rent, sysvar::Sysvar,
+};
+use solana_system_interface::instruction as system_instruction;
+
+use crate::{
+    arb::{asset_quotes, scan_opportunities, Buy, TryArbitrageArgs},
+    arb_msg,
+    error::ArbitrageProgramError,
+    result::set_return_data,
+    util::{read_versioned, versioned_len, write_versioned},
+};
+
+/// 记录套利机会的 PDA 种子前缀，完整种子为 `[OPPORTUNITY_SEED, owner]`
+pub const OPPORTUNITY_SEED: &[u8] = b"opportunity";
+
+/// `RecordOpportunity` 写入 PDA、并可由 `TryArbitrage` 读取执行的套利计划
+///
+/// 发现与执行可以因此拆分到两笔交易中：先低成本扫描并记录，再带优先费执行。
+/// 执行时会用当前余额重新检查该资产对，计划不再成立时拒绝执行。
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecordedOpportunity {
+    /// 用户代币账户的所有者
+    pub owner: Pubkey,
+    /// 记录时使用的 Swap #1 流动性池
+    pub swap_1_pool: Pubkey,
+    /// 记录时使用的 Swap #2 流动性池
+    pub swap_2_pool: Pubkey,
+    /// 支付资产的索引
+    pub mint_i: u8,
+    /// 中间资产的索引
+    pub mint_j: u8,
+    /// 买入方向
+    pub direction: Buy,
+    /// 记录时买入腿支付的资产 i 数量
+    pub pay_amount: u64,
+    /// 记录时在 Swap #1 支付资产 i 预计获得的资产 j 数量
+    pub r_swap_1: u64,
+    /// 记录时在 Swap #2 支付资产 i 预计获得的资产 j 数量
+    pub r_swap_2: u64,
+    /// 记录时的 slot
+    pub slot: u64,
+}
+
+/// 推导 `owner` 的套利计划 PDA
+pub fn find_record_address(program_id: &Pubkey, owner: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[OPPORTUNITY_SEED, owner.as_ref()], program_id)
+}
+
+/// 扫描套利机会，并把选中的机会写入套利计划 PDA
+///
+/// PDA 不存在时由 `payer` 支付租金创建；已存在时直接覆盖。
+///
+/// # 参数
+/// * `program_id` - 本程序 ID
+/// * `args` - 与 `TryArbitrage` 相同的已解析账户
+/// * `record_account` - 套利计划 PDA
+///
+/// # 返回值
+/// * `ProgramResult` - 没有可记录的机会时返回 `ArbitrageProgramError::NoArbitrage`
+pub fn record_opportunity<'a, 'b>(
+    program_id: &Pubkey,
+    args: TryArbitrageArgs<'a, 'b>,
+    record_account: &'a AccountInfo<'b>,
+) -> ProgramResult {
+    let quotes = asset_quotes(&args)?;
+    let scan = scan_opportunities(
+        &quotes,
+        &args.denied_pairs,
+        args.fees,
+        args.temperature,
+        args.options.max_edge_bps,
+        args.options.min_residual,
+        args.options.force_direction,
+        args.options.tie_break,
+        args.options.verbose,
+    )?;
+    let Some(opportunity) = scan.selected(args.options.min_qualifying_pairs) else {
+        arb_msg!("No opportunity to record");
+        set_return_data(&scan.report)?;
+        return Err(ArbitrageProgramError::NoArbitrage.into());
+    };
+
+    let record = RecordedOpportunity {
+        owner: *args.owner.key,
+        swap_1_pool: *args.swap_1_pool.key,
+        swap_2_pool: *args.swap_2_pool.key,
+        mint_i: opportunity.i as u8,
+        mint_j: opportunity.j as u8,
+        direction: opportunity.direction,
+        pay_amount: opportunity.pay_amount,
+        r_swap_1: opportunity.r_swap_1,
+        r_swap_2: opportunity.r_swap_2,
+        slot: Clock::get()?.slot,
+    };
+    let space = versioned_len(borsh::object_length(&record)?);
+
+    let (address, bump) = find_record_address(program_id, args.owner.key);
+    if !record_account.key.eq(&address) {
+        arb_msg!(
+            "Opportunity record: expected {}, got {}",
+            address,
+            record_account.key
+        );
+        return Err(ProgramError::InvalidSeeds);
+    }
+    if record_account.data_is_empty() {
+        invoke_signed(
+            &system_instruction::create_account(
+                args.payer.key,
+                record_account.key,
+                Rent::get()?.minimum_balance(space),
+                space as u64,
+                program_id,
+            ),
+            &[
+                args.payer.clone(),
+                record_account.clone(),
+                args.system_program.clone(),
+            ],
+            &[&[OPPORTUNITY_SEED, args.owner.key.as_ref(), &[bump]]],
+        )?;
+    } else if !record_account.owner.eq(program_id) {
+        arb_msg!(
+            "Opportunity record {} is not owned by this program",
+            record_account.key
+        );
+        return Err(ProgramError::IllegalOwner);
+    }
+
+    arb_msg!(
+        "Recorded pair ({},{}) buying on {:?}",
+        record.mint_i,
+        record.mint_j,
+        record.direction
+    );
+    write_versioned(record_account, &record)
+}
+
+/// 读取并校验套利计划 PDA
+///
+/// # 参数
+/// * `program_id` - 本程序 ID
+/// * `record_account` - 套利计划 PDA
+/// * `owner` - 用户代币账户的所有者
+/// * `swap_1_pool` / `swap_2_pool` - 本次指令使用的两个流动性池
+///
+/// # 错误
+/// * `ProgramError::InvalidSeeds` - PDA 地址不是由 `owner` 推导出的地址
+/// * `ProgramError::IllegalOwner` - PDA 不属于本程序
+/// * `ArbitrageProgramError::UnsupportedAccountVersion` - 计划的数据版本未知
+/// * `ProgramError::InvalidAccountData` - 计划无法解析，或记录的所有者、流动性池与本次指令不一致
+pub fn read_record(
+    program_id: &Pubkey,
+    record_account: &AccountInfo,
+    owner: &Pubkey,
+    swap_1_pool: &Pubkey,
+    swap_2_pool: &Pubkey,
+) -> Result<RecordedOpportunity, ProgramError> {
+    if !record_account
+        .key
+        .eq(&find_record_address(program_id, owner).0)
+    {
+        arb_msg!(
+            "Opportunity record {} is not the owner's PDA",
+            record_account.key
+        );
+        return Err(ProgramError::InvalidSeeds);
+    }
+    if !record_account.owner.eq(program_id) {
+        arb_msg!(
+            "Opportunity record {} is not owned by this program",
+            record_account.key
+        );
+        return Err(ProgramError::IllegalOwner);
+    }
+    let record: RecordedOpportunity = read_versioned(record_account)?;
+    if !record.owner.eq(owner)
+        || !record.swap_1_pool.eq(swap_1_pool)
+        || !record.swap_2_pool.eq(swap_2_pool)
+    {
+        arb_msg!("Opportunity record was made for different accounts");
+        return Err(ProgramError::InvalidAccountData);
+    }
+    Ok(record)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processor::process_record_opportunity;
+    use crate::testing::{
+        account, install, set_slot, set_token_amount, take_cpis, token_amount, Market,
+        MarketAccounts, PROGRAM_ID,
+    };
+    use crate::TryArbitrageOptions;
+
+    /// 在 Swap #1 买入资产 1 的市场，账户列表末尾追加尚未创建的套利计划 PDA
+    fn market_with_record() -> MarketAccounts {
+        let market = Market::new(
+            &[1_000, 0],
+            &[1_000_000, 2_000_000],
+            &[1_000_000, 1_000_000],
+        );
+        install(market.mock_swap());
+        let mut accounts = market.build();
+        let (address, _) = find_record_address(&PROGRAM_ID, accounts.owner().key);
+        accounts.accounts.push(account(
+            address,
+            solana_system_interface::program::ID,
+            vec![],
+            false,
+            true,
+            false,
+        ));
+        accounts
+    }
+
+    fn record(accounts: &MarketAccounts) -> ProgramResult {
+        process_record_opportunity(
+            &PROGRAM_ID,
+            &accounts.accounts,
+            &accounts.swap_1_program_id,
+            &accounts.swap_2_program_id,
+            accounts.concurrency as u8,
+            90,
+            TryArbitrageOptions::default(),
+        )
+    }
+
+    fn execute_plan(accounts: &MarketAccounts) -> ProgramResult {
+        accounts.try_arbitrage(
+            90,
+            TryArbitrageOptions {
+                execute_recorded_plan: true,
+                ..TryArbitrageOptions::default()
+            },
+        )
+    }
+
+    #[test]
+    fn recorded_plan_is_executed() {
+        let accounts = market_with_record();
+        set_slot(42);
+
+        assert_eq!(record(&accounts), Ok(()));
+        // 记录时只创建 PDA，不交易
+        let cpis = take_cpis();
+        assert_eq!(cpis.len(), 1);
+        assert_eq!(cpis[0].program_id, solana_system_interface::program::ID);
+        let record_account = accounts.accounts.last().unwrap();
+        assert_eq!(*record_account.owner, PROGRAM_ID);
+        let plan = read_record(
+            &PROGRAM_ID,
+            record_account,
+            accounts.owner().key,
+            accounts.accounts[7].key,
+            accounts.accounts[8].key,
+        )
+        .unwrap();
+        assert_eq!(
+            (plan.mint_i, plan.mint_j, plan.direction, plan.slot),
+            (0, 1, Buy::Swap1, 42)
+        );
+        assert_eq!(
+            (plan.pay_amount, plan.r_swap_1, plan.r_swap_2),
+            (1_000, 1_998, 999)
+        );
+
+        assert_eq!(execute_plan(&accounts), Ok(()));
+        assert_eq!(take_cpis().len(), 2);
+        assert_eq!(token_amount(accounts.user(0)), 1_994);
+    }
+
+    #[test]
+    fn stale_recorded_plan_is_rejected() {
+        let accounts = market_with_record();
+        assert_eq!(record(&accounts), Ok(()));
+        take_cpis();
+
+        // 记录之后 Swap #1 的价格回到与 Swap #2 相同
+        set_token_amount(accounts.swap_1(1), 1_000_000);
+        assert_eq!(
+            execute_plan(&accounts),
+            Err(ArbitrageProgramError::StaleRecordedOpportunity.into())
+        );
+        assert!(take_cpis().is_empty());
+        assert_eq!(token_amount(accounts.user(0)), 1_000);
+    }
+
+    #[test]
+    fn plan_recorded_for_other_pools_is_rejected() {
+        let accounts = market_with_record();
+        assert_eq!(record(&accounts), Ok(()));
+
+        assert_eq!(
+            read_record(
+                &PROGRAM_ID,
+                accounts.accounts.last().unwrap(),
+                accounts.owner().key,
+                accounts.accounts[8].key,
+                accounts.accounts[7].key,
+            ),
+            Err(ProgramError::InvalidAccountData)
+        );
+    }
+}
@@ -0,0 +1,346 @@
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::{program_error::ProgramError, pubkey::Pubkey};
+
+use crate::arb::Buy;
+
+/// 将 `value` 按 Borsh 序列化后写入返回数据
+///
+/// 本程序所有通过 `set_return_data` 返回的结构都经由该函数写入，保证编码一致。
+pub fn set_return_data<T: BorshSerialize>(value: &T) -> Result<(), ProgramError> {
+    solana_program::program::set_return_data(&borsh::to_vec(value)?);
+    Ok(())
+}
+
+/// 一条交易腿实际成交的数量
+///
+/// 数量由 CPI 前后用户代币账户的余额变化得出，而不是交易前的预估值。
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LegResult {
+    /// 执行该腿的交易所
+    pub venue: Buy,
+    /// 支付资产的索引
+    pub mint_in: u8,
+    /// 接收资产的索引
+    pub mint_out: u8,
+    /// 实际支付的数量
+    pub amount_in: u64,
+    /// 实际接收的数量
+    pub amount_out: u64,
+}
+
+/// 套利成功后通过 `set_return_data` 返回的结构化结果
+///
+/// 使用 Borsh 序列化，所有数量均为小端序 `u64`，与发送给 swap 程序的指令数据编码一致。
+/// 链下可以在模拟交易后通过 `get_return_data` 读取并反序列化。
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ArbitrageResult {
+    /// 买入方向
+    pub direction: Buy,
+    /// 支付资产的索引
+    pub mint_i: u8,
+    /// 中间资产的索引
+    pub mint_j: u8,
+    /// 买入腿支付的资产 i 数量
+    pub amount_in: u64,
+    /// 买入腿预计获得、并在卖出腿支付的资产 j 数量
+    pub intermediate_amount: u64,
+    /// 卖出腿预计换回的资产 i 数量
+    pub expected_amount_out: u64,
+    /// 按执行顺序排列的每条交易腿实际成交的数量，后一条腿支付的资产即前一条腿接收的资产
+    pub legs: Vec<LegResult>,
+    /// 卖出腿实际换回的资产 i 超出 `expected_amount_out` 的数量（正滑点），不足预估时为 0
+    pub positive_slippage: u64,
+    /// 整个往返实际的兑换率：卖出腿换回的资产 i 除以买入腿支付的资产 i，
+    /// 以 `EFFECTIVE_RATE_SCALE` 为 1 的定点数表示（向下取整）
+    pub effective_rate: u64,
+}
+
+/// `ArbitrageResult::effective_rate` 的定点数精度，兑换率 1.0 表示为该值
+pub const EFFECTIVE_RATE_SCALE: u64 = 1_000_000_000;
+
+impl ArbitrageResult {
+    /// 由实际支付与换回的资产 i 数量计算 `effective_rate`
+    ///
+    /// 支付数量为零时返回 0，超出 `u64` 的结果饱和为 `u64::MAX`。
+    pub fn effective_rate(amount_in: u64, amount_out: u64) -> u64 {
+        if amount_in == 0 {
+            return 0;
+        }
+        let rate =
+            u128::from(amount_out) * u128::from(EFFECTIVE_RATE_SCALE) / u128::from(amount_in);
+        u64::try_from(rate).unwrap_or(u64::MAX)
+    }
+}
+
+/// 资产对被跳过的原因数量
+pub const SKIP_REASONS: usize = 8;
+
+/// 扫描时某个资产对没有成为套利机会的原因
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SkipReason {
+    /// 资产对中有资产被排除在扫描之外
+    Excluded,
+    /// 至少一个交易池的接收预估值为零
+    ZeroReceive,
+    /// 至少一个交易池的接收预估值超过池子余额
+    InsufficientLiquidity,
+    /// 价差没有超过温度阈值
+    BelowThreshold,
+    /// 资产对在程序配置的黑名单中
+    Denied,
+    /// 用户的支付资产余额为零
+    ZeroBalance,
+    /// 价差超过 `max_edge_bps`，多半是池子状态陈旧或异常
+    TooGoodToBeTrue,
+    /// 兑换后池子留下的资产 j 少于 `min_residual`
+    BelowMinResidual,
+}
+
+/// 未执行交易时通过 `set_return_data` 返回的扫描统计，便于调优策略参数
+///
+/// `skipped_by_reason` 按 `SkipReason` 的声明顺序计数，计数达到 `u16::MAX` 后不再增加。
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ScanReport {
+    /// 扫描过的资产对数量
+    pub scanned: u16,
+    /// 按原因统计的被跳过的资产对数量
+    pub skipped_by_reason: [u16; SKIP_REASONS],
+    /// 跳过资产对最多的原因，客户端可以据此调整参数；计数相同时保留先达到该计数的原因，
+    /// 没有跳过任何资产对时为 `None`
+    pub dominant_reason: Option<SkipReason>,
+}
+
+impl ScanReport {
+    /// 记录一个因 `reason` 被跳过的资产对
+    pub fn skip(&mut self, reason: SkipReason) {
+        let count = &mut self.skipped_by_reason[reason as usize];
+        *count = count.saturating_add(1);
+        let count = *count;
+        if self
+            .dominant_reason
+            .is_none_or(|dominant| count > self.skipped_by_reason[dominant as usize])
+        {
+            self.dominant_reason = Some(reason);
+        }
+    }
+}
+
+/// 设置了 `report_no_arbitrage` 且没有执行交易时，指令成功返回并通过 `set_return_data` 写入的报告
+///
+/// 客户端无需一次失败的预检就能知道市场离阈值还有多远。
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct NoArbReport {
+    /// 所有可定价资产对中最大的价差（基点），没有可定价的资产对时为 0
+    pub best_edge_bps: u64,
+    /// 价差最大的资产对 (i, j)，没有可定价的资产对时为 `None`
+    pub best_pair: Option<(u8, u8)>,
+    /// 本次扫描的统计
+    pub scan: ScanReport,
+}
+
+/// 设置了 `quote_max_size` 时通过 `set_return_data` 返回的报价，不执行交易
+///
+/// `max_size` 是仍满足 `temperature` 阈值且预估利润不低于 `min_profit` 的最大支付数量，
+/// 不超过用户的资产 i 余额；没有满足条件的数量时为 0。
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MaxSizeQuote {
+    /// 买入方向
+    pub direction: Buy,
+    /// 支付资产的索引
+    pub mint_i: u8,
+    /// 中间资产的索引
+    pub mint_j: u8,
+    /// 最大的可盈利支付数量
+    pub max_size: u64,
+    /// 按 `max_size` 交易时预估的利润（资产 i 的最小单位）
+    pub expected_profit: u64,
+}
+
+/// 预估利润未达到 `min_profit` 的安全倍数时通过 `set_return_data` 返回的报价，不执行交易
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TradeQuote {
+    /// 买入方向
+    pub direction: Buy,
+    /// 支付资产的索引
+    pub mint_i: u8,
+    /// 中间资产的索引
+    pub mint_j: u8,
+    /// 买入腿支付的资产 i 数量
+    pub amount_in: u64,
+    /// 预估的利润（资产 i 的最小单位）
+    pub expected_profit: u64,
+    /// 执行交易所需的利润：`min_profit * safety_multiple_bps / 10_000`
+    pub required_profit: u64,
+}
+
+/// `DebugParse` 通过 `set_return_data` 返回的解析结果
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParsedAccount {
+    /// 按 `PartialTokenAccountState` 解析的代币账户
+    TokenAccount {
+        mint: Pubkey,
+        owner: Pubkey,
+        amount: u64,
+    },
+    /// 按 `PartialMintState` 解析的铸币账户
+    Mint { decimals: u8, supply: u64 },
+}
+
+/// `TryArbitrageMulti` 通过 `set_return_data` 返回的每次尝试的结果码
+///
+/// 结果码按尝试顺序排列：0 表示该尝试成功返回，其他值为该尝试返回的
+/// `ArbitrageProgramError` 错误码（目前只有 `NoArbitrage` 会被隔离）。
+///
+/// 只返回结果码：每次尝试自己写入的返回数据（`ArbitrageResult`、`ScanReport` 等）
+/// 会被之后的尝试和本结构覆盖，需要这些细节时应单独发送 `TryArbitrage`。
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq, Eq)]
+pub struct MultiArbitrageResult {
+    /// 按尝试顺序排列的结果码
+    pub codes: Vec<u32>,
+}
+
+/// 三角套利成功后通过 `set_return_data` 返回的结果
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TriangularResult {
+    /// 循环经过的资产索引 `[a, b, c]`，依次在 Swap #1、Swap #2、Swap #3 上兑换
+    pub mints: [u8; 3],
+    /// 第一条腿支付的资产 a 数量
+    pub amount_in: u64,
+    /// 最后一条腿预计换回的资产 a 数量
+    pub expected_amount_out: u64,
+    /// 用户资产 a 余额实际增加的数量
+    pub profit: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{install, return_data, take_cpis, Market, MockSwap};
+    use crate::TryArbitrageOptions;
+
+    #[test]
+    fn return_data_and_ix_data_amounts_are_little_endian() {
+        let market = Market::new(&[1_000, 0], &[1_000_000, 2_000_000], &[1_000_000; 2]);
+        install(market.mock_swap());
+        let accounts = market.build();
+        assert_eq!(
+            accounts.try_arbitrage(90, TryArbitrageOptions::default()),
+            Ok(())
+        );
+
+        let cpis = take_cpis();
+        let (_, data) = return_data().unwrap();
+        let result = ArbitrageResult::try_from_slice(&data).unwrap();
+        // 买入腿的指令数据在 8 字节指令标识之后是支付数量
+        assert_eq!(cpis[0].data[8..], result.amount_in.to_le_bytes());
+        assert_eq!(cpis[1].data[8..], result.intermediate_amount.to_le_bytes());
+        // 返回数据中 `amount_in` 紧跟在方向与两个资产索引之后，编码相同
+        assert_eq!(data[3..11], cpis[0].data[8..]);
+    }
+
+    #[test]
+    fn set_return_data_writes_the_borsh_encoding() {
+        install(MockSwap::default());
+        let report = NoArbReport {
+            best_edge_bps: 0x0102,
+            best_pair: Some((1, 2)),
+            scan: ScanReport::default(),
+        };
+        set_return_data(&report).unwrap();
+        assert_eq!(return_data().unwrap().1, borsh::to_vec(&report).unwrap());
+    }
+
+    #[test]
+    fn dominant_reason_keeps_the_first_to_reach_the_top_count() {
+        let mut report = ScanReport::default();
+        assert_eq!(report.dominant_reason, None);
+        report.skip(SkipReason::ZeroBalance);
+        report.skip(SkipReason::BelowThreshold);
+        assert_eq!(report.dominant_reason, Some(SkipReason::ZeroBalance));
+        report.skip(SkipReason::BelowThreshold);
+        assert_eq!(report.dominant_reason, Some(SkipReason::BelowThreshold));
+
+        report.skipped_by_reason[SkipReason::Denied as usize] = u16::MAX;
+        report.skip(SkipReason::Denied);
+        assert_eq!(
+            report.skipped_by_reason[SkipReason::Denied as usize],
+            u16::MAX
+        );
+    }
+
+    #[test]
+    fn effective_rate_matches_the_realized_amounts() {
+        let market = Market::new(&[1_000, 0], &[1_000_000, 2_000_000], &[1_000_000; 2]);
+        install(market.mock_swap());
+        assert_eq!(
+            market
+                .build()
+                .try_arbitrage(90, TryArbitrageOptions::default()),
+            Ok(())
+        );
+
+        let result = ArbitrageResult::try_from_slice(&return_data().unwrap().1).unwrap();
+        let (buy, sell) = (&result.legs[0], &result.legs[1]);
+        assert_eq!((buy.amount_in, sell.amount_out), (1_000, 1_994));
+        // 1994 / 1000 = 1.994
+        assert_eq!(result.effective_rate, 1_994_000_000);
+        assert_eq!(
+            u128::from(result.effective_rate) * u128::from(buy.amount_in),
+            u128::from(sell.amount_out) * u128::from(EFFECTIVE_RATE_SCALE)
+        );
+    }
+
+    #[test]
+    fn effective_rate_edge_cases() {
+        // 向下取整、支付数量为零、以及超出 `u64` 时饱和
+        assert_eq!(ArbitrageResult::effective_rate(3, 2), 666_666_666);
+        assert_eq!(ArbitrageResult::effective_rate(0, 1_000), 0);
+        assert_eq!(ArbitrageResult::effective_rate(1, u64::MAX), u64::MAX);
+    }
+
+    #[test]
+    fn return_data_deserializes_into_the_full_result() {
+        // 用户只持有资产 1，Swap #2 的资产 2 更便宜：在 Swap #2 买入资产 2、在 Swap #1 卖出
+        let market = Market::new(
+            &[0, 1_000, 0],
+            &[1_000_000; 3],
+            &[1_000_000, 1_000_000, 2_000_000],
+        );
+        install(market.mock_swap());
+        assert_eq!(
+            market
+                .build()
+                .try_arbitrage(90, TryArbitrageOptions::default()),
+            Ok(())
+        );
+
+        let (_, data) = return_data().unwrap();
+        let result = ArbitrageResult::try_from_slice(&data).unwrap();
+        let leg = |venue, mint_in, mint_out, amount_in, amount_out| LegResult {
+            venue,
+            mint_in,
+            mint_out,
+            amount_in,
+            amount_out,
+        };
+        assert_eq!(
+            result,
+            ArbitrageResult {
+                direction: Buy::Swap2,
+                mint_i: 1,
+                mint_j: 2,
+                amount_in: 1_000,
+                intermediate_amount: 1_998,
+                expected_amount_out: 1_994,
+                legs: vec![
+                    leg(Buy::Swap2, 1, 2, 1_000, 1_998),
+                    leg(Buy::Swap1, 2, 1, 1_998, 1_994),
+                ],
+                positive_slippage: 0,
+                effective_rate: 1_994_000_000,
+            }
+        );
+        // 返回数据正好是该结构的 Borsh 编码，没有多余的字节
+        assert_eq!(borsh::to_vec(&result).unwrap(), data);
+    }
+}
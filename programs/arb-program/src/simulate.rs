@@ -0,0 +1,339 @@
+use crate::arb::{scan_opportunities, AssetQuote, Buy, PoolFees, TieBreak};
+
+/// 某个交易所流动性池在某一时刻的快照
+///
+/// `balances` 按资产顺序排列，与 `TryArbitrage` 指令中池子代币账户的顺序一致。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PoolSnapshot {
+    /// 流动性池持有的各资产余额
+    pub balances: Vec<u64>,
+    /// 流动性池的手续费率（基点）
+    pub fee_bps: u16,
+    /// 加权池中各资产的权重，为空时按恒定乘积公式定价
+    pub weights: Vec<u8>,
+    /// 读取该快照时的 slot
+    pub slot: u64,
+}
+
+/// 某个资产铸币账户的快照
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MintSnapshot {
+    /// 资产的小数位数
+    pub decimals: u8,
+}
+
+/// 流动性池快照过旧，调用方需要重新读取储备
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StaleSnapshot {
+    /// 过旧快照在 `pools` 中的索引
+    pub pool: usize,
+    /// 读取该快照时的 slot
+    pub snapshot_slot: u64,
+    /// 当前 slot
+    pub current_slot: u64,
+}
+
+/// 检查所有流动性池快照都是在最近 `max_snapshot_age_slots` 个 slot 内读取的
+///
+/// 快照的 slot 晚于当前 slot（例如来自领先的 RPC 节点）时视为新鲜。
+///
+/// # 返回值
+/// * `Ok(())` - 所有快照都足够新
+/// * `Err(StaleSnapshot)` - 第一个过旧的快照
+pub fn check_snapshot_age(
+    pools: &[PoolSnapshot],
+    current_slot: u64,
+    max_snapshot_age_slots: u64,
+) -> Result<(), StaleSnapshot> {
+    match pools
+        .iter()
+        .position(|pool| current_slot.saturating_sub(pool.slot) > max_snapshot_age_slots)
+    {
+        Some(index) => Err(StaleSnapshot {
+            pool: index,
+            snapshot_slot: pools[index].slot,
+            current_slot,
+        }),
+        None => Ok(()),
+    }
+}
+
+/// 模拟得到的套利交易
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SimulatedTrade {
+    /// 支付资产的索引
+    pub i: usize,
+    /// 中间资产的索引
+    pub j: usize,
+    /// 买入方向
+    pub direction: Buy,
+    /// 两个交易池报价之间的价差（基点）
+    pub edge_bps: u64,
+    /// 买入腿支付的资产 i 数量
+    pub buy_amount: u64,
+    /// 卖出腿支付的资产 j 数量（买入腿预计获得的数量）
+    pub sell_amount: u64,
+}
+
+/// 在历史快照上运行与链上 `try_arbitrage` 完全相同的选择逻辑
+///
+/// 该函数不依赖任何 `AccountInfo`，便于机器人作者使用与链上相同的代码路径进行回测。
+///
+/// # 参数
+/// * `pools` - 两个交易所流动性池的快照，依次为 Swap #1 和 Swap #2
+/// * `mints` - 各资产铸币账户的快照
+/// * `balances` - 用户持有的各资产余额
+/// * `temperature` - 套利温度阈值
+///
+/// # 返回值
+/// * `Some(SimulatedTrade)` - 链上会执行的交易
+/// * `None` - 链上不会交易（未发现套利机会、快照不完整或定价失败）
+pub fn simulate_arbitrage(
+    pools: &[PoolSnapshot],
+    mints: &[MintSnapshot],
+    balances: &[u64],
+    temperature: u8,
+) -> Option<SimulatedTrade> {
+    let [swap_1, swap_2] = pools else {
+        return None;
+    };
+    // 小数位数在定价公式中相互抵消，定价只需要各资产的余额与权重
+    let quotes = (0..mints.len())
+        .map(|i| {
+            Some(AssetQuote {
+                user_balance: *balances.get(i)?,
+                swap_1_balance: *swap_1.balances.get(i)?,
+                swap_2_balance: *swap_2.balances.get(i)?,
+                swap_1_weight: swap_1.weights.get(i).copied().unwrap_or(0),
+                swap_2_weight: swap_2.weights.get(i).copied().unwrap_or(0),
+                excluded: false,
+            })
+        })
+        .collect::<Option<Vec<_>>>()?;
+
+    let fees = PoolFees {
+        swap_1_fee_bps: swap_1.fee_bps,
+        swap_2_fee_bps: swap_2.fee_bps,
+    };
+    let scan = scan_opportunities(
+        &quotes,
+        &[],
+        fees,
+        temperature,
+        None,
+        0,
+        None,
+        TieBreak::default(),
+        false,
+    )
+    .ok()?;
+    let opportunity = scan.selected(0)?;
+    Some(SimulatedTrade {
+        i: opportunity.i,
+        j: opportunity.j,
+        direction: opportunity.direction,
+        edge_bps: opportunity.edge_bps,
+        buy_amount: opportunity.pay_amount,
+        sell_amount: match opportunity.direction {
+            Buy::Swap1 => opportunity.r_swap_1,
+            Buy::Swap2 => opportunity.r_swap_2,
+        },
+    })
+}
+
+/// 先检查快照新鲜度，再运行 `simulate_arbitrage`
+///
+/// 缓存的储备一旦过旧就可能给出链上已不存在的套利机会，此时返回 `StaleSnapshot`，
+/// 由调用方重新读取储备后再模拟。
+///
+/// # 参数
+/// * `current_slot` - 当前 slot
+/// * `max_snapshot_age_slots` - 快照允许的最大年龄（slot 数）
+/// * 其余参数与 `simulate_arbitrage` 相同
+pub fn simulate_arbitrage_fresh(
+    pools: &[PoolSnapshot],
+    mints: &[MintSnapshot],
+    balances: &[u64],
+    temperature: u8,
+    current_slot: u64,
+    max_snapshot_age_slots: u64,
+) -> Result<Option<SimulatedTrade>, StaleSnapshot> {
+    check_snapshot_age(pools, current_slot, max_snapshot_age_slots)?;
+    Ok(simulate_arbitrage(pools, mints, balances, temperature))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::result::ArbitrageResult;
+    use crate::testing::{install, return_data, take_cpis, Market, MockPool, MockSwap};
+    use crate::TryArbitrageOptions;
+    use borsh::BorshDeserialize;
+
+    fn snapshot(balances: &[u64], fee_bps: u16) -> PoolSnapshot {
+        PoolSnapshot {
+            balances: balances.to_vec(),
+            fee_bps,
+            weights: vec![],
+            slot: 0,
+        }
+    }
+
+    /// 在模拟的链上环境中对同样的储备执行 `TryArbitrage`，返回写入的交易结果
+    fn on_chain(
+        pools: &[PoolSnapshot],
+        balances: &[u64],
+        temperature: u8,
+    ) -> Option<ArbitrageResult> {
+        let mut market = Market::new(balances, &pools[0].balances, &pools[1].balances);
+        market.swap_1_pool_data[..2].copy_from_slice(&pools[0].fee_bps.to_le_bytes());
+        market.swap_2_pool_data[..2].copy_from_slice(&pools[1].fee_bps.to_le_bytes());
+        install(MockSwap::new(vec![
+            MockPool {
+                fee_bps: pools[0].fee_bps,
+                ..MockPool::new(market.swap_1_program_id)
+            },
+            MockPool {
+                fee_bps: pools[1].fee_bps,
+                ..MockPool::new(market.swap_2_program_id)
+            },
+        ]));
+        let options = TryArbitrageOptions {
+            swap_1_fee_offset: Some(0),
+            swap_2_fee_offset: Some(0),
+            ..TryArbitrageOptions::default()
+        };
+        match market.build().try_arbitrage(temperature, options) {
+            Ok(()) => Some(ArbitrageResult::try_from_slice(&return_data()?.1).unwrap()),
+            Err(_) => {
+                assert!(take_cpis().is_empty());
+                None
+            }
+        }
+    }
+
+    #[test]
+    fn simulated_decisions_match_on_chain_decisions() {
+        let cases: &[(&[PoolSnapshot], &[u64], u8)] = &[
+            // Swap #1 上资产 1 更便宜
+            (
+                &[
+                    snapshot(&[1_000_000, 2_000_000], 0),
+                    snapshot(&[1_000_000; 2], 0),
+                ],
+                &[1_000, 0],
+                90,
+            ),
+            // Swap #2 上资产 1 更便宜
+            (
+                &[
+                    snapshot(&[1_000_000; 2], 0),
+                    snapshot(&[1_000_000, 2_000_000], 0),
+                ],
+                &[1_000, 0],
+                90,
+            ),
+            // 两个池子报价相同
+            (
+                &[snapshot(&[1_000_000; 2], 0), snapshot(&[1_000_000; 2], 0)],
+                &[1_000, 0],
+                90,
+            ),
+            // 价差低于阈值
+            (
+                &[
+                    snapshot(&[1_000_000, 1_050_000], 0),
+                    snapshot(&[1_000_000; 2], 0),
+                ],
+                &[1_000, 0],
+                90,
+            ),
+            // 三个资产、两个池子都收手续费
+            (
+                &[
+                    snapshot(&[1_000_000, 1_100_000, 2_000_000], 30),
+                    snapshot(&[1_000_000, 1_000_000, 1_500_000], 25),
+                ],
+                &[500, 800, 0],
+                95,
+            ),
+        ];
+        let mints = [MintSnapshot { decimals: 6 }; 3];
+        for (k, (pools, balances, temperature)) in cases.iter().enumerate() {
+            let simulated =
+                simulate_arbitrage(pools, &mints[..balances.len()], balances, *temperature);
+            let executed = on_chain(pools, balances, *temperature);
+            match (simulated, executed) {
+                (None, None) => {}
+                (Some(trade), Some(result)) => {
+                    assert_eq!(trade.direction, result.direction, "case {}", k);
+                    assert_eq!(
+                        (trade.i, trade.j),
+                        (usize::from(result.mint_i), usize::from(result.mint_j))
+                    );
+                    assert_eq!(trade.buy_amount, result.amount_in, "case {}", k);
+                    assert_eq!(trade.sell_amount, result.intermediate_amount, "case {}", k);
+                }
+                (simulated, executed) => panic!(
+                    "case {}: simulated {:?}, executed {:?}",
+                    k, simulated, executed
+                ),
+            }
+        }
+    }
+
+    #[test]
+    fn simulate_arbitrage_needs_two_complete_pools() {
+        let mints = [MintSnapshot { decimals: 6 }; 2];
+        let pool = snapshot(&[1_000_000, 2_000_000], 0);
+        assert_eq!(
+            simulate_arbitrage(std::slice::from_ref(&pool), &mints, &[1_000, 0], 90),
+            None
+        );
+        assert_eq!(
+            simulate_arbitrage(&[pool, snapshot(&[1_000_000], 0)], &mints, &[1_000, 0], 90),
+            None
+        );
+    }
+
+    fn snapshots_at(slots: [u64; 2]) -> [PoolSnapshot; 2] {
+        slots.map(|slot| PoolSnapshot {
+            slot,
+            ..snapshot(&[1_000_000, 2_000_000], 0)
+        })
+    }
+
+    #[test]
+    fn fresh_snapshot_is_simulated() {
+        let mut pools = snapshots_at([95, 100]);
+        pools[1].balances = vec![1_000_000; 2];
+        let mints = [MintSnapshot { decimals: 6 }; 2];
+
+        let trade = simulate_arbitrage_fresh(&pools, &mints, &[1_000, 0], 90, 105, 10);
+        assert_eq!(
+            trade,
+            Ok(simulate_arbitrage(&pools, &mints, &[1_000, 0], 90))
+        );
+        assert!(trade.unwrap().is_some());
+        // 快照的 slot 晚于当前 slot 时也视为新鲜
+        assert_eq!(
+            check_snapshot_age(&snapshots_at([120, 100]), 105, 10),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn stale_snapshot_forces_a_fresh_read() {
+        let pools = snapshots_at([100, 94]);
+        let mints = [MintSnapshot { decimals: 6 }; 2];
+
+        assert_eq!(
+            simulate_arbitrage_fresh(&pools, &mints, &[1_000, 0], 90, 105, 10),
+            Err(StaleSnapshot {
+                pool: 1,
+                snapshot_slot: 94,
+                current_slot: 105,
+            })
+        );
+    }
+}
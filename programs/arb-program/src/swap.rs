@@ -1,5 +1,6 @@
 use crate::error::ArbitrageProgramError;
 use solana_program::program_error::ProgramError;
+#[cfg(all(feature = "client", not(feature = "integer_only")))]
 use std::ops::{Add, Div, Mul};
 
 /// 计算交换操作中接收方应获得的代币数量
@@ -17,10 +18,11 @@ use std::ops::{Add, Div, Mul};
 /// * `pool_pay_balance` - 支付代币的池子余额
 /// * `pay_decimals` - 支付代币的小数位数
 /// * `pay_amount` - 支付的代币数量
+/// * `min_receive` - 最少应接收的代币数量（滑点下限），为 0 时不做限制
 ///
 /// # 返回值
 /// * `Ok(u64)` - 计算出的应接收代币数量
-
+///
 // 处理流程
 // 小数位标准化：使用 convert_to_float 函数将整数形式的代币数量转换为浮点数，以正确处理小数精度
 // 恒定乘积计算：应用公式计算用户应该收到的代币数量
@@ -28,11 +30,11 @@ use std::ops::{Add, Div, Mul};
 // 结果转换：将浮点数结果转换回整数形式的代币数量
 // 示例
 // 假设我们有一个交换池，包含以下参数：
-
+//
 // 1000 USDC (pool_receive_balance)，有6位小数 (receive_decimals)
 // 5000 DAI (pool_pay_balance)，有18位小数 (pay_decimals)
 // 用户想要支付100 DAI (pay_amount)
-
+//
 // 计算过程：
 // R = 1000 (标准化后)
 // P = 5000 (标准化后)
@@ -41,24 +43,29 @@ use std::ops::{Add, Div, Mul};
 // 假设初始池子状态：
 // USDC池：1000个 (pool_receive_balance = 1000_000000，6位小数)
 // DAI池：5000个 (pool_pay_balance = 5000_000000000000000000，18位小数)
-
+//
 // 当前兑换率计算：
 // 价格由公式 R * P = k 决定
 // 即：1000 * 5000 = 5,000,000 (这是k值)
-
+//
 // 如果用户想用DAI换USDC：
 // 用户支付100 DAI后，DAI池变为：5000 + 100 = 5100
 // 新的USDC池数量：5,000,000 / 5100 ≈ 980.39
 // 用户得到的USDC：1000 - 980.39 = 19.61
-
+//
 // 这意味着大约 100 DAI = 19.61 USDC，即 1 USDC ≈ 5.1 DAI
-/// * `Err(ProgramError)` - 计算错误或流动性不足时返回错误
+/// * `Err(ProgramError)` - 计算错误、流动性不足或低于 `min_receive` 时返回错误
+///
+/// 只在 `client` 构建中提供，供客户端按带小数位的数量估算报价；
+/// 链上定价只使用 `determine_swap_receive_checked` 及其加权版本
+#[cfg(all(feature = "client", not(feature = "integer_only")))]
 pub fn determine_swap_receive(
     pool_receive_balance: u64,
     receive_decimals: u8,
     pool_pay_balance: u64,
     pay_decimals: u8,
     pay_amount: u64,
+    min_receive: u64,
 ) -> Result<u64, ProgramError> {
     // 将整数金额转换为浮点数进行计算
     let big_r = convert_to_float(pool_receive_balance, receive_decimals);
@@ -74,7 +81,350 @@ pub fn determine_swap_receive(
     if r > big_r {
         return Err(ArbitrageProgramError::InvalidSwapNotEnoughLiquidity.into());
     }
-    Ok(convert_from_float(r, receive_decimals))
+    // f64 的换算可能向上舍入，结果不得超过精确有理数值向下取整的结果，避免高估接收数量
+    let r = convert_from_float(r, receive_decimals).min(exact_receive(
+        pool_receive_balance,
+        pool_pay_balance,
+        pay_amount,
+    ));
+
+    // 检查计算结果是否低于滑点下限
+    check_min_receive(r, min_receive)?;
+    Ok(r)
+}
+
+/// `integer_only` 构建中的 `determine_swap_receive`：直接使用 `determine_swap_receive_checked`
+/// 的整数实现，小数位数在恒定乘积公式中相互抵消，不参与计算
+#[cfg(all(feature = "client", feature = "integer_only"))]
+pub fn determine_swap_receive(
+    pool_receive_balance: u64,
+    _receive_decimals: u8,
+    pool_pay_balance: u64,
+    _pay_decimals: u8,
+    pay_amount: u64,
+    min_receive: u64,
+) -> Result<u64, ProgramError> {
+    determine_swap_receive_checked(
+        pool_receive_balance,
+        pool_pay_balance,
+        pay_amount,
+        min_receive,
+    )
+}
+
+/// 使用整数运算计算交换操作中接收方应获得的代币数量
+///
+/// 与 `determine_swap_receive` 使用相同的恒定乘积公式 `r = (R * p) / (P + p)`，但全程使用
+/// `u128` 整数运算。`R` 与 `r` 同为接收代币的最小单位，`P` 与 `p` 同为支付代币的最小单位，
+/// 小数位数在公式中相互抵消，因此直接在最小单位上计算即可得到精确结果（向下取整），
+/// 不会像浮点实现那样在 18 位小数、支付数量很小时把结果舍入为零。
+///
+/// 接收数量始终向下取整，永远不会大于精确有理数值；需要扣除手续费时，
+/// 先用 `apply_fee`（手续费向上取整）处理支付数量。
+///
+/// # 参数
+/// * `pool_receive_balance` - 接收代币的池子余额
+/// * `pool_pay_balance` - 支付代币的池子余额
+/// * `pay_amount` - 支付的代币数量
+/// * `min_receive` - 最少应接收的代币数量（滑点下限），为 0 时不做限制
+///
+/// # 返回值
+/// * `Ok(u64)` - 计算出的应接收代币数量
+/// * `Err(ProgramError)` - 结果超过池子余额时返回 `InvalidSwapNotEnoughLiquidity`，
+///   低于 `min_receive` 时返回 `BelowMinReceive`
+pub fn determine_swap_receive_checked(
+    pool_receive_balance: u64,
+    pool_pay_balance: u64,
+    pay_amount: u64,
+    min_receive: u64,
+) -> Result<u64, ProgramError> {
+    let r = exact_receive(pool_receive_balance, pool_pay_balance, pay_amount);
+
+    // 检查计算结果是否超过池子余额，防止流动性不足
+    if r > pool_receive_balance {
+        return Err(ArbitrageProgramError::InvalidSwapNotEnoughLiquidity.into());
+    }
+
+    // 检查计算结果是否低于滑点下限
+    check_min_receive(r, min_receive)?;
+    Ok(r)
+}
+
+/// 计算加权池（Balancer 式）中接收方应获得的代币数量
+///
+/// 使用公式: r = R * (1 - (P / (P + p)) ^ (w_in / w_out))，
+/// 其中 `w_in`、`w_out` 分别是支付代币与接收代币在池子中的权重。
+/// 幂运算使用 Q63 定点数完成：先开 `w_out` 次方根再求 `w_in` 次幂（指数先约分），
+/// 中间结果向上取整，使接收数量向下偏差，误差在最小单位级别。
+///
+/// # 参数
+/// * `pool_receive_balance` - 接收代币的池子余额
+/// * `pool_pay_balance` - 支付代币的池子余额
+/// * `pay_amount` - 支付的代币数量
+/// * `weights` - `(w_in, w_out)`，任一权重为 0 或两者相等时按恒定乘积公式精确计算
+/// * `min_receive` - 最少应接收的代币数量（滑点下限），为 0 时不做限制
+///
+/// # 返回值
+/// * `Ok(u64)` - 计算出的应接收代币数量
+/// * `Err(ProgramError)` - 与 `determine_swap_receive_checked` 相同
+pub fn determine_weighted_swap_receive_checked(
+    pool_receive_balance: u64,
+    pool_pay_balance: u64,
+    pay_amount: u64,
+    weights: (u8, u8),
+    min_receive: u64,
+) -> Result<u64, ProgramError> {
+    let (w_in, w_out) = weights;
+    if w_in == 0 || w_out == 0 || w_in == w_out {
+        return determine_swap_receive_checked(
+            pool_receive_balance,
+            pool_pay_balance,
+            pay_amount,
+            min_receive,
+        );
+    }
+    let bigp_plus_p = u128::from(pool_pay_balance) + u128::from(pay_amount);
+    let r = if bigp_plus_p == 0 {
+        0
+    } else {
+        // base = P / (P + p) <= 1，u64 乘以 2^63 不会超出 u128 的范围
+        let base = (u128::from(pool_pay_balance) * FIXED_ONE).div_ceil(bigp_plus_p);
+        let divisor = gcd(u128::from(w_in), u128::from(w_out));
+        let exp_num = (u128::from(w_in) / divisor) as u32;
+        let exp_den = (u128::from(w_out) / divisor) as u32;
+        let ratio = fixed_pow_ceil(fixed_root_ceil(base, exp_den), exp_num);
+        // ratio <= 1，结果不超过 R，转换回 u64 不会失败
+        (u128::from(pool_receive_balance) * (FIXED_ONE - ratio) / FIXED_ONE) as u64
+    };
+
+    // 检查计算结果是否低于滑点下限
+    check_min_receive(r, min_receive)?;
+    Ok(r)
+}
+
+/// 加权池定价使用的 Q63 定点数中的 1.0；两个不超过 1.0 的值相乘不会超出 u128 的范围
+const FIXED_ONE: u128 = 1 << 63;
+
+/// 两个不超过 1.0 的 Q63 定点数相乘，结果向上取整且不超过 1.0
+fn fixed_mul_ceil(a: u128, b: u128) -> u128 {
+    (a * b).div_ceil(FIXED_ONE)
+}
+
+/// 两个不超过 1.0 的 Q63 定点数相乘，结果向下取整
+fn fixed_mul_floor(a: u128, b: u128) -> u128 {
+    a * b / FIXED_ONE
+}
+
+/// 不超过 1.0 的 Q63 定点数的 `exp` 次幂，结果向上取整
+fn fixed_pow_ceil(mut base: u128, mut exp: u32) -> u128 {
+    let mut result = FIXED_ONE;
+    while exp > 0 {
+        if exp & 1 == 1 {
+            result = fixed_mul_ceil(result, base);
+        }
+        base = fixed_mul_ceil(base, base);
+        exp >>= 1;
+    }
+    result
+}
+
+/// 不超过 1.0 的 Q63 定点数的 `exp` 次幂，结果向下取整
+fn fixed_pow_floor(mut base: u128, mut exp: u32) -> u128 {
+    let mut result = FIXED_ONE;
+    while exp > 0 {
+        if exp & 1 == 1 {
+            result = fixed_mul_floor(result, base);
+        }
+        base = fixed_mul_floor(base, base);
+        exp >>= 1;
+    }
+    result
+}
+
+/// `fixed_root_ceil` 最多执行的牛顿迭代次数
+const ROOT_NEWTON_STEPS: u32 = 8;
+
+/// 不超过 1.0 的 Q63 定点数的 `n` 次方根的上界，结果满足 `x ^ n >= value`
+///
+/// 先由 `value` 的最高位得到满足 `x0 / 2 <= root <= x0` 的初值，二分 `bitlen(n) + 2` 次把相对误差
+/// 缩小到 `1 / 4n` 以内，再从上方做至多 `ROOT_NEWTON_STEPS` 次向上取整的牛顿迭代。
+/// 由算术-几何平均不等式，每次牛顿迭代的结果都不小于精确的方根，定价因此保持保守；
+/// `x ^ (n - 1)` 在定点数精度下下溢为零时停止迭代，返回二分得到的、按向上取整的幂满足条件的值。
+/// 迭代次数有界（`n <= 255` 时二分至多 10 次），不再需要逐位二分 63 次。
+fn fixed_root_ceil(value: u128, n: u32) -> u128 {
+    if n <= 1 || value == 0 || value >= FIXED_ONE {
+        return value.min(FIXED_ONE);
+    }
+    // value 位于 [2^-(e+1), 2^-e)，方根位于 [x0 / 2, x0]
+    let e = 63 - (128 - value.leading_zeros());
+    let x0 = FIXED_ONE >> (e / n);
+    let (mut lo, mut hi) = (x0 / 2, x0);
+    for _ in 0..(32 - n.leading_zeros()) + 2 {
+        let mid = lo + (hi - lo) / 2;
+        if fixed_pow_ceil(mid, n) >= value {
+            hi = mid;
+        } else {
+            lo = mid;
+        }
+    }
+    // x' = ((n - 1) * x + value / x ^ (n - 1)) / n，分母向下取整、其余向上取整
+    let mut x = hi;
+    for step in 0..ROOT_NEWTON_STEPS {
+        let denominator = fixed_pow_floor(x, n - 1);
+        if denominator == 0 {
+            break;
+        }
+        let next = ((u128::from(n - 1) * x + (value * FIXED_ONE).div_ceil(denominator))
+            .div_ceil(u128::from(n)))
+        .min(x0);
+        // 第一次迭代之后 x 已不小于精确的方根，迭代不再下降时即已收敛
+        if step > 0 && next >= x {
+            break;
+        }
+        x = next;
+    }
+    x
+}
+
+/// 精确计算 `floor(R * p / (P + p))`
+///
+/// 两个 u64 相加/相乘都不会超出 u128 的范围；由于 `p / (P + p) <= 1`，结果不超过 `R`，
+/// 转换回 u64 不会失败。`P` 与 `p` 同时为零时返回 0。
+fn exact_receive(pool_receive_balance: u64, pool_pay_balance: u64, pay_amount: u64) -> u64 {
+    let bigp_plus_p = u128::from(pool_pay_balance) + u128::from(pay_amount);
+    if bigp_plus_p == 0 {
+        return 0;
+    }
+    (u128::from(pool_receive_balance) * u128::from(pay_amount) / bigp_plus_p) as u64
+}
+
+/// 从支付数量中扣除池子的手续费，返回实际进入恒定乘积公式的数量
+///
+/// 手续费向上取整、剩余数量向下取整，保证据此计算的接收数量不会被高估。
+///
+/// # 参数
+/// * `pay_amount` - 支付的代币数量
+/// * `fee_bps` - 池子的手续费率（基点）
+///
+/// # 返回值
+/// * `Ok(u64)` - 扣除手续费后的支付数量
+/// * `Err(ProgramError)` - 手续费率超过 100% 时返回 `InvalidArgument`
+pub fn apply_fee(pay_amount: u64, fee_bps: u16) -> Result<u64, ProgramError> {
+    let fee_keep = BPS_DENOMINATOR
+        .checked_sub(u128::from(fee_bps))
+        .ok_or(ProgramError::InvalidArgument)?;
+    // 结果不会大于 pay_amount，转换回 u64 不会失败
+    Ok((u128::from(pay_amount) * fee_keep / BPS_DENOMINATOR) as u64)
+}
+
+/// 检查接收数量不低于滑点下限
+fn check_min_receive(receive: u64, min_receive: u64) -> Result<(), ProgramError> {
+    if receive < min_receive {
+        return Err(ArbitrageProgramError::BelowMinReceive.into());
+    }
+    Ok(())
+}
+
+/// 计算流动性池当前的边际价格（已扣除手续费），以有理数形式返回
+///
+/// 恒定乘积池在交易规模趋近于零时的价格为 `R / P`，即每支付一个支付代币可获得的
+/// 接收代币数量。该函数按小数位数换算为整币单位并乘以 `(1 - fee)`，返回精确的
+/// 分子/分母，便于链下在不损失精度的情况下比较不同池子的价格。
+///
+/// # 参数
+/// * `pool_receive_balance` - 接收代币的池子余额
+/// * `receive_decimals` - 接收代币的小数位数
+/// * `pool_pay_balance` - 支付代币的池子余额
+/// * `pay_decimals` - 支付代币的小数位数
+/// * `fee_bps` - 池子的手续费率（基点）
+///
+/// # 返回值
+/// * `Ok((numerator, denominator))` - 约分后的边际价格，单位为整币
+/// * `Err(ProgramError)` - 支付侧余额为零、手续费率超过 100% 或中间值溢出时返回错误
+pub fn pool_price(
+    pool_receive_balance: u64,
+    receive_decimals: u8,
+    pool_pay_balance: u64,
+    pay_decimals: u8,
+    fee_bps: u16,
+) -> Result<(u128, u128), ProgramError> {
+    if pool_pay_balance == 0 {
+        return Err(ArbitrageProgramError::InvalidSwapNotEnoughLiquidity.into());
+    }
+    let fee_keep = BPS_DENOMINATOR
+        .checked_sub(u128::from(fee_bps))
+        .ok_or(ProgramError::InvalidArgument)?;
+
+    // R * 10^pay_decimals / (P * 10^receive_decimals)，只对较小的一侧补齐小数位
+    let (num_scale, den_scale) = if pay_decimals >= receive_decimals {
+        (pow10(pay_decimals - receive_decimals)?, 1)
+    } else {
+        (1, pow10(receive_decimals - pay_decimals)?)
+    };
+    let numerator = u128::from(pool_receive_balance)
+        .checked_mul(num_scale)
+        .and_then(|n| n.checked_mul(fee_keep))
+        .ok_or(ArbitrageProgramError::MathOverflow)?;
+    let denominator = u128::from(pool_pay_balance)
+        .checked_mul(den_scale)
+        .and_then(|d| d.checked_mul(BPS_DENOMINATOR))
+        .ok_or(ArbitrageProgramError::MathOverflow)?;
+
+    let divisor = gcd(numerator, denominator);
+    Ok((numerator / divisor, denominator / divisor))
+}
+
+/// 计算流动性池中资产 a 对资产 b 的即时汇率（每个整币 a 可换得的整币 b 数量），供看板展示
+///
+/// 与恒定乘积定价模型一致：交易规模趋近于零时的价格为 `B / A`（按小数位数换算为整币），
+/// 不含手续费。链上不使用该函数，需要精确值时使用 `pool_price`。
+///
+/// # 参数
+/// * `pool_a_balance` - 池子中资产 a 的余额
+/// * `a_decimals` - 资产 a 的小数位数
+/// * `pool_b_balance` - 池子中资产 b 的余额
+/// * `b_decimals` - 资产 b 的小数位数
+///
+/// # 返回值
+/// 即时汇率；资产 a 余额为零时返回 `f64::INFINITY`
+///
+/// # 示例
+/// ```
+/// use arb_program::swap::implied_rate;
+///
+/// // 1 USDC（6 位小数）与 5 DAI（18 位小数）：1 USDC = 5 DAI
+/// // （5000 DAI 的最小单位数量会超出 u64，这里按 1:5 的比例缩小）
+/// let rate = implied_rate(1_000_000, 6, 5 * 10u64.pow(18), 18);
+/// assert_eq!(rate, 5.0);
+/// ```
+#[cfg(feature = "client")]
+pub fn implied_rate(
+    pool_a_balance: u64,
+    a_decimals: u8,
+    pool_b_balance: u64,
+    b_decimals: u8,
+) -> f64 {
+    let a = pool_a_balance as f64 / 10f64.powi(i32::from(a_decimals));
+    let b = pool_b_balance as f64 / 10f64.powi(i32::from(b_decimals));
+    b / a
+}
+
+/// 基点的分母（100% = 10_000 bps）
+pub(crate) const BPS_DENOMINATOR: u128 = 10_000;
+
+/// 计算 10 的 `exp` 次幂，溢出时返回 `MathOverflow`
+pub(crate) fn pow10(exp: u8) -> Result<u128, ProgramError> {
+    10u128
+        .checked_pow(u32::from(exp))
+        .ok_or_else(|| ArbitrageProgramError::MathOverflow.into())
+}
+
+/// 欧几里得算法求最大公约数，用于约分有理数
+fn gcd(mut a: u128, mut b: u128) -> u128 {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
 }
 
 /// 将一个无符号64位整数转换为浮点数，并根据指定的小数位数进行缩放
@@ -87,16 +437,18 @@ pub fn determine_swap_receive(
 /// * `decimals` - 指定小数点后保留的位数
 ///
 /// # 返回值
-/// 返回转换后的32位浮点数，其值等于 value / (10^decimals)
+/// 返回转换后的64位浮点数，其值等于 value / (10^decimals)；
+/// `f64` 约有 16 位十进制有效数字，`f32` 只有约 7 位，18 位小数的大额余额会误差数个完整代币
 ///
 /// # 示例
+/// 私有函数无法在文档测试中调用，同样的例子见单元测试 `convert_to_float_scales_by_decimals`：
+/// ```text
+/// convert_to_float(12345, 2) == 123.45
 /// ```
-/// let result = convert_to_float(12345, 2);
-/// // result 等于 123.45
-/// ```
-fn convert_to_float(value: u64, decimals: u8) -> f32 {
+#[cfg(all(feature = "client", not(feature = "integer_only")))]
+fn convert_to_float(value: u64, decimals: u8) -> f64 {
     // 将整数值转换为浮点数并除以10的decimals次幂，实现小数点定位
-    (value as f32).div(f32::powf(10.0, decimals as f32))
+    (value as f64).div(10f64.powi(i32::from(decimals)))
 }
 
 /// 将浮点数转换为整数表示
@@ -112,9 +464,405 @@ fn convert_to_float(value: u64, decimals: u8) -> f32 {
 /// 返回转换后的u64整数值
 ///
 /// # 示例
+/// 同样的例子见单元测试 `convert_from_float_scales_by_decimals`：
+/// ```text
+/// convert_from_float(123.45, 2) == 12345
 /// ```
-/// let result = convert_from_float(123.45, 2); // 返回 12345
-/// ```
-fn convert_from_float(value: f32, decimals: u8) -> u64 {
-    value.mul(f32::powf(10.0, decimals as f32)) as u64
+#[cfg(all(feature = "client", not(feature = "integer_only")))]
+fn convert_from_float(value: f64, decimals: u8) -> u64 {
+    value.mul(10f64.powi(i32::from(decimals))) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(all(feature = "client", not(feature = "integer_only")))]
+    #[test]
+    fn convert_to_float_scales_by_decimals() {
+        assert_eq!(convert_to_float(12345, 2), 123.45);
+        assert_eq!(convert_to_float(1, 18), 1e-18);
+    }
+
+    #[cfg(all(feature = "client", not(feature = "integer_only")))]
+    #[test]
+    fn convert_from_float_scales_by_decimals() {
+        assert_eq!(convert_from_float(123.45, 2), 12345);
+        assert_eq!(convert_from_float(1.5, 6), 1_500_000);
+    }
+
+    #[test]
+    fn constant_product_example_from_the_docs() {
+        // 1000 USDC / 5000 DAI 的池子，支付 100 DAI 约得到 19.61 USDC（向下取整）
+        assert_eq!(
+            determine_swap_receive_checked(1000_000000, 5000_000000, 100_000000, 0),
+            Ok(19_607843)
+        );
+    }
+
+    #[test]
+    fn pool_price_is_the_reduced_marginal_price() {
+        assert_eq!(pool_price(2_000_000, 6, 1_000_000, 6, 0), Ok((2, 1)));
+        // 扣除 0.3% 手续费：2 * 0.997
+        assert_eq!(pool_price(2_000_000, 6, 1_000_000, 6, 30), Ok((997, 500)));
+        // 1 USDC（6 位小数）对 5 DAI（18 位小数）：每个 DAI 换 0.2 USDC
+        assert_eq!(
+            pool_price(1_000000, 6, 5 * 10u64.pow(18), 18, 0),
+            Ok((1, 5))
+        );
+    }
+
+    #[test]
+    fn pool_price_bounds_the_constant_product_receive() {
+        let (pool_receive, pool_pay, fee_bps) = (3_000_000_000, 7_000_000_000, 25);
+        let (numerator, denominator) = pool_price(pool_receive, 6, pool_pay, 6, fee_bps).unwrap();
+        let receive = |pay| {
+            determine_swap_receive_checked(
+                pool_receive,
+                pool_pay,
+                apply_fee(pay, fee_bps).unwrap(),
+                0,
+            )
+            .unwrap()
+        };
+        // 恒定乘积的成交价格不优于边际价格
+        for pay in [1_000, 1_000_000, 100_000_000] {
+            assert!(u128::from(receive(pay)) * denominator <= u128::from(pay) * numerator);
+        }
+        // 交易规模很小时与按边际价格换算的数量只差取整误差
+        let marginal = u128::from(1_000u64) * numerator / denominator;
+        assert!(marginal - u128::from(receive(1_000)) <= 1);
+    }
+
+    #[test]
+    fn pool_price_rejects_an_empty_pay_side() {
+        assert_eq!(
+            pool_price(1_000, 6, 0, 6, 0),
+            Err(ArbitrageProgramError::InvalidSwapNotEnoughLiquidity.into())
+        );
+    }
+
+    #[cfg(feature = "client")]
+    #[test]
+    fn tiny_pay_into_an_18_decimal_pool_receives_the_exact_amount() {
+        // 池子：5 个 18 位小数的代币对 1 个 6 位小数的代币，支付 1 个最小单位（1e-6）
+        let (pool_receive, pool_pay) = (5 * 10u64.pow(18), 1_000000);
+        let expected = 4_999_995_000_004; // 5e18 * 1 / (1e6 + 1) 向下取整
+        assert_eq!(
+            determine_swap_receive_checked(pool_receive, pool_pay, 1, 0),
+            Ok(expected)
+        );
+        assert_eq!(
+            determine_swap_receive(pool_receive, 18, pool_pay, 6, 1, 0),
+            Ok(expected)
+        );
+    }
+
+    #[cfg(feature = "client")]
+    #[test]
+    fn min_receive_is_a_floor_on_the_receive() {
+        let receive = |min_receive| {
+            determine_swap_receive_checked(1000_000000, 5000_000000, 100_000000, min_receive)
+        };
+        assert_eq!(receive(19_000000), Ok(19_607843));
+        assert_eq!(receive(19_607843), Ok(19_607843));
+        assert_eq!(
+            receive(19_607844),
+            Err(ArbitrageProgramError::BelowMinReceive.into())
+        );
+        assert_eq!(
+            determine_swap_receive(1000_000000, 6, 5000_000000, 6, 100_000000, 19_607844),
+            Err(ArbitrageProgramError::BelowMinReceive.into())
+        );
+    }
+
+    /// 覆盖小数额、大数额与 18 位小数量级的 (R, P, p) 组合
+    const ROUNDING_CASES: &[(u64, u64, u64)] = &[
+        (1000_000000, 5000_000000, 100_000000),
+        (3, 7, 1),
+        (1_000_003, 999_983, 12_345),
+        (5 * 10u64.pow(18), 1_000000, 1),
+        (1_000000, 5 * 10u64.pow(18), 7 * 10u64.pow(15)),
+        (u64::MAX, u64::MAX, u64::MAX / 3),
+    ];
+
+    #[cfg(feature = "client")]
+    #[test]
+    fn receive_is_never_above_the_exact_rational() {
+        for &(big_r, big_p, p) in ROUNDING_CASES {
+            let (r, exact_num, exact_den) = (
+                determine_swap_receive_checked(big_r, big_p, p, 0).unwrap(),
+                u128::from(big_r) * u128::from(p),
+                u128::from(big_p) + u128::from(p),
+            );
+            // r 是精确有理数值 R * p / (P + p) 向下取整的结果
+            assert!(u128::from(r) * exact_den <= exact_num);
+            assert!((u128::from(r) + 1) * exact_den > exact_num);
+            let r_float = determine_swap_receive(big_r, 18, big_p, 6, p, 0).unwrap();
+            assert!(r_float <= r, "{r_float} > {r} for {:?}", (big_r, big_p, p));
+        }
+    }
+
+    #[test]
+    fn fee_is_rounded_up() {
+        // 1001 * 0.997 = 997.997，扣除手续费后向下取整为 997
+        assert_eq!(apply_fee(1001, 30), Ok(997));
+        for &(_, _, pay) in ROUNDING_CASES {
+            for fee_bps in [0, 1, 30, 9_999, 10_000] {
+                let kept = u128::from(apply_fee(pay, fee_bps).unwrap());
+                assert!(kept * 10_000 <= u128::from(pay) * u128::from(10_000 - fee_bps));
+            }
+        }
+        assert_eq!(apply_fee(1, 10_001), Err(ProgramError::InvalidArgument));
+    }
+
+    #[test]
+    fn weighted_80_20_pool_matches_the_closed_form() {
+        // (R, P, p, (w_in, w_out), R * (1 - (P / (P + p)) ^ (w_in / w_out)) 向下取整)
+        let cases = [
+            (1_000_000, 1_000_000, 10_000, (80, 20), 39_019),
+            (1_000_000, 1_000_000, 10_000, (20, 80), 2_484),
+            (
+                5_000_000_000,
+                2_000_000_000,
+                100_000_000,
+                (80, 20),
+                886_487_626,
+            ),
+            (
+                5_000_000_000,
+                2_000_000_000,
+                100_000_000,
+                (20, 80),
+                60_617_262,
+            ),
+        ];
+        for (big_r, big_p, p, weights, exact) in cases {
+            let r = determine_weighted_swap_receive_checked(big_r, big_p, p, weights, 0).unwrap();
+            // 定点误差只会让结果偏小，且在最小单位级别
+            assert!(
+                r <= exact && exact - r <= 1,
+                "{r} vs {exact} for {weights:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn equal_or_missing_weights_fall_back_to_constant_product() {
+        let constant_product =
+            determine_swap_receive_checked(1000_000000, 5000_000000, 100_000000, 0);
+        for weights in [(50, 50), (80, 80), (0, 20), (0, 0)] {
+            assert_eq!(
+                determine_weighted_swap_receive_checked(
+                    1000_000000,
+                    5000_000000,
+                    100_000000,
+                    weights,
+                    0
+                ),
+                constant_product
+            );
+        }
+    }
+
+    #[cfg(feature = "client")]
+    #[test]
+    fn implied_rate_matches_the_usdc_dai_example() {
+        // 注释中的池子为 1000 USDC 与 5000 DAI，DAI 余额按 18 位小数会超出 u64，按比例缩小为 1:5
+        let usdc = 1_000_000;
+        let dai = 5 * 10u64.pow(18);
+        assert_eq!(implied_rate(usdc, 6, dai, 18), 5.0);
+        assert_eq!(implied_rate(dai, 18, usdc, 6), 0.2);
+        // 即时汇率只取决于整币比例，与池子规模无关
+        assert_eq!(implied_rate(1_000 * usdc, 6, 5_000 * 10u64.pow(9), 9), 5.0);
+        assert_eq!(implied_rate(0, 6, dai, 18), f64::INFINITY);
+    }
+
+    /// 整数定价与 `client` 构建中带小数位的报价共用的定价用例：
+    /// (接收池余额, 接收小数位数, 支付池余额, 支付小数位数, 支付数量, 预期接收数量)
+    const RECEIVE_FIXTURES: &[(u64, u8, u64, u8, u64, u64)] = &[
+        (2_000_000, 6, 1_000_000, 6, 1_000, 1_998),
+        (1_000_000, 6, 2_000_000, 6, 1_998, 998),
+        (1_000_000_000, 6, 5_000_000_000, 6, 100_000_000, 19_607_843),
+        (1_000_000, 6, 1_000_000, 9, 1_000, 999),
+        (1_000_000_000, 9, 1_000_000, 6, 500_000, 333_333_333),
+    ];
+
+    #[test]
+    fn checked_receives_match_the_shared_fixtures() {
+        // 小数位数在恒定乘积公式中相互抵消，整数定价与之无关
+        for &(receive_balance, _, pay_balance, _, pay, expected) in RECEIVE_FIXTURES {
+            assert_eq!(
+                determine_swap_receive_checked(receive_balance, pay_balance, pay, 0),
+                Ok(expected),
+                "pay {} into {} / {}",
+                pay,
+                receive_balance,
+                pay_balance
+            );
+        }
+    }
+
+    #[cfg(feature = "client")]
+    #[test]
+    fn receives_match_the_shared_fixtures() {
+        for &(receive_balance, receive_decimals, pay_balance, pay_decimals, pay, expected) in
+            RECEIVE_FIXTURES
+        {
+            assert_eq!(
+                determine_swap_receive(
+                    receive_balance,
+                    receive_decimals,
+                    pay_balance,
+                    pay_decimals,
+                    pay,
+                    0
+                ),
+                Ok(expected),
+                "pay {} into {} / {}",
+                pay,
+                receive_balance,
+                pay_balance
+            );
+        }
+    }
+
+    #[cfg(feature = "client")]
+    #[test]
+    fn zero_decimal_against_18_decimal_pair() {
+        // 1000 个 0 位小数的资产与 10 个 18 位小数的资产：1 个整币 b 换 100 个 a
+        let (zero_decimal, eighteen_decimal) = (1_000, 10 * 10u64.pow(18));
+        assert_eq!(
+            pool_price(zero_decimal, 0, eighteen_decimal, 18, 0),
+            Ok((100, 1))
+        );
+        assert_eq!(
+            pool_price(eighteen_decimal, 18, zero_decimal, 0, 0),
+            Ok((1, 100))
+        );
+        // 18 位的小数位数跨度按 10^18 在 u128 中缩放：余额乘以 10^18 与手续费因子后
+        // 不超过 u128 时正常定价（0 位小数一侧的余额约 3.4e16 以内），超出时返回错误而不是回绕
+        assert!(pool_price(10u64.pow(15), 0, 1, 18, 30).is_ok());
+        assert!(pool_price(1, 18, 10u64.pow(15), 0, 30).is_ok());
+        assert_eq!(
+            pool_price(u64::MAX, 0, 1, 18, 30),
+            Err(ArbitrageProgramError::MathOverflow.into())
+        );
+
+        // 支付 0.1 个 18 位小数的资产：1000 * 1e17 / (1e19 + 1e17) = 9.9
+        assert_eq!(
+            determine_swap_receive(zero_decimal, 0, eighteen_decimal, 18, 10u64.pow(17), 0),
+            Ok(9)
+        );
+        // 支付 1 个 0 位小数的资产：1e19 / 1001，两种构建都精确到最小单位
+        assert_eq!(
+            determine_swap_receive(eighteen_decimal, 18, zero_decimal, 0, 1, 0),
+            Ok(9_990_009_990_009_990)
+        );
+    }
+
+    #[test]
+    fn decimal_span_beyond_u128_overflows() {
+        assert_eq!(
+            pool_price(1, 0, 1, 39, 0),
+            Err(ArbitrageProgramError::MathOverflow.into())
+        );
+    }
+
+    #[cfg(feature = "client")]
+    #[test]
+    fn large_18_decimal_balances_stay_within_one_base_unit() {
+        // `u64` 最多容纳约 18.4 个 18 位小数的代币；`f32` 在这个量级上会误差数个完整代币
+        let cases = [
+            // (R, 接收小数位, P, 支付小数位, p, 精确值 R * p / (P + p) 向下取整)
+            (
+                10 * 10u64.pow(18),
+                18,
+                5000_000000,
+                6,
+                100_000000,
+                196_078_431_372_549_019,
+            ),
+            (
+                18 * 10u64.pow(18),
+                18,
+                5000 * 10u64.pow(15),
+                18,
+                3 * 10u64.pow(18),
+                6_750_000_000_000_000_000,
+            ),
+            (
+                5000_000000,
+                6,
+                12 * 10u64.pow(18),
+                18,
+                10u64.pow(18),
+                384_615384,
+            ),
+        ];
+        for (big_r, receive_decimals, big_p, pay_decimals, p, reference) in cases {
+            assert_eq!(
+                determine_swap_receive_checked(big_r, big_p, p, 0),
+                Ok(reference)
+            );
+            let r =
+                determine_swap_receive(big_r, receive_decimals, big_p, pay_decimals, p, 0).unwrap();
+            assert!(
+                r <= reference && reference - r <= 1,
+                "{r} vs {reference} for {:?}",
+                (big_r, big_p, p)
+            );
+        }
+    }
+
+    #[cfg(all(feature = "client", not(feature = "integer_only")))]
+    #[test]
+    fn convert_to_float_keeps_18_decimal_precision() {
+        // 10 个代币加 1e-12：`f32` 会丢掉这部分，`f64` 还能保留
+        let value = 10 * 10u64.pow(18) + 10u64.pow(6);
+        assert_eq!(convert_to_float(value, 18), 10.000000000001);
+        assert_ne!(convert_to_float(value, 18), 10.0);
+    }
+
+    #[cfg(feature = "client")]
+    #[test]
+    fn integer_and_float_receives_agree_across_a_grid() {
+        // 浮点实现被精确值向下取整的结果封顶，只可能更少，且误差不超过 f64 的相对精度
+        let balances = [1, 997, 1_000_000, 10u64.pow(12), 10u64.pow(18), u64::MAX];
+        let decimals = [0, 6, 9, 18];
+        for &big_r in &balances {
+            for &big_p in &balances {
+                for p in [1, big_p / 1_000, big_p / 3, big_p] {
+                    if p == 0 {
+                        continue;
+                    }
+                    let r_int = determine_swap_receive_checked(big_r, big_p, p, 0).unwrap();
+                    for &receive_decimals in &decimals {
+                        for &pay_decimals in &decimals {
+                            let r_float = determine_swap_receive(
+                                big_r,
+                                receive_decimals,
+                                big_p,
+                                pay_decimals,
+                                p,
+                                0,
+                            )
+                            .unwrap();
+                            let case = (big_r, receive_decimals, big_p, pay_decimals, p);
+                            assert!(r_float <= r_int, "{r_float} > {r_int} for {case:?}");
+                            assert!(
+                                r_int - r_float <= 1.max(r_int >> 40),
+                                "{r_float} vs {r_int} for {case:?}"
+                            );
+                        }
+                    }
+                }
+            }
+        }
+        // 两个 `u64` 的乘积总能放进 `u128`，整数实现在极值下也不会溢出
+        assert_eq!(
+            determine_swap_receive_checked(u64::MAX, u64::MAX, u64::MAX, 0),
+            Ok(u64::MAX / 2)
+        );
+    }
 }
@@ -0,0 +1,756 @@
+// 供单元测试直接调用内部逻辑的入口，在单元测试或启用 `testing` feature 时编译
+//
+// 套利检测与指令数据编码本身不依赖运行时，可以直接调用；`invoke_arbitrage` 发起的 CPI
+// 需要运行时，这里通过 `program_stubs::set_syscall_stubs` 注入自定义的 `sol_invoke_signed`
+// 实现：每个 CPI 都被记录下来并交给当前线程的 `SwapInvoker` 执行，默认的 `MockSwap`
+// 按恒定乘积公式模拟 swap 程序并直接修改代币账户余额。`Market` 按 `ACCOUNT_LAYOUT`
+// 组装 `TryArbitrage` 的账户列表，再经由 `processor::process_arbitrage` 调用。
+//
+// 运行时状态（CPI 记录、日志、返回数据、slot）按线程保存，并行运行的测试互不影响。
+
+use std::cell::RefCell;
+use std::sync::Once;
+
+use solana_program::{
+    account_info::AccountInfo,
+    clock::Clock,
+    entrypoint::ProgramResult,
+    instruction::{AccountMeta, Instruction},
+    program_error::ProgramError,
+    program_pack::Pack,
+    program_stubs::{set_syscall_stubs, SyscallStubs},
+    pubkey::Pubkey,
+    rent::Rent,
+};
+
+use crate::arb::{self, AmountEncoding, Buy, DiscriminatorKind, SwapMode};
+use crate::processor::{process_arbitrage, ACCOUNT_LAYOUT};
+use crate::swap::{apply_fee, determine_swap_receive_checked, BPS_DENOMINATOR};
+use crate::TryArbitrageOptions;
+
+/// 调用 `check_for_arbitrage`，不输出详细日志
+///
+/// # 参数
+/// * `r_swap_1` - 在 Swap #1 支付资产 i 预计获得的资产 j 数量
+/// * `r_swap_2` - 在 Swap #2 支付资产 i 预计获得的资产 j 数量
+/// * `temperature` - 套利温度阈值
+pub fn check_for_arbitrage(r_swap_1: u64, r_swap_2: u64, temperature: u8) -> Option<Buy> {
+    arb::check_for_arbitrage(r_swap_1, r_swap_2, temperature, false)
+}
+
+/// 调用 `build_ix_data`，返回发往 swap 程序的指令数据
+///
+/// # 参数
+/// * `discriminator` - swap 程序识别指令的方式
+/// * `encoding` - 数量的字节序
+/// * `mode` - 兑换模式
+/// * `amount` - 支付的数量；`ExactOut` 模式下为允许支付的最大数量
+/// * `amount_out` - `ExactOut` 模式下固定的接收数量
+pub fn build_ix_data(
+    discriminator: DiscriminatorKind,
+    encoding: AmountEncoding,
+    mode: SwapMode,
+    amount: u64,
+    amount_out: u64,
+) -> Vec<u8> {
+    arb::build_ix_data(discriminator, encoding, mode, amount, amount_out)
+}
+
+/// 测试中本程序使用的程序 ID，顶层指令设置的返回数据以它标记
+pub const PROGRAM_ID: Pubkey = Pubkey::new_from_array([0xA7; 32]);
+
+/// 被记录下来的一次 CPI
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordedCpi {
+    /// 被调用的程序
+    pub program_id: Pubkey,
+    /// 指令携带的账户
+    pub accounts: Vec<AccountMeta>,
+    /// 指令数据
+    pub data: Vec<u8>,
+    /// `invoke_signed` 使用的签名种子，`invoke` 时为空
+    pub signer_seeds: Vec<Vec<Vec<u8>>>,
+}
+
+/// 执行被记录的 CPI，替代运行时调用被调用的程序
+pub trait SwapInvoker {
+    /// 执行一次 CPI；`accounts` 与传给 `invoke` 的账户相同，修改其数据即修改调用方看到的账户
+    fn invoke(&mut self, ix: &Instruction, accounts: &[AccountInfo]) -> ProgramResult;
+}
+
+/// 当前线程的模拟运行时状态
+#[derive(Default)]
+struct Runtime {
+    invoker: Option<Box<dyn SwapInvoker>>,
+    cpis: Vec<RecordedCpi>,
+    logs: Vec<String>,
+    return_data: Option<(Pubkey, Vec<u8>)>,
+    /// 正在执行的 CPI 的被调用程序，为空时是顶层指令
+    callees: Vec<Pubkey>,
+    slot: u64,
+}
+
+thread_local! {
+    static RUNTIME: RefCell<Runtime> = RefCell::new(Runtime::default());
+}
+
+/// 把系统调用转发给当前线程的 `Runtime`
+struct RecordingStubs;
+
+impl SyscallStubs for RecordingStubs {
+    fn sol_invoke_signed(
+        &self,
+        instruction: &Instruction,
+        account_infos: &[AccountInfo],
+        signers_seeds: &[&[&[u8]]],
+    ) -> ProgramResult {
+        // 运行时在每次 CPI 开始时清空返回数据；调用期间取出调用器，
+        // 使它可以再次访问运行时状态（例如设置返回数据）
+        let invoker = RUNTIME.with_borrow_mut(|runtime| {
+            runtime.cpis.push(RecordedCpi {
+                program_id: instruction.program_id,
+                accounts: instruction.accounts.clone(),
+                data: instruction.data.clone(),
+                signer_seeds: signers_seeds
+                    .iter()
+                    .map(|seeds| seeds.iter().map(|seed| seed.to_vec()).collect())
+                    .collect(),
+            });
+            runtime.return_data = None;
+            runtime.callees.push(instruction.program_id);
+            runtime.invoker.take()
+        });
+        let result = match invoker {
+            Some(mut invoker) => {
+                let result = invoker.invoke(instruction, account_infos);
+                RUNTIME.with_borrow_mut(|runtime| runtime.invoker = Some(invoker));
+                result
+            }
+            None => Ok(()),
+        };
+        RUNTIME.with_borrow_mut(|runtime| runtime.callees.pop());
+        result
+    }
+
+    fn sol_get_clock_sysvar(&self, var_addr: *mut u8) -> u64 {
+        let clock = Clock {
+            slot: RUNTIME.with_borrow(|runtime| runtime.slot),
+            ..Clock::default()
+        };
+        // `Clock::get` 传入的是一个 `Clock` 的地址
+        unsafe { *(var_addr as *mut Clock) = clock };
+        solana_program::entrypoint::SUCCESS
+    }
+
+    fn sol_get_rent_sysvar(&self, var_addr: *mut u8) -> u64 {
+        // `Rent::get` 传入的是一个 `Rent` 的地址
+        unsafe { *(var_addr as *mut Rent) = Rent::default() };
+        solana_program::entrypoint::SUCCESS
+    }
+
+    fn sol_get_return_data(&self) -> Option<(Pubkey, Vec<u8>)> {
+        RUNTIME.with_borrow(|runtime| runtime.return_data.clone())
+    }
+
+    fn sol_set_return_data(&self, data: &[u8]) {
+        RUNTIME.with_borrow_mut(|runtime| {
+            let program_id = runtime.callees.last().copied().unwrap_or(PROGRAM_ID);
+            runtime.return_data = (!data.is_empty()).then(|| (program_id, data.to_vec()));
+        });
+    }
+}
+
+/// 安装模拟运行时并清空当前线程的状态，之后的 CPI 交给 `invoker` 执行
+pub fn install(invoker: impl SwapInvoker + 'static) {
+    static STUBS: Once = Once::new();
+    STUBS.call_once(|| {
+        set_syscall_stubs(Box::new(RecordingStubs));
+    });
+    RUNTIME.set(Runtime {
+        invoker: Some(Box::new(invoker)),
+        ..Runtime::default()
+    });
+}
+
+/// 取出当前线程记录的所有 CPI
+pub fn take_cpis() -> Vec<RecordedCpi> {
+    RUNTIME.with_borrow_mut(|runtime| std::mem::take(&mut runtime.cpis))
+}
+
+/// 取出当前线程通过 `arb_msg!` 输出的所有日志（带 `LOG_PREFIX` 前缀）
+pub fn take_logs() -> Vec<String> {
+    RUNTIME.with_borrow_mut(|runtime| std::mem::take(&mut runtime.logs))
+}
+
+/// 返回当前的返回数据及设置它的程序
+pub fn return_data() -> Option<(Pubkey, Vec<u8>)> {
+    RUNTIME.with_borrow(|runtime| runtime.return_data.clone())
+}
+
+/// 设置 `Clock::get` 返回的 slot
+pub fn set_slot(slot: u64) {
+    RUNTIME.with_borrow_mut(|runtime| runtime.slot = slot);
+}
+
+/// 记录一条日志，由 `arb_msg!` 调用
+pub(crate) fn capture_log(message: &str) {
+    RUNTIME.with_borrow_mut(|runtime| runtime.logs.push(message.to_owned()));
+}
+
+/// `MockSwap` 模拟的一个 swap 程序
+#[derive(Debug, Clone)]
+pub struct MockPool {
+    /// swap 程序 ID
+    pub program_id: Pubkey,
+    /// 从支付数量中扣除的手续费率（基点）
+    pub fee_bps: u16,
+    /// 实际成交的接收数量占恒定乘积报价的比例（基点），用于模拟成交偏差
+    pub output_bps: u16,
+    /// 指令标识的字节数，之后是 `build_ix_data` 编码的数量
+    pub discriminator_len: usize,
+    /// 指令数据中数量的字节序
+    pub amount_encoding: AmountEncoding,
+    /// 该程序的账户重排表，与 `swap_*_account_order` 相同，为空时为 `leg_cpi` 的规范顺序
+    pub account_order: Vec<u8>,
+    /// 是否把接收数量（小端序 `u64`）写入返回数据
+    pub set_return_data: bool,
+}
+
+impl MockPool {
+    /// 无手续费、按报价全额成交的 Anchor swap 程序
+    pub fn new(program_id: Pubkey) -> Self {
+        Self {
+            program_id,
+            fee_bps: 0,
+            output_bps: 10_000,
+            discriminator_len: 8,
+            amount_encoding: AmountEncoding::LittleEndian,
+            account_order: vec![],
+            set_return_data: false,
+        }
+    }
+}
+
+/// 模拟 swap 程序、代币程序与系统程序的 `SwapInvoker`
+///
+/// * swap 程序：按 `leg_cpi` 的账户顺序读取池子与用户的代币账户，按恒定乘积公式成交；
+///   `ExactOut` 模式下支付允许的最大数量、接收固定的数量
+/// * 代币程序：`Transfer` 与 `TransferChecked`
+/// * 系统程序：`CreateAccount`，分配数据并修改账户所有者
+///
+/// 其他程序的 CPI 只被记录，直接返回成功。
+#[derive(Debug, Clone, Default)]
+pub struct MockSwap {
+    /// 模拟的 swap 程序
+    pub pools: Vec<MockPool>,
+}
+
+impl MockSwap {
+    /// 模拟给定的 swap 程序
+    pub fn new(pools: Vec<MockPool>) -> Self {
+        Self { pools }
+    }
+
+    fn swap(pool: &MockPool, ix: &Instruction, accounts: &[AccountInfo]) -> ProgramResult {
+        // 把 CPI 账户按重排表还原为 `leg_cpi` 的规范顺序
+        let canonical = |k: usize| -> Result<&AccountInfo, ProgramError> {
+            let position = if pool.account_order.is_empty() {
+                k
+            } else {
+                pool.account_order
+                    .iter()
+                    .position(|&o| usize::from(o) == k)
+                    .ok_or(ProgramError::NotEnoughAccountKeys)?
+            };
+            let meta = ix
+                .accounts
+                .get(position)
+                .ok_or(ProgramError::NotEnoughAccountKeys)?;
+            find_account(accounts, &meta.pubkey)
+        };
+        let (receive_pool, receive_user) = (canonical(2)?, canonical(3)?);
+        let (pay_pool, pay_user) = (canonical(5)?, canonical(6)?);
+
+        let amounts = ix
+            .data
+            .get(pool.discriminator_len..)
+            .ok_or(ProgramError::InvalidInstructionData)?;
+        let (pay, receive) = match amounts.len() {
+            8 => {
+                let pay = read_amount(amounts, 0, pool.amount_encoding);
+                let quote = determine_swap_receive_checked(
+                    token_amount(receive_pool),
+                    token_amount(pay_pool),
+                    apply_fee(pay, pool.fee_bps)?,
+                    0,
+                )?;
+                // 不超过报价，不会溢出 u64
+                let receive =
+                    (u128::from(quote) * u128::from(pool.output_bps) / BPS_DENOMINATOR) as u64;
+                (pay, receive)
+            }
+            16 => (
+                read_amount(amounts, 8, pool.amount_encoding),
+                read_amount(amounts, 0, pool.amount_encoding),
+            ),
+            _ => return Err(ProgramError::InvalidInstructionData),
+        };
+        move_tokens(pay_user, pay_pool, pay)?;
+        move_tokens(receive_pool, receive_user, receive)?;
+        if pool.set_return_data {
+            solana_program::program::set_return_data(&receive.to_le_bytes());
+        }
+        Ok(())
+    }
+}
+
+impl SwapInvoker for MockSwap {
+    fn invoke(&mut self, ix: &Instruction, accounts: &[AccountInfo]) -> ProgramResult {
+        if let Some(pool) = self
+            .pools
+            .iter()
+            .find(|pool| pool.program_id == ix.program_id)
+        {
+            return Self::swap(pool, ix, accounts);
+        }
+        let account = |k: usize| -> Result<&AccountInfo, ProgramError> {
+            let meta = ix
+                .accounts
+                .get(k)
+                .ok_or(ProgramError::NotEnoughAccountKeys)?;
+            find_account(accounts, &meta.pubkey)
+        };
+        if ix.program_id == spl_token::ID || ix.program_id == spl_token_2022::ID {
+            return match ix.data.first() {
+                // Transfer: source, destination, authority
+                Some(3) => move_tokens(account(0)?, account(1)?, read_u64(&ix.data, 1)),
+                // TransferChecked: source, mint, destination, authority
+                Some(12) => move_tokens(account(0)?, account(2)?, read_u64(&ix.data, 1)),
+                _ => Err(ProgramError::InvalidInstructionData),
+            };
+        }
+        if ix.program_id == solana_system_interface::program::ID {
+            // CreateAccount: u32 标签 0、lamports、space、owner
+            if ix.data.len() < 52 || ix.data[..4] != [0; 4] {
+                return Err(ProgramError::InvalidInstructionData);
+            }
+            let (from, to) = (account(0)?, account(1)?);
+            let lamports = read_u64(&ix.data, 4);
+            let space = read_u64(&ix.data, 12) as usize;
+            let owner = Pubkey::try_from(&ix.data[20..52])
+                .map_err(|_| ProgramError::InvalidInstructionData)?;
+            **from.try_borrow_mut_lamports()? = from
+                .lamports()
+                .checked_sub(lamports)
+                .ok_or(ProgramError::InsufficientFunds)?;
+            **to.try_borrow_mut_lamports()? += lamports;
+            *to.try_borrow_mut_data()? = Box::leak(vec![0; space].into_boxed_slice());
+            to.assign(&owner);
+            return Ok(());
+        }
+        Ok(())
+    }
+}
+
+/// 在 CPI 的账户中查找 `key`
+fn find_account<'a, 'b>(
+    accounts: &'a [AccountInfo<'b>],
+    key: &Pubkey,
+) -> Result<&'a AccountInfo<'b>, ProgramError> {
+    accounts
+        .iter()
+        .find(|account| account.key == key)
+        .ok_or(ProgramError::NotEnoughAccountKeys)
+}
+
+fn read_u64(data: &[u8], offset: usize) -> u64 {
+    read_amount(data, offset, AmountEncoding::LittleEndian)
+}
+
+fn read_amount(data: &[u8], offset: usize, encoding: AmountEncoding) -> u64 {
+    data.get(offset..offset + 8)
+        .and_then(|bytes| <[u8; 8]>::try_from(bytes).ok())
+        .map_or(0, |bytes| encoding.decode(bytes))
+}
+
+/// 读取代币账户的余额（数据的 64..72 字节）
+pub fn token_amount(account: &AccountInfo) -> u64 {
+    read_u64(&account.data.borrow(), 64)
+}
+
+/// 修改代币账户的余额
+pub fn set_token_amount(account: &AccountInfo, amount: u64) {
+    account.data.borrow_mut()[64..72].copy_from_slice(&amount.to_le_bytes());
+}
+
+/// 从 `from` 转出 `amount` 到 `to`，余额不足时返回 `InsufficientFunds`
+fn move_tokens(from: &AccountInfo, to: &AccountInfo, amount: u64) -> ProgramResult {
+    let remaining = token_amount(from)
+        .checked_sub(amount)
+        .ok_or(ProgramError::InsufficientFunds)?;
+    set_token_amount(from, remaining);
+    set_token_amount(to, token_amount(to).saturating_add(amount));
+    Ok(())
+}
+
+/// 创建一个生命周期为 `'static` 的账户，数据与公钥都泄漏在测试进程中
+pub fn account(
+    key: Pubkey,
+    owner: Pubkey,
+    data: Vec<u8>,
+    is_signer: bool,
+    is_writable: bool,
+    executable: bool,
+) -> AccountInfo<'static> {
+    AccountInfo::new(
+        Box::leak(Box::new(key)),
+        is_signer,
+        is_writable,
+        Box::leak(Box::new(1_000_000_000)),
+        Box::leak(data.into_boxed_slice()),
+        Box::leak(Box::new(owner)),
+        executable,
+        0,
+    )
+}
+
+/// 创建一个由 `token_program`（SPL Token 或 Token-2022）持有的已初始化代币账户
+pub fn token_account(
+    mint: &Pubkey,
+    owner: &Pubkey,
+    amount: u64,
+    token_program: &Pubkey,
+) -> AccountInfo<'static> {
+    let state = spl_token::state::Account {
+        mint: *mint,
+        owner: *owner,
+        amount,
+        state: spl_token::state::AccountState::Initialized,
+        ..Default::default()
+    };
+    let mut data = vec![0; spl_token::state::Account::LEN];
+    spl_token::state::Account::pack(state, &mut data).expect("token account packs");
+    account(
+        Pubkey::new_unique(),
+        *token_program,
+        data,
+        false,
+        true,
+        false,
+    )
+}
+
+/// 创建一个铸币账户，小数位数位于 `PartialMintState` 读取的第 41 个字节
+pub fn mint_account(decimals: u8) -> AccountInfo<'static> {
+    let mut data = vec![0; spl_token::state::Mint::LEN];
+    data[40] = decimals;
+    account(
+        Pubkey::new_unique(),
+        spl_token::ID,
+        data,
+        false,
+        false,
+        false,
+    )
+}
+
+/// 创建 swap 程序的流动性池 PDA 账户
+pub fn pool_account(swap_program_id: &Pubkey, data: Vec<u8>) -> AccountInfo<'static> {
+    let (pool, _) = Pubkey::find_program_address(&[b"liquidity_pool"], swap_program_id);
+    account(pool, *swap_program_id, data, false, true, false)
+}
+
+/// 创建一个可执行的程序账户
+pub fn program_account(program_id: &Pubkey) -> AccountInfo<'static> {
+    account(
+        *program_id,
+        solana_program::bpf_loader::ID,
+        vec![],
+        false,
+        false,
+        true,
+    )
+}
+
+/// 两个 swap 程序之间的一组资产，按 `ACCOUNT_LAYOUT` 组装 `TryArbitrage` 的账户列表
+#[derive(Debug, Clone)]
+pub struct Market {
+    /// Swap #1 程序 ID
+    pub swap_1_program_id: Pubkey,
+    /// Swap #2 程序 ID
+    pub swap_2_program_id: Pubkey,
+    /// 用户持有的每个资产余额
+    pub user_balances: Vec<u64>,
+    /// Swap #1 流动性池持有的每个资产余额
+    pub swap_1_balances: Vec<u64>,
+    /// Swap #2 流动性池持有的每个资产余额
+    pub swap_2_balances: Vec<u64>,
+    /// 每个资产的小数位数
+    pub decimals: Vec<u8>,
+    /// 持有代币账户的代币程序
+    pub token_program: Pubkey,
+    /// Swap #1 流动性池账户的数据（手续费率、最后更新 slot 等）
+    pub swap_1_pool_data: Vec<u8>,
+    /// Swap #2 流动性池账户的数据
+    pub swap_2_pool_data: Vec<u8>,
+}
+
+impl Market {
+    /// 两个新的 swap 程序，每个资产 6 位小数，代币账户由 SPL Token 持有
+    pub fn new(user_balances: &[u64], swap_1_balances: &[u64], swap_2_balances: &[u64]) -> Self {
+        Self {
+            swap_1_program_id: Pubkey::new_unique(),
+            swap_2_program_id: Pubkey::new_unique(),
+            user_balances: user_balances.to_vec(),
+            swap_1_balances: swap_1_balances.to_vec(),
+            swap_2_balances: swap_2_balances.to_vec(),
+            decimals: vec![6; user_balances.len()],
+            token_program: spl_token::ID,
+            swap_1_pool_data: vec![0; 64],
+            swap_2_pool_data: vec![0; 64],
+        }
+    }
+
+    /// 模拟两个 swap 程序的 `MockSwap`，两个池子都无手续费、按报价全额成交
+    pub fn mock_swap(&self) -> MockSwap {
+        MockSwap::new(vec![
+            MockPool::new(self.swap_1_program_id),
+            MockPool::new(self.swap_2_program_id),
+        ])
+    }
+
+    /// 按 `ACCOUNT_LAYOUT` 创建所有账户
+    pub fn build(&self) -> MarketAccounts {
+        let concurrency = self.user_balances.len();
+        let owner = account(
+            Pubkey::new_unique(),
+            solana_system_interface::program::ID,
+            vec![],
+            true,
+            true,
+            false,
+        );
+        let payer = account(
+            Pubkey::new_unique(),
+            solana_system_interface::program::ID,
+            vec![],
+            true,
+            true,
+            false,
+        );
+        let swap_1_pool = pool_account(&self.swap_1_program_id, self.swap_1_pool_data.clone());
+        let swap_2_pool = pool_account(&self.swap_2_program_id, self.swap_2_pool_data.clone());
+        let mints: Vec<_> = self.decimals.iter().map(|&d| mint_account(d)).collect();
+        let token_accounts = |holder: &Pubkey, balances: &[u64]| -> Vec<AccountInfo<'static>> {
+            (0..concurrency)
+                .map(|x| {
+                    token_account(
+                        mints[x].key,
+                        holder,
+                        balances.get(x).copied().unwrap_or(0),
+                        &self.token_program,
+                    )
+                })
+                .collect()
+        };
+        let user = token_accounts(owner.key, &self.user_balances);
+        let swap_1 = token_accounts(swap_1_pool.key, &self.swap_1_balances);
+        let swap_2 = token_accounts(swap_2_pool.key, &self.swap_2_balances);
+
+        let mut accounts = vec![
+            payer,
+            owner,
+            program_account(&self.token_program),
+            program_account(&solana_system_interface::program::ID),
+            program_account(&spl_associated_token_account::ID),
+            program_account(&self.swap_1_program_id),
+            program_account(&self.swap_2_program_id),
+            swap_1_pool,
+            swap_2_pool,
+        ];
+        accounts.extend(user);
+        accounts.extend(swap_1);
+        accounts.extend(swap_2);
+        accounts.extend(mints);
+        MarketAccounts {
+            swap_1_program_id: self.swap_1_program_id,
+            swap_2_program_id: self.swap_2_program_id,
+            concurrency,
+            accounts,
+        }
+    }
+}
+
+/// `Market::build` 创建的账户列表；可选账户按 `ACCOUNT_LAYOUT` 的说明追加到 `accounts` 末尾
+pub struct MarketAccounts {
+    /// Swap #1 程序 ID
+    pub swap_1_program_id: Pubkey,
+    /// Swap #2 程序 ID
+    pub swap_2_program_id: Pubkey,
+    /// 资产数量
+    pub concurrency: usize,
+    /// 指令的账户列表
+    pub accounts: Vec<AccountInfo<'static>>,
+}
+
+impl MarketAccounts {
+    /// 用户代币账户的所有者
+    pub fn owner(&self) -> &AccountInfo<'static> {
+        &self.accounts[1]
+    }
+
+    /// 资产 `x` 的用户代币账户
+    pub fn user(&self, x: usize) -> &AccountInfo<'static> {
+        &self.accounts[ACCOUNT_LAYOUT.len() + x]
+    }
+
+    /// 资产 `x` 在 Swap #1 流动性池中的代币账户
+    pub fn swap_1(&self, x: usize) -> &AccountInfo<'static> {
+        &self.accounts[ACCOUNT_LAYOUT.len() + self.concurrency + x]
+    }
+
+    /// 资产 `x` 在 Swap #2 流动性池中的代币账户
+    pub fn swap_2(&self, x: usize) -> &AccountInfo<'static> {
+        &self.accounts[ACCOUNT_LAYOUT.len() + 2 * self.concurrency + x]
+    }
+
+    /// 资产 `x` 的铸币账户
+    pub fn mint(&self, x: usize) -> &AccountInfo<'static> {
+        &self.accounts[ACCOUNT_LAYOUT.len() + 3 * self.concurrency + x]
+    }
+
+    /// 以 `PROGRAM_ID` 执行 `TryArbitrage`
+    pub fn try_arbitrage(&self, temperature: u8, options: TryArbitrageOptions) -> ProgramResult {
+        process_arbitrage(
+            &PROGRAM_ID,
+            &self.accounts,
+            &self.swap_1_program_id,
+            &self.swap_2_program_id,
+            self.concurrency as u8,
+            temperature,
+            options,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use borsh::BorshDeserialize;
+
+    use super::*;
+    use crate::error::ArbitrageProgramError;
+    use crate::result::ArbitrageResult;
+
+    /// Swap #2 上资产 1 明显比 Swap #1 便宜：在 Swap #1 买入资产 1，在 Swap #2 卖出
+    fn skewed_market() -> Market {
+        Market::new(
+            &[1_000, 0],
+            &[1_000_000, 2_000_000],
+            &[1_000_000, 1_000_000],
+        )
+    }
+
+    #[test]
+    fn check_for_arbitrage_follows_the_higher_quote() {
+        assert_eq!(check_for_arbitrage(200, 100, 90), Some(Buy::Swap1));
+        assert_eq!(check_for_arbitrage(100, 200, 90), Some(Buy::Swap2));
+        assert_eq!(check_for_arbitrage(105, 100, 90), None);
+        assert_eq!(check_for_arbitrage(100, 100, 100), None);
+    }
+
+    #[test]
+    fn build_ix_data_encodes_the_discriminator_and_amounts() {
+        let data = build_ix_data(
+            DiscriminatorKind::Anchor8,
+            AmountEncoding::LittleEndian,
+            SwapMode::ExactIn,
+            7,
+            0,
+        );
+        assert_eq!(
+            data[..8],
+            solana_program::hash::hash(b"global:swap").to_bytes()[..8]
+        );
+        assert_eq!(data[8..], 7u64.to_le_bytes());
+
+        let data = build_ix_data(
+            DiscriminatorKind::U8(9),
+            AmountEncoding::LittleEndian,
+            SwapMode::ExactOut,
+            7,
+            5,
+        );
+        assert_eq!(data[0], 9);
+        assert_eq!(data[1..9], 5u64.to_le_bytes());
+        assert_eq!(data[9..], 7u64.to_le_bytes());
+    }
+
+    #[test]
+    fn full_arbitrage_records_both_legs() {
+        let market = skewed_market();
+        install(market.mock_swap());
+        let accounts = market.build();
+
+        assert_eq!(
+            accounts.try_arbitrage(90, TryArbitrageOptions::default()),
+            Ok(())
+        );
+
+        let cpis = take_cpis();
+        assert_eq!(cpis.len(), 2);
+        assert_eq!(cpis[0].program_id, market.swap_1_program_id);
+        assert_eq!(cpis[1].program_id, market.swap_2_program_id);
+        // 买入腿支付全部资产 0，卖出腿卖出买入腿换到的全部资产 1
+        assert_eq!(cpis[0].data[8..], 1_000u64.to_le_bytes());
+        assert_eq!(cpis[1].data[8..], 1_998u64.to_le_bytes());
+        assert_eq!(cpis[0].accounts[0].pubkey, *accounts.accounts[7].key);
+        assert_eq!(cpis[0].accounts[6].pubkey, *accounts.user(0).key);
+        assert!(cpis.iter().all(|cpi| cpi.signer_seeds.is_empty()));
+
+        assert_eq!(token_amount(accounts.user(0)), 1_994);
+        assert_eq!(token_amount(accounts.user(1)), 0);
+        let (program_id, data) = return_data().unwrap();
+        assert_eq!(program_id, PROGRAM_ID);
+        let result = ArbitrageResult::try_from_slice(&data).unwrap();
+        assert_eq!(result.direction, Buy::Swap1);
+        assert_eq!((result.mint_i, result.mint_j), (0, 1));
+        assert_eq!(result.amount_in, 1_000);
+        assert_eq!(result.legs[1].amount_out, 1_994);
+        assert!(take_logs()
+            .iter()
+            .any(|line| line == "[ARB] PLACING TRADE!"));
+    }
+
+    #[test]
+    fn no_arbitrage_makes_no_cpi() {
+        let market = Market::new(&[1_000, 0], &[1_000_000; 2], &[1_000_000; 2]);
+        install(market.mock_swap());
+        let accounts = market.build();
+
+        assert_eq!(
+            accounts.try_arbitrage(90, TryArbitrageOptions::default()),
+            Err(ArbitrageProgramError::NoArbitrage.into())
+        );
+        assert!(take_cpis().is_empty());
+        assert_eq!(token_amount(accounts.user(0)), 1_000);
+    }
+
+    #[test]
+    fn failing_swap_aborts_the_arbitrage() {
+        struct Rejecting;
+        impl SwapInvoker for Rejecting {
+            fn invoke(&mut self, _ix: &Instruction, _accounts: &[AccountInfo]) -> ProgramResult {
+                Err(ProgramError::Custom(42))
+            }
+        }
+        let market = skewed_market();
+        install(Rejecting);
+        let accounts = market.build();
+
+        assert_eq!(
+            accounts.try_arbitrage(90, TryArbitrageOptions::default()),
+            Err(ProgramError::Custom(42))
+        );
+        assert_eq!(take_cpis().len(), 1);
+    }
+}
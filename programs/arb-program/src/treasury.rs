@@ -0,0 +1,320 @@
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::{account_info::AccountInfo, entrypoint::ProgramResult, pubkey::Pubkey};
+
+use crate::{
+    arb::{invoke_as_owner, AssetAccounts, TryArbitrageArgs},
+    arb_msg,
+    error::ArbitrageProgramError,
+    partial_state::{PartialMintState, PartialTokenAccountState},
+    swap::BPS_DENOMINATOR,
+};
+
+/// 套利成功后向 treasury 代币账户收取的费用，以支付资产 i 计价
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FeeMode {
+    /// 按利润的比例收取（基点）
+    Bps(u16),
+    /// 每笔交易收取固定数量
+    Flat(u64),
+}
+
+/// 按比例收取的 treasury 费用不是整数时的取整方向
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FeeRounding {
+    /// 向下取整，对用户有利
+    #[default]
+    Down,
+    /// 向上取整，对 treasury 有利
+    Up,
+}
+
+impl FeeMode {
+    /// 计算给定利润下应收取的费用
+    ///
+    /// # 参数
+    /// * `profit` - 利润
+    /// * `rounding` - 按比例收取时的取整方向，固定费用不受影响
+    ///
+    /// # 返回值
+    /// * `Some(u64)` - 应收取的费用
+    /// * `None` - 固定费用超过了利润，此时不应交易
+    pub fn fee(&self, profit: u64, rounding: FeeRounding) -> Option<u64> {
+        match *self {
+            // 比例不超过 100%（已在 `process_arbitrage` 中校验），向上取整时结果也不超过 profit
+            FeeMode::Bps(bps) => {
+                let scaled = u128::from(profit).checked_mul(u128::from(bps))?;
+                let fee = match rounding {
+                    FeeRounding::Down => scaled / BPS_DENOMINATOR,
+                    FeeRounding::Up => scaled.div_ceil(BPS_DENOMINATOR),
+                };
+                u64::try_from(fee).ok()
+            }
+            FeeMode::Flat(fee) => (fee <= profit).then_some(fee),
+        }
+    }
+
+    /// 比例费率是否合法（不超过 100%）
+    pub fn is_valid(&self) -> bool {
+        match *self {
+            FeeMode::Bps(bps) => u128::from(bps) <= BPS_DENOMINATOR,
+            FeeMode::Flat(_) => true,
+        }
+    }
+}
+
+/// 卖出腿完成后，按实际利润向 treasury 代币账户转入费用
+///
+/// # 参数
+/// * `args` - 已解析的账户
+/// * `pay` - 支付资产 i 的账户，费用从用户的资产 i 代币账户转出
+/// * `balance_before` - 交易前用户的资产 i 余额
+/// * `mode` - 收费方式
+///
+/// # 返回值
+/// * `ProgramResult` - 固定费用超过实际利润时返回 `ArbitrageProgramError::FeeExceedsProfit`，整笔交易回滚
+pub(crate) fn collect_treasury_fee<'a, 'b>(
+    args: &TryArbitrageArgs<'a, 'b>,
+    pay: &AssetAccounts<&'a AccountInfo<'b>>,
+    balance_before: u64,
+    mode: FeeMode,
+) -> ProgramResult {
+    let balance_after = PartialTokenAccountState::try_deserialize(pay.user, args.owner.key)?.3;
+    let profit = balance_after.saturating_sub(balance_before);
+    let Some(fee) = mode.fee(profit, args.options.treasury_fee_rounding) else {
+        arb_msg!("Treasury fee {:?} exceeds realized profit {}", mode, profit);
+        return Err(ArbitrageProgramError::FeeExceedsProfit.into());
+    };
+    if fee == 0 {
+        return Ok(());
+    }
+    let treasury = args
+        .treasury
+        .ok_or(ArbitrageProgramError::InvalidAccountsList)?;
+
+    arb_msg!("Transferring treasury fee {} to {}", fee, treasury.key);
+    transfer_from_user(args, pay, treasury, fee)
+}
+
+/// 卖出腿完成后，把两条腿没有完全抵消而留在用户账户中的中间资产 j 转入 dust 收集账户
+///
+/// 只转出本次交易新增的部分，用户原本持有的资产 j 不受影响。收集账户不是资产 j 的
+/// 代币账户时记录日志并跳过，不回滚已经完成的套利。
+///
+/// # 参数
+/// * `args` - 已解析的账户
+/// * `intermediate` - 中间资产 j 的账户
+/// * `balance_before` - 交易前用户的资产 j 余额
+/// * `dust_collector` - 接收剩余资产 j 的代币账户
+pub(crate) fn sweep_dust<'a, 'b>(
+    args: &TryArbitrageArgs<'a, 'b>,
+    intermediate: &AssetAccounts<&'a AccountInfo<'b>>,
+    balance_before: u64,
+    dust_collector: &'a AccountInfo<'b>,
+) -> ProgramResult {
+    let balance_after =
+        PartialTokenAccountState::try_deserialize(intermediate.user, args.owner.key)?.3;
+    let dust = balance_after.saturating_sub(balance_before);
+    if dust == 0 {
+        return Ok(());
+    }
+    let collector_mint = dust_collector
+        .data
+        .borrow()
+        .get(..32)
+        .and_then(|mint| Pubkey::try_from(mint).ok());
+    if collector_mint.is_none_or(|mint| !mint.eq(intermediate.mint.key)) {
+        arb_msg!(
+            "Dust collector {} does not hold mint {}, leaving {} in place",
+            dust_collector.key,
+            intermediate.mint.key,
+            dust
+        );
+        return Ok(());
+    }
+
+    arb_msg!("Sweeping dust {} to {}", dust, dust_collector.key);
+    transfer_from_user(args, intermediate, dust_collector, dust)
+}
+
+/// 以所有者身份从用户持有 `from` 资产的代币账户向 `to` 转账
+fn transfer_from_user<'a, 'b>(
+    args: &TryArbitrageArgs<'a, 'b>,
+    from: &AssetAccounts<&'a AccountInfo<'b>>,
+    to: &'a AccountInfo<'b>,
+    amount: u64,
+) -> ProgramResult {
+    // 使用铸币账户中的小数位数，指令中的覆盖值只用于 `min_decimals` 与按整币计的 `min_profit`
+    let (_, decimals) = PartialMintState::try_deserialize(from.mint)?;
+    invoke_as_owner(
+        args,
+        spl_token_2022::instruction::transfer_checked(
+            args.token_program.key,
+            from.user.key,
+            from.mint.key,
+            to.key,
+            args.owner.key,
+            &[],
+            amount,
+            decimals,
+        )?,
+        &[
+            from.user.clone(),
+            from.mint.clone(),
+            to.clone(),
+            args.owner.clone(),
+            args.token_program.clone(),
+        ],
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{install, take_cpis, token_account, token_amount, Market, MarketAccounts};
+    use crate::TryArbitrageOptions;
+
+    #[test]
+    fn fee_modes() {
+        assert_eq!(FeeMode::Bps(1_000).fee(994, FeeRounding::Down), Some(99));
+        assert_eq!(FeeMode::Bps(1_000).fee(994, FeeRounding::Up), Some(100));
+        assert_eq!(FeeMode::Flat(50).fee(994, FeeRounding::Up), Some(50));
+        assert_eq!(FeeMode::Flat(995).fee(994, FeeRounding::Down), None);
+        assert!(FeeMode::Bps(10_000).is_valid());
+        assert!(!FeeMode::Bps(10_001).is_valid());
+    }
+
+    /// 预估利润为 994 的市场，账户列表末尾追加资产 0 的 treasury 代币账户
+    fn market_with_treasury() -> (MarketAccounts, AccountInfo<'static>) {
+        let market = Market::new(
+            &[1_000, 0],
+            &[1_000_000, 2_000_000],
+            &[1_000_000, 1_000_000],
+        );
+        install(market.mock_swap());
+        let mut accounts = market.build();
+        let treasury = token_account(
+            accounts.mint(0).key,
+            &Pubkey::new_unique(),
+            0,
+            &spl_token::ID,
+        );
+        accounts.accounts.push(treasury.clone());
+        (accounts, treasury)
+    }
+
+    fn with_fee(treasury_fee: FeeMode) -> TryArbitrageOptions {
+        TryArbitrageOptions {
+            treasury_fee: Some(treasury_fee),
+            ..TryArbitrageOptions::default()
+        }
+    }
+
+    #[test]
+    fn proportional_fee_is_taken_from_the_realized_profit() {
+        let (accounts, treasury) = market_with_treasury();
+
+        assert_eq!(
+            accounts.try_arbitrage(90, with_fee(FeeMode::Bps(1_000))),
+            Ok(())
+        );
+        assert_eq!(take_cpis().len(), 3);
+        assert_eq!(token_amount(&treasury), 99);
+        assert_eq!(token_amount(accounts.user(0)), 1_994 - 99);
+    }
+
+    #[test]
+    fn flat_fee_is_taken_per_trade() {
+        let (accounts, treasury) = market_with_treasury();
+
+        assert_eq!(
+            accounts.try_arbitrage(90, with_fee(FeeMode::Flat(994))),
+            Ok(())
+        );
+        assert_eq!(token_amount(&treasury), 994);
+        assert_eq!(token_amount(accounts.user(0)), 1_000);
+    }
+
+    #[test]
+    fn flat_fee_above_the_profit_aborts_the_trade() {
+        let (accounts, treasury) = market_with_treasury();
+
+        assert_eq!(
+            accounts.try_arbitrage(90, with_fee(FeeMode::Flat(995))),
+            Err(ArbitrageProgramError::FeeExceedsProfit.into())
+        );
+        assert!(take_cpis().is_empty());
+        assert_eq!(token_amount(&treasury), 0);
+    }
+
+    #[test]
+    fn proportional_fee_rounds_in_the_configured_direction() {
+        // 994 * 10% = 99.4：向下取整收 99，向上取整收 100
+        for (rounding, fee) in [(FeeRounding::Down, 99), (FeeRounding::Up, 100)] {
+            let (accounts, treasury) = market_with_treasury();
+            let options = TryArbitrageOptions {
+                treasury_fee_rounding: rounding,
+                ..with_fee(FeeMode::Bps(1_000))
+            };
+
+            assert_eq!(accounts.try_arbitrage(90, options), Ok(()));
+            assert_eq!(token_amount(&treasury), fee);
+            assert_eq!(token_amount(accounts.user(0)), 1_994 - fee);
+        }
+        // 整数结果不受取整方向影响，最大利润下也不会溢出
+        assert_eq!(FeeMode::Bps(500).fee(1_000, FeeRounding::Up), Some(50));
+        assert_eq!(
+            FeeMode::Bps(10_000).fee(u64::MAX, FeeRounding::Up),
+            Some(u64::MAX)
+        );
+    }
+
+    /// 买入腿多成交 1% 的市场，账户列表末尾追加资产 `collector_mint` 的 dust 收集代币账户
+    fn market_with_dust_collector(collector_mint: usize) -> (MarketAccounts, AccountInfo<'static>) {
+        let market = Market::new(
+            &[1_000, 0],
+            &[1_000_000, 2_000_000],
+            &[1_000_000, 1_000_000],
+        );
+        let mut mock = market.mock_swap();
+        mock.pools[0].output_bps = 10_100;
+        install(mock);
+        let mut accounts = market.build();
+        let collector = token_account(
+            accounts.mint(collector_mint).key,
+            &Pubkey::new_unique(),
+            0,
+            &spl_token::ID,
+        );
+        accounts.accounts.push(collector.clone());
+        (accounts, collector)
+    }
+
+    #[test]
+    fn leftover_intermediate_asset_is_swept_to_the_dust_collector() {
+        let (accounts, collector) = market_with_dust_collector(1);
+        let options = TryArbitrageOptions {
+            sweep_dust: true,
+            ..TryArbitrageOptions::default()
+        };
+
+        assert_eq!(accounts.try_arbitrage(90, options), Ok(()));
+        // 买入腿换到 1998 * 1.01 = 2017，卖出腿只卖出预估的 1998
+        assert_eq!(take_cpis().len(), 3);
+        assert_eq!(token_amount(&collector), 19);
+        assert_eq!(token_amount(accounts.user(1)), 0);
+    }
+
+    #[test]
+    fn dust_collector_for_another_mint_leaves_the_dust_in_place() {
+        let (accounts, collector) = market_with_dust_collector(0);
+        let options = TryArbitrageOptions {
+            sweep_dust: true,
+            ..TryArbitrageOptions::default()
+        };
+
+        assert_eq!(accounts.try_arbitrage(90, options), Ok(()));
+        assert_eq!(take_cpis().len(), 2);
+        assert_eq!(token_amount(&collector), 0);
+        assert_eq!(token_amount(accounts.user(1)), 19);
+    }
+}
@@ -0,0 +1,506 @@
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::{
+    account_info::AccountInfo, entrypoint::ProgramResult, program_error::ProgramError,
+    pubkey::Pubkey,
+};
+
+use crate::{
+    arb::{
+        build_ix_data, invoke_arbitrage, invoke_swap, AssetQuote, Buy, DiscriminatorKind, SwapLeg,
+        SwapMode, TryArbitrageArgs,
+    },
+    arb_msg,
+    error::ArbitrageProgramError,
+    partial_state::{ArbitrageTokenAccountInfo, PartialTokenAccountState},
+    result::{set_return_data, TriangularResult},
+    swap::{
+        apply_fee, determine_swap_receive_checked, determine_weighted_swap_receive_checked,
+        BPS_DENOMINATOR,
+    },
+    util::ArbtrageEvaluateOption,
+};
+
+/// 三角套利使用的第三个流动性池
+///
+/// 设置后 `TryArbitrage` 不再扫描两池之间的往返，而是评估 A→B（Swap #1）、
+/// B→C（Swap #2）、C→A（Swap #3）的循环。Swap #3 按恒定乘积池定价。
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TriangularConfig {
+    /// 第三个 swap 程序的公钥
+    pub swap_3_program_id: Pubkey,
+    /// 第三个流动性池的手续费率（基点）
+    pub swap_3_fee_bps: u16,
+    /// 第三个 swap 程序识别指令的方式
+    pub swap_3_discriminator: DiscriminatorKind,
+}
+
+/// 已解析的 Swap #3 账户
+pub struct Swap3Accounts<'a, 'b> {
+    /// 第三个 swap 程序账户
+    pub program: &'a AccountInfo<'b>,
+    /// 第三个流动性池账户
+    pub pool: &'a AccountInfo<'b>,
+    /// 第三个流动性池的代币账户，按资产顺序排列
+    pub token_accounts: Vec<ArbitrageTokenAccountInfo<'a, 'b>>,
+    /// 指令给出的 Swap #3 配置
+    pub config: TriangularConfig,
+}
+
+/// 选出的三角套利循环
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Cycle {
+    /// 循环经过的资产索引 `[a, b, c]`
+    mints: [usize; 3],
+    /// 支付的资产 a 数量
+    pay_amount: u64,
+    /// 每条腿预计获得的数量
+    amounts_out: [u64; 3],
+}
+
+impl Cycle {
+    /// 预计往返一次获得的资产 a 利润
+    fn expected_profit(&self) -> u64 {
+        self.amounts_out[2].saturating_sub(self.pay_amount)
+    }
+}
+
+/// 评估所有三资产循环，执行预计利润最大的一个
+///
+/// 只有最后一条腿预计换回的资产 a 超过支付数量（且利润不低于 `min_profit`）时才交易；
+/// 执行后按实际换回的数量再次检查，第三条腿低于 `min_out_fraction_bps` 的下限、
+/// 或实际利润为零或低于 `min_profit` 时整笔交易回滚。
+///
+/// # 参数
+/// * `args` - 已解析的账户
+/// * `quotes` - Swap #1、Swap #2 的报价数据
+/// * `swap_3` - 已解析的 Swap #3 账户
+///
+/// # 返回值
+/// * `ProgramResult` - 没有获利的循环时返回 `ArbitrageProgramError::NoArbitrage`
+pub(crate) fn try_triangular_arbitrage<'a, 'b>(
+    args: &TryArbitrageArgs<'a, 'b>,
+    quotes: &[AssetQuote],
+    swap_3: &Swap3Accounts<'a, 'b>,
+) -> ProgramResult {
+    arb_msg!("Swap #3 Pool: {}", swap_3.pool.key);
+    let mut best: Option<Cycle> = None;
+    for a in 0..quotes.len() {
+        for b in (0..quotes.len()).filter(|&b| b != a) {
+            for c in (0..quotes.len()).filter(|&c| c != a && c != b) {
+                let Some(cycle) = quote_cycle(args, quotes, swap_3, [a, b, c])? else {
+                    continue;
+                };
+                if cycle.amounts_out[2] <= cycle.pay_amount
+                    || cycle.expected_profit() < args.min_profit(a)?
+                {
+                    continue;
+                }
+                if best.is_none_or(|best| cycle.expected_profit() > best.expected_profit()) {
+                    best = Some(cycle);
+                }
+            }
+        }
+    }
+    let Some(cycle) = best else {
+        arb_msg!("No profitable triangular cycle");
+        return Err(ArbitrageProgramError::NoArbitrage.into());
+    };
+    execute_cycle(args, swap_3, &cycle)
+}
+
+/// 估算循环 `[a, b, c]` 每条腿的接收数量，循环不可用时返回 `None`
+fn quote_cycle(
+    args: &TryArbitrageArgs<'_, '_>,
+    quotes: &[AssetQuote],
+    swap_3: &Swap3Accounts<'_, '_>,
+    mints: [usize; 3],
+) -> Result<Option<Cycle>, ProgramError> {
+    let [a, b, c] = mints;
+    let (quote_a, quote_b, quote_c) = (
+        quotes.get(a).ok_or_arb_err()?,
+        quotes.get(b).ok_or_arb_err()?,
+        quotes.get(c).ok_or_arb_err()?,
+    );
+    let denied = [(a, b), (b, c), (c, a)]
+        .iter()
+        .any(|&(x, y)| args.denied_pairs.contains(&(x.min(y), x.max(y))));
+    if quote_a.excluded || quote_b.excluded || quote_c.excluded || denied {
+        return Ok(None);
+    }
+    let swap_3_a = swap_3.token_accounts.get(a).ok_or_arb_err()?.3;
+    let swap_3_c = swap_3.token_accounts.get(c).ok_or_arb_err()?.3;
+    let pay_amount = quote_a.user_balance;
+    if pay_amount == 0 || swap_3_a == 0 || swap_3_c == 0 {
+        return Ok(None);
+    }
+
+    let r_1 = determine_weighted_swap_receive_checked(
+        quote_b.swap_1_balance,
+        quote_a.swap_1_balance,
+        apply_fee(pay_amount, args.fees.swap_1_fee_bps)?,
+        (quote_a.swap_1_weight, quote_b.swap_1_weight),
+        0,
+    )?;
+    let r_2 = determine_weighted_swap_receive_checked(
+        quote_c.swap_2_balance,
+        quote_b.swap_2_balance,
+        apply_fee(r_1, args.fees.swap_2_fee_bps)?,
+        (quote_b.swap_2_weight, quote_c.swap_2_weight),
+        0,
+    )?;
+    let r_3 = determine_swap_receive_checked(
+        swap_3_a,
+        swap_3_c,
+        apply_fee(r_2, swap_3.config.swap_3_fee_bps)?,
+        0,
+    )?;
+    // 任一腿的接收数量被向下取整为零时不能发起零数量的 CPI
+    if r_1 == 0 || r_2 == 0 || r_3 == 0 {
+        return Ok(None);
+    }
+    Ok(Some(Cycle {
+        mints,
+        pay_amount,
+        amounts_out: [r_1, r_2, r_3],
+    }))
+}
+
+/// 依次在 Swap #1、Swap #2、Swap #3 上执行循环的三条腿
+fn execute_cycle<'a, 'b>(
+    args: &TryArbitrageArgs<'a, 'b>,
+    swap_3: &Swap3Accounts<'a, 'b>,
+    cycle: &Cycle,
+) -> ProgramResult {
+    let [a, b, c] = cycle.mints;
+    let [r_1, r_2, r_3] = cycle.amounts_out;
+    arb_msg!(
+        "PLACING TRIANGULAR TRADE: {} -> {} -> {} -> {}, expected profit {}",
+        a,
+        b,
+        c,
+        a,
+        cycle.expected_profit()
+    );
+    let (asset_a, asset_b, asset_c) = (
+        args.asset_accounts(a)?,
+        args.asset_accounts(b)?,
+        args.asset_accounts(c)?,
+    );
+    let balance =
+        |account| PartialTokenAccountState::try_deserialize(account, args.owner.key).map(|a| a.3);
+    let (balance_before, c_balance_before) = (balance(asset_a.user)?, balance(asset_c.user)?);
+
+    // 前两条腿与两池套利一样经由 Swap #1、Swap #2 执行
+    let leg = |venue, pay, receive, amount, amount_out| SwapLeg {
+        venue,
+        pay,
+        receive,
+        amount,
+        amount_out,
+        mode: SwapMode::ExactIn,
+    };
+    let legs = invoke_arbitrage(
+        args,
+        &[
+            leg(Buy::Swap1, asset_a, asset_b, cycle.pay_amount, r_1),
+            leg(Buy::Swap2, asset_b, asset_c, r_1, r_2),
+        ],
+    )?;
+    // 第三条腿只支付第二条腿实际换到的资产 c，而不是预估值，不动用用户原有的资产 c
+    let leg_3_amount = legs.get(1).ok_or_arb_err()?.amount_out;
+    if leg_3_amount == 0 {
+        arb_msg!("Leg 2 received no asset {}", c);
+        return Err(ArbitrageProgramError::BelowMinReceive.into());
+    }
+
+    // 第三条腿：在 Swap #3 上支付资产 c 换回资产 a，账户顺序与 `leg_cpi` 相同
+    arb_msg!("Executing leg 3 on Swap3 ...");
+    let receive_before = balance(asset_a.user)?;
+    let mut accounts = vec![
+        swap_3.pool.to_owned(),
+        asset_a.mint.to_owned(),
+        swap_3.token_accounts.get(a).ok_or_arb_err()?.0.to_owned(),
+        asset_a.user.to_owned(),
+        asset_c.mint.to_owned(),
+        swap_3.token_accounts.get(c).ok_or_arb_err()?.0.to_owned(),
+        asset_c.user.to_owned(),
+        args.owner.to_owned(),
+        args.token_program.to_owned(),
+        args.system_program.to_owned(),
+    ];
+    accounts.extend(args.associated_token_program.cloned());
+    invoke_swap(
+        args,
+        *swap_3.program.key,
+        &accounts,
+        &build_ix_data(
+            swap_3.config.swap_3_discriminator,
+            args.options.amount_encoding,
+            SwapMode::ExactIn,
+            leg_3_amount,
+            0,
+        ),
+    )?;
+
+    // 循环结束后用户的资产 c 不得少于开始之前，否则第三条腿动用了用户原有的持仓
+    let c_balance_after = balance(asset_c.user)?;
+    if c_balance_after < c_balance_before {
+        arb_msg!(
+            "Triangular cycle reduced asset {} from {} to {}",
+            c,
+            c_balance_before,
+            c_balance_after
+        );
+        return Err(ArbitrageProgramError::BelowMinReceive.into());
+    }
+
+    // ExactIn 指令数据不携带滑点下限，与前两条腿一样在 CPI 之后按预估值检查
+    let balance_after = balance(asset_a.user)?;
+    if let Some(fraction_bps) = args.options.min_out_fraction_bps {
+        // 结果不超过预估值，不会溢出 u64
+        let floor = (u128::from(r_3) * u128::from(fraction_bps) / BPS_DENOMINATOR) as u64;
+        let received = balance_after.saturating_sub(receive_before);
+        if received < floor {
+            arb_msg!(
+                "Leg 3 received {}, below {} bps of the estimate {}",
+                received,
+                fraction_bps,
+                r_3
+            );
+            return Err(ArbitrageProgramError::BelowMinReceive.into());
+        }
+    }
+
+    // 按实际换回的数量检查循环是否获利，利润为零或低于 `min_profit` 时回滚前面的所有腿
+    let profit = balance_after.saturating_sub(balance_before);
+    let min_profit = args.min_profit(a)?;
+    if profit == 0 || profit < min_profit {
+        arb_msg!(
+            "Triangular cycle returned profit {} ({} -> {}), minimum {}",
+            profit,
+            balance_before,
+            balance_after,
+            min_profit
+        );
+        return Err(ArbitrageProgramError::BelowMinReceive.into());
+    }
+    set_return_data(&TriangularResult {
+        mints: [a as u8, b as u8, c as u8],
+        amount_in: cycle.pay_amount,
+        expected_amount_out: r_3,
+        profit,
+    })
+}
+
+/// 从可选账户中解析 Swap #3 的程序、流动性池与代币账户
+///
+/// # 参数
+/// * `trailing` - 可选账户迭代器，依次读取 Swap #3 程序、流动性池和 `concurrency` 个代币账户
+/// * `config` - 指令给出的 Swap #3 配置
+/// * `mints` - 已解析的铸币账户，Swap #3 的代币账户必须按相同的资产顺序排列
+///
+/// # 错误
+/// * `ArbitrageProgramError::InvalidAccountsList` - 程序与配置不一致、代币账户不属于该流动性池
+///   或铸币顺序不一致
+pub(crate) fn parse_swap_3_accounts<'a, 'b>(
+    trailing: &mut impl Iterator<Item = &'a AccountInfo<'b>>,
+    config: TriangularConfig,
+    mints: &[Pubkey],
+) -> Result<Swap3Accounts<'a, 'b>, ProgramError> {
+    let program = trailing.next().ok_or(ProgramError::NotEnoughAccountKeys)?;
+    let pool = trailing.next().ok_or(ProgramError::NotEnoughAccountKeys)?;
+    if !program.key.eq(&config.swap_3_program_id) {
+        arb_msg!(
+            "Swap #3 program: expected {}, got {}",
+            config.swap_3_program_id,
+            program.key
+        );
+        return Err(ArbitrageProgramError::InvalidAccountsList.into());
+    }
+    let mut token_accounts = Vec::with_capacity(mints.len());
+    for (x, mint) in mints.iter().enumerate() {
+        let account = PartialTokenAccountState::try_deserialize(
+            trailing.next().ok_or(ProgramError::NotEnoughAccountKeys)?,
+            pool.key,
+        )?;
+        if !account.1.eq(mint) {
+            arb_msg!(
+                "Swap #3 token account {} holds mint {}, which is not asset {}",
+                account.0.key,
+                account.1,
+                x
+            );
+            return Err(ArbitrageProgramError::InvalidAccountsList.into());
+        }
+        token_accounts.push(account);
+    }
+    Ok(Swap3Accounts {
+        program,
+        pool,
+        token_accounts,
+        config,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use borsh::BorshDeserialize;
+    use solana_program::instruction::Instruction;
+
+    use super::*;
+    use crate::testing::{
+        install, pool_account, program_account, return_data, set_token_amount, take_cpis,
+        take_logs, token_account, token_amount, Market, MarketAccounts, MockPool, MockSwap,
+        SwapInvoker,
+    };
+    use crate::TryArbitrageOptions;
+
+    /// 三资产市场：Swap #1、Swap #2 的报价相同，Swap #3 的资产 0 池余额为 `swap_3_a`，
+    /// 账户列表末尾追加 Swap #3 的程序、流动性池与代币账户
+    fn triangular_market(swap_3_a: u64) -> (MarketAccounts, TryArbitrageOptions) {
+        let (mock, accounts, options) = triangular_parts(&[1_000, 0, 0], swap_3_a);
+        install(mock);
+        (accounts, options)
+    }
+
+    /// 与 `triangular_market` 相同，但由调用方安装模拟的 swap 程序
+    fn triangular_parts(
+        user: &[u64],
+        swap_3_a: u64,
+    ) -> (MockSwap, MarketAccounts, TryArbitrageOptions) {
+        let market = Market::new(user, &[1_000_000; 3], &[1_000_000; 3]);
+        let swap_3_program_id = Pubkey::new_unique();
+        let mut mock = market.mock_swap();
+        mock.pools.push(MockPool::new(swap_3_program_id));
+        let mut accounts = market.build();
+        let pool = pool_account(&swap_3_program_id, vec![]);
+        let token_accounts: Vec<_> = [swap_3_a, 1_000_000, 1_000_000]
+            .iter()
+            .enumerate()
+            .map(|(x, &balance)| {
+                token_account(accounts.mint(x).key, pool.key, balance, &spl_token::ID)
+            })
+            .collect();
+        accounts.accounts.push(program_account(&swap_3_program_id));
+        accounts.accounts.push(pool);
+        accounts.accounts.extend(token_accounts);
+        let options = TryArbitrageOptions {
+            triangular: Some(TriangularConfig {
+                swap_3_program_id,
+                swap_3_fee_bps: 0,
+                swap_3_discriminator: DiscriminatorKind::Anchor8,
+            }),
+            ..TryArbitrageOptions::default()
+        };
+        (mock, accounts, options)
+    }
+
+    #[test]
+    fn three_leg_profit_is_the_final_receive_minus_the_pay_amount() {
+        let (accounts, options) = triangular_market(2_000_000);
+
+        assert_eq!(accounts.try_arbitrage(90, options), Ok(()));
+        // 0 -> 1：1e6 * 1000 / 1001000 = 999；1 -> 2：1e6 * 999 / 1000999 = 998；
+        // 2 -> 0：2e6 * 998 / 1000998 = 1994，利润 994
+        let cpis = take_cpis();
+        assert_eq!(cpis.len(), 3);
+        let amounts: Vec<_> = cpis.iter().map(|cpi| cpi.data[8..].to_vec()).collect();
+        assert_eq!(
+            amounts,
+            [1_000u64, 999, 998].map(|amount| amount.to_le_bytes().to_vec())
+        );
+        assert!(take_logs().contains(
+            &"[ARB] PLACING TRIANGULAR TRADE: 0 -> 1 -> 2 -> 0, expected profit 994".to_string()
+        ));
+        let result = TriangularResult::try_from_slice(&return_data().unwrap().1).unwrap();
+        assert_eq!(
+            result,
+            TriangularResult {
+                mints: [0, 1, 2],
+                amount_in: 1_000,
+                expected_amount_out: 1_994,
+                profit: 994,
+            }
+        );
+        assert_eq!(token_amount(accounts.user(0)), 1_994);
+    }
+
+    #[test]
+    fn cycle_returning_less_than_the_pay_amount_is_not_traded() {
+        // 三个池子报价相同，每条腿都因为价格冲击少得一点：1000 -> 999 -> 998 -> 997
+        let (accounts, options) = triangular_market(1_000_000);
+
+        assert_eq!(
+            accounts.try_arbitrage(90, options),
+            Err(ArbitrageProgramError::NoArbitrage.into())
+        );
+        assert!(take_cpis().is_empty());
+        assert!(take_logs().contains(&"[ARB] No profitable triangular cycle".to_string()));
+    }
+
+    #[test]
+    fn cycle_below_min_profit_is_not_traded() {
+        for (min_profit, result) in [
+            (994, Ok(())),
+            (995, Err(ArbitrageProgramError::NoArbitrage.into())),
+        ] {
+            let (accounts, options) = triangular_market(2_000_000);
+            let options = TryArbitrageOptions {
+                min_profit,
+                ..options
+            };
+            assert_eq!(accounts.try_arbitrage(90, options), result);
+        }
+    }
+
+    #[test]
+    fn leg_3_pays_the_actual_leg_2_output() {
+        // Swap #2 只成交报价的 90%：998 * 90% = 898，第三条腿支付 898 而不是预估的 998
+        let (mut mock, accounts, options) = triangular_parts(&[1_000, 0, 0], 2_000_000);
+        mock.pools[1].output_bps = 9_000;
+        install(mock);
+
+        assert_eq!(accounts.try_arbitrage(90, options), Ok(()));
+        let cpis = take_cpis();
+        assert_eq!(cpis[2].data[8..], 898u64.to_le_bytes());
+        // 2e6 * 898 / 1000898 = 1794
+        assert_eq!(token_amount(accounts.user(0)), 1_794);
+        assert_eq!(token_amount(accounts.user(2)), 0);
+    }
+
+    /// 在 Swap #3 成交后再从用户的资产 c 账户多扣 1 个单位的模拟 swap 程序
+    struct Overcharging {
+        mock: MockSwap,
+        swap_3_program_id: Pubkey,
+    }
+
+    impl SwapInvoker for Overcharging {
+        fn invoke(&mut self, ix: &Instruction, accounts: &[AccountInfo]) -> ProgramResult {
+            self.mock.invoke(ix, accounts)?;
+            if ix.program_id == self.swap_3_program_id {
+                // `leg_cpi` 顺序的第 7 个账户是支付资产的用户代币账户
+                let pay_user = ix.accounts[6].pubkey;
+                let user = accounts.iter().find(|a| a.key.eq(&pay_user)).unwrap();
+                set_token_amount(user, token_amount(user) - 1);
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn cycle_reducing_the_asset_c_balance_is_rejected() {
+        let (mock, accounts, options) = triangular_parts(&[1_000, 0, 500], 2_000_000);
+        let swap_3_program_id = options.triangular.unwrap().swap_3_program_id;
+        install(Overcharging {
+            mock,
+            swap_3_program_id,
+        });
+
+        assert_eq!(
+            accounts.try_arbitrage(90, options),
+            Err(ArbitrageProgramError::BelowMinReceive.into())
+        );
+        assert!(take_logs()
+            .contains(&"[ARB] Triangular cycle reduced asset 2 from 500 to 499".to_string()));
+    }
+}
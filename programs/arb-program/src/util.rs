@@ -1,9 +1,32 @@
+use borsh::{BorshDeserialize, BorshSerialize};
 use solana_program::{
-    account_info::AccountInfo, entrypoint::ProgramResult, instruction::AccountMeta,
-    program_error::ProgramError, pubkey::Pubkey,
+    account_info::AccountInfo, clock::Clock, entrypoint::ProgramResult, instruction::AccountMeta,
+    program_error::ProgramError, pubkey::Pubkey, sysvar::Sysvar,
 };
+use spl_associated_token_account::get_associated_token_address_with_program_id;
 
 use crate::error::ArbitrageProgramError;
+use crate::partial_state::{ArbitrageTokenAccountInfo, PartialTokenAccountState};
+
+/// 本程序所有日志的前缀，便于在包含多个程序的交易日志中筛选
+pub const LOG_PREFIX: &str = "[ARB]";
+
+/// 输出带 `LOG_PREFIX` 前缀的日志，用法与 `msg!` 相同
+#[macro_export]
+macro_rules! arb_msg {
+    ($($arg:tt)*) => {
+        $crate::util::log_message(format_args!($($arg)*))
+    };
+}
+
+/// `arb_msg!` 的实现：加上 `LOG_PREFIX` 后输出，测试时同时记录到 `testing::take_logs`
+#[doc(hidden)]
+pub fn log_message(args: std::fmt::Arguments) {
+    let message = format!("{} {}", LOG_PREFIX, args);
+    #[cfg(any(test, feature = "testing"))]
+    crate::testing::capture_log(&message);
+    solana_program::log::sol_log(&message);
+}
 
 /// 检查流动性池地址是否有效
 ///
@@ -30,6 +53,376 @@ pub fn check_pool_address(program_id: &Pubkey, pool: &Pubkey) -> ProgramResult {
     Ok(())
 }
 
+/// 检查代币账户是否为规范的关联代币账户（ATA）
+///
+/// 根据所有者、铸币地址以及持有该账户的代币程序推导出预期的 ATA 地址，
+/// 并与传入的代币账户地址进行比较。
+///
+/// # 参数
+/// * `token_account` - 待验证的代币账户
+/// * `owner` - 代币账户的所有者
+/// * `mint` - 代币账户对应的铸币地址
+///
+/// # 错误
+/// * `ArbitrageProgramError::NonCanonicalTokenAccount` - 当代币账户不是推导出的 ATA 时返回
+pub fn check_canonical_ata(
+    token_account: &AccountInfo,
+    owner: &Pubkey,
+    mint: &Pubkey,
+) -> ProgramResult {
+    let expected = get_associated_token_address_with_program_id(owner, mint, token_account.owner);
+    if !expected.eq(token_account.key) {
+        arb_msg!("Expected ATA: {}", expected);
+        arb_msg!("Got:          {}", token_account.key);
+        return Err(ArbitrageProgramError::NonCanonicalTokenAccount.into());
+    }
+    Ok(())
+}
+
+/// 检查一个流动性池的代币账户列表中没有重复的账户
+///
+/// 同一个代币账户出现在两个资产索引上会导致池子余额被重复计算。
+///
+/// # 参数
+/// * `pool` - 流动性池地址，仅用于日志
+/// * `token_accounts` - 该流动性池按资产顺序排列的代币账户
+///
+/// # 错误
+/// * `ArbitrageProgramError::DuplicateTokenAccount` - 当存在重复账户时返回
+pub fn check_distinct_token_accounts(
+    pool: &Pubkey,
+    token_accounts: &[ArbitrageTokenAccountInfo],
+) -> ProgramResult {
+    for (i, a) in token_accounts.iter().enumerate() {
+        if let Some(offset) = token_accounts[i + 1..]
+            .iter()
+            .position(|b| a.0.key.eq(b.0.key))
+        {
+            arb_msg!("Pool: {}", pool);
+            arb_msg!(
+                "Token account {} is used for assets {} and {}",
+                a.0.key,
+                i,
+                i + 1 + offset
+            );
+            return Err(ArbitrageProgramError::DuplicateTokenAccount.into());
+        }
+    }
+    Ok(())
+}
+
+/// 检查一个流动性池的代币账户列表中每个账户对应的铸币都不相同
+///
+/// 同一个铸币出现两次说明账户布局有误，或者有人试图用伪造的账户列表误导定价。
+///
+/// # 参数
+/// * `pool` - 流动性池地址，仅用于日志
+/// * `token_accounts` - 该流动性池按资产顺序排列的代币账户
+///
+/// # 错误
+/// * `ArbitrageProgramError::DuplicatePoolMint` - 当存在重复铸币时返回
+pub fn check_distinct_pool_mints(
+    pool: &Pubkey,
+    token_accounts: &[ArbitrageTokenAccountInfo],
+) -> ProgramResult {
+    for (i, a) in token_accounts.iter().enumerate() {
+        if let Some(offset) = token_accounts[i + 1..].iter().position(|b| a.1.eq(&b.1)) {
+            arb_msg!("Pool: {}", pool);
+            arb_msg!(
+                "Mint {} is used for assets {} and {}",
+                a.1,
+                i,
+                i + 1 + offset
+            );
+            return Err(ArbitrageProgramError::DuplicatePoolMint.into());
+        }
+    }
+    Ok(())
+}
+
+/// 检查 swap CPI 会修改的账户以可写方式传入
+///
+/// 客户端忘记设置可写标志时，CPI 会在 swap 程序内部失败，错误难以排查，因此提前检查。
+///
+/// # 参数
+/// * `account` - 待检查的账户
+/// * `name` - 账户在布局中的名称，仅用于日志
+///
+/// # 错误
+/// * `ArbitrageProgramError::AccountNotWritable` - 当账户不可写时返回
+pub fn check_writable(account: &AccountInfo, name: &str) -> ProgramResult {
+    if !account.is_writable {
+        arb_msg!("{} {} must be writable", name, account.key);
+        return Err(ArbitrageProgramError::AccountNotWritable.into());
+    }
+    Ok(())
+}
+
+/// 重新读取流动性池代币账户的余额，确认与之前解析得到的余额一致
+///
+/// 正常情况下同一条指令内池子余额不会变化；余额不一致说明存在异常的 CPI 重入，
+/// 按之前的余额定价不再可靠。
+///
+/// # 参数
+/// * `pool` - 流动性池地址，用于校验代币账户所有者
+/// * `token_accounts` - 之前解析得到的该流动性池代币账户
+///
+/// # 错误
+/// * `ArbitrageProgramError::ReserveMutated` - 当任意一个账户的余额发生变化时返回
+pub fn check_reserves_unchanged(
+    pool: &Pubkey,
+    token_accounts: &[ArbitrageTokenAccountInfo],
+) -> ProgramResult {
+    for (account, _, _, amount) in token_accounts {
+        let (_, _, _, reread) = PartialTokenAccountState::try_deserialize(account, pool)?;
+        if reread != *amount {
+            arb_msg!(
+                "Reserve {} changed from {} to {} mid-instruction",
+                account.key,
+                amount,
+                reread
+            );
+            return Err(ArbitrageProgramError::ReserveMutated.into());
+        }
+    }
+    Ok(())
+}
+
+/// 检查 swap CPI 的账户重排表是否是一个合法的排列
+///
+/// 空表表示使用默认顺序；否则长度必须等于 `len`，且 `0..len` 中每个下标恰好出现一次。
+///
+/// # 参数
+/// * `swap_program_id` - 该重排表对应的 swap 程序，仅用于日志
+/// * `order` - 账户重排表
+/// * `len` - swap CPI 的账户数量
+///
+/// # 错误
+/// * `ProgramError::InvalidInstructionData` - 当重排表不是合法排列时返回
+pub fn check_account_permutation(
+    swap_program_id: &Pubkey,
+    order: &[u8],
+    len: usize,
+) -> ProgramResult {
+    if order.is_empty() {
+        return Ok(());
+    }
+    let mut seen = vec![false; len];
+    let valid = order.len() == len
+        && order
+            .iter()
+            .all(|&index| match seen.get_mut(usize::from(index)) {
+                Some(used) if !*used => {
+                    *used = true;
+                    true
+                }
+                _ => false,
+            });
+    if !valid {
+        arb_msg!("Swap program: {}", swap_program_id);
+        arb_msg!(
+            "Account order {:?} is not a permutation of 0..{}",
+            order,
+            len
+        );
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    Ok(())
+}
+
+/// 检查代币账户所有者是由 `vault_seeds` 推导出的本程序 PDA 金库
+///
+/// # 参数
+/// * `program_id` - 本程序 ID
+/// * `owner` - 代币账户所有者
+/// * `vault_seeds` - 指令携带的金库种子（包含 bump）
+///
+/// # 错误
+/// * `ProgramError::InvalidSeeds` - 当种子无法推导出 `owner` 时返回
+pub fn check_vault_owner(
+    program_id: &Pubkey,
+    owner: &Pubkey,
+    vault_seeds: &[Vec<u8>],
+) -> ProgramResult {
+    let seeds: Vec<&[u8]> = vault_seeds.iter().map(Vec::as_slice).collect();
+    match Pubkey::create_program_address(&seeds, program_id) {
+        Ok(vault) if vault.eq(owner) => Ok(()),
+        _ => {
+            arb_msg!("Vault seeds do not derive owner {}", owner);
+            Err(ProgramError::InvalidSeeds)
+        }
+    }
+}
+
+/// 从流动性池账户数据中读取手续费率
+///
+/// # 参数
+/// * `pool` - 流动性池账户
+/// * `offset` - 手续费率（`u16` 小端序，基点）在账户数据中的偏移量，为 `None` 时视为无手续费
+///
+/// # 错误
+/// * `ProgramError::InvalidAccountData` - 偏移量越界或读取到的手续费率超过 100% 时返回
+pub fn read_pool_fee_bps(pool: &AccountInfo, offset: Option<u32>) -> Result<u16, ProgramError> {
+    let Some(offset) = offset else {
+        return Ok(0);
+    };
+    let start = offset as usize;
+    let data = pool.try_borrow_data()?;
+    let fee_bps = match data.get(start..start.saturating_add(2)) {
+        Some(bytes) => u16::from_le_bytes([bytes[0], bytes[1]]),
+        None => {
+            arb_msg!(
+                "Pool {}: fee offset {} is out of bounds (data len {})",
+                pool.key,
+                offset,
+                data.len()
+            );
+            return Err(ProgramError::InvalidAccountData);
+        }
+    };
+    if fee_bps > 10_000 {
+        arb_msg!("Pool {}: fee {} bps exceeds 100%", pool.key, fee_bps);
+        return Err(ProgramError::InvalidAccountData);
+    }
+    Ok(fee_bps)
+}
+
+/// 从元数据账户中读取资产的有效小数位数
+///
+/// 部分包装资产或合成资产的有效小数位数记录在单独的元数据账户中，而不是铸币账户。
+///
+/// # 参数
+/// * `metadata` - 元数据账户
+/// * `offset` - 小数位数（`u8`）在账户数据中的偏移量
+///
+/// # 错误
+/// * `ProgramError::InvalidAccountData` - 偏移量越界时返回
+pub fn read_metadata_decimals(metadata: &AccountInfo, offset: u32) -> Result<u8, ProgramError> {
+    let data = metadata.try_borrow_data()?;
+    data.get(offset as usize).copied().ok_or_else(|| {
+        arb_msg!(
+            "Metadata {}: decimals offset {} is out of bounds (data len {})",
+            metadata.key,
+            offset,
+            data.len()
+        );
+        ProgramError::InvalidAccountData
+    })
+}
+
+/// 检查流动性池账户数据中记录的最后更新 slot 距今不超过 `max_staleness_slots`
+///
+/// `slot_offset` 或 `max_staleness_slots` 为 `None` 时不检查。
+///
+/// # 参数
+/// * `pool` - 流动性池账户
+/// * `slot_offset` - 最后更新 slot（`u64` 小端序）在账户数据中的偏移量
+/// * `max_staleness_slots` - 允许的最大落后 slot 数
+///
+/// # 错误
+/// * `ProgramError::InvalidAccountData` - 偏移量越界时返回
+/// * `ArbitrageProgramError::StalePool` - 流动性池的最后更新 slot 过旧时返回
+pub fn check_pool_freshness(
+    pool: &AccountInfo,
+    slot_offset: Option<u32>,
+    max_staleness_slots: Option<u64>,
+) -> ProgramResult {
+    let (Some(offset), Some(max_staleness_slots)) = (slot_offset, max_staleness_slots) else {
+        return Ok(());
+    };
+    let start = offset as usize;
+    let last_update_slot = {
+        let data = pool.try_borrow_data()?;
+        match data
+            .get(start..start.saturating_add(8))
+            .and_then(|bytes| <[u8; 8]>::try_from(bytes).ok())
+        {
+            Some(bytes) => u64::from_le_bytes(bytes),
+            None => {
+                arb_msg!(
+                    "Pool {}: slot offset {} is out of bounds (data len {})",
+                    pool.key,
+                    offset,
+                    data.len()
+                );
+                return Err(ProgramError::InvalidAccountData);
+            }
+        }
+    };
+    let current_slot = Clock::get()?.slot;
+    let staleness = current_slot.saturating_sub(last_update_slot);
+    if staleness > max_staleness_slots {
+        arb_msg!(
+            "Pool {}: last updated at slot {}, {} slots ago (max {})",
+            pool.key,
+            last_update_slot,
+            staleness,
+            max_staleness_slots
+        );
+        return Err(ArbitrageProgramError::StalePool.into());
+    }
+    Ok(())
+}
+
+/// 本程序 PDA 数据的当前格式版本，写在数据的第一个字节，便于之后安全地迁移格式
+pub const ACCOUNT_VERSION: u8 = 1;
+
+/// 带版本前缀的 PDA 数据长度：版本字节加上 `len` 字节的内容
+pub const fn versioned_len(len: usize) -> usize {
+    1 + len
+}
+
+/// 把 `value` 以 `ACCOUNT_VERSION` 前缀写入 PDA 数据的开头
+///
+/// # 错误
+/// * `ProgramError::AccountDataTooSmall` - 账户空间不足时返回
+pub fn write_versioned<T: BorshSerialize>(account: &AccountInfo, value: &T) -> ProgramResult {
+    let mut data = vec![ACCOUNT_VERSION];
+    value.serialize(&mut data)?;
+    account
+        .try_borrow_mut_data()?
+        .get_mut(..data.len())
+        .ok_or(ProgramError::AccountDataTooSmall)?
+        .copy_from_slice(&data);
+    Ok(())
+}
+
+/// 读取带版本前缀的 PDA 数据，旧版本先经过 `migrate_account_data` 转换为当前格式
+///
+/// # 错误
+/// * `ArbitrageProgramError::UnsupportedAccountVersion` - 版本未知（例如由更新的程序写入）时返回
+/// * `ProgramError::InvalidAccountData` - 数据为空或无法解析时返回
+pub fn read_versioned<T: BorshDeserialize>(account: &AccountInfo) -> Result<T, ProgramError> {
+    let data = account.try_borrow_data()?;
+    let (&version, body) = data.split_first().ok_or(ProgramError::InvalidAccountData)?;
+    let migrated;
+    let mut body = match version {
+        ACCOUNT_VERSION => body,
+        _ => {
+            migrated = migrate_account_data(account.key, version, body)?;
+            &migrated[..]
+        }
+    };
+    T::deserialize(&mut body).map_err(|_| ProgramError::InvalidAccountData)
+}
+
+/// 把旧版本的 PDA 数据转换为当前格式
+///
+/// 目前只有一个版本，没有可迁移的旧格式；引入新版本时在这里按版本逐级转换。
+fn migrate_account_data(
+    account: &Pubkey,
+    version: u8,
+    _body: &[u8],
+) -> Result<Vec<u8>, ProgramError> {
+    arb_msg!(
+        "Account {}: unsupported data version {} (current {})",
+        account,
+        version,
+        ACCOUNT_VERSION
+    );
+    Err(ArbitrageProgramError::UnsupportedAccountVersion.into())
+}
+
 pub trait ArbtrageEvaluateOption<T> {
     fn ok_or_arb_err(self) -> Result<T, ProgramError>;
 }
@@ -71,3 +464,191 @@ impl ToAccountMeta for AccountInfo<'_> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{
+        account, install, set_token_amount, take_logs, token_account, MockSwap, PROGRAM_ID,
+    };
+
+    #[test]
+    fn canonical_ata_passes() {
+        let (owner, mint) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let ata = get_associated_token_address_with_program_id(&owner, &mint, &spl_token::ID);
+        let token_account = account(ata, spl_token::ID, vec![0; 165], false, true, false);
+
+        assert_eq!(check_canonical_ata(&token_account, &owner, &mint), Ok(()));
+    }
+
+    #[test]
+    fn non_canonical_token_account_is_rejected() {
+        let (owner, mint) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let token_account = token_account(&mint, &owner, 0, &spl_token::ID);
+
+        assert_eq!(
+            check_canonical_ata(&token_account, &owner, &mint),
+            Err(ArbitrageProgramError::NonCanonicalTokenAccount.into())
+        );
+    }
+
+    #[test]
+    fn canonical_ata_is_derived_with_the_holding_token_program() {
+        let (owner, mint) = (Pubkey::new_unique(), Pubkey::new_unique());
+        // SPL Token 的 ATA 地址由 Token-2022 持有时不是规范地址
+        let ata = get_associated_token_address_with_program_id(&owner, &mint, &spl_token::ID);
+        let token_account = account(ata, spl_token_2022::ID, vec![0; 165], false, true, false);
+
+        assert_eq!(
+            check_canonical_ata(&token_account, &owner, &mint),
+            Err(ArbitrageProgramError::NonCanonicalTokenAccount.into())
+        );
+    }
+
+    fn pool_with_fee(fee_bps: u16) -> AccountInfo<'static> {
+        let mut data = vec![0; 16];
+        data[4..6].copy_from_slice(&fee_bps.to_le_bytes());
+        account(
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            data,
+            false,
+            false,
+            false,
+        )
+    }
+
+    #[test]
+    fn pool_fee_is_read_from_the_configured_offset() {
+        assert_eq!(read_pool_fee_bps(&pool_with_fee(30), Some(4)), Ok(30));
+        assert_eq!(read_pool_fee_bps(&pool_with_fee(100), Some(4)), Ok(100));
+        assert_eq!(read_pool_fee_bps(&pool_with_fee(30), None), Ok(0));
+    }
+
+    #[test]
+    fn invalid_pool_fee_is_rejected() {
+        assert_eq!(
+            read_pool_fee_bps(&pool_with_fee(30), Some(15)),
+            Err(ProgramError::InvalidAccountData)
+        );
+        assert_eq!(
+            read_pool_fee_bps(&pool_with_fee(10_001), Some(4)),
+            Err(ProgramError::InvalidAccountData)
+        );
+    }
+
+    #[test]
+    fn arb_msg_prefixes_every_log() {
+        install(MockSwap::default());
+        let pool = Pubkey::new_unique();
+        crate::arb_msg!("Swap #1 Pool: {}", pool);
+        crate::arb_msg!("PLACING TRADE!");
+
+        assert_eq!(
+            take_logs(),
+            vec![
+                format!("{LOG_PREFIX} Swap #1 Pool: {pool}"),
+                "[ARB] PLACING TRADE!".to_string(),
+            ]
+        );
+    }
+
+    /// 程序持有的 PDA，数据为版本字节 `version` 加上 borsh 编码的 `value`
+    fn versioned_pda(version: u8, value: u64) -> AccountInfo<'static> {
+        let mut data = vec![version];
+        data.extend_from_slice(&value.to_le_bytes());
+        account(Pubkey::new_unique(), PROGRAM_ID, data, false, true, false)
+    }
+
+    #[test]
+    fn current_version_pda_is_read() {
+        let pda = versioned_pda(ACCOUNT_VERSION, 42);
+        assert_eq!(read_versioned::<u64>(&pda), Ok(42));
+
+        write_versioned(&pda, &7u64).unwrap();
+        assert_eq!(pda.data.borrow()[0], ACCOUNT_VERSION);
+        assert_eq!(read_versioned::<u64>(&pda), Ok(7));
+    }
+
+    #[test]
+    fn future_version_pda_is_rejected() {
+        install(MockSwap::new(vec![]));
+        let pda = versioned_pda(ACCOUNT_VERSION + 1, 42);
+
+        assert_eq!(
+            read_versioned::<u64>(&pda),
+            Err(ArbitrageProgramError::UnsupportedAccountVersion.into())
+        );
+        assert_eq!(
+            take_logs(),
+            vec![format!(
+                "[ARB] Account {}: unsupported data version {} (current {})",
+                pda.key,
+                ACCOUNT_VERSION + 1,
+                ACCOUNT_VERSION
+            )]
+        );
+    }
+
+    #[test]
+    fn empty_pda_is_invalid() {
+        let pda = account(Pubkey::new_unique(), PROGRAM_ID, vec![], false, true, false);
+        assert_eq!(
+            read_versioned::<u64>(&pda),
+            Err(ProgramError::InvalidAccountData)
+        );
+    }
+
+    #[test]
+    fn mutated_reserve_is_detected() {
+        install(MockSwap::default());
+        let pool = Pubkey::new_unique();
+        let reserves: Vec<_> = [1_000, 2_000]
+            .map(|amount| token_account(&Pubkey::new_unique(), &pool, amount, &spl_token::ID))
+            .to_vec();
+        let parsed: Vec<_> = reserves
+            .iter()
+            .map(|account| PartialTokenAccountState::try_deserialize(account, &pool).unwrap())
+            .collect();
+        assert_eq!(check_reserves_unchanged(&pool, &parsed), Ok(()));
+
+        // 模拟异常的 CPI 重入在两次读取之间修改了池子余额
+        set_token_amount(&reserves[1], 1_500);
+        assert_eq!(
+            check_reserves_unchanged(&pool, &parsed),
+            Err(ArbitrageProgramError::ReserveMutated.into())
+        );
+        assert_eq!(
+            take_logs(),
+            vec![format!(
+                "[ARB] Reserve {} changed from 2000 to 1500 mid-instruction",
+                reserves[1].key
+            )]
+        );
+    }
+
+    #[test]
+    fn metadata_decimals_are_read_at_the_offset() {
+        install(MockSwap::default());
+        let metadata = account(
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            vec![0, 0, 9],
+            false,
+            false,
+            false,
+        );
+        assert_eq!(read_metadata_decimals(&metadata, 2), Ok(9));
+        assert_eq!(
+            read_metadata_decimals(&metadata, 3),
+            Err(ProgramError::InvalidAccountData)
+        );
+        assert_eq!(
+            take_logs(),
+            vec![format!(
+                "[ARB] Metadata {}: decimals offset 3 is out of bounds (data len 3)",
+                metadata.key
+            )]
+        );
+    }
+}
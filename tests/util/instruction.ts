@@ -30,6 +30,457 @@ export function getPoolAddress(programId: PublicKey): PublicKey {
     )[0]
 }
 
+/** swap 指令数据中数量的字节序，对应程序中的 `AmountEncoding` */
+export enum AmountEncoding {
+    LittleEndian = 0,
+    BigEndian = 1,
+}
+
+/**
+ * TryArbitrage 指令的可选配置，对应程序中的 `TryArbitrageOptions`
+ */
+export interface TryArbitrageOptions {
+    /** 是否允许两个交换程序相同（同一程序内的池子间套利） */
+    allowSameProgram: boolean
+    /** 是否让程序输出详细日志 */
+    verbose: boolean
+    /** 至少需要多少个资产对超过阈值才执行交易（0 与 1 等价） */
+    minQualifyingPairs: number
+    /** 是否要求用户的代币账户为规范的关联代币账户 */
+    requireCanonicalAta: boolean
+    /** 是否要求每个 swap CPI 都返回由该 swap 程序标记的数据 */
+    verifySwapReturn: boolean
+    /** 按资产顺序排列的小数位数覆盖值，null 表示使用铸币账户中的值 */
+    decimalsOverrides: (number | null)[]
+    /** 是否按两个池子中较小的接收预估值确定卖出数量 */
+    conservativeSizing: boolean
+    /** 发往 swap 程序 1 的 CPI 账户重排表，为空时使用默认顺序 */
+    swap1AccountOrder: number[]
+    /** 发往 swap 程序 2 的 CPI 账户重排表，为空时使用默认顺序 */
+    swap2AccountOrder: number[]
+    /** 铸币账户无法解析时是否排除该资产而不是中止指令 */
+    skipUnparseableMints: boolean
+    /** 手续费率在 swap 程序 1 流动性池账户数据中的偏移量 */
+    swap1FeeOffset: number | null
+    /** 手续费率在 swap 程序 2 流动性池账户数据中的偏移量 */
+    swap2FeeOffset: number | null
+    /** 价差相同时的选择规则：0 = 索引较小，1 = 流动性更深，2 = 价格冲击更小 */
+    tieBreak: number
+    /** 是否只执行 RecordOpportunity 记录的计划，为 true 时套利计划 PDA 紧跟在四个账户列表之后 */
+    executeRecordedPlan: boolean
+    /** 套利成功后向 treasury 代币账户收取的费用，设置时需要传入 treasury 代币账户 */
+    treasuryFee: TreasuryFee | null
+    /** 代币账户所有者为本程序 PDA 金库时推导该金库的种子（包含 bump），为空时所有者必须签名 */
+    vaultSeeds: Uint8Array[]
+    /** 交易后实际换回数量与预估值之间允许的最大偏差（基点），超过时只输出警告日志 */
+    maxEstimateDriftBps: number | null
+    /** 第一档没有发现机会时，按更宽松的阈值和缩小的交易数量重新扫描一次 */
+    secondaryTier: SecondaryTier | null
+    /** 没有套利机会时是否成功返回并写入 NoArbReport 返回数据，默认让预检失败 */
+    reportNoArbitrage: boolean
+    /** 可信铸币的最少小数位数，涉及小数位数更少的铸币的资产对不参与扫描 */
+    minDecimals: number
+    /** 买入腿的兑换模式：0 为 ExactIn（支付固定数量），1 为 ExactOut（接收固定数量） */
+    buySwapMode: number
+    /** 卖出腿的兑换模式：0 为 ExactIn，1 为 ExactOut（锁定换回的资产 i 数量） */
+    sellSwapMode: number
+    /** 最后更新 slot 在 swap 程序 1 流动性池账户数据中的偏移量 */
+    swap1SlotOffset: number | null
+    /** 最后更新 slot 在 swap 程序 2 流动性池账户数据中的偏移量 */
+    swap2SlotOffset: number | null
+    /** 池子最后更新 slot 允许落后当前 slot 的最大数量 */
+    maxPoolStalenessSlots: number | null
+    /** swap 程序 1 为加权池时按资产顺序排列的权重，为空时按恒定乘积公式定价 */
+    swap1Weights: number[]
+    /** swap 程序 2 为加权池时按资产顺序排列的权重，为空时按恒定乘积公式定价 */
+    swap2Weights: number[]
+    /** 设置时在交易前输出建议的优先费（每 compute unit 的 micro-lamports） */
+    priorityFeeHint: PriorityFeeHint | null
+    /** 是否跳过 denylistAuthority 的程序配置 PDA 黑名单中的资产对，为 true 时配置 PDA 跟在 treasury 代币账户（如有）之后 */
+    useDenylist: boolean
+    /** 最多参与定价的资产数量，超出的资产不参与扫描；0 表示不限制 */
+    maxMints: number
+    /** 预估的交易费用（lamports），利润换算成 lamports 后不足该值时不交易；0 表示不检查 */
+    txCostLamports: number
+    /** 换算交易费用时作为参考的包装 SOL 资产索引 */
+    solAssetIndex: number
+    /** 按资产顺序排列的账户复用表，k 表示 Swap #2 该资产复用 Swap #1 资产 k 的池子代币账户（并从 Swap #2 列表中省略） */
+    swap2SharedAccounts: (number | null)[]
+    /** 是否允许 temperature 不小于 100（阈值为零，任何非零价差都会交易） */
+    allowZeroThreshold: boolean
+    /** 是否在解析完铸币后重新读取池子余额，不一致时返回 ReserveMutated */
+    paranoid: boolean
+    /** 可接受的最高池子手续费率（基点），任一池子超过时不交易；null 时不限制 */
+    maxAcceptableFeeBps: number | null
+    /** 按资产顺序排列，offset 表示该资产的小数位数记录在元数据账户中的该偏移处（元数据账户跟在其他可选账户之后） */
+    metadataDecimals: (number | null)[]
+    /** 往返一次预估至少获得的利润（资产 i 的最小单位），不足时不交易 */
+    minProfit: number
+    /** 是否只报价不交易：搜索最大的可盈利支付数量并以 MaxSizeQuote 写入返回数据 */
+    quoteMaxSize: boolean
+    /** 是否以整币为单位解释 minProfit（按资产 i 的小数位数换算） */
+    minProfitWholeTokens: boolean
+    /** 支付数量占池子该资产余额的最大比例（基点），超过时截断；null 时不限制 */
+    maxPayPoolRatioBps: number | null
+    /** 是否在交易成功后把交易记录通过 CPI 发送给 recordProgram */
+    persistRecord: boolean
+    /** 接收交易记录的日志程序地址 */
+    recordProgram: PublicKey
+    /** 执行交易要求的预估利润相对 minProfit 的倍数（基点）；未达到时只返回报价 */
+    safetyMultipleBps: number | null
+    /** 每条交易腿的滑点下限占预估接收数量的比例（基点）；null 时不检查 */
+    minOutFractionBps: number | null
+    /** 是否要求两个池子的所有代币账户属于同一个代币程序 */
+    requireUniformTokenProgram: boolean
+    /** Swap #1 程序识别指令的方式，非 Anchor 程序使用 1 字节或 `u32` 指令标签 */
+    swap1Discriminator: Discriminator
+    /** Swap #2 程序识别指令的方式，非 Anchor 程序使用 1 字节或 `u32` 指令标签 */
+    swap2Discriminator: Discriminator
+    /** 价差上限（基点），超过时跳过该资产对，`null` 表示不设上限 */
+    maxEdgeBps: number | null
+    /** 按比例收取的 treasury 费用的取整方向：0 = 向下取整（对用户有利），1 = 向上取整（对 treasury 有利） */
+    treasuryFeeRounding: number
+    /** 兑换后两个池子都至少要留下的资产 j 数量，不满足时跳过该资产对，0 表示不检查 */
+    minResidual: number
+    /** 所有 swap CPI 引用的账户总数上限，超过时在发起 CPI 之前失败，`null` 表示不检查 */
+    maxCpiAccounts: number | null
+    /** 指定买入池：0 = Swap #1，1 = Swap #2，`null` 表示由程序判断方向 */
+    forceDirection: number | null
+    /** 是否把卖出腿之后新增的中间资产转入 dust 收集代币账户，为 true 时该账户跟在日志程序（如有）之后 */
+    sweepDust: boolean
+    /** 设置时改为评估经过第三个流动性池的三资产循环，Swap #3 程序、流动性池与代币账户跟在 dust 收集账户（如有）之后 */
+    triangular: TriangularConfig | null
+    /** 设置了 useDenylist 时读取其黑名单的配置 authority，配置 PDA 由 ["config", denylistAuthority] 推导 */
+    denylistAuthority: PublicKey
+    /** 设置时账户列表省略 associated_token_program，其后的账户依次前移，swap CPI 也不再携带该账户 */
+    omitAssociatedTokenProgram: boolean
+    /** 所有 swap 指令数据中数量的字节序，默认小端序 */
+    amountEncoding: AmountEncoding
+}
+
+/**
+ * 默认配置，保持程序原有的行为；与程序中 `TryArbitrageOptions::default()` 逐字段一致，
+ * 等于默认值的字段不会写入指令数据
+ */
+export const DEFAULT_TRY_ARBITRAGE_OPTIONS: TryArbitrageOptions = {
+    allowSameProgram: false,
+    verbose: false,
+    minQualifyingPairs: 0,
+    requireCanonicalAta: false,
+    verifySwapReturn: false,
+    decimalsOverrides: [],
+    conservativeSizing: false,
+    swap1AccountOrder: [],
+    swap2AccountOrder: [],
+    skipUnparseableMints: false,
+    swap1FeeOffset: null,
+    swap2FeeOffset: null,
+    tieBreak: 0,
+    executeRecordedPlan: false,
+    treasuryFee: null,
+    vaultSeeds: [],
+    maxEstimateDriftBps: null,
+    secondaryTier: null,
+    reportNoArbitrage: false,
+    minDecimals: 0,
+    buySwapMode: 0,
+    sellSwapMode: 0,
+    swap1SlotOffset: null,
+    swap2SlotOffset: null,
+    maxPoolStalenessSlots: null,
+    swap1Weights: [],
+    swap2Weights: [],
+    priorityFeeHint: null,
+    useDenylist: false,
+    maxMints: 0,
+    txCostLamports: 0,
+    solAssetIndex: 0,
+    swap2SharedAccounts: [],
+    allowZeroThreshold: false,
+    paranoid: false,
+    maxAcceptableFeeBps: null,
+    metadataDecimals: [],
+    minProfit: 0,
+    quoteMaxSize: false,
+    minProfitWholeTokens: false,
+    maxPayPoolRatioBps: null,
+    persistRecord: false,
+    recordProgram: PublicKey.default,
+    safetyMultipleBps: null,
+    minOutFractionBps: null,
+    requireUniformTokenProgram: false,
+    swap1Discriminator: 'anchor8',
+    swap2Discriminator: 'anchor8',
+    maxEdgeBps: null,
+    treasuryFeeRounding: 0,
+    minResidual: 0,
+    maxCpiAccounts: null,
+    forceDirection: null,
+    sweepDust: false,
+    triangular: null,
+    denylistAuthority: PublicKey.default,
+    omitAssociatedTokenProgram: false,
+    amountEncoding: AmountEncoding.LittleEndian,
+}
+
+
+/**
+ * 扫描时资产对被跳过的原因，对应程序中的 `SkipReason`，顺序与 `ScanReport.skipped_by_reason` 的下标一致
+ */
+export enum SkipReason {
+    Excluded = 0,
+    ZeroReceive = 1,
+    InsufficientLiquidity = 2,
+    BelowThreshold = 3,
+    Denied = 4,
+    ZeroBalance = 5,
+    TooGoodToBeTrue = 6,
+    BelowMinResidual = 7,
+}
+
+/** `ScanReport.skipped_by_reason` 的长度，对应程序中的 `SKIP_REASONS` */
+export const SKIP_REASONS = 8
+
+/**
+ * treasury 费用，对应程序中的 `FeeMode`：按利润比例（基点）或每笔固定数量
+ */
+export type TreasuryFee = { bps: number } | { flat: number }
+
+/**
+ * `FeeMode` 的 borsh 枚举表示，`enum` 字段为变体名
+ */
+class FeeModeValue {
+    enum: string
+    bps?: number
+    flat?: number
+
+    constructor(fee: TreasuryFee) {
+        if ('bps' in fee) {
+            this.enum = 'bps'
+            this.bps = fee.bps
+        } else {
+            this.enum = 'flat'
+            this.flat = fee.flat
+        }
+    }
+}
+
+/**
+ * swap 程序识别指令的方式，对应程序中的 `DiscriminatorKind`：
+ * Anchor 的 8 字节哈希，1 字节 / `u32` 指令标签，或直接给出的 8 字节指令标识
+ */
+export type Discriminator = 'anchor8' | { u8: number } | { u32: number } | { custom: Uint8Array }
+
+/**
+ * 无字段的 borsh 枚举变体
+ */
+class UnitValue {}
+
+/**
+ * `DiscriminatorKind` 的 borsh 枚举表示，`enum` 字段为变体名
+ */
+class DiscriminatorKindValue {
+    enum: string
+    anchor8?: UnitValue
+    u8?: number
+    u32?: number
+    custom?: number[]
+
+    constructor(discriminator: Discriminator) {
+        if (discriminator === 'anchor8') {
+            this.enum = 'anchor8'
+            this.anchor8 = new UnitValue()
+        } else if ('u8' in discriminator) {
+            this.enum = 'u8'
+            this.u8 = discriminator.u8
+        } else if ('u32' in discriminator) {
+            this.enum = 'u32'
+            this.u32 = discriminator.u32
+        } else {
+            this.enum = 'custom'
+            this.custom = Array.from(discriminator.custom)
+        }
+    }
+}
+
+/**
+ * 三角套利的第三个流动性池，对应程序中的 `TriangularConfig`
+ */
+export interface TriangularConfig {
+    swap3ProgramId: PublicKey
+    swap3FeeBps: number
+    swap3Discriminator: Discriminator
+}
+
+/**
+ * `TriangularConfig` 的 borsh 结构体表示
+ */
+class TriangularConfigValue {
+    swap_3_program_id: Buffer
+    swap_3_fee_bps: number
+    swap_3_discriminator: DiscriminatorKindValue
+
+    constructor(config: TriangularConfig) {
+        this.swap_3_program_id = config.swap3ProgramId.toBuffer()
+        this.swap_3_fee_bps = config.swap3FeeBps
+        this.swap_3_discriminator = new DiscriminatorKindValue(config.swap3Discriminator)
+    }
+}
+
+/**
+ * 第二档阈值，对应程序中的 `SecondaryTier`：温度阈值与交易数量占用户余额的比例（基点）
+ */
+export interface SecondaryTier {
+    temperature: number
+    sizeBps: number
+}
+
+/**
+ * `SecondaryTier` 的 borsh 结构体表示
+ */
+class SecondaryTierValue {
+    temperature: number
+    size_bps: number
+
+    constructor(tier: SecondaryTier) {
+        this.temperature = tier.temperature
+        this.size_bps = tier.sizeBps
+    }
+}
+
+/**
+ * 优先费建议值的参数，对应程序中的 `PriorityFeeHint`：
+ * 建议值 = 基础价格 + 预估利润 * profitFactorBps / 10000
+ */
+export interface PriorityFeeHint {
+    baseMicroLamports: number
+    profitFactorBps: number
+}
+
+/**
+ * `PriorityFeeHint` 的 borsh 结构体表示
+ */
+class PriorityFeeHintValue {
+    base_micro_lamports: number
+    profit_factor_bps: number
+
+    constructor(hint: PriorityFeeHint) {
+        this.base_micro_lamports = hint.baseMicroLamports
+        this.profit_factor_bps = hint.profitFactorBps
+    }
+}
+
+/**
+ * `TryArbitrageOptions` 各字段的标签、borsh 类型与转换方式，数组下标即为字段标签，
+ * 与程序中 `tagged_options!` 的标签一一对应；新增字段只能追加在末尾
+ */
+const TRY_ARBITRAGE_OPTION_FIELDS: {
+    key: keyof TryArbitrageOptions
+    type: any
+    toWire: (value: any) => any
+}[] = [
+    { key: 'allowSameProgram', type: 'u8', toWire: (value) => value ? 1 : 0 },
+    { key: 'verbose', type: 'u8', toWire: (value) => value ? 1 : 0 },
+    { key: 'minQualifyingPairs', type: 'u8', toWire: (value) => value },
+    { key: 'requireCanonicalAta', type: 'u8', toWire: (value) => value ? 1 : 0 },
+    { key: 'verifySwapReturn', type: 'u8', toWire: (value) => value ? 1 : 0 },
+    { key: 'decimalsOverrides', type: [{ kind: 'option', type: 'u8' }], toWire: (value) => value },
+    { key: 'conservativeSizing', type: 'u8', toWire: (value) => value ? 1 : 0 },
+    { key: 'swap1AccountOrder', type: ['u8'], toWire: (value) => value },
+    { key: 'swap2AccountOrder', type: ['u8'], toWire: (value) => value },
+    { key: 'skipUnparseableMints', type: 'u8', toWire: (value) => value ? 1 : 0 },
+    { key: 'swap1FeeOffset', type: { kind: 'option', type: 'u32' }, toWire: (value) => value },
+    { key: 'swap2FeeOffset', type: { kind: 'option', type: 'u32' }, toWire: (value) => value },
+    { key: 'tieBreak', type: 'u8', toWire: (value) => value },
+    { key: 'executeRecordedPlan', type: 'u8', toWire: (value) => value ? 1 : 0 },
+    { key: 'treasuryFee', type: { kind: 'option', type: FeeModeValue }, toWire: (value) => value ? new FeeModeValue(value) : null },
+    { key: 'vaultSeeds', type: [['u8']], toWire: (value) => value.map((seed) => Array.from(seed)) },
+    { key: 'maxEstimateDriftBps', type: { kind: 'option', type: 'u16' }, toWire: (value) => value },
+    { key: 'secondaryTier', type: { kind: 'option', type: SecondaryTierValue }, toWire: (value) => value ? new SecondaryTierValue(value) : null },
+    { key: 'reportNoArbitrage', type: 'u8', toWire: (value) => value ? 1 : 0 },
+    { key: 'minDecimals', type: 'u8', toWire: (value) => value },
+    { key: 'buySwapMode', type: 'u8', toWire: (value) => value },
+    { key: 'sellSwapMode', type: 'u8', toWire: (value) => value },
+    { key: 'swap1SlotOffset', type: { kind: 'option', type: 'u32' }, toWire: (value) => value },
+    { key: 'swap2SlotOffset', type: { kind: 'option', type: 'u32' }, toWire: (value) => value },
+    { key: 'maxPoolStalenessSlots', type: { kind: 'option', type: 'u64' }, toWire: (value) => value },
+    { key: 'swap1Weights', type: ['u8'], toWire: (value) => value },
+    { key: 'swap2Weights', type: ['u8'], toWire: (value) => value },
+    { key: 'priorityFeeHint', type: { kind: 'option', type: PriorityFeeHintValue }, toWire: (value) => value ? new PriorityFeeHintValue(value) : null },
+    { key: 'useDenylist', type: 'u8', toWire: (value) => value ? 1 : 0 },
+    { key: 'maxMints', type: 'u8', toWire: (value) => value },
+    { key: 'txCostLamports', type: 'u64', toWire: (value) => value },
+    { key: 'solAssetIndex', type: 'u8', toWire: (value) => value },
+    { key: 'swap2SharedAccounts', type: [{ kind: 'option', type: 'u8' }], toWire: (value) => value },
+    { key: 'allowZeroThreshold', type: 'u8', toWire: (value) => value ? 1 : 0 },
+    { key: 'paranoid', type: 'u8', toWire: (value) => value ? 1 : 0 },
+    { key: 'maxAcceptableFeeBps', type: { kind: 'option', type: 'u16' }, toWire: (value) => value },
+    { key: 'metadataDecimals', type: [{ kind: 'option', type: 'u32' }], toWire: (value) => value },
+    { key: 'minProfit', type: 'u64', toWire: (value) => value },
+    { key: 'quoteMaxSize', type: 'u8', toWire: (value) => value ? 1 : 0 },
+    { key: 'minProfitWholeTokens', type: 'u8', toWire: (value) => value ? 1 : 0 },
+    { key: 'maxPayPoolRatioBps', type: { kind: 'option', type: 'u16' }, toWire: (value) => value },
+    { key: 'persistRecord', type: 'u8', toWire: (value) => value ? 1 : 0 },
+    { key: 'recordProgram', type: [32], toWire: (value) => value.toBuffer() },
+    { key: 'safetyMultipleBps', type: { kind: 'option', type: 'u16' }, toWire: (value) => value },
+    { key: 'minOutFractionBps', type: { kind: 'option', type: 'u16' }, toWire: (value) => value },
+    { key: 'requireUniformTokenProgram', type: 'u8', toWire: (value) => value ? 1 : 0 },
+    { key: 'swap1Discriminator', type: DiscriminatorKindValue, toWire: (value) => new DiscriminatorKindValue(value) },
+    { key: 'swap2Discriminator', type: DiscriminatorKindValue, toWire: (value) => new DiscriminatorKindValue(value) },
+    { key: 'maxEdgeBps', type: { kind: 'option', type: 'u16' }, toWire: (value) => value },
+    { key: 'treasuryFeeRounding', type: 'u8', toWire: (value) => value },
+    { key: 'minResidual', type: 'u64', toWire: (value) => value },
+    { key: 'maxCpiAccounts', type: { kind: 'option', type: 'u16' }, toWire: (value) => value },
+    { key: 'forceDirection', type: { kind: 'option', type: 'u8' }, toWire: (value) => value },
+    { key: 'sweepDust', type: 'u8', toWire: (value) => value ? 1 : 0 },
+    { key: 'triangular', type: { kind: 'option', type: TriangularConfigValue }, toWire: (value) => value ? new TriangularConfigValue(value) : null },
+    { key: 'denylistAuthority', type: [32], toWire: (value) => value.toBuffer() },
+    { key: 'omitAssociatedTokenProgram', type: 'u8', toWire: (value) => value ? 1 : 0 },
+    { key: 'amountEncoding', type: 'u8', toWire: (value) => value },
+]
+
+/**
+ * 承载单个配置字段的 borsh 结构体
+ */
+class OptionFieldValue {
+    value: any
+
+    constructor(value: any) {
+        this.value = value
+    }
+}
+
+/**
+ * 按字段自身的 borsh 类型序列化一个配置字段的值
+ */
+function encodeOptionField(type: any, value: any): Buffer {
+    const schema = new Map<any, any>(OptionValueSchema)
+    schema.set(OptionFieldValue, { kind: 'struct', fields: [['value', type]] })
+    return Buffer.from(borsh.serialize(schema, new OptionFieldValue(value)))
+}
+
+/**
+ * 将 `TryArbitrageOptions` 序列化为程序使用的带标签编码：
+ * 一个 `u8` 条目数，随后按标签递增排列、不等于默认值的字段，每个条目为 `u8` 标签加该字段的 borsh 编码
+ *
+ * @param options - 完整的配置
+ * @returns 紧跟在 `temperature` 之后的配置数据
+ */
+export function encodeTryArbitrageOptions(options: TryArbitrageOptions): Buffer {
+    const entries: Buffer[] = []
+    TRY_ARBITRAGE_OPTION_FIELDS.forEach((field, tag) => {
+        const value = encodeOptionField(field.type, field.toWire(options[field.key]))
+        const defaultValue = encodeOptionField(
+            field.type,
+            field.toWire(DEFAULT_TRY_ARBITRAGE_OPTIONS[field.key])
+        )
+        if (!value.equals(defaultValue)) {
+            entries.push(Buffer.from([tag]), value)
+        }
+    })
+    return Buffer.concat([Buffer.from([entries.length / 2]), ...entries])
+}
+
 /**
  * ArbitrageProgramInstruction 类用于构建套利程序的指令数据
  * 该类将套利交易的相关参数序列化为可发送到区块链程序的二进制数据
@@ -40,6 +491,7 @@ class ArbitrageProgramInstruction {
     swap_2_program_id: Uint8Array
     concurrency: number
     temperature: number
+    options: TryArbitrageOptions
 
     /**
      * 构造函数，初始化套利程序指令参数
@@ -47,19 +499,22 @@ class ArbitrageProgramInstruction {
      * @param props.swapProgram1 第一个交换程序的公钥
      * @param props.swapProgram2 第二个交换程序的公钥
      * @param props.concurrency 并发数，控制同时执行的交易数量
-     * @param props.temperature 温度参数，用于控制交易的敏感度
+     * @param props.temperature 交易的激进程度，价差百分比超过 100 - temperature 时才交易，越大越激进
+     * @param props.options 可选配置，序列化在 temperature 之后
      */
     constructor(props: {
         swapProgram1: PublicKey
         swapProgram2: PublicKey
         concurrency: number
         temperature: number
+        options: TryArbitrageOptions
     }) {
         this.instruction = 0
         this.swap_1_program_id = props.swapProgram1.toBuffer()
         this.swap_2_program_id = props.swapProgram2.toBuffer()
         this.concurrency = props.concurrency
         this.temperature = props.temperature
+        this.options = props.options
     }
 
     /**
@@ -68,10 +523,11 @@ class ArbitrageProgramInstruction {
      * @returns 序列化后的 Buffer 对象，可用于发送到区块链程序
      */
     toBuffer() {
-        // 使用 borsh 序列化方案将当前对象序列化为二进制数据
-        return Buffer.from(
-            borsh.serialize(ArbitrageProgramInstructionSchema, this)
-        )
+        // 先序列化固定的指令头，再追加带标签的配置条目
+        return Buffer.concat([
+            Buffer.from(borsh.serialize(ArbitrageProgramInstructionSchema, this)),
+            encodeTryArbitrageOptions(this.options),
+        ])
     }
 }
 
@@ -79,7 +535,8 @@ class ArbitrageProgramInstruction {
  * 定义套利程序指令的数据结构模式
  * 
  * 该模式用于序列化和反序列化套利程序的指令数据，包含执行套利交易所需的核心参数配置。
- * 指令结构定义了两个交换程序的标识、并发级别和温度控制参数等关键字段。
+ * 指令结构定义了两个交换程序的标识、并发级别和温度控制参数等关键字段，
+ * `TryArbitrageOptions` 由 `encodeTryArbitrageOptions` 单独编码后追加在其后。
  */
 const ArbitrageProgramInstructionSchema = new Map([
     [
@@ -97,6 +554,74 @@ const ArbitrageProgramInstructionSchema = new Map([
     ],
 ])
 
+/**
+ * 配置字段中嵌套的 borsh 结构体与枚举的模式
+ */
+const OptionValueSchema = new Map<any, any>([
+    [
+        UnitValue,
+        {
+            kind: 'struct',
+            fields: [],
+        },
+    ],
+    [
+        DiscriminatorKindValue,
+        {
+            kind: 'enum',
+            field: 'enum',
+            values: [
+                ['anchor8', UnitValue],
+                ['u8', 'u8'],
+                ['u32', 'u32'],
+                ['custom', [8]],
+            ],
+        },
+    ],
+    [
+        FeeModeValue,
+        {
+            kind: 'enum',
+            field: 'enum',
+            values: [
+                ['bps', 'u16'],
+                ['flat', 'u64'],
+            ],
+        },
+    ],
+    [
+        TriangularConfigValue,
+        {
+            kind: 'struct',
+            fields: [
+                ['swap_3_program_id', [32]],
+                ['swap_3_fee_bps', 'u16'],
+                ['swap_3_discriminator', DiscriminatorKindValue],
+            ],
+        },
+    ],
+    [
+        SecondaryTierValue,
+        {
+            kind: 'struct',
+            fields: [
+                ['temperature', 'u8'],
+                ['size_bps', 'u16'],
+            ],
+        },
+    ],
+    [
+        PriorityFeeHintValue,
+        {
+            kind: 'struct',
+            fields: [
+                ['base_micro_lamports', 'u64'],
+                ['profit_factor_bps', 'u16'],
+            ],
+        },
+    ],
+])
+
 /**
  * 创建一个默认的账户元数据对象
  * 
@@ -117,9 +642,11 @@ export function defaultAccountMeta(pubkey: PublicKey): AccountMeta {
  * @param tokenAccountsSwap2 - 第二个swap程序的代币账户公钥数组
  * @param mints - 涉及的代币mint账户公钥数组
  * @param concurrency - 并发参数，控制套利指令的并发级别
- * @param temperature - 温度参数，控制套利指令的敏感度
+ * @param temperature - 交易的激进程度，价差百分比超过 100 - temperature 时才交易，越大越激进
  * @param swapProgram1 - 第一个swap程序的公钥
  * @param swapProgram2 - 第二个swap程序的公钥
+ * @param owner - 用户代币账户的所有者（交易授权方），默认与 payer 相同
+ * @param options - 可选配置，未指定的字段使用 `DEFAULT_TRY_ARBITRAGE_OPTIONS`
  * @returns 表示套利操作的TransactionInstruction对象
  */
 export function createArbitrageInstruction(
@@ -132,7 +659,9 @@ export function createArbitrageInstruction(
     concurrency: number,
     temperature: number,
     swapProgram1: PublicKey,
-    swapProgram2: PublicKey
+    swapProgram2: PublicKey,
+    owner: PublicKey = payer,
+    options: Partial<TryArbitrageOptions> = {}
 ): TransactionInstruction {
     // 获取两个swap程序的流动性池地址
     let swapPool1 = getPoolAddress(swapProgram1)
@@ -144,12 +673,15 @@ export function createArbitrageInstruction(
         swapProgram2,
         concurrency,
         temperature,
+        options: { ...DEFAULT_TRY_ARBITRAGE_OPTIONS, ...options },
     }).toBuffer()
 
     // 初始化账户元数据数组，包含所需的系统和程序账户
     let keys: AccountMeta[] = [
         // 支付账户
         { pubkey: payer, isSigner: true, isWritable: true },
+        // 代币账户所有者
+        { pubkey: owner, isSigner: true, isWritable: true },
         // 代币程序
         { pubkey: TOKEN_PROGRAM_ID, isSigner: false, isWritable: false },
         // 系统程序